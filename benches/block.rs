@@ -0,0 +1,82 @@
+//! How block transfers compare with moving bytes one at a time: the copies
+//! themselves, then programs writing a page of data memory to video memory
+//! with graphics function 7, and with function 2 a byte at a time.
+//!
+//! Run with `cargo bench`, adding `--features simd` for the SIMD path.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use pact::asm::assemble;
+use pact::block;
+use pact::config::RimConfig;
+use pact::graphics::Graphics;
+use pact::Rim;
+
+/// Selects bank 1 for the next I/O, leaving Rc alone.
+const BANK: &str = "    li rb, 1\n    li ra, 2\n    ioi cpu, 7\n";
+
+/// Prints the average time `f` takes over `iterations` calls.
+fn time(name: &str, iterations: u32, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+
+    println!("{name:<40} {:>12?}", start.elapsed() / iterations);
+}
+
+/// A machine with graphics, and page 0x10 of data memory counting up,
+/// ready to run `source`.
+fn machine(source: &str) -> Rim {
+    let mut rim = RimConfig::new().graphics(Graphics::new()).build(assemble(source).unwrap());
+    let mut transaction = rim.memory_transaction();
+    transaction.write_all(pact::addr::Addr::new(0x10, 0), &std::array::from_fn::<u8, 256, _>(|i| i as u8));
+    transaction.commit();
+    rim
+}
+
+/// Writes data memory from page 0x10 to video memory with function 7.
+fn block_program() -> String {
+    format!("    li rc, 0x10\n{BANK}    ior kbd, 7\n")
+}
+
+/// Writes the same bytes with function 2, loading each into Rc first.
+fn byte_program() -> String {
+    let mut source = String::new();
+    for page in 0x10..0x20 {
+        source += &format!("    li rd, {page}\n");
+        for offset in 0..16 {
+            source += &format!("    li ra, {offset}\n    ioi cpu, 3\n    sub rc, rc\n    add ra, rc\n{BANK}    ior kbd, 2\n");
+        }
+    }
+
+    source
+}
+
+fn run(rim: &Rim) {
+    let mut rim = rim.clone();
+    rim.run().unwrap();
+    assert_eq!(rim.graphics().unwrap().vram()[..256], rim.data()[0x100..0x200]);
+    black_box(rim);
+}
+
+fn main() {
+    let src: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+    let mut dest = vec![0; 4096];
+
+    for len in [16, 256, 4096] {
+        time(&format!("copy_from_slice, {len} bytes"), 100_000, || {
+            black_box(&mut dest[..len]).copy_from_slice(black_box(&src[..len]));
+        });
+        time(&format!("block::copy, {len} bytes"), 100_000, || {
+            block::copy(black_box(&mut dest[..len]), black_box(&src[..len]));
+        });
+        time(&format!("block::fill, {len} bytes"), 100_000, || block::fill(black_box(&mut dest[..len]), 0x55));
+    }
+
+    let block = machine(&block_program());
+    let bytes = machine(&byte_program());
+    time("program, function 7, 256 bytes", 10_000, || run(&block));
+    time("program, function 2, 256 bytes", 1_000, || run(&bytes));
+}
@@ -0,0 +1,5 @@
+; Add adds src into dest.
+    li rb, 20
+    li rc, 30
+    add rb, rc
+    ioi cpu, 0
@@ -0,0 +1,7 @@
+; With ids, Add reads its operands' registers from the named registers.
+    li rc, 7
+    li rd, 9
+    li rb, 3        ; rb names rd
+    li ra, 2        ; ra names rc
+    add [ra], [rb]  ; rd += rc
+    ioi cpu, 0
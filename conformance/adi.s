@@ -0,0 +1,4 @@
+; Adi adds its immediate to Ra.
+    adi 5
+    adi 31
+    ioi cpu, 0
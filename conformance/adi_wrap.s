@@ -0,0 +1,11 @@
+; Adi wraps around, setting zero and carry.
+    adi 31
+    adi 31
+    adi 31
+    adi 31
+    adi 31
+    adi 31
+    adi 31
+    adi 31
+    adi 8
+    ioi cpu, 0
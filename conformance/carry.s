@@ -0,0 +1,11 @@
+; The carry condition compares unsigned: 100 < 200.
+    li rd, 1        ; the labels below are in page 1
+    li rb, 100
+    li rc, 200
+    li ra, 3
+    sub rc, rb
+    ioi cpu, 7
+    jl done         ; tests carry, not sign
+    adi 1
+done:
+    ioi cpu, 0
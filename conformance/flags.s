@@ -0,0 +1,5 @@
+; Mth functions 6 and 7 read and write the flags.
+    li ra, 0b0101
+    ioi mth, 7      ; sign and carry
+    ioi mth, 6
+    ioi cpu, 0
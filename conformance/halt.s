@@ -0,0 +1,4 @@
+; Cpu function 0 halts immediately.
+    adi 1
+    ioi cpu, 0
+    adi 1
@@ -0,0 +1,12 @@
+; Cpu functions 5 and 6 load and store through a pointer in Rd's page,
+; using its low 4 bits.
+    li rd, 3
+    li rb, 0x29
+    li ra, 1
+    ior cpu, 4      ; data[0x031] = 0x29, the pointer
+    li rb, 77
+    li ra, 1
+    ior cpu, 6      ; data[0x039] = rb, through the pointer
+    li ra, 1
+    ioi cpu, 5      ; ra = data[0x039], through the pointer
+    ioi cpu, 0
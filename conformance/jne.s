@@ -0,0 +1,7 @@
+; Jne loops until the zero flag is set.
+    li rb, 1
+    li ra, 5
+loop:
+    sub rb, ra
+    jne loop
+    ioi cpu, 0
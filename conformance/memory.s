@@ -0,0 +1,7 @@
+; Cpu functions 3 and 4 load and store through Ra.
+    li rb, 42
+    li ra, 1
+    ior cpu, 4      ; data[1] = rb
+    li ra, 1
+    ioi cpu, 3      ; ra = data[1]
+    ioi cpu, 0
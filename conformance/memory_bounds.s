@@ -0,0 +1,7 @@
+; Only the low 4 bits of an offset count, so memory ends where Rd's last
+; page does.
+    li rd, 255      ; ra = 255 too
+    ioi cpu, 4      ; data[0xfff] = ra
+    li ra, 0x3f
+    ioi cpu, 3      ; ra = data[0xfff]
+    ioi cpu, 0
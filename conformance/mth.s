@@ -0,0 +1,6 @@
+; Mth function 0 multiplies Ra by the register it names, into Ra (low) and
+; Rb (high).
+    li rc, 200
+    li ra, 2
+    ioi mth, 0      ; ra = 2 * rc
+    ioi cpu, 0
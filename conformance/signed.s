@@ -0,0 +1,12 @@
+; Jl and Jg compare dest against src as signed numbers: 200 is -56.
+    li rd, 1        ; the labels below are in page 1
+    li rb, 200
+    li rc, 100
+    ioi cpu, 2
+    sub rc, rb      ; rb = 200 - 100, comparing -56 against 100
+    jg wrong        ; not taken
+    jl right        ; taken
+wrong:
+    adi 1
+right:
+    ioi cpu, 0
@@ -0,0 +1,5 @@
+; Sub subtracts src from dest, borrowing into carry.
+    li rb, 5
+    li rc, 3
+    sub rb, rc      ; rc = 3 - 5
+    ioi cpu, 0
@@ -0,0 +1,70 @@
+//! Compile-time assembly of Rim programs.
+
+use std::path::PathBuf;
+
+use proc_macro::{Delimiter, Group, Literal, Span, TokenStream, TokenTree};
+
+/// Assembles a Rim source file at compile time, yielding its image as a
+/// `&'static [u8]` ready for `pact::read_bytes`. The path is relative to the
+/// crate's `Cargo.toml`. Assembler errors become compile errors.
+///
+/// ```ignore
+/// const PROGRAM: &[u8] = pact_macros::include_rim!("programs/hello.s");
+/// let mut rim = pact::read_bytes(PROGRAM).unwrap();
+/// ```
+#[proc_macro]
+pub fn include_rim(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+
+    let (literal, span) = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => {
+            let span = literal.span();
+            (literal.to_string(), span)
+        }
+        (Some(tree), _) => return error("expected a single string literal", tree.span()),
+        (None, _) => return error("expected a single string literal", Span::call_site()),
+    };
+
+    let Some(relative) = literal.strip_prefix('"').and_then(|l| l.strip_suffix('"')) else {
+        return error("expected a single string literal", span);
+    };
+
+    let mut path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    path.push(relative);
+
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) => return error(&format!("couldn't read {}: {e}", path.display()), span),
+    };
+
+    let bytes = match pact::asm::assemble_str(&source) {
+        Ok(bytes) => bytes,
+        Err(e) => return error(&format!("{}: {e}", path.display()), span),
+    };
+
+    // `include_str!` makes cargo rebuild whenever the source changes.
+    let expansion = format!(
+        "{{ const _: &str = include_str!({:?}); let bytes: &'static [u8] = {}; bytes }}",
+        path.display().to_string(),
+        Literal::byte_string(&bytes),
+    );
+
+    expansion.parse().unwrap()
+}
+
+fn error(message: &str, span: Span) -> TokenStream {
+    let mut message = Literal::string(message);
+    message.set_span(span);
+
+    let args = Group::new(Delimiter::Parenthesis, TokenTree::Literal(message).into());
+    let tokens: TokenStream = "compile_error!".parse().unwrap();
+
+    tokens
+        .into_iter()
+        .chain([TokenTree::Group(args)])
+        .map(|mut tree| {
+            tree.set_span(span);
+            tree
+        })
+        .collect()
+}
@@ -0,0 +1,140 @@
+//! Addresses, and the memory model they imply.
+//!
+//! Code and data are both addressed in 12 bits, as 256 pages of 16
+//! instructions or bytes. An address is a page and an offset in it: the
+//! page comes from Rd (or, for the system calls that set handlers, Rb), and
+//! the offset from the instruction, memory, or a register. Offsets are 4
+//! bits, and when one comes from a whole byte its high bits are dropped,
+//! so an address never leaves its page, and every address is in memory.
+//!
+//! Programs can be shorter than memory, so one way to miss is jumping past
+//! the end of a program, which is a fault. Running off its end halts. The
+//! other is using data past the end of a machine given less than all 4096
+//! bytes (see [`Rim::set_memory_len`](crate::Rim::set_memory_len)), which
+//! is a fault too.
+//!
+//! ```
+//! use pact::{asm, Rim};
+//!
+//! // Ra is both the value and the offset, and only 5 of 0xf5 is used.
+//! let mut rim = Rim::new(asm::assemble("
+//!     li rd, 0x12
+//!     li ra, 0xf5
+//!     ioi cpu, 4      ; data[0x125] = 0xf5
+//!     li ra, 0xa5
+//!     ioi cpu, 3      ; Ra = data[0x125]
+//! ").unwrap());
+//! rim.run().unwrap();
+//! assert_eq!(rim.data()[0x125], 0xf5);
+//! assert_eq!(rim.data()[0x1f5], 0);
+//! assert_eq!(rim.registers()[0], 0xf5);
+//! ```
+//!
+//! A pointer jump from the last page stays in it, landing past the end of
+//! this short program, until it's made long enough to reach:
+//!
+//! ```
+//! use pact::{asm, Rim};
+//!
+//! let source = "
+//!     li rd, 0xff
+//!     li ra, 0xf3
+//!     ioi cpu, 4      ; data[0xff3] = 0xf3
+//!     jne [3]         ; to offset 3 of page 0xff
+//! ";
+//!
+//! let mut rim = Rim::new(asm::assemble(source).unwrap());
+//! assert!(rim.run().is_err());
+//!
+//! let mut program = asm::assemble(source).unwrap();
+//! let halt = asm::assemble("ioi cpu, 0").unwrap()[0];
+//! program.resize(0xff3, asm::assemble("adi 0").unwrap()[0]);
+//! program.push(halt);
+//! let mut rim = Rim::new(program);
+//! rim.run().unwrap();
+//! assert_eq!(rim.pc(), 0xff4);
+//! ```
+//!
+//! Cpu functions 5 and 6 load and store through a pointer byte, which is
+//! an offset in the same page too:
+//!
+//! ```
+//! use pact::{asm, Rim};
+//!
+//! let mut rim = Rim::new(asm::assemble("
+//!     li rd, 3
+//!     li rb, 0x29
+//!     li ra, 1
+//!     ior cpu, 4      ; data[0x031] = 0x29, the pointer
+//!     li rb, 77
+//!     li ra, 1
+//!     ior cpu, 6      ; data[0x039] = 77, through the pointer
+//!     sub rb, rb
+//!     li ra, 1
+//!     ioi cpu, 5      ; Ra = data[0x039], through the pointer
+//! ").unwrap());
+//! rim.run().unwrap();
+//! assert_eq!(rim.data()[0x031], 0x29);
+//! assert_eq!(rim.data()[0x039], 77);
+//! assert_eq!(rim.data()[0x029], 0);
+//! assert_eq!(rim.registers()[0], 77);
+//! ```
+
+use std::fmt::{self, Display};
+
+/// A code or data address: an offset in a page.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Addr(u16);
+
+impl Addr {
+    /// How many bytes or instructions there are in a page.
+    pub const PAGE_SIZE: usize = 16;
+
+    /// How many addresses there are.
+    pub const COUNT: usize = 4096;
+
+    /// The address of `offset` in `page`, using its low 4 bits.
+    pub const fn new(page: u8, offset: u8) -> Self {
+        Self((page as u16) << 4 | (offset & 0b1111) as u16)
+    }
+
+    /// The address at an index into memory, if it's in it.
+    pub const fn from_index(index: usize) -> Option<Self> {
+        if index < Self::COUNT {
+            Some(Self(index as u16))
+        } else {
+            None
+        }
+    }
+
+    pub const fn page(self) -> u8 {
+        (self.0 >> 4) as u8
+    }
+
+    pub const fn offset(self) -> u8 {
+        (self.0 & 0b1111) as u8
+    }
+
+    /// The index of this address into memory, or a program.
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Another offset in the same page.
+    pub const fn with_offset(self, offset: u8) -> Self {
+        Self::new(self.page(), offset)
+    }
+}
+
+impl From<Addr> for usize {
+    fn from(addr: Addr) -> Self {
+        addr.index()
+    }
+}
+
+/// Formats an address as 3 hexadecimal digits, like `0x1f3`.
+impl Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#05x}", self.0)
+    }
+}
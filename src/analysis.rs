@@ -0,0 +1,529 @@
+//! Static analysis by abstract interpretation.
+//!
+//! Instead of running a program on bytes, this runs it on ranges of them:
+//! each register is tracked as the interval of values it could hold at each
+//! instruction, and each flag as set, clear, or unknown. Every path is
+//! followed until nothing changes, so the ranges cover every run from
+//! reset. That's enough to find branches that can never, or must always, go
+//! one way, and code nothing can reach.
+//!
+//! The analysis assumes the Harvard architecture and wrapping arithmetic,
+//! and that nothing but the program itself sets the registers. Anything it
+//! can't follow, like a pointer jump or a jump with Rd unknown, makes it
+//! assume the worst: unknown values, and that anything may be reachable.
+
+use std::fmt;
+
+use crate::addr::Addr;
+use crate::{Device, Instruction, InstructionData, Opcode, Register};
+
+/// Loops that keep widening a range are cut short after this many visits,
+/// by giving up on the range.
+const WIDEN_AFTER: usize = 8;
+
+/// The range of values a byte could hold, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Value {
+    pub lo: u8,
+    pub hi: u8,
+}
+
+impl Value {
+    pub const UNKNOWN: Self = Self { lo: 0, hi: 255 };
+
+    pub fn exact(value: u8) -> Self {
+        Self { lo: value, hi: value }
+    }
+
+    /// The value, if there's only one it could be.
+    pub fn known(self) -> Option<u8> {
+        (self.lo == self.hi).then_some(self.lo)
+    }
+
+    pub fn contains(self, value: u8) -> bool {
+        (self.lo..=self.hi).contains(&value)
+    }
+
+    fn join(self, other: Self) -> Self {
+        Self {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    /// The range as signed bytes.
+    fn signed(self) -> (i16, i16) {
+        match (self.lo, self.hi) {
+            (_, 0..=127) => (self.lo as i16, self.hi as i16),
+            (128.., _) => (self.lo as i8 as i16, self.hi as i8 as i16),
+            _ => (-128, 127),
+        }
+    }
+
+    /// The range of an unsigned result, with whether it carried out of the
+    /// byte, from its unwrapped bounds.
+    fn wrapped(lo: i16, hi: i16) -> (Self, Option<bool>) {
+        match (lo, hi) {
+            (0.., ..=255) => (Self { lo: lo as u8, hi: hi as u8 }, Some(false)),
+            (256.., _) => (Self { lo: (lo - 256) as u8, hi: (hi - 256) as u8 }, Some(true)),
+            (_, ..=-1) => (Self { lo: (lo + 256) as u8, hi: (hi + 256) as u8 }, Some(true)),
+            _ => (Self::UNKNOWN, None),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.known() {
+            Some(value) => write!(f, "{value}"),
+            None if *self == Self::UNKNOWN => write!(f, "?"),
+            None => write!(f, "{}..={}", self.lo, self.hi),
+        }
+    }
+}
+
+/// A flag that's set, clear, or either.
+pub type Flag = Option<bool>;
+
+/// What could be true on entry to an instruction, on any path there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    pub registers: [Value; 4],
+    pub sign: Flag,
+    pub zero: Flag,
+    pub carry: Flag,
+    /// Whether the next I/O instruction may go to an extension bank.
+    pub ext: bool,
+    /// Whether the next jump tests carry instead, if known.
+    pub carry_condition: Flag,
+    /// Whether `ior` may send a register chosen with system call 10 instead
+    /// of the one Ra selects.
+    pub ior_chosen: bool,
+}
+
+impl Default for State {
+    /// The state at reset.
+    fn default() -> Self {
+        Self {
+            registers: [Value::exact(0); 4],
+            sign: Some(false),
+            zero: Some(false),
+            carry: Some(false),
+            ext: false,
+            carry_condition: Some(false),
+            ior_chosen: false,
+        }
+    }
+}
+
+fn join_flag(a: Flag, b: Flag) -> Flag {
+    if a == b {
+        a
+    } else {
+        None
+    }
+}
+
+impl State {
+    /// Nothing known at all.
+    pub fn unknown() -> Self {
+        Self {
+            registers: [Value::UNKNOWN; 4],
+            sign: None,
+            zero: None,
+            carry: None,
+            ext: true,
+            carry_condition: None,
+            ior_chosen: true,
+        }
+    }
+
+    fn join(self, other: Self) -> Self {
+        Self {
+            registers: std::array::from_fn(|i| self.registers[i].join(other.registers[i])),
+            sign: join_flag(self.sign, other.sign),
+            zero: join_flag(self.zero, other.zero),
+            carry: join_flag(self.carry, other.carry),
+            ext: self.ext || other.ext,
+            carry_condition: join_flag(self.carry_condition, other.carry_condition),
+            ior_chosen: self.ior_chosen || other.ior_chosen,
+        }
+    }
+
+    /// Like join, but giving up on any range that grew.
+    fn widen(self, other: Self) -> Self {
+        let mut joined = self.join(other);
+        for (register, old) in joined.registers.iter_mut().zip(self.registers) {
+            if *register != old {
+                *register = Value::UNKNOWN;
+            }
+        }
+
+        joined
+    }
+
+    /// Whether a jump's condition holds, from its own condition.
+    fn condition(&self, own: Flag) -> Flag {
+        match self.carry_condition {
+            Some(false) => own,
+            Some(true) => self.carry,
+            None if own == self.carry => own,
+            None => None,
+        }
+    }
+
+    fn set_result(&mut self, value: Value, sign: (i16, i16), carry: Flag) {
+        self.sign = match sign {
+            (_, ..=-1) => Some(true),
+            (0.., _) => Some(false),
+            _ => None,
+        };
+
+        self.zero = if value.known() == Some(0) {
+            Some(true)
+        } else if value.contains(0) {
+            None
+        } else {
+            Some(false)
+        };
+
+        self.carry = carry;
+    }
+}
+
+/// Something the analysis found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A jump whose condition never holds, at an address.
+    NeverTaken(usize),
+    /// A jump whose condition always holds.
+    AlwaysTaken(usize),
+    /// A jump, at an address, that can only go past the end of the program.
+    JumpPastEnd { pc: usize, target: usize },
+    /// Instructions nothing can reach, from `start` up to `end`.
+    Unreachable { start: usize, end: usize },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NeverTaken(pc) => write!(f, "jump at {pc:#05x} is never taken"),
+            Self::AlwaysTaken(pc) => write!(f, "jump at {pc:#05x} is always taken"),
+            Self::JumpPastEnd { pc, target } => {
+                write!(f, "jump at {pc:#05x} goes to {target:#05x}, past the end of the program")
+            }
+            Self::Unreachable { start, end } if end - start == 1 => {
+                write!(f, "instruction at {start:#05x} is unreachable")
+            }
+            Self::Unreachable { start, end } => {
+                write!(f, "instructions {start:#05x} to {:#05x} are unreachable", end - 1)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// The state on entry to each instruction, or `None` if it's
+    /// unreachable.
+    pub states: Vec<Option<State>>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    pub fn state_at(&self, pc: usize) -> Option<&State> {
+        self.states.get(pc)?.as_ref()
+    }
+
+    /// The target of the non-pointer jump at `pc`, if Rd can only hold one
+    /// value there, along with that value.
+    pub fn static_target(&self, pc: usize, instruction: Instruction) -> Option<(u8, usize)> {
+        let state = self.state_at(pc)?;
+        Some((state.registers[3].known()?, jump_target(instruction, state)?))
+    }
+}
+
+/// Analyzes a program from reset.
+pub fn analyze(instructions: &[Instruction]) -> Analysis {
+    analyze_from(instructions, State::default())
+}
+
+/// Analyzes a program starting at address 0 in `state`.
+pub fn analyze_from(instructions: &[Instruction], state: State) -> Analysis {
+    let len = instructions.len();
+    let mut states: Vec<Option<State>> = vec![None; len];
+    let mut visits = vec![0; len];
+    let mut worklist = vec![(0, state)];
+    // Whether control may go somewhere the analysis can't see, making any
+    // instruction potentially reachable.
+    let mut escaped = false;
+
+    while let Some((pc, state)) = worklist.pop() {
+        let Some(&instruction) = instructions.get(pc) else {
+            continue;
+        };
+
+        let merged = match states[pc] {
+            Some(old) => {
+                visits[pc] += 1;
+                let new = if visits[pc] > WIDEN_AFTER { old.widen(state) } else { old.join(state) };
+                if new == old {
+                    continue;
+                }
+
+                new
+            }
+            None => state,
+        };
+
+        states[pc] = Some(merged);
+        let step = successors(pc, instruction, merged);
+        escaped |= step.escaped;
+        worklist.extend(step.next.into_iter().filter(|&(next, _)| next < len));
+    }
+
+    let mut diagnostics = Vec::new();
+    for (pc, (&instruction, state)) in instructions.iter().zip(&states).enumerate() {
+        let Some(state) = state else {
+            continue;
+        };
+
+        let Some(condition) = jump_condition(instruction, state) else {
+            continue;
+        };
+
+        match condition {
+            Some(false) => diagnostics.push(Diagnostic::NeverTaken(pc)),
+            Some(true) => diagnostics.push(Diagnostic::AlwaysTaken(pc)),
+            None => {}
+        }
+
+        if let Some(target) = jump_target(instruction, state).filter(|&target| target >= len) {
+            if condition != Some(false) {
+                diagnostics.push(Diagnostic::JumpPastEnd { pc, target });
+            }
+        }
+    }
+
+    if !escaped {
+        let mut pc = 0;
+        while pc < len {
+            if states[pc].is_some() {
+                pc += 1;
+                continue;
+            }
+
+            let start = pc;
+            while pc < len && states[pc].is_none() {
+                pc += 1;
+            }
+
+            diagnostics.push(Diagnostic::Unreachable { start, end: pc });
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| match *diagnostic {
+        Diagnostic::NeverTaken(pc) | Diagnostic::AlwaysTaken(pc) => pc,
+        Diagnostic::JumpPastEnd { pc, .. } => pc,
+        Diagnostic::Unreachable { start, .. } => start,
+    });
+
+    Analysis { states, diagnostics }
+}
+
+/// A jump's condition, or `None` if the instruction isn't a jump.
+fn jump_condition(instruction: Instruction, state: &State) -> Option<Flag> {
+    let own = match instruction.0 {
+        Opcode::Jne => state.zero.map(|zero| !zero),
+        Opcode::Jg => match (state.sign, state.zero) {
+            (Some(true), _) | (_, Some(true)) => Some(false),
+            (Some(false), Some(false)) => Some(true),
+            _ => None,
+        },
+        Opcode::Jl => state.sign,
+        _ => return None,
+    };
+
+    Some(state.condition(own))
+}
+
+/// A jump's target, if it's known.
+fn jump_target(instruction: Instruction, state: &State) -> Option<usize> {
+    match instruction.1 {
+        InstructionData::Mem { is_ptr: false, addr } => {
+            Some(Addr::new(state.registers[3].known()?, addr as u8).index())
+        }
+        _ => None,
+    }
+}
+
+/// Where an instruction can go next, and in what state.
+struct Step {
+    next: Vec<(usize, State)>,
+    /// Whether it may also go somewhere unknown.
+    escaped: bool,
+}
+
+fn successors(pc: usize, instruction: Instruction, state: State) -> Step {
+    let mut after = state;
+    let mut step = Step { next: Vec::new(), escaped: false };
+
+    match instruction.1 {
+        InstructionData::Imm(imm) => {
+            let ra = state.registers[0];
+            let (value, carry) = Value::wrapped(ra.lo as i16 + imm as i16, ra.hi as i16 + imm as i16);
+            let (lo, hi) = ra.signed();
+            after.registers[0] = value;
+            after.set_result(value, (lo + imm as i16, hi + imm as i16), carry);
+        }
+        InstructionData::Reg { is_id, src, dest } => {
+            let select = |register: Register| match is_id {
+                true => state.registers[register as usize].known().map(|id| Register::from(id) as usize),
+                false => Some(register as usize),
+            };
+
+            match (select(src), select(dest)) {
+                (Some(src), Some(dest)) => {
+                    let (a, b) = (state.registers[dest], state.registers[src]);
+                    let ((a_lo, a_hi), (b_lo, b_hi)) = (a.signed(), b.signed());
+                    let (value, carry, sign) = if instruction.0 == Opcode::Add {
+                        let (value, carry) = Value::wrapped(a.lo as i16 + b.lo as i16, a.hi as i16 + b.hi as i16);
+                        (value, carry, (a_lo + b_lo, a_hi + b_hi))
+                    } else {
+                        let (value, carry) = Value::wrapped(a.lo as i16 - b.hi as i16, a.hi as i16 - b.lo as i16);
+                        (value, carry, (a_lo - b_hi, a_hi - b_lo))
+                    };
+
+                    after.registers[dest] = value;
+                    after.set_result(value, sign, carry);
+                }
+                _ => {
+                    after.registers = [Value::UNKNOWN; 4];
+                    (after.sign, after.zero, after.carry) = (None, None, None);
+                }
+            }
+        }
+        InstructionData::Mem { is_ptr, .. } => {
+            after.carry_condition = Some(false);
+            let condition = jump_condition(instruction, &state).flatten();
+
+            if condition != Some(true) {
+                step.next.push((pc + 1, after));
+            }
+
+            if condition != Some(false) {
+                match jump_target(instruction, &state).filter(|_| !is_ptr) {
+                    Some(target) => step.next.push((target, after)),
+                    None => step.escaped = true,
+                }
+            }
+
+            return step;
+        }
+        InstructionData::Io { device, function } => {
+            let value = match instruction.0 {
+                Opcode::Ioi => state.registers[0],
+                _ if state.ior_chosen => Value::UNKNOWN,
+                _ => match state.registers[0].known() {
+                    Some(id @ 0..=3) => state.registers[id as usize],
+                    Some(_) => return step,
+                    None => Value::UNKNOWN,
+                },
+            };
+
+            after.ext = false;
+            if state.ext {
+                // It might be extension I/O, which only ever sets Ra.
+                let mut ext = after;
+                ext.registers[0] = Value::UNKNOWN;
+                step.next.push((pc + 1, ext));
+            }
+
+            if !io(&mut after, &mut step, device, function as u8, value) {
+                return step;
+            }
+        }
+    }
+
+    step.next.push((pc + 1, after));
+    step
+}
+
+/// Applies standard I/O, returning whether it continues to the next
+/// instruction.
+fn io(after: &mut State, step: &mut Step, device: Device, function: u8, value: Value) -> bool {
+    let ra = &mut after.registers[0];
+    match (device, function) {
+        (Device::Cpu, 0) => return false,
+        (Device::Cpu, 2) | (Device::Scr, 4) => *ra = Value::exact(0),
+        (Device::Cpu, 3 | 5) | (Device::Kbd, 0 | 3 | 4) | (Device::Scr, 3) => *ra = Value::UNKNOWN,
+        (Device::Cpu, 7) => return sys(after, step, value),
+        (Device::Kbd, 1 | 5) => *ra = Value { lo: 0, hi: 1 },
+        (Device::Kbd, 2) => *ra = Value { lo: 0, hi: 4 },
+        (Device::Mth, 0) => {
+            after.registers[..2].fill(Value::UNKNOWN);
+            after.zero = None;
+        }
+        (Device::Mth, 1..=5) => {
+            *ra = Value::UNKNOWN;
+            after.zero = None;
+        }
+        (Device::Mth, 6) => *ra = Value::UNKNOWN,
+        (Device::Mth, 7) => {
+            let flags = value.known().map(crate::Flags::from_bits);
+            after.sign = flags.map(|flags| flags.sign());
+            after.zero = flags.map(|flags| flags.zero());
+            after.carry = flags.map(|flags| flags.carry());
+        }
+        _ => {}
+    }
+
+    true
+}
+
+fn sys(after: &mut State, step: &mut Step, value: Value) -> bool {
+    match value.known() {
+        // Switching programs leaves this one.
+        Some(0 | 1) => {
+            step.escaped = true;
+            false
+        }
+        Some(2) => {
+            after.ext = true;
+            true
+        }
+        Some(3) => {
+            after.carry_condition = Some(true);
+            true
+        }
+        // Handlers can be entered from anywhere, in any state.
+        Some(4 | 7) => {
+            step.escaped = true;
+            true
+        }
+        Some(5) => {
+            step.escaped = true;
+            false
+        }
+        Some(8) => {
+            after.registers[..3].fill(Value::UNKNOWN);
+            true
+        }
+        Some(10) => {
+            after.ior_chosen = true;
+            true
+        }
+        // The next instruction is extended, and could do anything.
+        Some(9) => {
+            step.escaped = true;
+            *after = State::unknown();
+            true
+        }
+        Some(_) => true,
+        None => {
+            step.escaped = true;
+            *after = State::unknown();
+            true
+        }
+    }
+}
@@ -0,0 +1,830 @@
+//! A simple assembler for Rim programs.
+//!
+//! Each line holds at most one instruction, optionally preceded by any
+//! number of `label:`s. Comments start with `;` or `#`. Operands are
+//! comma-separated:
+//!
+//! ```text
+//! start:
+//!     adi 5           ; Ra += 5
+//!     add rb, ra      ; src, dest
+//!     sub [rb], [ra]  ; registers holding register ids
+//!     jne start       ; labels resolve to their low 4 bits, see below
+//!     jg [3]          ; pointer mode; see `Flags` for conditions
+//!     ioi scr, 2
+//!     li rb, 200      ; pseudo-op, see `Instruction::li`
+//! ```
+//!
+//! Jumps take the high 8 bits of their target from Rd, so a jump to a label
+//! only lands there if Rd holds the label's page (its address `>> 4`).
+//! `li rd, page(label)` loads it, and `li rc, offset(label)` loads the low 4
+//! bits. A few more pseudo-ops take care of it entirely, clobbering Ra, Rd,
+//! and the flags:
+//!
+//! - `jmp label` jumps unconditionally
+//! - `call label` jumps, with Rc holding the page to come back to, and the
+//!   offset at [`LINK_SLOT`] in that page of data memory
+//! - `ret` jumps back, from what Rc and the link slot hold
+//! - `enter` pushes the link onto a stack, freeing Rc
+//! - `leave` pops it back, before `ret`
+//!
+//! ## Calling convention
+//!
+//! So that routines from different authors work together, they:
+//!
+//! - take their first argument in Rb, and return their result there, if
+//!   they have one; Rb is theirs to clobber otherwise
+//! - take any further arguments in cells `0x7e` and then `0x7d`, where
+//!   cell `n` is data byte `n << 4 | 1`, the same as [`bf`](crate::bf)'s
+//! - may clobber Ra, Rd, and the flags
+//! - leave Rc holding the link until `enter`, and put it back with `leave`
+//!   before every `ret`; calling another routine changes Rc too
+//!
+//! The link stack lives in the link slots of page [`LINK_STACK`] and those
+//! below it, so a program that returns into code on those pages corrupts
+//! it. Cell `0x7f` and offset 2 of pages `0x7d`–`0x7f` are scratch space
+//! for the standard routines.
+//!
+//! A routine runs from a label some `call` names to the next such label.
+//! The assembler warns about routines that change Rc while it holds the
+//! link, return without `leave`, or never return at all, and
+//! [`strict_calls`](Assembler::strict_calls) makes those errors:
+//!
+//! ```
+//! use pact::asm::Assembler;
+//!
+//! let source = "
+//!     call clobber
+//!     ioi cpu, 0
+//! clobber:
+//!     li rc, 1
+//!     ret
+//! ";
+//!
+//! assert!(Assembler::new().assemble(source).is_ok());
+//! assert!(Assembler::new().strict_calls(true).assemble(source).is_err());
+//! ```
+//!
+//! ## Standard routines
+//!
+//! `.include <std/print.s>` pulls in one of the [`STD`] files, where it is,
+//! so put it somewhere that isn't run straight through, like after the
+//! program halts. Each file is only pulled in once, and its routines
+//! follow the convention:
+//!
+//! | File            | Routine     | Does                                                     |
+//! |-----------------|-------------|----------------------------------------------------------|
+//! | `std/print.s`   | `print_str` | Prints the string starting at cell Rb, up to a 0         |
+//! | `std/read.s`    | `read_line` | Reads a line into the cells from Rb, echoing it and ending it with a 0; Rb = its length |
+//! | `std/decimal.s` | `print_dec` | Prints Rb in decimal                                     |
+//! | `std/mac.s`     | `mac`       | Adds Rb times cell `0x7e` to cell `0x7d`; Rb = the total |
+//! | `std/memcpy.s`  | `memcpy`    | Copies Rb cells from the one cell `0x7e` names to the one cell `0x7d` names |
+//!
+//! ```
+//! let program = pact::asm::assemble("
+//!     li rb, 123
+//!     call print_dec
+//!     ioi cpu, 0
+//! .include <std/decimal.s>
+//! ").unwrap();
+//!
+//! let mut rim = pact::Rim::new(program);
+//! rim.run().unwrap();
+//! assert_eq!(rim.screen().text().trim_end(), "123");
+//! ```
+//!
+//! ## Build scripts
+//!
+//! [`assemble_str`] and [`assemble_file`] are the stable entry points, and
+//! are meant to be usable from other crates' build scripts. Depend on `pact`
+//! with `default-features = false` to leave out the CLI:
+//!
+//! ```no_run
+//! // build.rs
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("prog.rim");
+//! pact::asm::assemble_file("src/prog.s", &out).unwrap();
+//! println!("cargo:rerun-if-changed=src/prog.s");
+//! ```
+//!
+//! The program can then be embedded with
+//! `include_bytes!(concat!(env!("OUT_DIR"), "/prog.rim"))` and loaded with
+//! [`read_bytes`](crate::read_bytes).
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::error::{AsmError, LoadError, RimError, RimResult};
+use crate::helper::{U3, U4};
+use crate::symbols::Symbols;
+use crate::{Device, Instruction, InstructionData, Opcode, Register};
+
+/// Assembles a program with the default options.
+pub fn assemble(source: &str) -> RimResult<Vec<Instruction>> {
+    Assembler::new().assemble(source)
+}
+
+/// Assembles a program with the default options into a loadable image.
+pub fn assemble_str(source: &str) -> RimResult<Vec<u8>> {
+    crate::to_bytes(&assemble(source)?)
+}
+
+/// Assembles a source file with the default options, writing the image to
+/// `output` and its symbols alongside it.
+pub fn assemble_file<S: AsRef<Path>, O: AsRef<Path>>(source: S, output: O) -> RimResult<()> {
+    let path = source.as_ref();
+    let (instructions, symbols) = std::fs::read_to_string(path)
+        .map_err(RimError::from)
+        .and_then(|source| Assembler::new().assemble_with_symbols(&source))
+        .map_err(|e| e.in_file(path))?;
+
+    crate::write_file(&output, &instructions)?;
+    symbols.write_file(Symbols::path_for(output))
+}
+
+/// An assembler, with its options.
+#[derive(Debug, Default, Clone)]
+pub struct Assembler {
+    expand_immediates: bool,
+    strict_calls: bool,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to split `Adi`s with out-of-range immediates into multiple
+    /// instructions, instead of rejecting them.
+    pub fn expand_immediates(mut self, expand: bool) -> Self {
+        self.expand_immediates = expand;
+        self
+    }
+
+    /// Whether to reject routines that break the calling convention,
+    /// instead of warning about them.
+    pub fn strict_calls(mut self, strict: bool) -> Self {
+        self.strict_calls = strict;
+        self
+    }
+
+    pub fn assemble(&self, source: &str) -> RimResult<Vec<Instruction>> {
+        self.assemble_with_symbols(source).map(|(instructions, _)| instructions)
+    }
+
+    /// Assembles a program, also returning the address of every label.
+    pub fn assemble_with_symbols(&self, source: &str) -> RimResult<(Vec<Instruction>, Symbols)> {
+        self.assemble_annotated(source).map(|(instructions, symbols, _)| (instructions, symbols))
+    }
+
+    /// Assembles a program like [`assemble_with_symbols`](Self::assemble_with_symbols),
+    /// also returning the comment on each instruction's line, by address,
+    /// to embed in an image as its
+    /// [`annotations`](crate::image::Image::annotations). A line that
+    /// expands to several instructions annotates the first, and comments on
+    /// lines of their own are dropped.
+    pub fn assemble_annotated(&self, source: &str) -> RimResult<(Vec<Instruction>, Symbols, BTreeMap<usize, String>)> {
+        let mut unit = Unit::default();
+        self.assemble_lines(source, None, &mut unit)?;
+        let Unit { labels, pending, annotations, events, .. } = unit;
+
+        for (line, message) in check_calls(&events) {
+            if self.strict_calls {
+                return Err(AsmError::Source { line, message }.into());
+            }
+
+            log::warn!("line {line}: {message}");
+        }
+
+        // Resolve every target to the item it names up front, so laying the
+        // program out only deals in items.
+        let targets = pending
+            .iter()
+            .map(|item| match *item {
+                Pending::Ready(_) => Ok(0),
+                Pending::Jump { line, target, .. } | Pending::Load { line, target, .. } => match target {
+                    Target::Item(item) => Ok(item),
+                    Target::Label(label) => labels.get(label).copied().ok_or_else(|| {
+                        RimError::Asm(AsmError::Source { line, message: format!("unknown label `{label}`") })
+                    }),
+                },
+            })
+            .collect::<RimResult<Vec<_>>>()?;
+
+        // Loading an address takes more instructions the bigger it is, which
+        // can move later labels, so lay the program out until nothing moves,
+        // padding loads rather than ever shortening them so that it settles.
+        let mut lens: Vec<usize> = pending.iter().map(|item| usize::from(!matches!(item, Pending::Load { .. }))).collect();
+        let mut addrs = vec![0; pending.len() + 1];
+        loop {
+            let mut pc = 0;
+            for (addr, len) in addrs.iter_mut().zip(&lens) {
+                *addr = pc;
+                pc += len;
+            }
+            addrs[pending.len()] = pc;
+
+            if pc > crate::MAX_PROGRAM_LEN {
+                return Err(LoadError::ProgramTooLarge(pc).into());
+            }
+
+            let mut moved = false;
+            for ((item, len), &target) in pending.iter().zip(&mut lens).zip(&targets) {
+                if let Pending::Load { dest, part, .. } = *item {
+                    let needed = Instruction::li(dest, part.of(addrs[target])).len();
+                    moved |= needed > *len;
+                    *len = (*len).max(needed);
+                }
+            }
+
+            if !moved {
+                break;
+            }
+        }
+
+        let mut instructions = Vec::new();
+        for (i, (item, &target)) in pending.into_iter().zip(&targets).enumerate() {
+            match item {
+                Pending::Ready(instruction) => instructions.push(instruction),
+                Pending::Jump { line, opcode, is_ptr, target: name, warn } => {
+                    let (pc, addr) = (addrs[i], addrs[target]);
+                    if warn && !is_ptr && addr >> 4 != pc >> 4 {
+                        let name = match name {
+                            Target::Label(label) => label,
+                            Target::Item(_) => "<return>",
+                        };
+                        log::warn!(
+                            "line {line}: jump to `{name}` crosses from page {:#x} to {:#x}, so make sure Rd holds {:#x}",
+                            pc >> 4,
+                            addr >> 4,
+                            addr >> 4,
+                        );
+                    }
+
+                    instructions.push(Instruction(opcode, InstructionData::Mem {
+                        is_ptr,
+                        addr: U4::from(addr as u8),
+                    }));
+                }
+                Pending::Load { dest, part, .. } => {
+                    let load = Instruction::li(dest, part.of(addrs[target]));
+                    // Ra gets the value either way, and the flags are clobbered.
+                    let padding = lens[i] - load.len();
+                    instructions.extend(load);
+                    instructions.extend(std::iter::repeat_n(Instruction(Opcode::Adi, InstructionData::Imm(0)), padding));
+                }
+            }
+        }
+
+        let mut symbols = Symbols::new();
+        for (label, item) in labels {
+            symbols.insert(label, addrs[item]);
+        }
+
+        let annotations = annotations.into_iter().map(|(item, comment)| (addrs[item], comment)).collect();
+        Ok((instructions, symbols, annotations))
+    }
+
+    /// Assembles a source's lines into `unit`. Lines of an included file
+    /// report errors at the `.include` in the program that pulled it in.
+    fn assemble_lines<'a>(&self, source: &'a str, include: Option<(usize, &'a str)>, unit: &mut Unit<'a>) -> RimResult<()> {
+        for (i, line) in source.lines().enumerate() {
+            let line_no = i + 1;
+            let reported = include.map_or(line_no, |(line, _)| line);
+            let err = |message: String| {
+                let message = match include {
+                    Some((_, name)) => format!("in `<{name}>` line {line_no}: {message}"),
+                    None => message,
+                };
+                RimError::Asm(AsmError::Source { line: reported, message })
+            };
+
+            let (line, comment) = line.split_once([';', '#']).unwrap_or((line, ""));
+            let mut line = line.trim();
+
+            while let Some((label, rest)) = line.split_once(':') {
+                let label = label.trim();
+                if !is_identifier(label) {
+                    return Err(err(format!("invalid label `{label}`")));
+                }
+
+                if unit.labels.insert(label, unit.pending.len()).is_some() {
+                    return Err(err(format!("duplicate label `{label}`")));
+                }
+
+                unit.events.push((reported, Event::Label(label)));
+
+                line = rest.trim();
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(file) = line.strip_prefix(".include") {
+                let file = file.trim();
+                let name = file.strip_prefix('<').and_then(|file| file.strip_suffix('>'));
+                let Some((name, source)) = name.and_then(|name| STD.iter().find(|(std, _)| *std == name)) else {
+                    return Err(err(format!("unknown include `{file}`, expected one of the `<std/...>` files")));
+                };
+
+                // Each file is only pulled in once, however many ask for it.
+                if unit.included.insert(name) {
+                    self.assemble_lines(source, Some((reported, name)), unit)?;
+                }
+
+                continue;
+            }
+
+            let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let operands: Vec<&str> = operands
+                .split(',')
+                .map(str::trim)
+                .filter(|op| !op.is_empty())
+                .collect();
+
+            let item = unit.pending.len();
+            let mnemonic = mnemonic.to_lowercase();
+            // Included files are written with their pages in mind.
+            let warn = include.is_none();
+            self.parse(reported, warn, &mnemonic, &operands, &mut unit.pending)
+                .map_err(err)?;
+
+            let writes_rc = |dest: &str| parse_register(dest) == Ok((false, Register::Rc));
+            let event = match mnemonic.as_str() {
+                "enter" => Some(Event::Enter),
+                "leave" => Some(Event::Leave),
+                "ret" => Some(Event::Ret),
+                "call" => Some(Event::Call(operands[0])),
+                "li" if writes_rc(operands[0]) => Some(Event::WriteRc),
+                "add" | "sub" if writes_rc(operands[1]) => Some(Event::WriteRc),
+                _ => None,
+            };
+            unit.events.extend(event.map(|event| (reported, event)));
+
+            let comment = comment.trim();
+            if !comment.is_empty() && unit.pending.len() > item {
+                unit.annotations.insert(item, comment.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse<'a>(
+        &self,
+        line: usize,
+        warn: bool,
+        mnemonic: &str,
+        operands: &[&'a str],
+        out: &mut Vec<Pending<'a>>,
+    ) -> Result<(), String> {
+        let arity = |n: usize| {
+            if operands.len() == n {
+                Ok(())
+            } else {
+                Err(format!("`{mnemonic}` takes {n} operand(s), found {}", operands.len()))
+            }
+        };
+
+        match mnemonic {
+            "adi" => {
+                arity(1)?;
+                let imm = parse_number(operands[0])?;
+
+                if self.expand_immediates {
+                    out.extend(Instruction::adi_expanded(imm).into_iter().map(Pending::Ready));
+                } else {
+                    let instruction = Instruction::adi(imm).map_err(|e| e.to_string())?;
+                    out.push(Pending::Ready(instruction));
+                }
+            }
+            "li" => {
+                arity(2)?;
+                let (is_id, dest) = parse_register(operands[0])?;
+                if is_id {
+                    return Err("`li` takes a register, not an id".to_string());
+                }
+
+                let address = |part| operands[1].strip_prefix(part)?.strip_prefix('(')?.strip_suffix(')').map(str::trim);
+                if let Some(label) = address("page") {
+                    out.push(Pending::Load { line, dest, target: Target::Label(label_operand(label)?), part: Part::Page });
+                } else if let Some(label) = address("offset") {
+                    out.push(Pending::Load { line, dest, target: Target::Label(label_operand(label)?), part: Part::Offset });
+                } else {
+                    let imm = parse_number(operands[1])?;
+                    out.extend(Instruction::li(dest, imm).into_iter().map(Pending::Ready));
+                }
+            }
+            "add" | "sub" => {
+                arity(2)?;
+                let (src_id, src) = parse_register(operands[0])?;
+                let (dest_id, dest) = parse_register(operands[1])?;
+                if src_id != dest_id {
+                    return Err("operands must both be ids or both be registers".to_string());
+                }
+
+                let opcode = if mnemonic == "add" { Opcode::Add } else { Opcode::Sub };
+                out.push(Pending::Ready(Instruction(opcode, InstructionData::Reg {
+                    is_id: src_id,
+                    src,
+                    dest,
+                })));
+            }
+            "jne" | "jg" | "jl" => {
+                arity(1)?;
+                let opcode = match mnemonic {
+                    "jne" => Opcode::Jne,
+                    "jg" => Opcode::Jg,
+                    _ => Opcode::Jl,
+                };
+
+                let (is_ptr, target) = match unwrap_brackets(operands[0]) {
+                    Some(target) => (true, target),
+                    None => (false, operands[0]),
+                };
+
+                if is_identifier(target) {
+                    out.push(Pending::Jump { line, opcode, is_ptr, target: Target::Label(target), warn });
+                } else {
+                    let addr = parse_number(target)?;
+                    if addr > 0b1111 {
+                        return Err(format!("address {addr} doesn't fit in 4 bits"));
+                    }
+
+                    out.push(Pending::Ready(Instruction(opcode, InstructionData::Mem {
+                        is_ptr,
+                        addr: U4::from(addr),
+                    })));
+                }
+            }
+            "ioi" | "ior" => {
+                arity(2)?;
+                let device = parse_device(operands[0])?;
+                let function = parse_number(operands[1])?;
+                if function > 0b111 {
+                    return Err(format!("function {function} doesn't fit in 3 bits"));
+                }
+
+                let opcode = if mnemonic == "ioi" { Opcode::Ioi } else { Opcode::Ior };
+                out.push(Pending::Ready(Instruction(opcode, InstructionData::Io {
+                    device,
+                    function: U3::from(function),
+                })));
+            }
+            "jmp" => {
+                arity(1)?;
+                let target = Target::Label(label_operand(operands[0])?);
+                jump(line, target, out);
+            }
+            "call" => {
+                arity(1)?;
+                let target = Target::Label(label_operand(operands[0])?);
+                let start = out.len();
+
+                // The return point is only known once the call is pushed, so
+                // its loads are patched after.
+                let ret = Target::Item(start);
+                out.push(Pending::Load { line, dest: Register::Rd, target: ret, part: Part::Page });
+                out.push(Pending::Load { line, dest: Register::Rc, target: ret, part: Part::Offset });
+                out.extend(Instruction::li(Register::Ra, LINK_SLOT).into_iter().map(Pending::Ready));
+                out.push(Pending::Ready(Instruction::ior(Device::Cpu, U3::B100)));
+                out.push(Pending::Load { line, dest: Register::Rc, target: ret, part: Part::Page });
+                jump(line, target, out);
+
+                let ret = out.len();
+                for item in &mut out[start..] {
+                    if let Pending::Load { target: Target::Item(item), .. } = item {
+                        *item = ret;
+                    }
+                }
+            }
+            "enter" => {
+                arity(0)?;
+                out.extend(enter().into_iter().map(Pending::Ready));
+            }
+            "leave" => {
+                arity(0)?;
+                out.extend(leave().into_iter().map(Pending::Ready));
+            }
+            "ret" => {
+                arity(0)?;
+                out.extend(
+                    [
+                        Instruction::sub(Register::Rd, Register::Rd),
+                        Instruction::add(Register::Rc, Register::Rd),
+                        Instruction::ioi(Device::Cpu, U3::B010),
+                        Instruction(Opcode::Adi, InstructionData::Imm(1)),
+                        Instruction(Opcode::Jne, InstructionData::Mem { is_ptr: true, addr: U4::from(LINK_SLOT) }),
+                    ]
+                    .map(Pending::Ready),
+                );
+            }
+            _ => return Err(format!("unknown instruction `{mnemonic}`")),
+        }
+
+        Ok(())
+    }
+}
+
+/// Where a `call` leaves the offset to return to, in the return point's
+/// page.
+pub const LINK_SLOT: u8 = 2;
+
+/// The page whose link slot holds the link stack's depth. Its frames are
+/// in the link slots of the pages below it, two to a frame.
+pub const LINK_STACK: u8 = 0x7c;
+
+/// The files `.include <std/...>` can pull in, by name.
+pub const STD: &[(&str, &str)] = &[
+    ("std/print.s", include_str!("asm/std/print.s")),
+    ("std/read.s", include_str!("asm/std/read.s")),
+    ("std/decimal.s", include_str!("asm/std/decimal.s")),
+    ("std/mac.s", include_str!("asm/std/mac.s")),
+    ("std/memcpy.s", include_str!("asm/std/memcpy.s")),
+];
+
+/// A program's lines so far, with those of the files it included.
+#[derive(Default)]
+struct Unit<'a> {
+    /// The item each label is at.
+    labels: HashMap<&'a str, usize>,
+    pending: Vec<Pending<'a>>,
+    annotations: BTreeMap<usize, String>,
+    included: HashSet<&'a str>,
+    /// What the calling convention is checked against, by line.
+    events: Vec<(usize, Event<'a>)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Event<'a> {
+    Label(&'a str),
+    Call(&'a str),
+    Enter,
+    Leave,
+    Ret,
+    /// Anything else that changes Rc.
+    WriteRc,
+}
+
+/// Where a routine is, in terms of the link.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    /// Rc holds the link.
+    Entry,
+    /// The link is saved, so Rc is free.
+    Entered,
+    /// The link is back in Rc, to return.
+    Left,
+}
+
+/// A routine being checked, from its label to the next routine's.
+struct Routine<'a> {
+    name: &'a str,
+    line: usize,
+    enters: bool,
+    frame: Frame,
+    returns: bool,
+}
+
+/// Checks each routine against the calling convention, returning the line
+/// and description of everything that breaks it.
+fn check_calls(events: &[(usize, Event)]) -> Vec<(usize, String)> {
+    let routines: HashSet<&str> = events
+        .iter()
+        .filter_map(|&(_, event)| match event {
+            Event::Call(label) => Some(label),
+            _ => None,
+        })
+        .collect();
+
+    let mut violations = Vec::new();
+    let finish = |routine: Option<Routine>, violations: &mut Vec<_>| {
+        if let Some(Routine { name, line, returns: false, .. }) = routine {
+            violations.push((line, format!("routine `{name}` never returns with `ret`")));
+        }
+    };
+
+    let mut routine = None;
+    for &(line, event) in events {
+        if let Event::Label(label) = event {
+            if routines.contains(label) {
+                finish(routine.take(), &mut violations);
+                routine = Some(Routine { name: label, line, enters: false, frame: Frame::Entry, returns: false });
+            }
+
+            continue;
+        }
+
+        let Some(routine) = &mut routine else {
+            continue;
+        };
+
+        match event {
+            Event::Enter => {
+                routine.enters = true;
+                routine.frame = Frame::Entered;
+            }
+            Event::Leave => routine.frame = Frame::Left,
+            Event::Ret => {
+                if routine.frame == Frame::Entered {
+                    violations.push((line, format!("routine `{}` returns without `leave`", routine.name)));
+                }
+
+                // Whatever follows is another way through the routine.
+                routine.returns = true;
+                routine.frame = if routine.enters { Frame::Entered } else { Frame::Entry };
+            }
+            Event::Call(_) | Event::WriteRc if routine.frame != Frame::Entered => {
+                violations.push((line, format!("routine `{}` changes Rc while it holds the link, without `enter`", routine.name)));
+            }
+            _ => {}
+        }
+    }
+
+    finish(routine, &mut violations);
+    violations
+}
+
+/// An instruction, or some waiting on an address.
+enum Pending<'a> {
+    Ready(Instruction),
+    Jump {
+        line: usize,
+        opcode: Opcode,
+        is_ptr: bool,
+        target: Target<'a>,
+        /// Whether to warn if it crosses pages.
+        warn: bool,
+    },
+    /// An `li` of part of an address.
+    Load {
+        line: usize,
+        dest: Register,
+        target: Target<'a>,
+        part: Part,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Label(&'a str),
+    /// The item at this index, for addresses that have no label.
+    Item(usize),
+}
+
+#[derive(Clone, Copy)]
+enum Part {
+    Page,
+    Offset,
+}
+
+impl Part {
+    fn of(self, addr: usize) -> u8 {
+        match self {
+            Part::Page => (addr >> 4) as u8,
+            Part::Offset => (addr & 0b1111) as u8,
+        }
+    }
+}
+
+/// Loads the page of the link stack's frame into Rd: `below` under
+/// [`LINK_STACK`], less its depth.
+fn frame_page(out: &mut Vec<Instruction>, below: u8) {
+    out.extend(Instruction::li(Register::Rd, LINK_STACK));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ioi(Device::Cpu, U3::B011));
+    out.push(Instruction::sub(Register::Rd, Register::Rd));
+    out.push(Instruction::add(Register::Ra, Register::Rd));
+    out.extend(Instruction::li(Register::Ra, LINK_STACK - below));
+    out.push(Instruction::sub(Register::Rd, Register::Ra));
+    out.push(Instruction::sub(Register::Rd, Register::Rd));
+    out.push(Instruction::add(Register::Ra, Register::Rd));
+}
+
+/// Pushes the link: the page in Rc, then the offset in its link slot.
+fn enter() -> Vec<Instruction> {
+    let mut out = Vec::new();
+    frame_page(&mut out, 1);
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ior(Device::Cpu, U3::B100));
+
+    out.push(Instruction::sub(Register::Rd, Register::Rd));
+    out.push(Instruction::add(Register::Rc, Register::Rd));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ioi(Device::Cpu, U3::B011));
+    out.push(Instruction::sub(Register::Rc, Register::Rc));
+    out.push(Instruction::add(Register::Ra, Register::Rc));
+    frame_page(&mut out, 2);
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ior(Device::Cpu, U3::B100));
+
+    out.extend(Instruction::li(Register::Rd, LINK_STACK));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ioi(Device::Cpu, U3::B011));
+    out.push(Instruction(Opcode::Adi, InstructionData::Imm(2)));
+    out.push(Instruction::sub(Register::Rc, Register::Rc));
+    out.push(Instruction::add(Register::Ra, Register::Rc));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ior(Device::Cpu, U3::B100));
+    out
+}
+
+/// Pops the link, putting the page back in Rc and the offset back in its
+/// link slot.
+fn leave() -> Vec<Instruction> {
+    let mut out = Vec::new();
+    out.extend(Instruction::li(Register::Rd, LINK_STACK));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ioi(Device::Cpu, U3::B011));
+    out.push(Instruction::sub(Register::Rc, Register::Rc));
+    out.push(Instruction::add(Register::Ra, Register::Rc));
+    out.extend(Instruction::li(Register::Ra, 2));
+    out.push(Instruction::sub(Register::Ra, Register::Rc));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ior(Device::Cpu, U3::B100));
+
+    frame_page(&mut out, 2);
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ioi(Device::Cpu, U3::B011));
+    out.push(Instruction::sub(Register::Rc, Register::Rc));
+    out.push(Instruction::add(Register::Ra, Register::Rc));
+    out.extend(Instruction::li(Register::Ra, 1));
+    out.push(Instruction::add(Register::Ra, Register::Rd));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ioi(Device::Cpu, U3::B011));
+    out.push(Instruction::sub(Register::Rd, Register::Rd));
+    out.push(Instruction::add(Register::Ra, Register::Rd));
+    out.extend(Instruction::li(Register::Ra, LINK_SLOT));
+    out.push(Instruction::ior(Device::Cpu, U3::B100));
+    out.push(Instruction::sub(Register::Rc, Register::Rc));
+    out.push(Instruction::add(Register::Rd, Register::Rc));
+    out
+}
+
+/// Jumps to a target unconditionally, clobbering Ra, Rd, and the flags.
+fn jump<'a>(line: usize, target: Target<'a>, out: &mut Vec<Pending<'a>>) {
+    out.push(Pending::Load { line, dest: Register::Rd, target, part: Part::Page });
+    out.push(Pending::Ready(Instruction::ioi(Device::Cpu, U3::B010)));
+    out.push(Pending::Ready(Instruction(Opcode::Adi, InstructionData::Imm(1))));
+    out.push(Pending::Jump { line, opcode: Opcode::Jne, is_ptr: false, target, warn: false });
+}
+
+fn label_operand(s: &str) -> Result<&str, String> {
+    if is_identifier(s) {
+        Ok(s)
+    } else {
+        Err(format!("expected a label, found `{s}`"))
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn unwrap_brackets(s: &str) -> Option<&str> {
+    s.strip_prefix('[')?.strip_suffix(']').map(str::trim)
+}
+
+pub(crate) fn parse_number(s: &str) -> Result<u8, String> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x") {
+        u8::from_str_radix(hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u8::from_str_radix(bin, 2)
+    } else {
+        s.parse()
+    };
+
+    parsed.map_err(|_| format!("invalid number `{s}`"))
+}
+
+fn parse_register(s: &str) -> Result<(bool, Register), String> {
+    let (is_id, name) = match unwrap_brackets(s) {
+        Some(name) => (true, name),
+        None => (false, s),
+    };
+
+    let register = match name.to_lowercase().as_str() {
+        "ra" => Register::Ra,
+        "rb" => Register::Rb,
+        "rc" => Register::Rc,
+        "rd" => Register::Rd,
+        _ => return Err(format!("unknown register `{name}`")),
+    };
+
+    Ok((is_id, register))
+}
+
+fn parse_device(s: &str) -> Result<Device, String> {
+    match s.to_lowercase().as_str() {
+        "cpu" => Ok(Device::Cpu),
+        "kbd" => Ok(Device::Kbd),
+        "scr" => Ok(Device::Scr),
+        "mth" => Ok(Device::Mth),
+        _ => match parse_number(s) {
+            Ok(n) if n <= 0b11 => Ok(Device::from(n)),
+            _ => Err(format!("unknown device `{s}`")),
+        },
+    }
+}
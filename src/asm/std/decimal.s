@@ -0,0 +1,64 @@
+; print_dec: prints Rb in decimal, without leading zeros.
+
+print_dec:
+    enter
+    sub rc, rc          ; Rc = the hundreds
+_print_dec_hundreds:
+    li rd, page(_print_dec_tens)
+    li ra, 3
+    ioi cpu, 7          ; the next jump tests carry
+    li ra, 100
+    sub ra, rb
+    jne _print_dec_tens ; taken if Rb was under 100
+    li ra, 1
+    add ra, rc
+    jmp _print_dec_hundreds
+_print_dec_tens:
+    li ra, 100
+    add ra, rb          ; undo the last subtraction
+    li rd, 126
+    li ra, 2
+    ior cpu, 4          ; keep the hundreds
+    li rd, page(_print_dec_put_hundreds)
+    ioi cpu, 2
+    add rc, ra
+    jne _print_dec_put_hundreds
+    jmp _print_dec_count_tens
+_print_dec_put_hundreds:
+    li ra, 48
+    add rc, ra
+    ioi scr, 2
+_print_dec_count_tens:
+    ; Counting the tens on top of the hundreds makes Rc zero only if
+    ; both are.
+    li rd, page(_print_dec_ones)
+    li ra, 3
+    ioi cpu, 7
+    li ra, 10
+    sub ra, rb
+    jne _print_dec_ones
+    li ra, 1
+    add ra, rc
+    jmp _print_dec_count_tens
+_print_dec_ones:
+    li ra, 10
+    add ra, rb
+    li rd, page(_print_dec_put_tens)
+    ioi cpu, 2
+    add rc, ra
+    jne _print_dec_put_tens
+    jmp _print_dec_put_ones
+_print_dec_put_tens:
+    li rd, 126
+    li ra, 2
+    ioi cpu, 3
+    sub ra, rc          ; take the hundreds back off
+    li ra, 48
+    add rc, ra
+    ioi scr, 2
+_print_dec_put_ones:
+    li ra, 48
+    add rb, ra
+    ioi scr, 2
+    leave
+    ret
@@ -0,0 +1,36 @@
+; mac: adds Rb times cell 0x7e to cell 0x7d, wrapping. Rb = the new
+; total.
+
+mac:
+    enter
+    li rd, 127
+    li ra, 1
+    ior cpu, 4          ; cell 0x7f = Rb
+    li rd, 126
+    li ra, 1
+    ioi cpu, 3
+    sub rc, rc
+    add ra, rc          ; Rc = how many times to add it
+    li rd, 125
+    li ra, 1
+    ioi cpu, 3
+    sub rb, rb
+    add ra, rb          ; Rb = the total
+_mac_next:
+    li rd, page(_mac_add)
+    ioi cpu, 2
+    add rc, ra
+    jne _mac_add
+    li rd, 125
+    li ra, 1
+    ior cpu, 4          ; store the total
+    leave
+    ret
+_mac_add:
+    li rd, 127
+    li ra, 1
+    ioi cpu, 3
+    add ra, rb
+    li ra, 1
+    sub ra, rc
+    jmp _mac_next
@@ -0,0 +1,50 @@
+; memcpy: copies Rb cells from the one cell 0x7e names to the one cell
+; 0x7d names, first to last, leaving both pointing past their ends.
+
+memcpy:
+    enter
+    sub rc, rc
+    add rb, rc          ; Rc = how many are left
+_memcpy_next:
+    li rd, page(_memcpy_copy)
+    ioi cpu, 2
+    add rc, ra
+    jne _memcpy_copy
+    leave
+    ret
+_memcpy_copy:
+    li rd, 126
+    li ra, 1
+    ioi cpu, 3
+    sub rd, rd
+    add ra, rd
+    li ra, 1
+    ioi cpu, 3
+    sub rb, rb
+    add ra, rb          ; Rb = the byte
+    li rd, 125
+    li ra, 1
+    ioi cpu, 3
+    sub rd, rd
+    add ra, rd
+    li ra, 1
+    ior cpu, 4
+    li rd, 126
+    li ra, 1
+    ioi cpu, 3
+    adi 1
+    sub rb, rb
+    add ra, rb
+    li ra, 1
+    ior cpu, 4          ; the next source
+    li rd, 125
+    li ra, 1
+    ioi cpu, 3
+    adi 1
+    sub rb, rb
+    add ra, rb
+    li ra, 1
+    ior cpu, 4          ; the next destination
+    li ra, 1
+    sub ra, rc
+    jmp _memcpy_next
@@ -0,0 +1,24 @@
+; print_str: prints the string starting at cell Rb, up to a 0.
+;
+; Cell n is data byte n << 4 | 1, so a string has a character a page.
+
+print_str:
+    enter
+_print_str_next:
+    sub rd, rd
+    add rb, rd
+    li ra, 1
+    ioi cpu, 3          ; Ra = the character
+    sub rc, rc
+    add ra, rc          ; zero at the end
+    li rd, page(_print_str_put)
+    ioi cpu, 2
+    add rc, ra
+    jne _print_str_put
+    leave
+    ret
+_print_str_put:
+    ioi scr, 2
+    li ra, 1
+    add ra, rb          ; the next cell
+    jmp _print_str_next
@@ -0,0 +1,45 @@
+; read_line: reads keys into the cells from Rb, echoing them, until a
+; newline, then ends them with a 0 in place of the newline. Rb = how many
+; were read.
+
+read_line:
+    enter
+    li rd, 127
+    li ra, 1
+    ior cpu, 4          ; cell 0x7f = the first cell
+    sub rc, rc
+    add rb, rc          ; Rc = the cell to fill
+_read_line_wait:
+    li rd, page(_read_line_key)
+    ioi kbd, 0
+    sub rb, rb
+    add ra, rb          ; Rb = the key, or 0 if there isn't one
+    jne _read_line_key
+    jmp _read_line_wait
+_read_line_key:
+    ioi scr, 2
+    li rd, page(_read_line_store)
+    li ra, 10
+    sub rb, ra          ; zero at a newline
+    jne _read_line_store
+    sub rb, rb
+    sub rd, rd
+    add rc, rd
+    li ra, 1
+    ior cpu, 4          ; end with a 0
+    li rd, 127
+    li ra, 1
+    ioi cpu, 3          ; Ra = the first cell
+    sub rb, rb
+    add rc, rb
+    sub ra, rb          ; Rb = the length
+    leave
+    ret
+_read_line_store:
+    sub rd, rd
+    add rc, rd
+    li ra, 1
+    ior cpu, 4
+    li ra, 1
+    add ra, rc          ; the next cell
+    jmp _read_line_wait
@@ -0,0 +1,170 @@
+//! Running every program in a directory, for benchmarking the interpreter
+//! against a corpus or grading a pile of submissions at once.
+//!
+//! A [`Batch`] runs each program with a [`Runner`], with the same config,
+//! input, and limits, and reports each as a row of CSV or an object in a
+//! JSON array. A program that doesn't load gets a row too, with its error.
+//!
+//! ```no_run
+//! use pact::batch::{self, Batch};
+//!
+//! let results = Batch::new().input("hi").run_dir("submissions").unwrap();
+//! print!("{}", batch::to_csv(&results));
+//! ```
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::cast::escape;
+use crate::config::RimConfig;
+use crate::error::{RimError, RimResult};
+use crate::grade::{self, Limits};
+use crate::runner::{RunReport, Runner};
+
+/// How each program in a batch is run.
+pub struct Batch {
+    /// Called for each program, so each gets its own devices.
+    config: Box<dyn Fn() -> RimConfig>,
+    input: Vec<u8>,
+    limits: Limits,
+}
+
+/// How one program went.
+#[derive(Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub result: RimResult<RunReport>,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self { config: Box::new(RimConfig::new), input: Vec::new(), limits: Limits::default() }
+    }
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how to configure each program's machine.
+    pub fn config(mut self, config: impl Fn() -> RimConfig + 'static) -> Self {
+        self.config = Box::new(config);
+        self
+    }
+
+    /// Sets the keys fed to each program's keyboard.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Runs every program image in a directory, those named `.rim`,
+    /// `.rim.gz`, or `.rim.zst`, in order of name.
+    pub fn run_dir<P: AsRef<Path>>(&self, dir: P) -> RimResult<Vec<Entry>> {
+        let dir = dir.as_ref();
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(|e| RimError::from(e).in_file(dir))? {
+            let path = entry.map_err(|e| RimError::from(e).in_file(dir))?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if [".rim", ".rim.gz", ".rim.zst"].iter().any(|extension| name.ends_with(extension)) {
+                paths.push(path);
+            }
+        }
+
+        paths.sort();
+        Ok(self.run_files(paths))
+    }
+
+    pub fn run_files(&self, paths: impl IntoIterator<Item = PathBuf>) -> Vec<Entry> {
+        paths.into_iter().map(|path| Entry { result: self.run(&path), path }).collect()
+    }
+
+    fn run(&self, path: &Path) -> RimResult<RunReport> {
+        let bytes = std::fs::read(path).map_err(|e| RimError::from(e).in_file(path))?;
+        let rim = grade::load(&bytes, &self.limits.load).map_err(|e| e.in_file(path))?;
+        let runner = Runner::new(rim).config((self.config)());
+        Ok(runner.input(self.input.clone()).limits(self.limits).execute())
+    }
+}
+
+impl Entry {
+    /// How the run ended: `halted`, `faulted`, `out_of_steps`,
+    /// `out_of_time`, or `invalid` if the program didn't load.
+    pub fn outcome(&self) -> &'static str {
+        match &self.result {
+            Ok(report) => report.termination.kind(),
+            Err(_) => "invalid",
+        }
+    }
+
+    /// The fault, or why the program didn't load.
+    pub fn error(&self) -> Option<String> {
+        match &self.result {
+            Ok(report) => report.termination.error().map(ToString::to_string),
+            Err(e) => Some(e.innermost().to_string()),
+        }
+    }
+}
+
+/// The columns of [`to_csv`], and the keys of [`to_json`]'s objects.
+pub const COLUMNS: [&str; 8] = ["path", "outcome", "error", "steps", "instructions", "elapsed_ms", "output_bytes", "output"];
+
+/// Each entry as a row, under a header. Cycles are steps: every step takes
+/// one, and `instructions` counts those that weren't waiting on input or a
+/// mailbox.
+pub fn to_csv(entries: &[Entry]) -> String {
+    let quote = |field: &str| format!("\"{}\"", field.replace('"', "\"\""));
+    let mut csv = COLUMNS.join(",");
+    csv.push('\n');
+
+    for entry in entries {
+        let report = entry.result.as_ref().ok();
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{:.3},{},{}",
+            quote(&entry.path.to_string_lossy()),
+            entry.outcome(),
+            quote(&entry.error().unwrap_or_default()),
+            report.map_or(0, |report| report.steps),
+            report.map_or(0, |report| report.instructions),
+            report.map_or(0.0, |report| report.elapsed.as_secs_f64() * 1000.0),
+            report.map_or(0, |report| report.output.len()),
+            quote(&report.map(|report| String::from_utf8_lossy(&report.output)).unwrap_or_default()),
+        );
+    }
+
+    csv
+}
+
+/// Each entry as an object with [`COLUMNS`] as its keys, in an array, with
+/// `error` null if there was none.
+pub fn to_json(entries: &[Entry]) -> String {
+    let string = |text: &str| format!("\"{}\"", escape(text));
+    let mut json = String::from("[");
+
+    for (i, entry) in entries.iter().enumerate() {
+        let report = entry.result.as_ref().ok();
+        let _ = write!(
+            json,
+            "{}\n  {{\"path\": {}, \"outcome\": \"{}\", \"error\": {}, \"steps\": {}, \"instructions\": {}, \"elapsed_ms\": {:.3}, \"output_bytes\": {}, \"output\": {}}}",
+            if i == 0 { "" } else { "," },
+            string(&entry.path.to_string_lossy()),
+            entry.outcome(),
+            entry.error().map_or("null".to_string(), |e| string(&e)),
+            report.map_or(0, |report| report.steps),
+            report.map_or(0, |report| report.instructions),
+            report.map_or(0.0, |report| report.elapsed.as_secs_f64() * 1000.0),
+            report.map_or(0, |report| report.output.len()),
+            string(&report.map(|report| String::from_utf8_lossy(&report.output)).unwrap_or_default()),
+        );
+    }
+
+    json.push_str(if entries.is_empty() { "]\n" } else { "\n]\n" });
+    json
+}
@@ -0,0 +1,130 @@
+//! A compiler from Brainfuck to Rim programs.
+//!
+//! The tape has 256 cells, and the pointer wraps around at either end, as
+//! do cells. Cell `n` lives at data address `n << 4 | 1`, the second byte
+//! of page `n`, so the pointer can be used as Rd and cells read and written
+//! with the CPU memory functions. `,` reads a key, 0 if there's none, and
+//! `.` writes a cell to the screen. Anything else in the source is a
+//! comment.
+//!
+//! Between commands, Rc holds the pointer and so does Rd, except that Rd
+//! has to hold a jump's page for it to land, so loops set it as they go.
+//! Runs of `+`, `-`, `>`, and `<` each compile to a single step.
+//!
+//! ```no_run
+//! let program = pact::bf::compile("++++++++[>++++++++<-]>+.").unwrap();
+//! pact::Rim::new(program).run().unwrap();
+//! ```
+
+use crate::codegen::Code;
+use crate::error::{AsmError, RimResult};
+use crate::helper::U3;
+use crate::{Device, Instruction, Register};
+
+/// Compiles a program, failing on unmatched brackets or if it's too long
+/// to address.
+pub fn compile(source: &str) -> RimResult<Vec<Instruction>> {
+    let mut code = Code::new();
+    let mut loops = Vec::new();
+    let mut commands = commands(source).peekable();
+
+    while let Some((line, command)) = commands.next() {
+        let mut count = 1u8;
+        while matches!(command, '+' | '-' | '>' | '<') && commands.peek().map(|&(_, next)| next) == Some(command) {
+            commands.next();
+            count = count.wrapping_add(1);
+        }
+
+        match command {
+            '+' | '-' => {
+                load_cell(&mut code);
+                code.extend(Instruction::adi_expanded(if command == '+' { count } else { count.wrapping_neg() }));
+                store_ra(&mut code);
+            }
+            '>' | '<' => {
+                code.push(Instruction::ioi(Device::Cpu, U3::B010));
+                code.extend(Instruction::adi_expanded(count));
+                let step = if command == '>' { Instruction::add } else { Instruction::sub };
+                code.push(step(Register::Ra, Register::Rc));
+                restore_rd(&mut code);
+            }
+            '.' => {
+                load_cell(&mut code);
+                code.push(Instruction::ioi(Device::Scr, U3::B010));
+            }
+            ',' => {
+                code.push(Instruction::ioi(Device::Kbd, U3::B000));
+                store_ra(&mut code);
+            }
+            '[' => {
+                let (body, after) = (code.label(), code.label());
+                loops.push((line, body, after));
+
+                // Into the body if the cell isn't 0, and past the loop if it
+                // is.
+                branch_if_nonzero(&mut code, body);
+                code.jump(after);
+                code.place(body);
+                restore_rd(&mut code);
+            }
+            ']' => {
+                let (_, body, after) = loops.pop().ok_or_else(|| AsmError::Source {
+                    line,
+                    message: "unmatched `]`".to_string(),
+                })?;
+
+                branch_if_nonzero(&mut code, body);
+                code.place(after);
+                restore_rd(&mut code);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if let Some((line, ..)) = loops.pop() {
+        return Err(AsmError::Source { line, message: "unmatched `[`".to_string() }.into());
+    }
+
+    // Jumps past the last loop need somewhere to land.
+    code.push(Instruction::ioi(Device::Cpu, U3::B000));
+    code.link()
+}
+
+fn commands(source: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| line.chars().map(move |c| (i + 1, c)))
+        .filter(|(_, c)| "+-<>.,[]".contains(*c))
+}
+
+/// Sets Rd back to the pointer.
+fn restore_rd(code: &mut Code) {
+    code.push(Instruction::sub(Register::Rd, Register::Rd));
+    code.push(Instruction::add(Register::Rc, Register::Rd));
+}
+
+/// Reads the current cell into Ra.
+fn load_cell(code: &mut Code) {
+    code.extend(Instruction::li(Register::Ra, 1));
+    code.push(Instruction::ioi(Device::Cpu, U3::B011));
+}
+
+/// Writes Ra to the current cell, through Rb.
+fn store_ra(code: &mut Code) {
+    code.push(Instruction::sub(Register::Rb, Register::Rb));
+    code.push(Instruction::add(Register::Ra, Register::Rb));
+    code.extend(Instruction::li(Register::Ra, 1));
+    code.push(Instruction::ior(Device::Cpu, U3::B100));
+}
+
+/// Jumps to a label if the current cell isn't 0.
+fn branch_if_nonzero(code: &mut Code, label: usize) {
+    load_cell(code);
+    code.push(Instruction::sub(Register::Rb, Register::Rb));
+    code.push(Instruction::add(Register::Ra, Register::Rb));
+    code.load_page(label);
+    code.push(Instruction::sub(Register::Ra, Register::Ra));
+    code.push(Instruction::add(Register::Rb, Register::Ra));
+    code.jne(label);
+}
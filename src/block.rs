@@ -0,0 +1,92 @@
+//! Copying and filling blocks of bytes, for the devices' block transfers
+//! (see [`disk`](crate::disk) and [`graphics`](crate::graphics)).
+//!
+//! By default these are [`slice::copy_from_slice`] and [`slice::fill`],
+//! which the compiler turns into the host's `memcpy` and `memset`. With the
+//! `simd` feature, they move 16 bytes at a time with SSE2 on x86-64 and
+//! NEON on AArch64 instead, for hosts whose `memcpy` is slow for blocks
+//! this short. A good `memcpy` is as fast or faster, so measure first:
+//! `cargo bench` compares the two, and both with a program moving bytes
+//! one at a time.
+
+/// How many bytes the SIMD path moves at once.
+#[cfg(feature = "simd")]
+const LANES: usize = 16;
+
+/// Copies `src` into `dest`.
+///
+/// # Panics
+///
+/// Panics if they're different lengths, like
+/// [`slice::copy_from_slice`].
+pub fn copy(dest: &mut [u8], src: &[u8]) {
+    assert_eq!(dest.len(), src.len(), "source and destination are different lengths");
+
+    #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        let mut dest_chunks = dest.chunks_exact_mut(LANES);
+        let mut src_chunks = src.chunks_exact(LANES);
+        for (dest, src) in (&mut dest_chunks).zip(&mut src_chunks) {
+            simd::copy(dest, src);
+        }
+
+        dest_chunks.into_remainder().copy_from_slice(src_chunks.remainder());
+    }
+
+    #[cfg(not(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+    dest.copy_from_slice(src);
+}
+
+/// Sets every byte of `dest` to `value`.
+pub fn fill(dest: &mut [u8], value: u8) {
+    #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        let mut chunks = dest.chunks_exact_mut(LANES);
+        for chunk in &mut chunks {
+            simd::fill(chunk, value);
+        }
+
+        chunks.into_remainder().fill(value);
+    }
+
+    #[cfg(not(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+    dest.fill(value);
+}
+
+/// Moves of exactly [`LANES`] bytes. SSE2 and NEON are part of their
+/// targets' baselines, so there's nothing to detect at runtime.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd {
+    use std::arch::x86_64::{__m128i, _mm_loadu_si128, _mm_set1_epi8, _mm_storeu_si128};
+
+    pub fn copy(dest: &mut [u8], src: &[u8]) {
+        assert!(dest.len() == super::LANES && src.len() == super::LANES);
+        // SAFETY: both are 16 bytes long, and these loads and stores don't
+        // need to be aligned.
+        unsafe { _mm_storeu_si128(dest.as_mut_ptr().cast(), _mm_loadu_si128(src.as_ptr().cast::<__m128i>())) }
+    }
+
+    pub fn fill(dest: &mut [u8], value: u8) {
+        assert!(dest.len() == super::LANES);
+        // SAFETY: as for `copy`.
+        unsafe { _mm_storeu_si128(dest.as_mut_ptr().cast(), _mm_set1_epi8(value as i8)) }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod simd {
+    use std::arch::aarch64::{vdupq_n_u8, vld1q_u8, vst1q_u8};
+
+    pub fn copy(dest: &mut [u8], src: &[u8]) {
+        assert!(dest.len() == super::LANES && src.len() == super::LANES);
+        // SAFETY: both are 16 bytes long, and these loads and stores don't
+        // need to be aligned.
+        unsafe { vst1q_u8(dest.as_mut_ptr(), vld1q_u8(src.as_ptr())) }
+    }
+
+    pub fn fill(dest: &mut [u8], value: u8) {
+        assert!(dest.len() == super::LANES);
+        // SAFETY: as for `copy`.
+        unsafe { vst1q_u8(dest.as_mut_ptr(), vdupq_n_u8(value)) }
+    }
+}
@@ -0,0 +1,187 @@
+//! A cache of loaded programs, keyed by their contents, for embedders that
+//! load the same programs over and over, like `pact serve` or an
+//! autograder running a submission against each of its tests.
+//!
+//! [`ProgramCache::load`] works like [`grade::load`](crate::grade::load).
+//! The first time it sees an image, it decompresses, parses, and validates
+//! it as usual, and finds its blocks and jump targets the way
+//! [`Rim::burst`] would. After that, the same bytes load straight from the
+//! cache: the machine shares the decoded program rather than copying it,
+//! and since the blocks are kept by program, a burst picks them up without
+//! finding them again. Only the [`LoadLimits`] are checked each time, since
+//! they can differ between loads.
+//!
+//! Images are keyed by a hash of their bytes, and compared in full on a
+//! hit, so a collision can't load the wrong program. Once the cache is
+//! full, the image loaded least recently makes way.
+//!
+//! ```no_run
+//! use pact::cache::ProgramCache;
+//! use pact::image::LoadLimits;
+//!
+//! let cache = ProgramCache::new(64);
+//! let image = std::fs::read("submission.rim").unwrap();
+//! for _ in 0..10 {
+//!     let mut rim = cache.load(&image, &LoadLimits::default()).unwrap();
+//!     rim.run().unwrap();
+//! }
+//! assert_eq!(cache.hits(), 9);
+//! ```
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::error::RimResult;
+use crate::fusion::{Fusion, Table};
+use crate::image::{Image, LoadLimits};
+use crate::isa::IsaLevel;
+use crate::{Instruction, Rim};
+
+/// How many images [`ProgramCache::default`] holds.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Loaded images, shared between threads.
+#[derive(Debug)]
+pub struct ProgramCache {
+    capacity: usize,
+    entries: Mutex<HashMap<u64, Vec<Entry>>>,
+    /// Counts loads, so each entry knows when it was last used.
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// An image as it was loaded, under its hash.
+#[derive(Debug)]
+struct Entry {
+    bytes: Box<[u8]>,
+    code: Arc<Vec<Instruction>>,
+    data: Vec<u8>,
+    isa: IsaLevel,
+    table: Arc<Table>,
+    used: u64,
+}
+
+impl Default for ProgramCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ProgramCache {
+    /// A cache holding up to `capacity` images. A capacity of 0 caches
+    /// nothing.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// A machine ready to run an image, as [`grade::load`](crate::grade::load)
+    /// would make it. Images that don't load aren't cached, so they fail
+    /// the same way every time.
+    pub fn load(&self, bytes: &[u8], limits: &LoadLimits) -> RimResult<Rim> {
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        let hash = hasher.finish();
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(entry) = entries.get_mut(&hash).and_then(|bucket| bucket.iter_mut().find(|entry| *entry.bytes == *bytes)) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            entry.used = now;
+            return entry.rim(limits);
+        }
+
+        // Parsed with the lock held, so threads loading the same new image
+        // at once don't each parse it.
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let image = Image::parse(bytes)?;
+        for warning in &image.warnings {
+            log::warn!("{warning}");
+        }
+
+        let code = Arc::new(image.code);
+        let entry = Entry {
+            bytes: bytes.into(),
+            table: Arc::new(Table::build(0, &code, Fusion::DEFAULT)),
+            code,
+            data: image.data,
+            isa: image.isa,
+            used: now,
+        };
+        let rim = entry.rim(limits);
+
+        if self.capacity > 0 {
+            if entries.values().map(Vec::len).sum::<usize>() >= self.capacity {
+                evict(&mut entries);
+            }
+            entries.entry(hash).or_default().push(entry);
+        }
+
+        rim
+    }
+
+    /// How many loads were of an image already in the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// How many loads had to parse their image.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// How many images the cache holds.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner).values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets every image. Machines already loaded keep their programs.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
+
+impl Entry {
+    fn rim(&self, limits: &LoadLimits) -> RimResult<Rim> {
+        LoadLimits::check("program length", self.code.len(), limits.max_program_len)?;
+        LoadLimits::check("data length", self.data.len(), limits.max_data_len)?;
+
+        let mut rim = Rim {
+            programs: vec![self.code.clone()],
+            fused: Some(self.table.clone()),
+            ..Default::default()
+        };
+        rim.set_isa(self.isa);
+        rim.set_load_limits(*limits);
+        rim.data_mut()[..self.data.len()].copy_from_slice(&self.data);
+        Ok(rim)
+    }
+}
+
+/// Removes the entry used least recently.
+fn evict(entries: &mut HashMap<u64, Vec<Entry>>) {
+    let oldest = entries
+        .iter()
+        .flat_map(|(&hash, bucket)| bucket.iter().enumerate().map(move |(i, entry)| (entry.used, hash, i)))
+        .min();
+
+    if let Some((_, hash, i)) = oldest {
+        let bucket = entries.get_mut(&hash).unwrap();
+        bucket.swap_remove(i);
+        if bucket.is_empty() {
+            entries.remove(&hash);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Recording what a program writes to the terminal as an asciinema cast.
+//!
+//! A cast is a line of JSON describing the terminal, followed by a line for
+//! each piece of output, with when it was written (see
+//! <https://docs.asciinema.org/manual/asciicast/v2/>). Casts play with
+//! `asciinema play` or in a browser, showing a program as it looked, at the
+//! speed it ran. Output is grouped into a piece every [`TICK`], and bytes
+//! that aren't UTF-8 are replaced. Only output is recorded, not the keys
+//! typed.
+//!
+//! ```no_run
+//! let mut rim = pact::read_file("hello.rim").unwrap();
+//! let file = std::fs::File::create("hello.cast").unwrap();
+//! let mut recorder = pact::cast::Recorder::new(std::io::BufWriter::new(file));
+//! recorder.run(&mut rim).unwrap();
+//! recorder.finish().unwrap();
+//! ```
+
+use std::io::Write;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::console::Console;
+use crate::error::RimResult;
+use crate::{Rim, Status, TICK};
+
+/// Records a running machine's terminal output as a cast.
+pub struct Recorder<W: Write> {
+    out: W,
+    /// When recording started, once the header is written.
+    started: Option<Instant>,
+    /// Output that hasn't been written yet, and when it started.
+    pending: Option<(Instant, Vec<u8>)>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, started: None, pending: None }
+    }
+
+    /// Steps the machine, and records what it wrote. A machine on the
+    /// terminal is switched to [`Console::Recording`] first.
+    pub fn step(&mut self, rim: &mut Rim) -> RimResult<Status> {
+        if self.started.is_none() {
+            self.write_header(rim.screen().width(), rim.screen().height())?;
+        }
+
+        if *rim.console() == Console::Terminal {
+            rim.set_console(Console::Recording(Vec::new()));
+        }
+
+        // Output from a step that faults still happened.
+        let status = rim.step();
+        let output = rim.console_mut().take_recorded();
+        if !output.is_empty() {
+            match &mut self.pending {
+                Some((since, pending)) if since.elapsed() < TICK => pending.extend(output),
+                _ => {
+                    self.write_pending()?;
+                    self.pending = Some((Instant::now(), output));
+                }
+            }
+        }
+
+        status
+    }
+
+    /// Runs the machine until it halts, like [`Rim::run`], recording as it
+    /// goes.
+    pub fn run(&mut self, rim: &mut Rim) -> RimResult<()> {
+        while self.step(rim)? == Status::Running {
+            if rim.is_waiting() {
+                std::thread::sleep(TICK);
+            }
+
+            if let Some(wait) = rim.until_next_frame().filter(|_| rim.is_blocked()) {
+                std::thread::sleep(wait);
+            }
+
+            match rim.take_sleep() {
+                Some(0) => std::thread::yield_now(),
+                Some(ticks) => std::thread::sleep(TICK * ticks as u32),
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last of the output, returning the writer.
+    pub fn finish(mut self) -> RimResult<W> {
+        if self.started.is_none() {
+            self.write_header(crate::screen::WIDTH, crate::screen::HEIGHT)?;
+        }
+
+        self.write_pending()?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_header(&mut self, width: usize, height: usize) -> RimResult<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        writeln!(self.out, r#"{{"version": 2, "width": {width}, "height": {height}, "timestamp": {timestamp}}}"#)?;
+        self.started = Some(Instant::now());
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> RimResult<()> {
+        let (Some(started), Some((since, output))) = (self.started, self.pending.take()) else {
+            return Ok(());
+        };
+
+        let time = since.duration_since(started).as_secs_f64();
+        writeln!(self.out, "[{time:.6}, \"o\", \"{}\"]", escape(&String::from_utf8_lossy(&output)))?;
+        Ok(())
+    }
+}
+
+/// Escapes text for a JSON string.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' || c == '\x7f' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
@@ -0,0 +1,220 @@
+//! Control-flow graphs, reconstructed from bytecode.
+//!
+//! A jump's target depends on Rd at runtime, which can't be known from the
+//! bytecode alone. The graph assumes Rd holds the page of the jump itself,
+//! which is how the assembler's labels work within a page. Pointer jumps
+//! have no static target at all, and get an [`Edge::Indirect`].
+
+use std::fmt::Write;
+
+use crate::addr::Addr;
+use crate::encoding::Format;
+use crate::symbols::Symbols;
+use crate::{Device, Instruction, InstructionData};
+
+/// A run of instructions that's only ever entered at its start, and only
+/// ever left at its end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// One past the last instruction.
+    pub end: usize,
+    pub successors: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// On to the next instruction, including when a jump isn't taken.
+    Fallthrough(usize),
+    /// A taken jump.
+    Jump(usize),
+    /// A pointer jump, to wherever memory says.
+    Indirect,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+}
+
+/// The target of a non-pointer jump at `pc`, assuming Rd holds its page.
+pub fn static_target(pc: usize, instruction: Instruction) -> Option<usize> {
+    match instruction.1 {
+        InstructionData::Mem { is_ptr: false, addr } => Some(Addr::from_index(pc)?.with_offset(addr as u8).index()),
+        _ => None,
+    }
+}
+
+/// Whether an instruction always halts.
+fn halts(instruction: Instruction) -> bool {
+    matches!(instruction.1, InstructionData::Io { device: Device::Cpu, function } if function as u8 == 0)
+}
+
+/// Whether an instruction can transfer control somewhere unknown, like a
+/// system call switching programs.
+fn may_transfer(instruction: Instruction) -> bool {
+    matches!(instruction.1, InstructionData::Io { device: Device::Cpu, function } if function as u8 == 7)
+}
+
+impl Cfg {
+    pub fn build(instructions: &[Instruction]) -> Self {
+        let len = instructions.len();
+        let mut leaders = vec![false; len + 1];
+        if len != 0 {
+            leaders[0] = true;
+        }
+
+        for (pc, &instruction) in instructions.iter().enumerate() {
+            let ends_block = Format::from(instruction.0) == Format::Mem
+                || halts(instruction)
+                || may_transfer(instruction);
+
+            if ends_block {
+                leaders[pc + 1] = true;
+            }
+
+            if let Some(target) = static_target(pc, instruction).filter(|&t| t < len) {
+                leaders[target] = true;
+            }
+        }
+
+        let starts: Vec<usize> = (0..len).filter(|&pc| leaders[pc]).collect();
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = starts.get(i + 1).copied().unwrap_or(len);
+                let last = instructions[end - 1];
+
+                let mut successors = Vec::new();
+                if Format::from(last.0) == Format::Mem {
+                    match static_target(end - 1, last) {
+                        Some(target) => successors.push(Edge::Jump(target)),
+                        None => successors.push(Edge::Indirect),
+                    }
+                }
+
+                if !halts(last) && end < len {
+                    successors.push(Edge::Fallthrough(end));
+                }
+
+                BasicBlock { start, end, successors }
+            })
+            .collect();
+
+        Self { blocks }
+    }
+
+    /// The block containing `pc`.
+    pub fn block_at(&self, pc: usize) -> Option<&BasicBlock> {
+        self.blocks.iter().find(|block| (block.start..block.end).contains(&pc))
+    }
+
+    /// Renders the graph in Graphviz's DOT language.
+    pub fn to_dot(&self, instructions: &[Instruction], symbols: &Symbols) -> String {
+        self.dot(instructions, symbols, None)
+    }
+
+    /// Renders the graph in DOT, with blocks colored by how often they ran,
+    /// from a [`Profile`](crate::profile::Profile)'s counts.
+    pub fn to_dot_profiled(&self, instructions: &[Instruction], symbols: &Symbols, counts: &[u64]) -> String {
+        self.dot(instructions, symbols, Some(counts))
+    }
+
+    /// Renders the graph as a Mermaid flowchart.
+    pub fn to_mermaid(&self, instructions: &[Instruction], symbols: &Symbols) -> String {
+        self.mermaid(instructions, symbols, None)
+    }
+
+    /// Renders the graph as a Mermaid flowchart, with blocks colored by how
+    /// often they ran.
+    pub fn to_mermaid_profiled(&self, instructions: &[Instruction], symbols: &Symbols, counts: &[u64]) -> String {
+        self.mermaid(instructions, symbols, Some(counts))
+    }
+
+    fn dot(&self, instructions: &[Instruction], symbols: &Symbols, counts: Option<&[u64]>) -> String {
+        let mut out = String::from("digraph program {\n    node [shape=box fontname=\"monospace\"];\n");
+
+        for block in &self.blocks {
+            let label = block_text(block, instructions, symbols, counts, "\\l").replace('"', "\\\"");
+            match counts {
+                Some(counts) => {
+                    let color = heat(block, counts);
+                    let _ = writeln!(out, "    b{} [label=\"{label}\\l\" style=filled fillcolor=\"{color}\"];", block.start);
+                }
+                None => {
+                    let _ = writeln!(out, "    b{} [label=\"{label}\\l\"];", block.start);
+                }
+            }
+        }
+
+        for block in &self.blocks {
+            for edge in &block.successors {
+                let _ = match edge {
+                    Edge::Fallthrough(to) => writeln!(out, "    b{} -> b{to};", block.start),
+                    Edge::Jump(to) => writeln!(out, "    b{} -> b{to} [label=\"taken\"];", block.start),
+                    Edge::Indirect => writeln!(out, "    b{} -> indirect [style=dashed];", block.start),
+                };
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    fn mermaid(&self, instructions: &[Instruction], symbols: &Symbols, counts: Option<&[u64]>) -> String {
+        let mut out = String::from("flowchart TD\n");
+
+        for block in &self.blocks {
+            let label = block_text(block, instructions, symbols, counts, "<br>");
+            let _ = writeln!(out, "    b{}[\"{}\"]", block.start, label.replace('"', "#quot;"));
+            if let Some(counts) = counts {
+                let _ = writeln!(out, "    style b{} fill:{}", block.start, heat(block, counts));
+            }
+        }
+
+        for block in &self.blocks {
+            for edge in &block.successors {
+                let _ = match edge {
+                    Edge::Fallthrough(to) => writeln!(out, "    b{} --> b{to}", block.start),
+                    Edge::Jump(to) => writeln!(out, "    b{} -->|taken| b{to}", block.start),
+                    Edge::Indirect => writeln!(out, "    b{} -.-> indirect", block.start),
+                };
+            }
+        }
+
+        out
+    }
+}
+
+fn block_text(
+    block: &BasicBlock,
+    instructions: &[Instruction],
+    symbols: &Symbols,
+    counts: Option<&[u64]>,
+    newline: &str,
+) -> String {
+    let mut lines = Vec::new();
+    if let Some(label) = symbols.label(block.start) {
+        lines.push(format!("{label}:"));
+    }
+
+    if let Some(counts) = counts {
+        lines.push(format!("ran {}x", counts.get(block.start).copied().unwrap_or(0)));
+    }
+
+    for (pc, instruction) in instructions.iter().enumerate().take(block.end).skip(block.start) {
+        lines.push(format!("{pc:04x} {instruction}"));
+    }
+
+    lines.join(newline)
+}
+
+/// A color from white, for blocks that never ran, to red, for the hottest.
+fn heat(block: &BasicBlock, counts: &[u64]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let count = counts.get(block.start).copied().unwrap_or(0);
+    let cool = 255 - (count * 255 / max) as u8;
+
+    format!("#ff{cool:02x}{cool:02x}")
+}
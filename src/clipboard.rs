@@ -0,0 +1,137 @@
+//! A clipboard device, for text-manipulation programs to exchange text with
+//! the host's clipboard.
+//!
+//! [`Clipboard`] is a [`HostDevice`], attached by default as device 1 of
+//! bank 3 (ID [`CLIPBOARD_DEVICE`]), which streams the clipboard's text a
+//! byte at a time. Its functions are:
+//!
+//! | Function | Effect                                                   |
+//! |----------|----------------------------------------------------------|
+//! | 0        | Take the clipboard's text, to read from its start        |
+//! | 1        | Read the next byte of the text into Ra, or 0 at its end  |
+//! | 2        | Set Ra to 1 if any of the text is left to read           |
+//! | 3        | Start writing new text                                   |
+//! | 4        | Append `value` to the text being written                 |
+//! | 5        | Put the text written on the clipboard                    |
+//!
+//! Where the text comes from and goes is up to a [`Backend`]. [`Memory`]
+//! keeps it to itself, for tests and for passing text between programs,
+//! and with the `clipboard` feature, `System` uses the desktop's
+//! clipboard, as `pact run --clipboard` does:
+//!
+//! ```no_run
+//! # #[cfg(feature = "clipboard")] {
+//! use pact::clipboard::{Clipboard, System, CLIPBOARD_DEVICE};
+//!
+//! let mut rim = pact::read_file("paste.rim").unwrap();
+//! rim.attach_device(CLIPBOARD_DEVICE, Clipboard::new(System::new().unwrap()));
+//! rim.run().unwrap();
+//! # }
+//! ```
+//!
+//! Text is UTF-8, so bytes past ASCII are read as they're encoded, and
+//! text written that isn't valid UTF-8 has the invalid bytes replaced.
+
+use crate::helper::U3;
+use crate::host::{DeviceError, HostDevice, FIRST_HOST_DEVICE};
+
+/// The device ID the clipboard is usually attached at.
+pub const CLIPBOARD_DEVICE: usize = FIRST_HOST_DEVICE + 1;
+
+/// Where a [`Clipboard`] gets and puts its text.
+pub trait Backend: Send {
+    fn get(&mut self) -> Result<String, DeviceError>;
+    fn set(&mut self, text: String) -> Result<(), DeviceError>;
+}
+
+/// A clipboard of the device's own.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Memory(pub String);
+
+impl Backend for Memory {
+    fn get(&mut self) -> Result<String, DeviceError> {
+        Ok(self.0.clone())
+    }
+
+    fn set(&mut self, text: String) -> Result<(), DeviceError> {
+        self.0 = text;
+        Ok(())
+    }
+}
+
+/// The desktop's clipboard.
+#[cfg(feature = "clipboard")]
+pub struct System(arboard::Clipboard);
+
+#[cfg(feature = "clipboard")]
+impl System {
+    pub fn new() -> Result<Self, DeviceError> {
+        Ok(Self(arboard::Clipboard::new()?))
+    }
+}
+
+#[cfg(feature = "clipboard")]
+impl Backend for System {
+    fn get(&mut self) -> Result<String, DeviceError> {
+        Ok(self.0.get_text()?)
+    }
+
+    fn set(&mut self, text: String) -> Result<(), DeviceError> {
+        Ok(self.0.set_text(text)?)
+    }
+}
+
+/// The clipboard device.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Clipboard<B> {
+    backend: B,
+    /// The text taken, and how much of it has been read.
+    reading: Vec<u8>,
+    read: usize,
+    writing: Vec<u8>,
+}
+
+impl<B: Backend> Clipboard<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            reading: Vec::new(),
+            read: 0,
+            writing: Vec::new(),
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+}
+
+impl<B: Backend> HostDevice for Clipboard<B> {
+    fn io(&mut self, function: U3, value: u8) -> Result<Option<u8>, DeviceError> {
+        match function as u8 {
+            0 => {
+                self.reading = self.backend.get()?.into_bytes();
+                self.read = 0;
+            }
+            1 => {
+                let byte = self.reading.get(self.read).copied();
+                self.read += byte.is_some() as usize;
+                return Ok(Some(byte.unwrap_or(0)));
+            }
+            2 => return Ok(Some((self.read < self.reading.len()) as u8)),
+            3 => self.writing.clear(),
+            4 => self.writing.push(value),
+            5 => {
+                let text = String::from_utf8_lossy(&self.writing).into_owned();
+                self.backend.set(text)?;
+            }
+            _ => {}
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,130 @@
+//! Code generation shared by the compilers: emitting instructions with
+//! jumps to labels, and laying them out.
+
+use crate::error::{LoadError, RimResult};
+use crate::helper::{U3, U4};
+use crate::{Device, Instruction, InstructionData, Opcode, Register, MAX_PROGRAM_LEN};
+
+/// An instruction, or one waiting on a label's address.
+enum Item {
+    Ready(Instruction),
+    /// Loads the label's page into Rd.
+    LoadPage(usize),
+    /// A `jne`, `jg`, or `jl` to a label.
+    Branch(Opcode, usize),
+    Label(usize),
+}
+
+/// A program being generated.
+#[derive(Default)]
+pub(crate) struct Code {
+    items: Vec<Item>,
+    labels: usize,
+}
+
+impl Code {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new label, not yet placed.
+    pub(crate) fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    /// Places a label at the next instruction.
+    pub(crate) fn place(&mut self, label: usize) {
+        self.items.push(Item::Label(label));
+    }
+
+    pub(crate) fn push(&mut self, instruction: Instruction) {
+        self.items.push(Item::Ready(instruction));
+    }
+
+    pub(crate) fn extend(&mut self, instructions: Vec<Instruction>) {
+        self.items.extend(instructions.into_iter().map(Item::Ready));
+    }
+
+    /// Loads a label's page into Rd, clobbering Ra and the flags.
+    pub(crate) fn load_page(&mut self, label: usize) {
+        self.items.push(Item::LoadPage(label));
+    }
+
+    /// A `jne` to a label, which only lands if Rd holds its page.
+    pub(crate) fn jne(&mut self, label: usize) {
+        self.branch(Opcode::Jne, label);
+    }
+
+    /// A `jne`, `jg`, or `jl` to a label, which only lands if Rd holds its
+    /// page.
+    pub(crate) fn branch(&mut self, opcode: Opcode, label: usize) {
+        self.items.push(Item::Branch(opcode, label));
+    }
+
+    /// Jumps to a label unconditionally, clobbering Ra, Rd, and the flags.
+    pub(crate) fn jump(&mut self, label: usize) {
+        self.load_page(label);
+        self.push(Instruction::ioi(Device::Cpu, U3::B010));
+        self.push(Instruction(Opcode::Adi, InstructionData::Imm(1)));
+        self.jne(label);
+    }
+
+    /// Resolves labels. Loading a page takes more instructions the bigger
+    /// it is, which can move labels onto later pages, so this lays the
+    /// program out until nothing moves, padding loads rather than ever
+    /// shortening them so that it settles.
+    pub(crate) fn link(self) -> RimResult<Vec<Instruction>> {
+        let mut lens = vec![0; self.items.len()];
+        let mut addrs = vec![0; self.labels];
+
+        loop {
+            let mut pc = 0;
+            let mut moved = false;
+            for (item, len) in self.items.iter().zip(&mut lens) {
+                match *item {
+                    Item::Label(label) => {
+                        moved |= addrs[label] != pc;
+                        addrs[label] = pc;
+                    }
+                    Item::LoadPage(label) => {
+                        let needed = Instruction::li(Register::Rd, (addrs[label] >> 4) as u8).len();
+                        moved |= needed > *len;
+                        *len = (*len).max(needed);
+                        pc += *len;
+                    }
+                    _ => pc += 1,
+                }
+            }
+
+            if pc > MAX_PROGRAM_LEN {
+                return Err(LoadError::ProgramTooLarge(pc).into());
+            }
+
+            if !moved {
+                break;
+            }
+        }
+
+        let mut instructions = Vec::new();
+        for (item, &len) in self.items.iter().zip(&lens) {
+            match *item {
+                Item::Ready(instruction) => instructions.push(instruction),
+                Item::LoadPage(label) => {
+                    let load = Instruction::li(Register::Rd, (addrs[label] >> 4) as u8);
+                    // Ra gets the page either way, and the flags are clobbered.
+                    let padding = len - load.len();
+                    instructions.extend(load);
+                    instructions.extend(std::iter::repeat_n(Instruction(Opcode::Adi, InstructionData::Imm(0)), padding));
+                }
+                Item::Branch(opcode, label) => instructions.push(Instruction(opcode, InstructionData::Mem {
+                    is_ptr: false,
+                    addr: U4::from(addrs[label] as u8),
+                })),
+                Item::Label(_) => {}
+            }
+        }
+
+        Ok(instructions)
+    }
+}
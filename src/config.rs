@@ -0,0 +1,165 @@
+//! Everything about how a machine is set up, apart from its program.
+//!
+//! A [`RimConfig`] collects settings and devices, and either builds a
+//! machine around a program or applies them to one already loaded, such as
+//! from an image. Settings left alone keep the machine's own, so applying a
+//! config doesn't undo what an image asked for.
+//!
+//! ```no_run
+//! use pact::config::RimConfig;
+//! use pact::disk::Disk;
+//! use pact::Arithmetic;
+//!
+//! let program = pact::asm::assemble("adi 1").unwrap();
+//! let mut rim = RimConfig::new()
+//!     .arithmetic(Arithmetic::Faulting)
+//!     .disk(Disk::read_file("data.disk").unwrap())
+//!     .build(program);
+//! rim.run().unwrap();
+//! ```
+
+use crate::console::Console;
+use crate::disk::Disk;
+use crate::graphics::Graphics;
+use crate::isa::IsaLevel;
+use crate::mailbox::Mailbox;
+use crate::screen::Present;
+use crate::sound::Sound;
+use crate::{Architecture, Arithmetic, Instruction, Rim};
+
+/// Settings and devices for a machine.
+#[derive(Default)]
+pub struct RimConfig {
+    architecture: Option<Architecture>,
+    arithmetic: Option<Arithmetic>,
+    isa: Option<IsaLevel>,
+    memory_len: Option<usize>,
+    present: Option<Present>,
+    console: Option<Console>,
+    disk: Option<Disk>,
+    graphics: Option<Graphics>,
+    sound: Option<Sound>,
+    mailbox: Option<Mailbox>,
+    denied: Vec<usize>,
+}
+
+impl RimConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn architecture(mut self, architecture: Architecture) -> Self {
+        self.architecture = Some(architecture);
+        self
+    }
+
+    pub fn arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        self.arithmetic = Some(arithmetic);
+        self
+    }
+
+    pub fn isa(mut self, isa: IsaLevel) -> Self {
+        self.isa = Some(isa);
+        self
+    }
+
+    /// Limits the program to the first `len` bytes of data memory; see
+    /// [`Rim::set_memory_len`].
+    pub fn memory_len(mut self, len: usize) -> Self {
+        self.memory_len = Some(len);
+        self
+    }
+
+    pub fn present(mut self, present: Present) -> Self {
+        self.present = Some(present);
+        self
+    }
+
+    pub fn console(mut self, console: Console) -> Self {
+        self.console = Some(console);
+        self
+    }
+
+    pub fn disk(mut self, disk: Disk) -> Self {
+        self.disk = Some(disk);
+        self
+    }
+
+    pub fn graphics(mut self, graphics: Graphics) -> Self {
+        self.graphics = Some(graphics);
+        self
+    }
+
+    pub fn sound(mut self, sound: Sound) -> Self {
+        self.sound = Some(sound);
+        self
+    }
+
+    pub fn mailbox(mut self, mailbox: Mailbox) -> Self {
+        self.mailbox = Some(mailbox);
+        self
+    }
+
+    /// Denies the program a device, by its ID (see the [`image`](crate::image)
+    /// module). Can be given more than once.
+    pub fn deny(mut self, id: usize) -> Self {
+        self.denied.push(id);
+        self
+    }
+
+    /// Builds a machine to run a program.
+    pub fn build(self, program: Vec<Instruction>) -> Rim {
+        let mut rim = Rim::new(program);
+        self.apply(&mut rim);
+        rim
+    }
+
+    /// Sets up a machine that's already loaded. The architecture is set
+    /// last, so that the von Neumann architecture copies the program as it
+    /// is by then.
+    pub fn apply(self, rim: &mut Rim) {
+        if let Some(arithmetic) = self.arithmetic {
+            rim.set_arithmetic(arithmetic);
+        }
+
+        if let Some(isa) = self.isa {
+            rim.set_isa(isa);
+        }
+
+        if let Some(len) = self.memory_len {
+            rim.set_memory_len(len);
+        }
+
+        if let Some(present) = self.present {
+            rim.set_present_mode(present);
+        }
+
+        if let Some(console) = self.console {
+            rim.set_console(console);
+        }
+
+        if let Some(disk) = self.disk {
+            rim.attach_disk(disk);
+        }
+
+        if let Some(graphics) = self.graphics {
+            rim.attach_graphics(graphics);
+        }
+
+        if let Some(sound) = self.sound {
+            rim.attach_sound(sound);
+        }
+
+        if let Some(mailbox) = self.mailbox {
+            rim.attach_mailbox(mailbox);
+        }
+
+        for id in self.denied {
+            rim.deny_device(id);
+        }
+
+        if let Some(architecture) = self.architecture {
+            rim.set_architecture(architecture);
+        }
+    }
+}
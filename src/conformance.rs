@@ -0,0 +1,123 @@
+//! The ISA conformance suite, bundled from the `conformance` directory. See
+//! its README for the expectation format.
+
+use crate::error::{LoadError, RimResult};
+use crate::{read_bytes, Status};
+
+/// The most steps a case may take before it's considered stuck.
+pub const MAX_STEPS: usize = 100_000;
+
+/// A conformance program and the state it must halt in.
+#[derive(Debug, Clone, Copy)]
+pub struct Case {
+    pub name: &'static str,
+    pub program: &'static [u8],
+    pub expected: &'static str,
+}
+
+macro_rules! case {
+    ($name:literal) => {
+        Case {
+            name: $name,
+            program: include_bytes!(concat!("../conformance/", $name, ".rim")),
+            expected: include_str!(concat!("../conformance/", $name, ".expect")),
+        }
+    };
+}
+
+pub const CASES: &[Case] = &[
+    case!("adi"),
+    case!("adi_wrap"),
+    case!("add"),
+    case!("add_id"),
+    case!("sub"),
+    case!("jne"),
+    case!("signed"),
+    case!("carry"),
+    case!("memory"),
+    case!("memory_bounds"),
+    case!("indirect"),
+    case!("mth"),
+    case!("flags"),
+    case!("halt"),
+];
+
+impl Case {
+    pub fn check(&self) -> RimResult<Vec<String>> {
+        check(self.program, self.expected)
+    }
+}
+
+/// Runs a program and compares its final state against an expectation,
+/// returning a description of each mismatch.
+pub fn check(program: &[u8], expected: &str) -> RimResult<Vec<String>> {
+    let mut rim = read_bytes(program)?;
+
+    let mut halted = false;
+    for _ in 0..MAX_STEPS {
+        match rim.step() {
+            Ok(Status::Running) => {}
+            Ok(Status::Halted) => {
+                halted = true;
+                break;
+            }
+            Err(e) => return Ok(vec![format!("faulted: {e}")]),
+        }
+    }
+
+    if !halted {
+        return Ok(vec![format!("didn't halt within {MAX_STEPS} steps")]);
+    }
+
+    let snapshot = rim.snapshot();
+    let mut mismatches = Vec::new();
+
+    for (i, line) in expected.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        let err = || LoadError::InvalidExpectation(i + 1);
+        let (key, value) = line.split_once('=').ok_or_else(err)?;
+        let (key, value) = (key.trim(), value.trim());
+
+        let actual = match key {
+            "pc" => format!("{:#05x}", snapshot.pc),
+            "ra" | "rb" | "rc" | "rd" => {
+                let index = (key.as_bytes()[1] - b'a') as usize;
+                format!("{:#04x}", snapshot.registers[index])
+            }
+            "flags" => snapshot.flags.to_string(),
+            _ => {
+                let addr = key
+                    .strip_prefix("mem[")
+                    .and_then(|key| key.strip_suffix(']'))
+                    .and_then(parse_number)
+                    .filter(|&addr| addr < rim.data().len())
+                    .ok_or_else(err)?;
+
+                format!("{:#04x}", rim.data()[addr])
+            }
+        };
+
+        let matches = if key == "flags" {
+            actual == value
+        } else {
+            parse_number(value).ok_or_else(err)? == parse_number(&actual).unwrap_or_default()
+        };
+
+        if !matches {
+            mismatches.push(format!("{key}: expected {value}, found {actual}"));
+        }
+    }
+
+    Ok(mismatches)
+}
+
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -0,0 +1,505 @@
+//! Where the keyboard reads from and the screen writes to.
+//!
+//! The keyboard has the following functions:
+//!
+//! | Function | Effect                                                   |
+//! |----------|----------------------------------------------------------|
+//! | 0        | Read the next key into Ra, or 0 if there's no more input |
+//! | 1        | Set Ra to 1 if a key is waiting, or 0 if not             |
+//! | 2        | Take the next mouse event, and read its button into Ra   |
+//! | 3        | Read the row of the last mouse event into Ra             |
+//! | 4        | Read the column of the last mouse event into Ra          |
+//! | 5        | Set Ra to 1 if a mouse event is waiting, or 0 if not     |
+//!
+//! Other keyboard functions do nothing. The terminal can't tell whether a
+//! key is waiting without blocking, so it always claims one is.
+//!
+//! Mouse events are clicks and moves over screen cells, which frontends
+//! send through a [`Buffer`] with [`Buffer::push_mouse`] (as the control
+//! protocol's `mouse` requests do), or an [`Inbox`]. Buttons read as their
+//! [`MouseEvent::code`], or 0 if no event was waiting, in which case the
+//! last event's row and column stay. The plain terminal reads a line at a
+//! time, so it never has mouse events.
+//!
+//! An [`Inbox`] is for feeding a machine from other threads while it runs
+//! on its own: keys and mouse events sent through any handle to it reach
+//! the machine, and [`Rim::run`](crate::Rim::run) parks while the program
+//! waits for input (system call 11), until something is sent or an
+//! interrupt is asked for. With the `crossterm` feature, `Crossterm` feeds
+//! one from the terminal, a key at a time and with the mouse captured, as
+//! `pact run --mouse` does:
+//!
+//! ```no_run
+//! # #[cfg(feature = "crossterm")] {
+//! use pact::console::Crossterm;
+//!
+//! let mut rim = pact::read_file("paint.rim").unwrap();
+//! let terminal = Crossterm::start().unwrap();
+//! rim.set_console(terminal.console());
+//! rim.run().unwrap();
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+#[cfg(feature = "crossterm")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "crossterm")]
+use std::thread::JoinHandle;
+#[cfg(feature = "crossterm")]
+use std::time::Duration;
+
+/// A machine's keyboard and screen.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Console {
+    /// Reads from stdin, and writes to stdout.
+    #[default]
+    Terminal,
+    /// Reads scripted input, and captures output.
+    Buffer(Buffer),
+    /// Reads from stdin, and writes to stdout, keeping what it writes to be
+    /// taken, for recording a session.
+    Recording(Vec<u8>),
+    /// Reads what other threads send, and writes to stdout. Like the
+    /// terminal, reading a key blocks until one is sent, or the inbox is
+    /// closed.
+    Inbox(Inbox),
+}
+
+impl Console {
+    /// The next key, if any.
+    pub fn read(&mut self) -> Option<u8> {
+        match self {
+            Self::Terminal | Self::Recording(_) => {
+                let mut byte = [0];
+                match std::io::stdin().read(&mut byte) {
+                    Ok(1) => Some(byte[0]),
+                    _ => None,
+                }
+            }
+            Self::Buffer(buffer) => buffer.input.pop_front(),
+            Self::Inbox(inbox) => inbox.read(),
+        }
+    }
+
+    pub fn poll(&self) -> bool {
+        match self {
+            Self::Terminal | Self::Recording(_) => true,
+            Self::Buffer(buffer) => !buffer.input.is_empty(),
+            Self::Inbox(inbox) => {
+                let state = inbox.state();
+                !state.keys.is_empty() || state.closed
+            }
+        }
+    }
+
+    /// The next mouse event, if any.
+    pub fn read_mouse(&mut self) -> Option<MouseEvent> {
+        match self {
+            Self::Terminal | Self::Recording(_) => None,
+            Self::Buffer(buffer) => buffer.mouse.pop_front(),
+            Self::Inbox(inbox) => inbox.state().mouse.pop_front(),
+        }
+    }
+
+    pub fn poll_mouse(&self) -> bool {
+        match self {
+            Self::Terminal | Self::Recording(_) => false,
+            Self::Buffer(buffer) => !buffer.mouse.is_empty(),
+            Self::Inbox(inbox) => !inbox.state().mouse.is_empty(),
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Terminal | Self::Inbox(_) => {
+                let _ = std::io::stdout().write_all(bytes);
+            }
+            Self::Buffer(buffer) => buffer.write(bytes),
+            Self::Recording(recorded) => {
+                let _ = std::io::stdout().write_all(bytes);
+                recorded.extend_from_slice(bytes);
+            }
+        }
+    }
+
+    /// Takes what was written since this was last called, if recording.
+    pub fn take_recorded(&mut self) -> Vec<u8> {
+        match self {
+            Self::Recording(recorded) => std::mem::take(recorded),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A mouse click or move, over a screen cell.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub row: u8,
+    pub col: u8,
+    /// The button clicked, or `None` if the mouse just moved.
+    pub button: Option<MouseButton>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseEvent {
+    /// What the program reads for the event's button: 1 to 3 for the left,
+    /// middle, and right buttons, and 4 for a move.
+    pub fn code(&self) -> u8 {
+        match self.button {
+            Some(MouseButton::Left) => 1,
+            Some(MouseButton::Middle) => 2,
+            Some(MouseButton::Right) => 3,
+            None => 4,
+        }
+    }
+}
+
+/// Scripted input and captured output, for running programs unattended.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Buffer {
+    input: VecDeque<u8>,
+    mouse: VecDeque<MouseEvent>,
+    output: Vec<u8>,
+    limit: Option<usize>,
+    truncated: bool,
+    /// How much output was taken, for the limit.
+    taken: usize,
+}
+
+impl Buffer {
+    pub fn new(input: impl Into<VecDeque<u8>>) -> Self {
+        Self {
+            input: input.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Caps the captured output at `limit` bytes, dropping anything after.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = Some(limit);
+    }
+
+    /// The input that hasn't been read yet.
+    pub fn input(&self) -> &VecDeque<u8> {
+        &self.input
+    }
+
+    /// Adds keys after the unread input.
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// The mouse events that haven't been read yet.
+    pub fn mouse(&self) -> &VecDeque<MouseEvent> {
+        &self.mouse
+    }
+
+    /// Adds mouse events after the unread ones.
+    pub fn push_mouse(&mut self, events: &[MouseEvent]) {
+        self.mouse.extend(events);
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Takes the output captured so far, leaving none. It still counts
+    /// towards the limit.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let taken = std::mem::take(&mut self.output);
+        self.taken += taken.len();
+        taken
+    }
+
+    /// Whether output was dropped for going over the limit.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let room = self.limit.map_or(usize::MAX, |limit| limit.saturating_sub(self.taken + self.output.len()));
+        if bytes.len() > room {
+            self.truncated = true;
+        }
+
+        self.output.extend_from_slice(&bytes[..bytes.len().min(room)]);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inbound {
+    keys: VecDeque<u8>,
+    mouse: VecDeque<MouseEvent>,
+    /// Whether an interrupt was asked for and not yet taken.
+    interrupt: bool,
+    /// Whether nothing more will be sent.
+    closed: bool,
+}
+
+/// Keys, mouse events, and interrupts sent to a machine from other
+/// threads. It's a handle, so clones of it send to the same machine.
+///
+/// ```no_run
+/// use pact::console::{Console, Inbox};
+///
+/// let inbox = Inbox::new();
+/// let mut rim = pact::read_file("echo.rim").unwrap();
+/// rim.set_console(Console::Inbox(inbox.clone()));
+///
+/// let machine = std::thread::spawn(move || rim.run());
+/// inbox.send_keys(b"hi\n");
+/// machine.join().unwrap().unwrap();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Inbox {
+    shared: Arc<(Mutex<Inbound>, Condvar)>,
+}
+
+impl Inbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, Inbound> {
+        // Nothing can panic while holding the lock, so it's never poisoned.
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds keys after the unread ones, waking the machine if it's waiting.
+    pub fn send_keys(&self, keys: &[u8]) {
+        self.state().keys.extend(keys);
+        self.shared.1.notify_all();
+    }
+
+    /// Adds mouse events after the unread ones.
+    pub fn send_mouse(&self, events: &[MouseEvent]) {
+        self.state().mouse.extend(events);
+        self.shared.1.notify_all();
+    }
+
+    /// Asks the machine to take an interrupt (see
+    /// [`Rim::interrupt`](crate::Rim::interrupt)), which
+    /// [`Rim::run`](crate::Rim::run) does soon after, waking it if it's
+    /// waiting. Asking again before it's taken does nothing more.
+    pub fn interrupt(&self) {
+        self.state().interrupt = true;
+        self.shared.1.notify_all();
+    }
+
+    /// Takes the interrupt asked for, if there is one.
+    pub fn take_interrupt(&self) -> bool {
+        std::mem::take(&mut self.state().interrupt)
+    }
+
+    /// Says nothing more will be sent, so reads past the keys already sent
+    /// end the input, rather than waiting.
+    pub fn close(&self) {
+        self.state().closed = true;
+        self.shared.1.notify_all();
+    }
+
+    /// Blocks until a key is waiting, an interrupt is asked for, or the
+    /// inbox is closed.
+    pub fn wait(&self) {
+        let state = self.state();
+        let _state = self
+            .shared
+            .1
+            .wait_while(state, |state| state.keys.is_empty() && !state.interrupt && !state.closed)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+
+    /// The next key, waiting for one, or `None` once it's closed and they've
+    /// all been read.
+    fn read(&self) -> Option<u8> {
+        let state = self.state();
+        let mut state = self
+            .shared
+            .1
+            .wait_while(state, |state| state.keys.is_empty() && !state.closed)
+            .unwrap_or_else(|e| e.into_inner());
+        state.keys.pop_front()
+    }
+}
+
+/// Inboxes are equal if they're handles to the same one.
+impl PartialEq for Inbox {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl Eq for Inbox {}
+
+/// The terminal, read through crossterm a key at a time and with the mouse
+/// captured, by a thread of its own that sends what it reads to an
+/// [`Inbox`]. Dropping it puts the terminal back the way it was, and closes
+/// the inbox.
+///
+/// Keys are sent as the bytes they type, with Enter as a newline. Clicks
+/// and moves over the terminal's cells are sent as mouse events, at the
+/// cell's row and column. Ctrl-C ends the process, as it would have without
+/// the terminal in raw mode.
+#[cfg(feature = "crossterm")]
+pub struct Crossterm {
+    inbox: Inbox,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "crossterm")]
+impl Crossterm {
+    /// How long the reader waits for an event before checking whether to
+    /// stop.
+    const POLL: Duration = Duration::from_millis(50);
+
+    /// Puts the terminal in raw mode, captures the mouse, and starts
+    /// reading.
+    pub fn start() -> std::io::Result<Self> {
+        crossterm::terminal::enable_raw_mode()?;
+        CAPTURED.store(true, Ordering::Relaxed);
+        keep_output_processing();
+        crossterm::execute!(std::io::stdout(), crossterm::event::EnableMouseCapture)?;
+
+        let (inbox, stop) = (Inbox::new(), Arc::new(AtomicBool::new(false)));
+        let reader = {
+            let (inbox, stop) = (inbox.clone(), stop.clone());
+            std::thread::spawn(move || Self::read(&inbox, &stop))
+        };
+
+        Ok(Self { inbox, stop, reader: Some(reader) })
+    }
+
+    /// Puts the terminal back if one was started and hasn't been, for
+    /// exiting the process without dropping it.
+    pub fn restore() {
+        if CAPTURED.swap(false, Ordering::Relaxed) {
+            let _ = crossterm::execute!(std::io::stdout(), crossterm::event::DisableMouseCapture);
+            let _ = crossterm::terminal::disable_raw_mode();
+        }
+    }
+
+    pub fn inbox(&self) -> &Inbox {
+        &self.inbox
+    }
+
+    /// A console reading from the terminal.
+    pub fn console(&self) -> Console {
+        Console::Inbox(self.inbox.clone())
+    }
+
+    fn read(inbox: &Inbox, stop: &AtomicBool) {
+        use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+        while !stop.load(Ordering::Relaxed) {
+            match event::poll(Self::POLL) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(_) => break,
+            }
+
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => {
+                    if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                        Self::restore();
+                        std::process::exit(130);
+                    }
+
+                    if let Some(bytes) = key_bytes(key) {
+                        inbox.send_keys(&bytes);
+                    }
+                }
+                Ok(Event::Mouse(mouse)) => {
+                    if let Some(mouse) = mouse_event(mouse) {
+                        inbox.send_mouse(&[mouse]);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+
+        inbox.close();
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Drop for Crossterm {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+
+        Self::restore();
+    }
+}
+
+/// Whether a [`Crossterm`] has the terminal.
+#[cfg(feature = "crossterm")]
+static CAPTURED: AtomicBool = AtomicBool::new(false);
+
+/// Turns newline translation back on after raw mode turns it off, so
+/// programs' output comes out as it does on the terminal otherwise.
+#[cfg(all(feature = "crossterm", unix))]
+fn keep_output_processing() {
+    // SAFETY: `termios` is plain data, filled in by `tcgetattr` before it's
+    // read.
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) == 0 {
+            termios.c_oflag |= libc::OPOST | libc::ONLCR;
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &termios);
+        }
+    }
+}
+
+/// Raw mode leaves output alone on other platforms.
+#[cfg(all(feature = "crossterm", not(unix)))]
+fn keep_output_processing() {}
+
+/// The bytes a key types, if it types any.
+#[cfg(feature = "crossterm")]
+pub fn key_bytes(key: crossterm::event::KeyEvent) -> Option<Vec<u8>> {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    Some(match key.code {
+        // Ctrl with a letter types its control character, as in a terminal.
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) && c.is_ascii_alphabetic() => {
+            vec![c.to_ascii_lowercase() as u8 - b'a' + 1]
+        }
+        KeyCode::Char(c) => c.to_string().into_bytes(),
+        KeyCode::Enter => vec![b'\n'],
+        KeyCode::Tab => vec![b'\t'],
+        KeyCode::Backspace => vec![8],
+        KeyCode::Esc => vec![27],
+        _ => return None,
+    })
+}
+
+/// The mouse event a crossterm one is, if the keyboard has one for it:
+/// button presses are clicks, and moves and drags are moves.
+#[cfg(feature = "crossterm")]
+pub fn mouse_event(event: crossterm::event::MouseEvent) -> Option<MouseEvent> {
+    use crossterm::event::{MouseButton as Button, MouseEventKind};
+
+    let button = match event.kind {
+        MouseEventKind::Down(Button::Left) => Some(MouseButton::Left),
+        MouseEventKind::Down(Button::Middle) => Some(MouseButton::Middle),
+        MouseEventKind::Down(Button::Right) => Some(MouseButton::Right),
+        MouseEventKind::Moved | MouseEventKind::Drag(_) => None,
+        _ => return None,
+    };
+
+    let cell = |n: u16| u8::try_from(n).unwrap_or(u8::MAX);
+    Some(MouseEvent { row: cell(event.row), col: cell(event.column), button })
+}
@@ -0,0 +1,326 @@
+//! A JSON-RPC protocol for driving a machine remotely, for web UIs and
+//! external debuggers.
+//!
+//! `pact serve --control` listens for TCP connections, each of which gets a
+//! machine of its own. Requests and responses are
+//! [JSON-RPC 2.0](https://www.jsonrpc.org/specification) objects, one per
+//! line. The methods are:
+//!
+//! | Method         | Params                         | Result                       |
+//! |----------------|--------------------------------|------------------------------|
+//! | `load`         | `source` or `image`            | The state                    |
+//! | `step`         | `count`, default 1             | A run                        |
+//! | `run`          | `max_steps`, `max_time_ms`     | A run                        |
+//! | `state`        |                                | The state                    |
+//! | `set_state`    | `registers` and/or `flags`     | The state                    |
+//! | `read_memory`  | `addr` and `len`               | An array of bytes            |
+//! | `write_memory` | `addr` and `bytes`             | `null`                       |
+//! | `input`        | `text`                         | `null`                       |
+//! | `mouse`        | `row`, `col`, and `button`     | `null`                       |
+//! | `screen`       |                                | `text`, `row`, and `col`     |
+//! | `subscribe`    |                                | `null`                       |
+//! | `unsubscribe`  |                                | `null`                       |
+//!
+//! `source` is assembler source, and `image` a program image as an array of
+//! bytes. A state is the `slot`, `pc`, `registers`, and `flags` (packed as
+//! by [`Flags::to_bits`](crate::Flags::to_bits)). A run is a state with the
+//! `outcome` (`running`, `halted`, `faulted`, `out_of_steps`, or
+//! `out_of_time`), the `error` if it faulted, the `steps` taken, and the
+//! `output` written. Runs are capped by the server's limits, and a run that
+//! reaches one can be continued with another.
+//!
+//! A `mouse` event is a click of its `button`, `left`, `middle`, or
+//! `right`, over the screen cell at `row` and `col`, or a move to the cell
+//! if the button is left out or `null` (see [`console`](crate::console)).
+//!
+//! After subscribing, every request that changes the screen is followed by
+//! a `screen` notification, with the changed `cells` as `[row, col, char]`
+//! arrays, so a UI can redraw just those.
+//!
+//! The same messages could be carried over any transport: a wasm build can
+//! pass them to [`Control::handle`] directly, sending its keys and mouse
+//! events as `input` and `mouse` requests the way the playground's page
+//! does.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::console::{Buffer, Console, MouseButton, MouseEvent};
+use crate::error::{RimError, RimResult};
+use crate::grade::{self, Limits, Outcome};
+use crate::{Flags, Rim, Status};
+
+/// The code of an error from the machine itself, as opposed to one in the
+/// request.
+pub const MACHINE_ERROR: i64 = -32000;
+
+/// Serves the protocol on `addr` until the process exits, with each
+/// connection on its own thread, within `limits`.
+pub fn serve(addr: &str, limits: Limits) -> RimResult<()> {
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        std::thread::spawn(move || connect(stream, limits));
+    }
+
+    Ok(())
+}
+
+fn connect(stream: TcpStream, limits: Limits) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+
+    let mut control = Control::new(limits);
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        for message in control.handle(&line) {
+            if writeln!(writer, "{message}").is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// One client's machine, and what it's subscribed to.
+pub struct Control {
+    rim: Rim,
+    limits: Limits,
+    subscribed: bool,
+}
+
+/// A failed request, as a JSON-RPC error code and message.
+type Failure = (i64, String);
+
+impl Control {
+    pub fn new(limits: Limits) -> Self {
+        let mut control = Self { rim: Rim::default(), limits, subscribed: false };
+        control.reset(Rim::default());
+        control
+    }
+
+    /// Handles a request line, returning the response, if it wasn't a
+    /// notification, and any notifications to send after it.
+    pub fn handle(&mut self, line: &str) -> Vec<Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return vec![error(Value::Null, (-32700, e.to_string()))],
+        };
+
+        let id = request.get("id").cloned();
+        let result = match request.get("method").and_then(Value::as_str) {
+            Some(method) => self.call(method, request.get("params").unwrap_or(&Value::Null)),
+            None => Err((-32600, "expected a `method`".to_string())),
+        };
+
+        let mut messages = Vec::new();
+        if let Some(id) = id {
+            messages.push(match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(failure) => error(id, failure),
+            });
+        }
+
+        let cells = self.rim.screen_mut().take_dirty();
+        if self.subscribed && !cells.is_empty() {
+            let cells: Vec<_> = cells.into_iter().map(|(row, col)| json!([row, col, self.rim.screen().cell(row, col)])).collect();
+            messages.push(json!({ "jsonrpc": "2.0", "method": "screen", "params": { "cells": cells } }));
+        }
+
+        messages
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, Failure> {
+        match method {
+            "load" => {
+                let program = match (params.get("source"), params.get("image")) {
+                    (Some(Value::String(source)), None) => crate::asm::assemble_str(source).map_err(machine)?,
+                    (None, Some(image)) => bytes(image, "image")?,
+                    _ => return Err(invalid("expected exactly one of `source` or `image`")),
+                };
+
+                let rim = grade::load(&program, &self.limits.load).map_err(machine)?;
+                self.reset(rim);
+                Ok(self.state())
+            }
+            "step" => {
+                let count = optional(params, "count")?.unwrap_or(1).min(self.limits.max_steps);
+                let mut steps = 0;
+                let mut outcome = Outcome::OutOfSteps;
+                while steps < count {
+                    steps += 1;
+                    match self.rim.step() {
+                        Ok(Status::Running) => {}
+                        Ok(Status::Halted) => {
+                            outcome = Outcome::Halted;
+                            break;
+                        }
+                        Err(e) => {
+                            outcome = Outcome::Faulted(e);
+                            break;
+                        }
+                    }
+                }
+
+                // Stepping as asked isn't running out of anything.
+                let kind = match outcome {
+                    Outcome::OutOfSteps => "running",
+                    ref outcome => outcome.kind(),
+                };
+                Ok(self.run_result(kind, outcome.error(), steps))
+            }
+            "run" => {
+                let max_time = self.limits.max_time.map_or(usize::MAX, |max| max.as_millis() as usize);
+                let limits = Limits {
+                    max_steps: optional(params, "max_steps")?.unwrap_or(usize::MAX).min(self.limits.max_steps),
+                    max_time: Some(Duration::from_millis(optional(params, "max_time_ms")?.unwrap_or(usize::MAX).min(max_time) as u64)),
+                    ..self.limits
+                };
+
+                let (mut steps, mut instructions) = (0, 0);
+                let outcome = grade::run(&mut self.rim, &limits, Instant::now(), &mut steps, &mut instructions);
+                Ok(self.run_result(outcome.kind(), outcome.error(), steps))
+            }
+            "state" => Ok(self.state()),
+            "set_state" => {
+                if let Some(registers) = params.get("registers") {
+                    let registers = bytes(registers, "registers")?;
+                    let registers = registers.try_into().map_err(|_| invalid("`registers` must have 4 bytes"))?;
+                    self.rim.set_registers(registers);
+                }
+
+                if let Some(flags) = params.get("flags") {
+                    let flags = flags.as_u64().and_then(|flags| u8::try_from(flags).ok()).ok_or_else(|| invalid("`flags` must be a byte"))?;
+                    self.rim.set_flags(Flags::from_bits(flags));
+                }
+
+                Ok(self.state())
+            }
+            "read_memory" => {
+                let (addr, len) = (required(params, "addr")?, required(params, "len")?);
+                let data = self.rim.data();
+                let end = addr.checked_add(len).filter(|&end| end <= data.len()).ok_or_else(|| invalid("the range is past the end of memory"))?;
+                Ok(json!(data[addr..end]))
+            }
+            "write_memory" => {
+                let addr = required(params, "addr")?;
+                let bytes = bytes(params.get("bytes").unwrap_or(&Value::Null), "bytes")?;
+                let data = self.rim.data_mut();
+                let end = addr.checked_add(bytes.len()).filter(|&end| end <= data.len()).ok_or_else(|| invalid("the range is past the end of memory"))?;
+                data[addr..end].copy_from_slice(&bytes);
+                Ok(Value::Null)
+            }
+            "input" => {
+                let text = params.get("text").and_then(Value::as_str).ok_or_else(|| invalid("`text` must be a string"))?;
+                if let Console::Buffer(buffer) = self.rim.console_mut() {
+                    buffer.push_input(text.as_bytes());
+                }
+
+                Ok(Value::Null)
+            }
+            "mouse" => {
+                let cell = |key| required(params, key).and_then(|n| u8::try_from(n).map_err(|_| invalid(&format!("`{key}` must be a byte"))));
+                let button = match params.get("button") {
+                    None | Some(Value::Null) => None,
+                    Some(Value::String(button)) if button == "left" => Some(MouseButton::Left),
+                    Some(Value::String(button)) if button == "middle" => Some(MouseButton::Middle),
+                    Some(Value::String(button)) if button == "right" => Some(MouseButton::Right),
+                    Some(_) => return Err(invalid("`button` must be `left`, `middle`, or `right`")),
+                };
+
+                let event = MouseEvent { row: cell("row")?, col: cell("col")?, button };
+                if let Console::Buffer(buffer) = self.rim.console_mut() {
+                    buffer.push_mouse(&[event]);
+                }
+
+                Ok(Value::Null)
+            }
+            "screen" => {
+                let screen = self.rim.screen();
+                let (row, col) = screen.cursor();
+                Ok(json!({ "text": screen.text(), "row": row, "col": col }))
+            }
+            "subscribe" | "unsubscribe" => {
+                self.subscribed = method == "subscribe";
+                Ok(Value::Null)
+            }
+            _ => Err((-32601, format!("unknown method `{method}`"))),
+        }
+    }
+
+    /// Replaces the machine, giving it a console the client feeds.
+    fn reset(&mut self, mut rim: Rim) {
+        let mut buffer = Buffer::new(Vec::new());
+        buffer.set_limit(self.limits.max_output);
+        rim.set_console(Console::Buffer(buffer));
+        rim.set_load_limits(self.limits.load);
+        self.rim = rim;
+    }
+
+    fn state(&self) -> Value {
+        let snapshot = self.rim.snapshot();
+        json!({
+            "slot": snapshot.slot,
+            "pc": snapshot.pc,
+            "registers": snapshot.registers,
+            "flags": snapshot.flags.to_bits(),
+        })
+    }
+
+    fn run_result(&mut self, outcome: &str, error: Option<&RimError>, steps: usize) -> Value {
+        let output = match self.rim.console_mut() {
+            Console::Buffer(buffer) => buffer.take_output(),
+            _ => Vec::new(),
+        };
+
+        let mut result = self.state();
+        result["outcome"] = json!(outcome);
+        result["error"] = json!(error.map(ToString::to_string));
+        result["steps"] = json!(steps);
+        result["output"] = json!(String::from_utf8_lossy(&output));
+        result
+    }
+}
+
+fn error(id: Value, (code, message): Failure) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn invalid(message: &str) -> Failure {
+    (-32602, message.to_string())
+}
+
+fn machine(e: RimError) -> Failure {
+    (MACHINE_ERROR, e.to_string())
+}
+
+fn optional(params: &Value, key: &str) -> Result<Option<usize>, Failure> {
+    match params.get(key) {
+        Some(value) => value.as_u64().map(|n| Some(n as usize)).ok_or_else(|| invalid(&format!("`{key}` must be a non-negative integer"))),
+        None => Ok(None),
+    }
+}
+
+fn required(params: &Value, key: &str) -> Result<usize, Failure> {
+    optional(params, key)?.ok_or_else(|| invalid(&format!("missing `{key}`")))
+}
+
+fn bytes(value: &Value, key: &str) -> Result<Vec<u8>, Failure> {
+    value
+        .as_array()
+        .and_then(|bytes| bytes.iter().map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok())).collect())
+        .ok_or_else(|| invalid(&format!("`{key}` must be an array of bytes")))
+}
@@ -0,0 +1,91 @@
+//! Performance counters, which a program can read to measure itself.
+//!
+//! Every machine counts its cycles, instructions, and conditional jumps,
+//! for the host through [`Rim::counters`](crate::Rim::counters), and for
+//! the program through the counters device. Every step is a cycle, but a
+//! step that blocked on a mailbox or waited for a key is retried, so it
+//! didn't execute an instruction.
+//!
+//! The counters device is extension device 0 of bank 2, and is always
+//! present. It reads from a copy of the counters, latched all at once so
+//! that reading one byte at a time gives consistent values. Its functions
+//! are:
+//!
+//! | Function | Effect                                                     |
+//! |----------|------------------------------------------------------------|
+//! | 0        | Latch the counters                                         |
+//! | 1        | Read byte `value` of the latched cycle count into Ra       |
+//! | 2        | Read byte `value` of the latched instruction count into Ra |
+//! | 3        | Read byte `value` of the latched jump count into Ra        |
+//! | 4        | Read byte `value` of the latched taken jump count into Ra  |
+//! | 5        | Reset the counters                                         |
+//!
+//! Counts are 64 bits, and byte 0 is the lowest; bytes past 7 read as 0.
+//! The instruction that latches the counters isn't in them yet, and the
+//! one that resets them is the first they count. Functions 6 and 7 are
+//! reserved, and do nothing.
+//!
+//! ```
+//! use pact::{asm, Rim};
+//!
+//! let mut rim = Rim::new(asm::assemble("
+//!     li rc, 0
+//!     li rb, 2
+//!     li ra, 2
+//!     ioi cpu, 7      ; the next I/O goes to bank 2
+//!     ioi cpu, 0      ; latch
+//!     li rb, 2
+//!     li ra, 2
+//!     ioi cpu, 7
+//!     ior cpu, 2      ; byte Rc of the instruction count
+//! ").unwrap());
+//! rim.run().unwrap();
+//!
+//! // The instructions before the latch, of all those that ran.
+//! let latched = rim.registers()[0] as u64;
+//! assert!(latched > 0 && latched < rim.counters().instructions);
+//! ```
+
+use crate::helper::U3;
+
+/// What a machine has executed, since it was created or its counters were
+/// last reset.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    /// Steps, including ones that were retried.
+    pub cycles: u64,
+    pub instructions: u64,
+    /// Conditional jumps executed, taken or not.
+    pub jumps: u64,
+    pub taken: u64,
+}
+
+/// The counters device's state.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Latch {
+    latched: Counters,
+}
+
+impl Latch {
+    /// Performs a function, given the live counters, returning what to set
+    /// Ra to, if anything.
+    pub(crate) fn io(&mut self, counters: &mut Counters, function: U3, value: u8) -> Option<u8> {
+        let count = match function as u8 {
+            0 => {
+                self.latched = *counters;
+                return None;
+            }
+            1 => self.latched.cycles,
+            2 => self.latched.instructions,
+            3 => self.latched.jumps,
+            4 => self.latched.taken,
+            5 => {
+                *counters = Counters::default();
+                return None;
+            }
+            _ => return None,
+        };
+
+        Some(count.to_le_bytes().get(value as usize).copied().unwrap_or(0))
+    }
+}
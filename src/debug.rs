@@ -0,0 +1,419 @@
+//! The core of the debugger: breakpoints, watchpoints, watches, and
+//! stepping.
+//!
+//! A watchpoint stops the program when a byte of data memory changes,
+//! whatever changed it:
+//!
+//! ```
+//! use pact::addr::Addr;
+//! use pact::debug::{Debugger, Stop};
+//! use pact::symbols::Symbols;
+//! use pact::{asm, Rim};
+//!
+//! let program = asm::assemble("
+//!     li rd, 1
+//!     li ra, 0xf
+//!     ioi cpu, 4      ; data[0x01f] = 0xf
+//!     adi 1
+//! ").unwrap();
+//! let mut debugger = Debugger::new(Rim::new(program), Symbols::new());
+//! debugger.add_watchpoint(Addr::new(1, 0xf));
+//!
+//! let stop = debugger.cont().unwrap();
+//! assert_eq!(stop, Stop::Watchpoint { addr: Addr::new(1, 0xf), old: 0, new: 0xf });
+//! assert_eq!(debugger.cont().unwrap(), Stop::Halted);
+//! ```
+//!
+//! Breakpoints, watchpoints, and watches can be kept
+//! between sessions in a [`Session`] file alongside the binary, holding the
+//! checksum of the program they were set on, then a line for each:
+//!
+//! ```text
+//! checksum 9c1f0a2e4b7d3385
+//! break 0012
+//! watchpoint 01f
+//! watch mem[0x01f]
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+use crate::addr::Addr;
+use crate::error::{AsmError, LoadError, RimError, RimResult};
+use crate::symbols::Symbols;
+use crate::{Flags, Instruction, Register, Rim, Status};
+
+/// Why [`Debugger::cont`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(usize),
+    /// A watched byte changed, from `old` to `new`.
+    Watchpoint { addr: Addr, old: u8, new: u8 },
+    Halted,
+}
+
+/// One of the condition flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Sign,
+    Zero,
+    Carry,
+    Overflow,
+}
+
+impl Flag {
+    /// Whether it's set.
+    pub fn of(self, flags: Flags) -> bool {
+        match self {
+            Self::Sign => flags.sign(),
+            Self::Zero => flags.zero(),
+            Self::Carry => flags.carry(),
+            Self::Overflow => flags.overflow(),
+        }
+    }
+}
+
+/// Something the debugger shows after every step: `pc`, a register like
+/// `ra`, a byte of data memory like `mem[0x1f]`, every flag as `flags`, or
+/// one like `flags.zero`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Pc,
+    Register(Register),
+    Memory(Addr),
+    Flags,
+    Flag(Flag),
+}
+
+impl Watch {
+    pub fn parse(expr: &str) -> Option<Self> {
+        let expr = expr.trim().to_ascii_lowercase();
+        let watch = match expr.as_str() {
+            "pc" => Self::Pc,
+            "ra" => Self::Register(Register::Ra),
+            "rb" => Self::Register(Register::Rb),
+            "rc" => Self::Register(Register::Rc),
+            "rd" => Self::Register(Register::Rd),
+            "flags" => Self::Flags,
+            "flags.sign" => Self::Flag(Flag::Sign),
+            "flags.zero" => Self::Flag(Flag::Zero),
+            "flags.carry" => Self::Flag(Flag::Carry),
+            "flags.overflow" => Self::Flag(Flag::Overflow),
+            _ => {
+                let addr = expr.strip_prefix("mem[")?.strip_suffix(']')?.trim();
+                let index = match addr.strip_prefix("0x") {
+                    Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+                    None => addr.parse().ok()?,
+                };
+                Self::Memory(Addr::from_index(index)?)
+            }
+        };
+
+        Some(watch)
+    }
+
+    /// Its value on a machine as a number, with flags as their bits, and a
+    /// flag as 0 or 1.
+    pub fn value(self, rim: &Rim) -> usize {
+        let flags = rim.flags();
+        match self {
+            Self::Pc => rim.pc(),
+            Self::Register(register) => rim.registers()[register as usize] as usize,
+            Self::Memory(addr) => rim.data()[addr.index()] as usize,
+            Self::Flags => flags.to_bits() as usize,
+            Self::Flag(flag) => flag.of(flags) as usize,
+        }
+    }
+
+    /// Its value on a machine, formatted for showing.
+    pub fn eval(self, rim: &Rim) -> String {
+        let byte = |value: u8| format!("{value:#04x} ({value})");
+        let flags = rim.flags();
+        match self {
+            Self::Pc => format!("{:#05x}", rim.pc()),
+            Self::Register(register) => byte(rim.registers()[register as usize]),
+            Self::Memory(addr) => byte(rim.data()[addr.index()]),
+            Self::Flags => flags.to_string(),
+            Self::Flag(flag) => flag.of(flags).to_string(),
+        }
+    }
+}
+
+/// Formats a watch the way [`Watch::parse`] reads it.
+impl Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pc => write!(f, "pc"),
+            Self::Register(register) => write!(f, "{register}"),
+            Self::Memory(addr) => write!(f, "mem[{addr}]"),
+            Self::Flags => write!(f, "flags"),
+            Self::Flag(flag) => {
+                let name = match flag {
+                    Flag::Sign => "sign",
+                    Flag::Zero => "zero",
+                    Flag::Carry => "carry",
+                    Flag::Overflow => "overflow",
+                };
+                write!(f, "flags.{name}")
+            }
+        }
+    }
+}
+
+/// A 64-bit FNV-1a hash of a program's encoding, to tell whether it's the
+/// same program as before.
+pub fn checksum(instructions: &[Instruction]) -> u64 {
+    instructions.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &instruction| {
+        (hash ^ u8::from(instruction) as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The breakpoints, watchpoints, and watches of a debugging session, to
+/// pick up again when debugging the same program later.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// The [`checksum`] of the program they were set on.
+    pub checksum: u64,
+    pub breakpoints: BTreeSet<usize>,
+    pub watchpoints: BTreeSet<Addr>,
+    pub watches: Vec<Watch>,
+}
+
+impl Session {
+    pub fn parse(text: &str) -> RimResult<Self> {
+        let mut session = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = || LoadError::InvalidSession(i + 1);
+            let (key, value) = line.split_once(char::is_whitespace).ok_or_else(err)?;
+            let value = value.trim();
+            match key {
+                "checksum" => session.checksum = u64::from_str_radix(value, 16).map_err(|_| err())?,
+                "break" => {
+                    session.breakpoints.insert(usize::from_str_radix(value, 16).map_err(|_| err())?);
+                }
+                "watchpoint" => {
+                    let index = usize::from_str_radix(value, 16).map_err(|_| err())?;
+                    session.watchpoints.insert(Addr::from_index(index).ok_or_else(err)?);
+                }
+                "watch" => session.watches.push(Watch::parse(value).ok_or_else(err)?),
+                _ => return Err(err().into()),
+            }
+        }
+
+        Ok(session)
+    }
+
+    pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Self> {
+        let path = f.as_ref();
+        std::fs::read_to_string(path).map_err(RimError::from).and_then(|text| Self::parse(&text)).map_err(|e| e.in_file(path))
+    }
+
+    pub fn write_file<F: AsRef<Path>>(&self, f: F) -> RimResult<()> {
+        std::fs::write(&f, self.to_string()).map_err(|e| RimError::from(e).in_file(f))
+    }
+
+    /// Where the session for a binary lives: alongside it, with a `.dbg`
+    /// extension.
+    pub fn path_for<F: AsRef<Path>>(binary: F) -> PathBuf {
+        binary.as_ref().with_extension("dbg")
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "checksum {:016x}", self.checksum)?;
+        for addr in &self.breakpoints {
+            writeln!(f, "break {addr:04x}")?;
+        }
+
+        for addr in &self.watchpoints {
+            writeln!(f, "watchpoint {:03x}", addr.index())?;
+        }
+
+        for watch in &self.watches {
+            writeln!(f, "watch {watch}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    rim: Rim,
+    symbols: Symbols,
+    breakpoints: BTreeSet<usize>,
+    /// Each watchpoint, and its byte as of the last step.
+    watchpoints: BTreeMap<Addr, u8>,
+    watches: Vec<Watch>,
+    /// Of the program as it was when debugging started.
+    checksum: u64,
+}
+
+impl Debugger {
+    pub fn new(rim: Rim, symbols: Symbols) -> Self {
+        Self {
+            checksum: checksum(rim.instructions()),
+            rim,
+            symbols,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            watches: Vec::new(),
+        }
+    }
+
+    /// The breakpoints, watchpoints, and watches set so far.
+    pub fn session(&self) -> Session {
+        Session {
+            checksum: self.checksum,
+            breakpoints: self.breakpoints.clone(),
+            watchpoints: self.watchpoints().collect(),
+            watches: self.watches.clone(),
+        }
+    }
+
+    /// Adds a session's breakpoints, watchpoints, and watches, if it was
+    /// saved debugging this program, and returns whether it was.
+    /// Breakpoints saved against a different build would land on the wrong
+    /// instructions.
+    pub fn resume(&mut self, session: &Session) -> bool {
+        if session.checksum != self.checksum {
+            return false;
+        }
+
+        self.breakpoints.extend(&session.breakpoints);
+        for &addr in &session.watchpoints {
+            self.add_watchpoint(addr);
+        }
+
+        for &watch in &session.watches {
+            self.add_watch(watch);
+        }
+
+        true
+    }
+
+    pub fn rim(&self) -> &Rim {
+        &self.rim
+    }
+
+    pub fn rim_mut(&mut self) -> &mut Rim {
+        &mut self.rim
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Resolves a label, or a decimal or `0x`-prefixed hexadecimal address.
+    pub fn resolve(&self, target: &str) -> RimResult<usize> {
+        if let Some(addr) = self.symbols.addr(target) {
+            return Ok(addr);
+        }
+
+        let addr = match target.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => target.parse(),
+        };
+
+        addr.map_err(|_| AsmError::UnknownSymbol(target.to_string()).into())
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn has_breakpoint(&self, addr: usize) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    /// Returns false if there was already a breakpoint at `addr`.
+    pub fn add_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    /// Returns false if there was no breakpoint at `addr`.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = Addr> + '_ {
+        self.watchpoints.keys().copied()
+    }
+
+    /// Returns false if there was already a watchpoint at `addr`.
+    pub fn add_watchpoint(&mut self, addr: Addr) -> bool {
+        let value = self.rim.data()[addr.index()];
+        self.watchpoints.insert(addr, value).is_none()
+    }
+
+    /// Returns false if there was no watchpoint at `addr`.
+    pub fn remove_watchpoint(&mut self, addr: Addr) -> bool {
+        self.watchpoints.remove(&addr).is_some()
+    }
+
+    /// The watch list, in the order watches were added.
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    /// Returns false if `watch` was already on the watch list.
+    pub fn add_watch(&mut self, watch: Watch) -> bool {
+        if self.watches.contains(&watch) {
+            return false;
+        }
+
+        self.watches.push(watch);
+        true
+    }
+
+    /// Returns false if `watch` wasn't on the watch list.
+    pub fn remove_watch(&mut self, watch: Watch) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|&w| w != watch);
+        self.watches.len() != len
+    }
+
+    pub fn step(&mut self) -> RimResult<Status> {
+        self.rim.step()
+    }
+
+    /// Runs until the program halts, changes a watchpoint, or is about to
+    /// execute a breakpoint. Always executes at least one instruction, so
+    /// continuing from a breakpoint doesn't stop at it again.
+    pub fn cont(&mut self) -> RimResult<Stop> {
+        loop {
+            if let Some(stop) = self.step_checked()? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    /// Steps once, and returns why to stop there, if [`cont`](Self::cont)
+    /// would. A watchpoint compares its byte to what it was before the
+    /// step, so a change the host made in between doesn't count.
+    pub fn step_checked(&mut self) -> RimResult<Option<Stop>> {
+        for (addr, value) in &mut self.watchpoints {
+            *value = self.rim.data()[addr.index()];
+        }
+
+        if self.rim.step()? == Status::Halted {
+            return Ok(Some(Stop::Halted));
+        }
+
+        let data = self.rim.data();
+        if let Some((&addr, &old)) = self.watchpoints.iter().find(|(addr, &old)| data[addr.index()] != old) {
+            return Ok(Some(Stop::Watchpoint { addr, old, new: data[addr.index()] }));
+        }
+
+        let pc = self.rim.pc();
+        Ok(self.has_breakpoint(pc).then_some(Stop::Breakpoint(pc)))
+    }
+}
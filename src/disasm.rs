@@ -0,0 +1,96 @@
+//! Turns instructions back into assembler source.
+//!
+//! [`lines`] gives the disassembly as data, for a frontend to lay out and
+//! decorate however it likes; [`disassemble`] renders it as source.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::symbols::Symbols;
+use crate::Instruction;
+
+/// One instruction of a disassembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisasmLine {
+    pub addr: usize,
+    /// The instruction's encoding, which is one byte, or none if it was
+    /// built with data its opcode can't encode.
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+    /// The labels pointing at the instruction, in order of name, since any
+    /// number can.
+    pub labels: Vec<String>,
+    /// Anything to say about the instruction, written after its address.
+    pub comment: Option<String>,
+}
+
+impl DisasmLine {
+    /// The first of the instruction's labels.
+    pub fn label(&self) -> Option<&str> {
+        self.labels.first().map(String::as_str)
+    }
+}
+
+/// A line per instruction, labelled from `symbols`, and without comments.
+pub fn lines(instructions: &[Instruction], symbols: &Symbols) -> Vec<DisasmLine> {
+    instructions
+        .iter()
+        .enumerate()
+        .map(|(addr, &instruction)| DisasmLine {
+            addr,
+            bytes: instruction.encode().into_iter().collect(),
+            instruction,
+            labels: symbols.iter().filter(|&(_, a)| a == addr).map(|(label, _)| label.to_string()).collect(),
+            comment: None,
+        })
+        .collect()
+}
+
+/// Sets each line's comment to its annotation, from an
+/// [`Image`](crate::image::Image)'s annotations.
+pub fn annotate(lines: &mut [DisasmLine], annotations: &BTreeMap<usize, String>) {
+    for line in lines {
+        if let Some(annotation) = annotations.get(&line.addr) {
+            line.comment = Some(annotation.clone());
+        }
+    }
+}
+
+/// Lines as source, one instruction per line, each commented with its
+/// address and then its comment. Labels are emitted before the instructions
+/// they point to.
+pub fn render(lines: &[DisasmLine]) -> String {
+    let mut out = String::new();
+
+    for line in lines {
+        for label in &line.labels {
+            let _ = writeln!(out, "{label}:");
+        }
+
+        let _ = match &line.comment {
+            Some(comment) => writeln!(out, "    {:<16}; {:04x} {comment}", line.instruction.to_string(), line.addr),
+            None => writeln!(out, "    {:<16}; {:04x}", line.instruction.to_string(), line.addr),
+        };
+    }
+
+    out
+}
+
+/// Disassembles a program, one instruction per line, each commented with
+/// its address. Labels from `symbols` are emitted before the instructions
+/// they point to.
+pub fn disassemble(instructions: &[Instruction], symbols: &Symbols) -> String {
+    render(&lines(instructions, symbols))
+}
+
+/// Disassembles a program like [`disassemble`], with each instruction also
+/// commented with how many times it ran, from a
+/// [`Profile`](crate::profile::Profile)'s counts.
+pub fn disassemble_profiled(instructions: &[Instruction], symbols: &Symbols, counts: &[u64]) -> String {
+    let mut lines = lines(instructions, symbols);
+    for line in &mut lines {
+        line.comment = Some(format!("{:>10}", counts.get(line.addr).copied().unwrap_or(0)));
+    }
+
+    render(&lines)
+}
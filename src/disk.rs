@@ -0,0 +1,144 @@
+//! The storage device: a host-provided byte array split into sectors.
+//!
+//! The disk is extension device 0 of bank 1 (see [`Rim`](crate::Rim)'s
+//! `ext` system call), and has the following functions:
+//!
+//! | Function | Effect                                                   |
+//! |----------|----------------------------------------------------------|
+//! | 0        | Seek to the start of sector `value`                      |
+//! | 1        | Seek to offset `value` within the current sector         |
+//! | 2        | Read the byte under the head into Ra, and advance        |
+//! | 3        | Write `value` under the head, and advance                |
+//! | 4        | Set Ra to the number of sectors, saturating at 255       |
+//! | 5        | Read a sector's worth from the head into data memory from page `value`, and advance |
+//! | 6        | Write a sector's worth from data memory from page `value` under the head, and advance |
+//!
+//! Reading or writing past the end of the disk does nothing, and reads 0.
+//! Functions 5 and 6 move [`SECTOR_SIZE`] bytes at once, or up to the end
+//! of data memory, as fast as the host can copy them (see [`block`]), so
+//! a program can load a framebuffer's worth of data without a loop of
+//! single bytes.
+//!
+//! Disks can also hold boot images. A boot image starts at the beginning of
+//! a sector, and is a big-endian `u16` length followed by a program image,
+//! as produced by [`to_bytes`](crate::to_bytes). The `boot` system call
+//! loads one and jumps into it.
+
+use std::path::Path;
+
+use crate::block;
+use crate::error::{RimError, RimResult, RuntimeError};
+use crate::helper::U3;
+use crate::Instruction;
+
+pub const SECTOR_SIZE: usize = 256;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Disk {
+    bytes: Vec<u8>,
+    head: usize,
+}
+
+impl Disk {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, head: 0 }
+    }
+
+    /// A zeroed disk, `sectors` sectors long.
+    pub fn blank(sectors: usize) -> Self {
+        Self::new(vec![0; sectors * SECTOR_SIZE])
+    }
+
+    pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Self> {
+        let bytes = std::fs::read(&f).map_err(|e| RimError::from(e).in_file(f))?;
+        if bytes.len() % SECTOR_SIZE != 0 {
+            log::warn!("disk is {} bytes long, so its last sector is partial", bytes.len());
+        }
+
+        Ok(Self::new(bytes))
+    }
+
+    pub fn write_file<F: AsRef<Path>>(&self, f: F) -> RimResult<()> {
+        std::fs::write(&f, &self.bytes).map_err(|e| RimError::from(e).in_file(f))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn sectors(&self) -> usize {
+        self.bytes.len().div_ceil(SECTOR_SIZE)
+    }
+
+    /// Writes a boot image of `instructions` at the start of `sector`,
+    /// growing the disk if needed. Returns the number of sectors used.
+    pub fn write_image(&mut self, sector: u8, instructions: &[Instruction]) -> RimResult<usize> {
+        let image = crate::to_bytes(instructions)?;
+        let len = u16::try_from(image.len()).map_err(|_| RuntimeError::InvalidBootImage(sector))?;
+
+        let start = sector as usize * SECTOR_SIZE;
+        let end = start + 2 + image.len();
+        if self.bytes.len() < end {
+            self.bytes.resize(end.next_multiple_of(SECTOR_SIZE), 0);
+        }
+
+        self.bytes[start..start + 2].copy_from_slice(&len.to_be_bytes());
+        self.bytes[start + 2..end].copy_from_slice(&image);
+
+        Ok((end - start).div_ceil(SECTOR_SIZE))
+    }
+
+    /// Reads the boot image at the start of `sector`.
+    pub fn read_image(&self, sector: u8) -> RimResult<Vec<Instruction>> {
+        let start = sector as usize * SECTOR_SIZE;
+        let err = || RuntimeError::InvalidBootImage(sector);
+
+        let len = self.bytes.get(start..start + 2).ok_or_else(err)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        let image = self.bytes.get(start + 2..start + 2 + len).ok_or_else(err)?;
+
+        let rim = crate::read_bytes(image).map_err(|_| err())?;
+        Ok(rim.instructions().to_vec())
+    }
+
+    /// Fills `dest` from the head on, with 0 past the end, and advances.
+    pub(crate) fn read_block(&mut self, dest: &mut [u8]) {
+        let start = self.head.min(self.bytes.len());
+        let len = dest.len().min(self.bytes.len() - start);
+        block::copy(&mut dest[..len], &self.bytes[start..start + len]);
+        block::fill(&mut dest[len..], 0);
+        self.head += dest.len();
+    }
+
+    /// Writes `src` from the head on, dropping what's past the end, and
+    /// advances.
+    pub(crate) fn write_block(&mut self, src: &[u8]) {
+        let start = self.head.min(self.bytes.len());
+        let len = src.len().min(self.bytes.len() - start);
+        block::copy(&mut self.bytes[start..start + len], &src[..len]);
+        self.head += src.len();
+    }
+
+    pub(crate) fn io(&mut self, function: U3, value: u8) -> Option<u8> {
+        match function as u8 {
+            0 => self.head = value as usize * SECTOR_SIZE,
+            1 => self.head = self.head / SECTOR_SIZE * SECTOR_SIZE + value as usize,
+            2 => {
+                let byte = self.bytes.get(self.head).copied().unwrap_or(0);
+                self.head += 1;
+                return Some(byte);
+            }
+            3 => {
+                if let Some(byte) = self.bytes.get_mut(self.head) {
+                    *byte = value;
+                }
+
+                self.head += 1;
+            }
+            4 => return Some(self.sectors().min(255) as u8),
+            _ => {}
+        }
+
+        None
+    }
+}
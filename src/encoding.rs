@@ -0,0 +1,188 @@
+//! The authoritative instruction encoding.
+//!
+//! Every instruction is a single byte. The low three bits hold the opcode,
+//! and the remaining five bits hold the operands, laid out according to the
+//! opcode's [`Format`]:
+//!
+//! | Format | Opcodes               | Operand fields                        |
+//! |--------|-----------------------|---------------------------------------|
+//! | `Imm`  | `Adi`                 | imm: bits 3..8                        |
+//! | `Reg`  | `Add`, `Sub`          | is_id: bit 3, src: 4..6, dest: 6..8   |
+//! | `Mem`  | `Jne`, `Jg`, `Jl`     | is_ptr: bit 3, addr: 4..8             |
+//! | `Io`   | `Ioi`, `Ior`          | device: bits 3..5, function: 5..8     |
+//!
+//! Both the encoder and the decoder go through the [`Field`]s defined here,
+//! so they can never disagree. [`to_markdown`] and [`to_json`] generate the
+//! table above from them too, for tools outside the crate.
+//!
+//! Every byte decodes to an instruction that encodes back to it:
+//!
+//! ```
+//! use pact::Instruction;
+//!
+//! for b in 0..=255u8 {
+//!     assert_eq!(Instruction::decode(b).encode().ok(), Some(b));
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use crate::Opcode;
+
+/// A bitfield within an instruction byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub shift: u8,
+    pub width: u8,
+}
+
+impl Field {
+    /// The largest value this field can hold.
+    pub const fn max(self) -> u8 {
+        ((1u16 << self.width) - 1) as u8
+    }
+
+    /// The mask of this field's bits within an instruction byte.
+    pub const fn mask(self) -> u8 {
+        self.max() << self.shift
+    }
+
+    /// Extracts this field from an instruction byte.
+    pub const fn get(self, byte: u8) -> u8 {
+        (byte >> self.shift) & self.max()
+    }
+
+    /// Places a value into this field, discarding any bits that don't fit.
+    pub const fn pack(self, value: u8) -> u8 {
+        (value & self.max()) << self.shift
+    }
+
+    /// Places a value into this field, returning `None` if it doesn't fit.
+    pub const fn put(self, value: u8) -> Option<u8> {
+        if value > self.max() {
+            None
+        } else {
+            Some(value << self.shift)
+        }
+    }
+}
+
+pub const OPCODE: Field = Field { name: "opcode", shift: 0, width: 3 };
+
+pub const IMM: Field = Field { name: "imm", shift: 3, width: 5 };
+
+pub const IS_ID: Field = Field { name: "is_id", shift: 3, width: 1 };
+pub const SRC: Field = Field { name: "src", shift: 4, width: 2 };
+pub const DEST: Field = Field { name: "dest", shift: 6, width: 2 };
+
+pub const IS_PTR: Field = Field { name: "is_ptr", shift: 3, width: 1 };
+pub const ADDR: Field = Field { name: "addr", shift: 4, width: 4 };
+
+pub const DEVICE: Field = Field { name: "device", shift: 3, width: 2 };
+pub const FUNCTION: Field = Field { name: "function", shift: 5, width: 3 };
+
+/// The operand layout of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Imm,
+    Reg,
+    Mem,
+    Io,
+}
+
+impl Format {
+    /// The operand fields of this format, from lowest to highest bit.
+    pub const fn fields(self) -> &'static [Field] {
+        match self {
+            Self::Imm => &[IMM],
+            Self::Reg => &[IS_ID, SRC, DEST],
+            Self::Mem => &[IS_PTR, ADDR],
+            Self::Io => &[DEVICE, FUNCTION],
+        }
+    }
+}
+
+impl From<Opcode> for Format {
+    fn from(opcode: Opcode) -> Self {
+        match opcode {
+            Opcode::Adi => Self::Imm,
+            Opcode::Add | Opcode::Sub => Self::Reg,
+            Opcode::Jne | Opcode::Jg | Opcode::Jl => Self::Mem,
+            Opcode::Ioi | Opcode::Ior => Self::Io,
+        }
+    }
+}
+
+impl Format {
+    fn name(self) -> String {
+        format!("{self:?}").to_lowercase()
+    }
+}
+
+/// An opcode's byte from the highest bit to the lowest, with its opcode
+/// bits, and the first letter of the operand field each other bit is in,
+/// after any `is_`: `ddssi001` for `add`, and `aaaap011` for `jne`.
+pub fn pattern(opcode: Opcode) -> String {
+    let fields = Format::from(opcode).fields();
+    (0..8)
+        .rev()
+        .map(|bit| match fields.iter().find(|field| field.mask() & 1 << bit != 0) {
+            Some(field) => field.name.trim_start_matches("is_").chars().next().unwrap_or('?'),
+            None if opcode as u8 & 1 << bit != 0 => '1',
+            None => '0',
+        })
+        .collect()
+}
+
+/// The encoding table as Markdown, a row per opcode.
+pub fn to_markdown() -> String {
+    let mut out = String::from("| Opcode | Mnemonic | Format | Pattern    | Operand fields |\n");
+    out.push_str("|--------|----------|--------|------------|----------------|\n");
+
+    for opcode in Opcode::ALL {
+        let format = Format::from(opcode);
+        let fields = format
+            .fields()
+            .iter()
+            .map(|field| format!("{}: bits {}..{}", field.name, field.shift, field.shift + field.width))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let _ = writeln!(
+            out,
+            "| {:<6} | {:<8} | {:<6} | `{}` | {fields} |",
+            format!("{:03b}", opcode as u8),
+            format!("{opcode:?}").to_lowercase(),
+            format.name(),
+            pattern(opcode),
+        );
+    }
+
+    out
+}
+
+/// The encoding table as JSON: the opcode field, then an object per
+/// opcode with its value, mnemonic, format, [`pattern`], and operand
+/// fields, each with its `name`, `shift`, and `width`.
+pub fn to_json() -> String {
+    let field = |field: &Field| format!("{{\"name\": \"{}\", \"shift\": {}, \"width\": {}}}", field.name, field.shift, field.width);
+    let mut json = format!("{{\n  \"opcode\": {},\n  \"opcodes\": [", field(&OPCODE));
+
+    for (i, opcode) in Opcode::ALL.into_iter().enumerate() {
+        let format = Format::from(opcode);
+        let fields = format.fields().iter().map(field).collect::<Vec<_>>().join(", ");
+        let _ = write!(
+            json,
+            "{}\n    {{\"value\": {}, \"mnemonic\": \"{}\", \"format\": \"{}\", \"pattern\": \"{}\", \"fields\": [{fields}]}}",
+            if i == 0 { "" } else { "," },
+            opcode as u8,
+            format!("{opcode:?}").to_lowercase(),
+            format.name(),
+            pattern(opcode),
+        );
+    }
+
+    json.push_str("\n  ]\n}\n");
+    json
+}
@@ -1,24 +1,304 @@
+//! Errors, split by where they come from: loading programs and files,
+//! assembling and encoding code, and running it.
+//!
+//! [`RimError`] covers all of them, so most functions return
+//! [`RimResult`], but each kind is its own `#[non_exhaustive]` enum, to
+//! match on precisely:
+//!
+//! ```no_run
+//! use pact::error::{RimError, RuntimeError};
+//!
+//! let mut rim = pact::read_file("program.rim").unwrap();
+//! match rim.run() {
+//!     Ok(()) => println!("halted"),
+//!     Err(RimError::Runtime(RuntimeError::Overflow(pc))) => println!("overflowed at {pc:#05x}"),
+//!     Err(e) => println!("{e}"),
+//! }
+//! ```
+
 use std::error::Error;
 use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::Instruction;
 
 pub type RimResult<T> = Result<T, RimError>;
 
 #[derive(Debug)]
 pub enum RimError {
-    InvalidMagic,
+    Load(LoadError),
+    Asm(AsmError),
+    Runtime(RuntimeError),
     IoError(std::io::Error),
+    /// A [host device](crate::host) failed, performing a function at pc.
+    Device { device: usize, function: u8, pc: usize, source: crate::host::DeviceError },
+    /// Another error, reading or writing a file.
+    File { path: PathBuf, error: Box<RimError> },
+}
+
+/// A program, image, or other file that can't be read or used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoadError {
+    InvalidMagic,
+    /// The tag of the section that's wrong, and the offset of its header in
+    /// the decompressed image, or of where it should have been.
+    InvalidSection { tag: u8, offset: usize },
+    UnsupportedCompression(&'static str),
+    UnsupportedIsa(u8),
+    ImageTooLarge,
+    /// The program's length, in instructions.
+    ProgramTooLarge(usize),
+    /// The line of a symbol file that's wrong.
+    InvalidSymbols(usize),
+    /// The line of an expectation file that's wrong.
+    InvalidExpectation(usize),
+    /// The line of a debugger session file that's wrong.
+    InvalidSession(usize),
+    InvalidTrace,
+    /// The version of a save state newer than this version of pact reads.
+    UnsupportedState(u8),
+    /// Something about a program over a host's [`LoadLimits`], like
+    /// `"program length"`, how much of it there is, and the limit.
+    ///
+    /// [`LoadLimits`]: crate::image::LoadLimits
+    OverLimit { limit: &'static str, value: usize, max: usize },
+    /// The tag of the [package](crate::package) entry that's wrong, and the
+    /// offset of its header, or of where it should have been.
+    InvalidPackage { tag: u8, offset: usize },
+}
+
+/// Code that can't be assembled, compiled, or encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AsmError {
+    /// A mistake in the source, at a line counting from 1.
+    Source { line: usize, message: String },
+    UnknownSymbol(String),
+    InvalidInstruction(Instruction),
+    ImmediateOutOfRange(u8),
+}
+
+/// Something a running program did wrong. Each has a code a fault handler
+/// sees (see [`RuntimeError::fault_code`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RuntimeError {
+    /// A jump's target, and where it jumped from.
+    JumpOutOfRange { target: usize, pc: usize },
+    Overflow(usize),
+    DivisionByZero(usize),
+    DeviceDenied { device: usize, pc: usize },
+    NoDisk,
+    NoSuchProgram(usize),
+    /// The sector with no image.
+    InvalidBootImage(u8),
+    InvalidRegister { register: u8, pc: usize },
+    /// An address past the end of the machine's memory (see
+    /// [`Rim::set_memory_len`](crate::Rim::set_memory_len)), and where it
+    /// was used.
+    MemoryOutOfRange { addr: usize, pc: usize },
 }
 
 impl Display for RimError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::InvalidMagic => write!(f, "Invalid magic bytes at start of file"),
+            Self::Load(e) => e.fmt(f),
+            Self::Asm(e) => e.fmt(f),
+            Self::Runtime(e) => e.fmt(f),
             Self::IoError(e) => e.fmt(f),
+            Self::Device { device, function, pc, .. } => {
+                write!(f, "{} failed at function {function}, called at {pc:#05x}", crate::image::device_name(*device))
+            }
+            Self::File { path, .. } => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "Invalid magic bytes at start of file"),
+            Self::InvalidSection { tag, offset } => {
+                write!(f, "Image section {tag} at byte {offset} is missing, truncated, or too long")
+            }
+            Self::UnsupportedCompression(kind) => write!(f, "Image is {kind}-compressed, but pact was built without the `{kind}` feature"),
+            Self::UnsupportedIsa(level) => write!(f, "Image needs instruction set v{level}, but pact only supports up to {}", crate::isa::IsaLevel::LATEST),
+            Self::ImageTooLarge => write!(f, "Image decompresses to over 1 MiB"),
+            Self::ProgramTooLarge(len) => write!(f, "Program is {len} instructions long, but at most 4096 are addressable"),
+            Self::InvalidSymbols(line) => write!(f, "Invalid symbol on line {line}"),
+            Self::InvalidExpectation(line) => write!(f, "Invalid expectation on line {line}"),
+            Self::InvalidSession(line) => write!(f, "Invalid debugger session on line {line}"),
+            Self::InvalidTrace => write!(f, "Trace is truncated or corrupt, or has no such step"),
+            Self::UnsupportedState(version) => write!(f, "Save state is format v{version}, but pact only reads up to v{}", crate::state::VERSION),
+            Self::OverLimit { limit, value, max } => write!(f, "The {limit} is {value}, over the limit of {max}"),
+            Self::InvalidPackage { tag, offset } => {
+                write!(f, "Package entry {tag} at byte {offset} is missing, truncated, or invalid")
+            }
+        }
+    }
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Source { line, message } => write!(f, "Line {line}: {message}"),
+            Self::UnknownSymbol(label) => write!(f, "Unknown symbol `{label}`"),
+            Self::InvalidInstruction(i) => write!(f, "Instruction cannot be encoded: {i:?}"),
+            Self::ImmediateOutOfRange(imm) => write!(f, "Immediate {imm} doesn't fit in 5 bits"),
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::JumpOutOfRange { target, pc } => write!(f, "Jumped to {target:#05x} from {pc:#05x}, past the end of the program"),
+            Self::Overflow(pc) => write!(f, "Arithmetic overflow at {pc:#05x}"),
+            Self::DivisionByZero(pc) => write!(f, "Division by zero at {pc:#05x}"),
+            Self::DeviceDenied { device, pc } => {
+                write!(f, "Access to {} denied at {pc:#05x}", crate::image::device_name(*device))
+            }
+            Self::NoDisk => write!(f, "No disk attached"),
+            Self::NoSuchProgram(slot) => write!(f, "No program loaded in slot {slot}"),
+            Self::InvalidBootImage(sector) => write!(f, "No valid boot image at sector {sector}"),
+            Self::InvalidRegister { register, pc } => write!(f, "No register {register}, selected at {pc:#05x}"),
+            Self::MemoryOutOfRange { addr, pc } => write!(f, "Accessed {addr:#05x} at {pc:#05x}, past the end of memory"),
+        }
+    }
+}
+
+impl RimError {
+    /// A short, stable name for the kind of error, for counting and logging.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Load(e) => e.kind(),
+            Self::Asm(e) => e.kind(),
+            Self::Runtime(e) => e.kind(),
+            Self::IoError(_) => "io",
+            Self::Device { .. } => "device",
+            Self::File { error, .. } => error.kind(),
+        }
+    }
+
+    /// Says which file this error happened with.
+    pub fn in_file(self, path: impl AsRef<Path>) -> Self {
+        Self::File { path: path.as_ref().to_path_buf(), error: Box::new(self) }
+    }
+
+    /// The error underneath any files it happened with.
+    pub fn innermost(&self) -> &Self {
+        match self {
+            Self::File { error, .. } => error.innermost(),
+            e => e,
+        }
+    }
+
+    /// The code a fault handler sees for this error, if it's a fault the
+    /// program caused and can recover from.
+    pub fn fault_code(&self) -> Option<u8> {
+        match self {
+            Self::Runtime(e) => Some(e.fault_code()),
+            _ => None,
+        }
+    }
+}
+
+impl LoadError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidMagic => "invalid_magic",
+            Self::InvalidSection { .. } => "invalid_section",
+            Self::UnsupportedCompression(_) => "unsupported_compression",
+            Self::UnsupportedIsa(_) => "unsupported_isa",
+            Self::ImageTooLarge => "image_too_large",
+            Self::ProgramTooLarge(_) => "program_too_large",
+            Self::InvalidSymbols(_) => "invalid_symbols",
+            Self::InvalidExpectation(_) => "invalid_expectation",
+            Self::InvalidSession(_) => "invalid_session",
+            Self::InvalidTrace => "invalid_trace",
+            Self::UnsupportedState(_) => "unsupported_state",
+            Self::OverLimit { .. } => "over_limit",
+            Self::InvalidPackage { .. } => "invalid_package",
+        }
+    }
+}
+
+impl AsmError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Source { .. } => "asm",
+            Self::UnknownSymbol(_) => "unknown_symbol",
+            Self::InvalidInstruction(_) => "invalid_instruction",
+            Self::ImmediateOutOfRange(_) => "immediate_out_of_range",
         }
     }
 }
 
-impl Error for RimError {}
+impl RuntimeError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::JumpOutOfRange { .. } => "jump_out_of_range",
+            Self::Overflow(_) => "overflow",
+            Self::DivisionByZero(_) => "division_by_zero",
+            Self::DeviceDenied { .. } => "device_denied",
+            Self::NoDisk => "no_disk",
+            Self::NoSuchProgram(_) => "no_such_program",
+            Self::InvalidBootImage(_) => "invalid_boot_image",
+            Self::InvalidRegister { .. } => "invalid_register",
+            Self::MemoryOutOfRange { .. } => "memory_out_of_range",
+        }
+    }
+
+    pub fn fault_code(&self) -> u8 {
+        match self {
+            Self::JumpOutOfRange { .. } => 1,
+            Self::Overflow(_) => 2,
+            Self::DivisionByZero(_) => 3,
+            Self::DeviceDenied { .. } => 4,
+            Self::NoDisk => 5,
+            Self::NoSuchProgram(_) => 6,
+            Self::InvalidBootImage(_) => 7,
+            Self::InvalidRegister { .. } => 8,
+            Self::MemoryOutOfRange { .. } => 9,
+        }
+    }
+}
+
+impl Error for RimError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Load(e) => e.source(),
+            Self::Asm(e) => e.source(),
+            Self::Runtime(e) => e.source(),
+            Self::IoError(e) => e.source(),
+            Self::Device { source, .. } => Some(source.as_ref()),
+            Self::File { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+impl Error for LoadError {}
+impl Error for AsmError {}
+impl Error for RuntimeError {}
+
+impl From<LoadError> for RimError {
+    fn from(e: LoadError) -> Self {
+        Self::Load(e)
+    }
+}
+
+impl From<AsmError> for RimError {
+    fn from(e: AsmError) -> Self {
+        Self::Asm(e)
+    }
+}
+
+impl From<RuntimeError> for RimError {
+    fn from(e: RuntimeError) -> Self {
+        Self::Runtime(e)
+    }
+}
 
 impl From<std::io::Error> for RimError {
     fn from(e: std::io::Error) -> Self {
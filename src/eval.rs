@@ -0,0 +1,104 @@
+//! One-call evaluation of assembler source, for notebooks and teaching
+//! material where setting up a [`Rim`] by hand gets in the way.
+//!
+//! ```
+//! use pact::eval::{eval, Inputs};
+//!
+//! let inputs = Inputs { registers: [0, 3, 0, 0], ..Default::default() };
+//! let state = eval("add rb, ra\nadd rb, ra\nioi cpu, 0", inputs).unwrap();
+//! assert_eq!(state.snapshot.registers[0], 6);
+//!
+//! // Keys come from the inputs, and what's written to the screen comes
+//! // back, rather than either going through the notebook's terminal.
+//! let inputs = Inputs { keys: b"hi".to_vec(), ..Default::default() };
+//! let state = eval("ioi kbd, 0\nioi scr, 2\nioi kbd, 0\nioi scr, 2", inputs).unwrap();
+//! assert_eq!(state.output, "hi");
+//! ```
+
+use std::fmt;
+
+use crate::asm::assemble;
+use crate::console::{Buffer, Console};
+use crate::error::RimResult;
+use crate::{Rim, Snapshot, Status};
+
+/// The most steps a program may take by default.
+pub const MAX_STEPS: usize = 100_000;
+
+/// The machine's starting state, and how long it may run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inputs {
+    pub registers: [u8; 4],
+    /// Written to the start of data memory.
+    pub data: Vec<u8>,
+    /// Read by the keyboard in order, after which it reads 0.
+    pub keys: Vec<u8>,
+    pub max_steps: usize,
+}
+
+impl Default for Inputs {
+    fn default() -> Self {
+        Self {
+            registers: [0; 4],
+            data: Vec::new(),
+            keys: Vec::new(),
+            max_steps: MAX_STEPS,
+        }
+    }
+}
+
+/// The machine after a program stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineState {
+    pub snapshot: Snapshot,
+    pub data: Vec<u8>,
+    /// What the program wrote to the screen, with invalid UTF-8 replaced.
+    pub output: String,
+    pub steps: usize,
+    /// False if the program ran out of steps instead.
+    pub halted: bool,
+}
+
+impl fmt::Display for MachineState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let how = if self.halted { "halted" } else { "stopped" };
+        write!(f, "{} ({how} after {} steps)", self.snapshot, self.steps)
+    }
+}
+
+/// Assembles `source` and runs it from `inputs`, returning the final state.
+/// Assembly errors and faults are returned as errors.
+///
+/// The machine has a console of its own, so it never reads the process's
+/// stdin or writes to its stdout.
+pub fn eval(source: &str, inputs: Inputs) -> RimResult<MachineState> {
+    let mut rim = Rim::new(assemble(source)?);
+    rim.set_console(Console::Buffer(Buffer::new(inputs.keys)));
+    rim.set_registers(inputs.registers);
+    for (byte, input) in rim.data_mut().iter_mut().zip(&inputs.data) {
+        *byte = *input;
+    }
+
+    let mut steps = 0;
+    let mut halted = false;
+    while steps < inputs.max_steps {
+        steps += 1;
+        if rim.step()? == Status::Halted {
+            halted = true;
+            break;
+        }
+    }
+
+    let output = match rim.console_mut() {
+        Console::Buffer(buffer) => buffer.take_output(),
+        _ => unreachable!("the console was set to a buffer"),
+    };
+
+    Ok(MachineState {
+        snapshot: rim.snapshot(),
+        data: rim.data().to_vec(),
+        output: String::from_utf8_lossy(&output).into_owned(),
+        steps,
+        halted,
+    })
+}
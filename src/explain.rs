@@ -0,0 +1,181 @@
+//! Field-by-field breakdowns of instruction bytes, for learning the
+//! encoding.
+//!
+//! ```
+//! let explanation = pact::explain::explain(0b11010110);
+//! assert_eq!(explanation.instruction.to_string(), "ioi scr, 6");
+//! ```
+
+use std::fmt;
+
+use crate::asm::{self, Assembler};
+use crate::encoding::{self, Field, Format};
+use crate::error::{AsmError, RimResult};
+use crate::{Device, Instruction, InstructionData, Opcode, Register};
+
+/// One field of an instruction byte, with what its value means.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldValue {
+    pub field: Field,
+    pub value: u8,
+    /// The value as the assembler would write it, like a register or
+    /// device name.
+    pub meaning: String,
+}
+
+/// An instruction byte taken apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub byte: u8,
+    pub instruction: Instruction,
+    /// The opcode, then the operands, from lowest to highest bit.
+    pub fields: Vec<FieldValue>,
+    /// What the instruction does, in a sentence or two.
+    pub summary: String,
+}
+
+/// Takes apart an instruction byte.
+pub fn explain(byte: u8) -> Explanation {
+    let instruction = Instruction::decode(byte);
+    let opcode = instruction.0;
+
+    let mut fields = vec![FieldValue {
+        field: encoding::OPCODE,
+        value: encoding::OPCODE.get(byte),
+        meaning: mnemonic(opcode),
+    }];
+
+    for &field in Format::from(opcode).fields() {
+        let value = field.get(byte);
+        let meaning = match field.name {
+            "src" | "dest" => Register::from(value).to_string(),
+            "device" => Device::from(value).to_string(),
+            "is_id" | "is_ptr" => (value != 0).to_string(),
+            _ => value.to_string(),
+        };
+
+        fields.push(FieldValue { field, value, meaning });
+    }
+
+    Explanation {
+        byte,
+        instruction,
+        fields,
+        summary: summary(instruction),
+    }
+}
+
+/// Parses what to explain: a byte as a number, in binary, hex, or decimal,
+/// or a single instruction in assembler syntax.
+pub fn parse(input: &str) -> RimResult<u8> {
+    let input = input.trim();
+    if input.starts_with(|c: char| c.is_ascii_digit()) {
+        return asm::parse_number(input).map_err(|message| AsmError::Source { line: 1, message }.into());
+    }
+
+    match Assembler::new().assemble(input)?.as_slice() {
+        &[instruction] => instruction.encode(),
+        instructions => Err(AsmError::Source {
+            line: 1,
+            message: format!("`{input}` assembles to {} instructions, not one", instructions.len()),
+        }
+        .into()),
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:#010b} ({:#04x}): {}", self.byte, self.byte, self.instruction)?;
+
+        for FieldValue { field, value, meaning } in &self.fields {
+            let bits = format!("{}..{}", field.shift, field.shift + field.width);
+            let value = format!("{value:0width$b}", width = field.width as usize);
+            writeln!(f, "  bits {bits:<5} {:<9} {value:>5}  {meaning}", field.name)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{}", self.summary)
+    }
+}
+
+fn mnemonic(opcode: Opcode) -> String {
+    format!("{opcode:?}").to_lowercase()
+}
+
+/// What an instruction does. Device functions are described as `ioi` runs
+/// them, on Ra; `ior` passes a register instead.
+fn summary(instruction: Instruction) -> String {
+    match (instruction.0, instruction.1) {
+        (Opcode::Adi, InstructionData::Imm(imm)) => {
+            format!("Adds {imm} to Ra, setting sign, zero, and carry.")
+        }
+        (opcode @ (Opcode::Add | Opcode::Sub), InstructionData::Reg { is_id, src, dest }) => {
+            let (src, dest) = match is_id {
+                true => (format!("the register {src} names"), format!("the register {dest} names")),
+                false => (src.to_string(), dest.to_string()),
+            };
+
+            match opcode {
+                Opcode::Add => format!("Adds {src} to {dest}, setting sign, zero, and carry."),
+                _ => format!("Subtracts {src} from {dest}, setting sign, zero, and carry as borrow."),
+            }
+        }
+        (opcode, InstructionData::Mem { is_ptr, addr }) => {
+            let condition = match opcode {
+                Opcode::Jne => "zero is clear",
+                Opcode::Jg => "sign and zero are clear",
+                _ => "sign is set",
+            };
+            let target = match is_ptr {
+                true => format!("the offset stored at offset {addr} of the page Rd selects"),
+                false => format!("offset {addr} of the page Rd selects"),
+            };
+
+            format!("Jumps to {target} if {condition}, or if carry is set after system call 3.")
+        }
+        (opcode, InstructionData::Io { device, function }) => {
+            let effect = io_summary(device, function as u8);
+            match opcode {
+                Opcode::Ior => format!("{effect} Takes the register Ra selects in place of Ra."),
+                _ => effect.to_string(),
+            }
+        }
+        _ => "Has operands its opcode can't encode.".to_string(),
+    }
+}
+
+fn io_summary(device: Device, function: u8) -> &'static str {
+    match (device, function) {
+        (Device::Cpu, 0) => "Halts the machine.",
+        (Device::Cpu, 1) => "Does nothing.",
+        (Device::Cpu, 2) => "Sets Ra to 0.",
+        (Device::Cpu, 3) => "Loads the byte at offset Ra of the page Rd selects into Ra.",
+        (Device::Cpu, 4) => "Stores Ra at the offset Ra holds in the page Rd selects.",
+        (Device::Cpu, 5) => "Loads through the pointer at offset Ra of the page Rd selects into Ra.",
+        (Device::Cpu, 6) => "Stores Ra through the pointer at offset Ra of the page Rd selects.",
+        (Device::Cpu, _) => "Makes the system call Ra selects, with its argument in Rb.",
+        (Device::Kbd, 0) => "Reads a key into Ra, or 0 if none is waiting.",
+        (Device::Kbd, 1) => "Sets Ra to whether a key is waiting.",
+        (Device::Kbd, 2) => "Takes the next mouse event, reading its button into Ra, or 0 if none is waiting.",
+        (Device::Kbd, 3) => "Reads the row of the last mouse event into Ra.",
+        (Device::Kbd, 4) => "Reads the column of the last mouse event into Ra.",
+        (Device::Kbd, 5) => "Sets Ra to whether a mouse event is waiting.",
+        (Device::Kbd, _) => "Reserved; does nothing.",
+        (Device::Scr, 0) => "Moves the cursor to row Ra.",
+        (Device::Scr, 1) => "Moves the cursor to column Ra.",
+        (Device::Scr, 2) => "Writes the character Ra at the cursor.",
+        (Device::Scr, 3) => "Reads the character at the cursor into Ra, or 0 if the cell is blank.",
+        (Device::Scr, 4) => "Reserved for reading attributes; sets Ra to 0.",
+        (Device::Scr, 5) => "Clears the screen.",
+        (Device::Scr, 6) => "Presents the screen, unless it presents itself immediately.",
+        (Device::Scr, _) => "Waits for the next frame, presenting the screen if it changed.",
+        (Device::Mth, 0) => "Multiplies Ra by the register Ra selects, with the low byte in Ra and the high in Rb.",
+        (Device::Mth, 1) => "Divides Ra by the register Ra selects, faulting on zero.",
+        (Device::Mth, 2) => "Sets Ra to Ra and the register Ra selects.",
+        (Device::Mth, 3) => "Sets Ra to Ra or the register Ra selects.",
+        (Device::Mth, 4) => "Sets Ra to Ra xor the register Ra selects.",
+        (Device::Mth, 5) => "Inverts the bits of Ra.",
+        (Device::Mth, 6) => "Reads the flags into Ra.",
+        (Device::Mth, _) => "Sets the flags from Ra.",
+    }
+}
@@ -0,0 +1,97 @@
+//! Enumerating where a program can get to under different input.
+//!
+//! Exploring runs a fork of a machine, and every time the program reads a
+//! key, forks it again for each key it could be given. Each run ends when
+//! the program halts or faults, or runs out of steps, so the number of runs
+//! grows with the number of keys to the power of the number of reads.
+//!
+//! ```no_run
+//! use pact::grade::Outcome;
+//!
+//! let program = pact::asm::assemble("ioi kbd, 0\nioi cpu, 0").unwrap();
+//! let rim = pact::Rim::new(program);
+//!
+//! for leaf in rim.explore(1000, b"yn") {
+//!     if matches!(leaf.outcome, Outcome::Halted) {
+//!         println!("{:?} -> {}", leaf.inputs, leaf.rim.snapshot());
+//!     }
+//! }
+//! ```
+
+use crate::console::{Buffer, Console};
+use crate::grade::Outcome;
+use crate::{Device, InstructionData, Rim, Status};
+
+/// Where one run of an exploration ended.
+pub struct Leaf {
+    /// The keys the program read, in order.
+    pub inputs: Vec<u8>,
+    /// The machine at the end of the run.
+    pub rim: Rim,
+    /// Never [`Outcome::OutOfTime`].
+    pub outcome: Outcome,
+}
+
+impl Rim {
+    /// Explores the runs of up to `depth` steps from here, trying each of
+    /// `keys` wherever the program reads one. The program's console is
+    /// replaced with an empty buffer, so if `keys` is empty, reads get 0.
+    /// Runs are returned depth-first, in the order of `keys`.
+    pub fn explore(&self, depth: usize, keys: &[u8]) -> Vec<Leaf> {
+        let mut root = self.fork();
+        root.set_console(Console::Buffer(Buffer::default()));
+
+        let mut leaves = Vec::new();
+        // Each run, with the keys it read, its steps, and whether the next
+        // read has a key waiting for it.
+        let mut stack = vec![(root, Vec::new(), 0, false)];
+
+        while let Some((mut rim, inputs, mut steps, injected)) = stack.pop() {
+            if !injected && !keys.is_empty() && rim.reads_key() {
+                for &key in keys.iter().rev() {
+                    let mut fork = rim.fork();
+                    if let Console::Buffer(buffer) = fork.console_mut() {
+                        buffer.push_input(&[key]);
+                    }
+
+                    let mut inputs = inputs.clone();
+                    inputs.push(key);
+                    stack.push((fork, inputs, steps, true));
+                }
+
+                continue;
+            }
+
+            let outcome = loop {
+                if steps == depth {
+                    break Some(Outcome::OutOfSteps);
+                }
+
+                steps += 1;
+                match rim.step() {
+                    Ok(Status::Halted) => break Some(Outcome::Halted),
+                    Ok(Status::Running) if !keys.is_empty() && rim.reads_key() => break None,
+                    Ok(Status::Running) => {}
+                    Err(e) => break Some(Outcome::Faulted(e)),
+                }
+            };
+
+            match outcome {
+                Some(outcome) => leaves.push(Leaf { inputs, rim, outcome }),
+                None => stack.push((rim, inputs, steps, false)),
+            }
+        }
+
+        leaves
+    }
+
+    /// Whether the next instruction reads a key.
+    fn reads_key(&self) -> bool {
+        self.bank.is_none()
+            && self.opcode_page.is_none()
+            && matches!(
+                self.next_instruction().map(|instruction| instruction.1),
+                Some(InstructionData::Io { device: Device::Kbd, function }) if function as u8 == 0
+            )
+    }
+}
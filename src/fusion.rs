@@ -0,0 +1,210 @@
+//! Superinstructions: runs of instructions that [`Rim::burst`] executes in
+//! one go.
+//!
+//! Each step of the interpreter fetches an instruction, checks for an
+//! extended opcode page, and dispatches through the microcode table. A
+//! burst instead splits the program into basic blocks, each a run of `adi`,
+//! `add`, and `sub` ending at a jump or before an I/O instruction, and
+//! executes a block with a tight loop calling each instruction's semantics
+//! directly. The result is exactly what stepping through the block would
+//! do, faults included, and counts a step per instruction; only the
+//! dispatch in between is skipped. I/O instructions run by a step as
+//! usual.
+//!
+//! A block is cached by the address it starts at, since a jump can enter
+//! a run of instructions partway through, for the same program: blocks are
+//! found when a burst first runs a program, and again whenever it changes,
+//! unless a [`ProgramCache`](crate::cache::ProgramCache) found them already.
+//!
+//! Which instructions can share a block is a [`Fusion`], a set of pairs:
+//! a block only goes on from one instruction to the next if the pair is in
+//! it. The default holds the pairs most executed in profiles of compiled
+//! Brainfuck, where `sub` then `add` (copying a register) and runs of `adi`
+//! account for nearly a third of all steps; [`Fusion::from_profile`] picks
+//! them for a particular program instead, and [`Fusion::ALL`] puts every
+//! run in one block.
+//!
+//! Jumps are resolved ahead of time too. Where the
+//! [`analysis`](crate::analysis) finds that Rd can only hold one value at
+//! a non-pointer jump, the table keeps the jump's target, once it's checked
+//! to be in the program, and a burst jumps straight to it whenever Rd does
+//! hold that value, falling back to the usual decoding when it doesn't.
+//!
+//! Nothing is fused or resolved under the von Neumann architecture, where a
+//! program can rewrite the instruction after the one executing, or once any
+//! microcode is replaced.
+
+use std::sync::{Arc, Weak};
+
+use crate::analysis::analyze;
+use crate::error::RimResult;
+use crate::microcode;
+use crate::profile::Profile;
+use crate::{Instruction, Opcode, Rim, Status};
+
+/// A set of pairs of opcodes that can share a block, the first followed by
+/// the second. Only pairs that [`Fusion::can_fuse`] ever do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fusion(u64);
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Fusion {
+    pub const NONE: Self = Self(0);
+
+    pub const DEFAULT: Self = Self::NONE
+        .with(Opcode::Sub, Opcode::Add)
+        .with(Opcode::Adi, Opcode::Adi)
+        .with(Opcode::Adi, Opcode::Sub)
+        .with(Opcode::Add, Opcode::Sub)
+        .with(Opcode::Adi, Opcode::Add)
+        .with(Opcode::Sub, Opcode::Sub)
+        .with(Opcode::Add, Opcode::Jne)
+        .with(Opcode::Sub, Opcode::Jne)
+        .with(Opcode::Sub, Opcode::Jg)
+        .with(Opcode::Sub, Opcode::Jl);
+
+    /// Every pair that can be fused.
+    pub const ALL: Self = {
+        let mut fusion = Self::NONE;
+        let mut pair = 0;
+        while pair < 64 {
+            let (first, second) = (Opcode::ALL[pair / 8], Opcode::ALL[pair % 8]);
+            if Self::can_fuse(first, second) {
+                fusion = fusion.with(first, second);
+            }
+            pair += 1;
+        }
+        fusion
+    };
+
+    /// Whether two opcodes can be fused at all: the first must be `adi`,
+    /// `add`, or `sub`, which always move on to the next instruction, and
+    /// the second one of those or a jump. Neither can do I/O.
+    pub const fn can_fuse(first: Opcode, second: Opcode) -> bool {
+        matches!(first, Opcode::Adi | Opcode::Add | Opcode::Sub) && !matches!(second, Opcode::Ioi | Opcode::Ior)
+    }
+
+    pub const fn with(self, first: Opcode, second: Opcode) -> Self {
+        if Self::can_fuse(first, second) {
+            Self(self.0 | 1 << (first as u64 * 8 + second as u64))
+        } else {
+            self
+        }
+    }
+
+    pub const fn contains(self, first: Opcode, second: Opcode) -> bool {
+        self.0 & 1 << (first as u64 * 8 + second as u64) != 0
+    }
+
+    /// The pairs that account for at least `min_share` (from 0 to 1) of the
+    /// steps a profile counted in a program, so that the pairs a program
+    /// actually runs are fused, whatever they are.
+    pub fn from_profile(instructions: &[Instruction], profile: &Profile, slot: usize, min_share: f64) -> Self {
+        let counts = profile.counts(slot);
+        let mut pairs = [0u64; 64];
+        for (pc, pair) in instructions.windows(2).enumerate() {
+            // The second instruction runs at most as often as the first.
+            let count = counts.get(pc).copied().unwrap_or(0).min(counts.get(pc + 1).copied().unwrap_or(0));
+            pairs[pair[0].0 as usize * 8 + pair[1].0 as usize] += count;
+        }
+
+        let total = profile.total().max(1) as f64;
+        (0..64)
+            .filter(|&pair| pairs[pair] as f64 / total >= min_share)
+            .fold(Self::NONE, |fusion, pair| fusion.with(Opcode::ALL[pair / 8], Opcode::ALL[pair % 8]))
+    }
+}
+
+/// The blocks in a program, and where its jumps go.
+#[derive(Debug, Clone)]
+pub(crate) struct Table {
+    slot: usize,
+    /// Doesn't keep the program alive, and stops matching once it's
+    /// changed, since changing a shared program copies it.
+    program: Weak<Vec<Instruction>>,
+    fusion: Fusion,
+    /// The length of the block starting at each address, or 0 if the
+    /// instruction there has to be stepped.
+    blocks: Vec<u16>,
+    /// The value Rd holds at each jump with a static target, and that target.
+    targets: Vec<Option<(u8, usize)>>,
+}
+
+impl Table {
+    pub(crate) fn build(slot: usize, program: &Arc<Vec<Instruction>>, fusion: Fusion) -> Self {
+        // Each block is one longer than the one after it, where it goes on.
+        let mut blocks = vec![0u16; program.len()];
+        for pc in (0..program.len()).rev() {
+            let opcode = program[pc].0;
+            blocks[pc] = match program.get(pc + 1) {
+                _ if matches!(opcode, Opcode::Ioi | Opcode::Ior) => 0,
+                Some(next) if fusion.contains(opcode, next.0) => blocks[pc + 1] + 1,
+                _ => 1,
+            };
+        }
+
+        let analysis = analyze(program);
+        let targets = program
+            .iter()
+            .enumerate()
+            .map(|(pc, &instruction)| analysis.static_target(pc, instruction).filter(|&(_, target)| target < program.len()))
+            .collect();
+
+        Self { slot, program: Arc::downgrade(program), fusion, blocks, targets }
+    }
+
+    fn matches(&self, slot: usize, program: &Arc<Vec<Instruction>>, fusion: Fusion) -> bool {
+        self.slot == slot && self.fusion == fusion && std::ptr::eq(self.program.as_ptr(), Arc::as_ptr(program))
+    }
+
+    /// The length of the block starting at `pc`.
+    pub(crate) fn block(&self, pc: usize) -> usize {
+        self.blocks.get(pc).copied().unwrap_or(0) as usize
+    }
+
+    /// The target of the jump at `pc`, if it was resolved for `rd`.
+    pub(crate) fn target(&self, pc: usize, rd: u8) -> Option<usize> {
+        match self.targets.get(pc) {
+            Some(&Some((page, target))) if page == rd => Some(target),
+            _ => None,
+        }
+    }
+}
+
+/// The table for the executing program, building it if it's out of date.
+pub(crate) fn table(rim: &mut Rim) -> &Table {
+    let program = &rim.programs[rim.current];
+    if !rim.fused.as_ref().is_some_and(|table| table.matches(rim.current, program, rim.fusion)) {
+        rim.fused = Some(Arc::new(Table::build(rim.current, program, rim.fusion)));
+    }
+
+    rim.fused.as_ref().unwrap()
+}
+
+/// Executes an instruction in a block with its standard semantics, once pc
+/// has moved past it. Only called once
+/// [`table`] is up to date.
+#[inline(always)]
+pub(crate) fn execute(rim: &mut Rim, instruction: Instruction) -> RimResult<Status> {
+    let data = instruction.1;
+    match instruction.0 {
+        Opcode::Adi => microcode::adi(rim, data),
+        Opcode::Add => microcode::add(rim, data),
+        Opcode::Sub => microcode::sub(rim, data),
+        opcode @ (Opcode::Jne | Opcode::Jg | Opcode::Jl) => {
+            let resolved = rim.fused.as_ref().and_then(|table| table.target(rim.pc - 1, rim.registers[3]));
+            match (resolved, opcode) {
+                (Some(target), _) => Ok(microcode::jump_resolved(rim, opcode, target)),
+                (None, Opcode::Jne) => microcode::jne(rim, data),
+                (None, Opcode::Jg) => microcode::jg(rim, data),
+                (None, _) => microcode::jl(rim, data),
+            }
+        }
+        Opcode::Ioi | Opcode::Ior => unreachable!("I/O is never in a block"),
+    }
+}
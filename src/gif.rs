@@ -0,0 +1,386 @@
+//! Recording the screen as an animated GIF, with the `gif` feature.
+//!
+//! Each frame is the character grid, drawn with a built-in 5x7 font in 8x8
+//! cells, light on black, so the standard 80x25 screen is 640x200 pixels.
+//! Characters outside printable ASCII are drawn as blanks.
+//!
+//! Frames are taken as programs show them: whenever the screen is presented
+//! (see [`Present`]), or under [`Present::Immediate`], whenever it has
+//! changed, at most every [`FRAME_INTERVAL`]. The last frame is taken when
+//! the program halts. Each frame lasts until the next one was taken, and
+//! only holds the cells that changed since the one before.
+//!
+//! ```no_run
+//! let mut rim = pact::read_file("hello.rim").unwrap();
+//! let file = std::fs::File::create("hello.gif").unwrap();
+//! let mut recorder = pact::gif::Recorder::new(std::io::BufWriter::new(file));
+//! recorder.run(&mut rim).unwrap();
+//! recorder.finish().unwrap();
+//! ```
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::error::RimResult;
+use crate::screen::Present;
+use crate::{Rim, Status, TICK};
+
+/// The shortest time a frame is shown for. Browsers show faster frames
+/// slower, not faster.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Each cell is this many pixels across and down.
+const CELL: usize = 8;
+
+/// Background and foreground, padded to the smallest table LZW allows.
+const PALETTE: [[u8; 3]; 4] = [[0x00, 0x00, 0x00], [0xc0, 0xc0, 0xc0], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00]];
+const MIN_CODE_SIZE: u8 = 2;
+const MAX_CODES: u16 = 4096;
+
+/// A box of cells: its left column, top row, width, and height.
+type Area = (usize, usize, usize, usize);
+
+/// Records a running machine's screen as a GIF.
+pub struct Recorder<W: Write> {
+    out: W,
+    /// The screen's size in cells, once the header is written.
+    size: Option<(usize, usize)>,
+    /// The cells of the last frame taken, and when it was.
+    last: Vec<u8>,
+    taken: Option<Instant>,
+    /// The last frame taken, which can't be written until its delay is
+    /// known, and its pixels.
+    pending: Option<(Area, Vec<u8>)>,
+    frames: u64,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, size: None, last: Vec::new(), taken: None, pending: None, frames: 0 }
+    }
+
+    /// How many frames have been taken.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Steps the machine, and takes a frame if one is due.
+    pub fn step(&mut self, rim: &mut Rim) -> RimResult<Status> {
+        let status = rim.step()?;
+        let screen = rim.screen();
+        if screen.cells() != self.last {
+            let shown = match rim.present_mode() {
+                Present::Immediate => self.taken.is_none_or(|taken| taken.elapsed() >= FRAME_INTERVAL),
+                // Present marks the screen unchanged, so a changed screen is
+                // one that's still being drawn.
+                Present::Manual | Present::Rate(_) => !screen.changed(),
+            };
+            if shown || status == Status::Halted {
+                self.take(rim)?;
+            }
+        }
+
+        Ok(status)
+    }
+
+    /// Runs the machine until it halts, like [`Rim::run`], taking frames as
+    /// it goes.
+    pub fn run(&mut self, rim: &mut Rim) -> RimResult<()> {
+        while self.step(rim)? == Status::Running {
+            if rim.is_waiting() {
+                std::thread::sleep(TICK);
+            }
+
+            if let Some(wait) = rim.until_next_frame().filter(|_| rim.is_blocked()) {
+                std::thread::sleep(wait);
+            }
+
+            match rim.take_sleep() {
+                Some(0) => std::thread::yield_now(),
+                Some(ticks) => std::thread::sleep(TICK * ticks as u32),
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last frame and the trailer, returning the writer.
+    pub fn finish(mut self) -> RimResult<W> {
+        if self.size.is_none() {
+            // Nothing was ever drawn, so the GIF is a single blank frame.
+            self.write_header(crate::screen::WIDTH, crate::screen::HEIGHT)?;
+            let (width, height) = (crate::screen::WIDTH, crate::screen::HEIGHT);
+            self.pending = Some(((0, 0, width, height), vec![0; width * height * CELL * CELL]));
+        }
+
+        self.write_pending(0)?;
+        self.out.write_all(&[0x3b])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn take(&mut self, rim: &Rim) -> RimResult<()> {
+        let screen = rim.screen();
+        let (width, height) = (screen.width(), screen.height());
+        if self.size.is_none() {
+            self.write_header(width, height)?;
+            self.last = vec![0; width * height];
+        }
+
+        // Only the box around the changed cells is drawn, over the frames
+        // before it. The first frame is always whole.
+        let cells = screen.cells();
+        let changed = |i: &usize| self.frames == 0 || cells[*i] != self.last[*i];
+        let (mut top, mut left, mut bottom, mut right) = (height, width, 0, 0);
+        for i in (0..cells.len()).filter(changed) {
+            let (row, col) = (i / width, i % width);
+            (top, left) = (top.min(row), left.min(col));
+            (bottom, right) = (bottom.max(row + 1), right.max(col + 1));
+        }
+
+        let frame = (left, top, right - left, bottom - top);
+        let pixels = draw(cells, width, frame);
+        let delay = self.taken.map_or(0, |taken| taken.elapsed().as_millis() / 10);
+        self.write_pending(delay.clamp(2, u16::MAX as u128) as u16)?;
+
+        self.pending = Some((frame, pixels));
+        self.last.copy_from_slice(cells);
+        self.taken = Some(Instant::now());
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn write_header(&mut self, width: usize, height: usize) -> RimResult<()> {
+        self.size = Some((width, height));
+
+        let mut header = b"GIF89a".to_vec();
+        header.extend(((width * CELL) as u16).to_le_bytes());
+        header.extend(((height * CELL) as u16).to_le_bytes());
+        // A global color table of 2^(1 + 1) colors, with a background of 0.
+        header.extend([0b1000_0001, 0, 0]);
+        header.extend(PALETTE.iter().flatten());
+        self.out.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Writes the pending frame, to be shown for `delay` hundredths of a
+    /// second.
+    fn write_pending(&mut self, delay: u16) -> RimResult<()> {
+        let Some(((left, top, width, height), pixels)) = self.pending.take() else {
+            return Ok(());
+        };
+
+        // A graphic control extension, leaving the frame in place for the
+        // next to draw over.
+        let mut out = vec![0x21, 0xf9, 4, 0b0000_0100];
+        out.extend(delay.to_le_bytes());
+        out.extend([0, 0]);
+
+        out.push(0x2c);
+        for n in [left, top, width, height] {
+            out.extend(((n * CELL) as u16).to_le_bytes());
+        }
+        out.push(0);
+
+        out.push(MIN_CODE_SIZE);
+        for block in lzw(&pixels).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend(block);
+        }
+        out.push(0);
+
+        self.out.write_all(&out)?;
+        Ok(())
+    }
+}
+
+/// Draws a box of cells as palette indices, row by row.
+fn draw(cells: &[u8], width: usize, (left, top, columns, rows): Area) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(columns * rows * CELL * CELL);
+    for row in top..top + rows {
+        for y in 0..CELL {
+            for col in left..left + columns {
+                let glyph = glyph(cells[row * width + col]);
+                pixels.extend((0..CELL).map(|x| glyph[y] >> (CELL - 1 - x) & 1));
+            }
+        }
+    }
+
+    pixels
+}
+
+fn glyph(c: u8) -> [u8; 8] {
+    match c {
+        b' '..=b'~' => FONT[(c - b' ') as usize],
+        _ => [0; 8],
+    }
+}
+
+/// Compresses palette indices into GIF's variable-width LZW codes, packed
+/// least significant bit first.
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+
+    // Every code's extensions by one more index, or 0 if there isn't one
+    // yet, since codes that low are never extensions.
+    let mut table = vec![[0u16; 1 << MIN_CODE_SIZE]; MAX_CODES as usize];
+    let mut next = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+
+    let mut out = Vec::new();
+    let (mut bits, mut len) = (0u32, 0);
+    let mut emit = |code: u16, size: u8| {
+        bits |= (code as u32) << len;
+        len += size;
+        while len >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            len -= 8;
+        }
+    };
+
+    emit(clear, size);
+    let Some((&first, rest)) = pixels.split_first() else {
+        emit(end, size);
+        if len > 0 {
+            out.push(bits as u8);
+        }
+        return out;
+    };
+
+    let mut prefix = first as u16;
+    for &pixel in rest {
+        let code = table[prefix as usize][pixel as usize];
+        if code != 0 {
+            prefix = code;
+            continue;
+        }
+
+        emit(prefix, size);
+        table[prefix as usize][pixel as usize] = next;
+        next += 1;
+        if next > 1 << size && size < 12 {
+            size += 1;
+        }
+
+        // Start over once the table is full, since codes can't be wider.
+        if next == MAX_CODES {
+            emit(clear, size);
+            table.iter_mut().for_each(|codes| *codes = [0; 1 << MIN_CODE_SIZE]);
+            next = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        }
+
+        prefix = pixel as u16;
+    }
+
+    emit(prefix, size);
+    emit(end, size);
+    if len > 0 {
+        out.push(bits as u8);
+    }
+
+    out
+}
+
+/// The printable ASCII characters, from space, a row per byte with the
+/// leftmost pixel in bit 7.
+const FONT: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x10, 0x00], // !
+    [0x28, 0x28, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x28, 0x28, 0x7c, 0x28, 0x7c, 0x28, 0x28, 0x00], // #
+    [0x10, 0x3c, 0x50, 0x38, 0x14, 0x78, 0x10, 0x00], // $
+    [0x60, 0x64, 0x08, 0x10, 0x20, 0x4c, 0x0c, 0x00], // %
+    [0x30, 0x48, 0x50, 0x20, 0x54, 0x48, 0x34, 0x00], // &
+    [0x10, 0x10, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x08, 0x10, 0x20, 0x20, 0x20, 0x10, 0x08, 0x00], // (
+    [0x20, 0x10, 0x08, 0x08, 0x08, 0x10, 0x20, 0x00], // )
+    [0x00, 0x10, 0x54, 0x38, 0x54, 0x10, 0x00, 0x00], // *
+    [0x00, 0x10, 0x10, 0x7c, 0x10, 0x10, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x30, 0x10, 0x20, 0x00], // ,
+    [0x00, 0x00, 0x00, 0x7c, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x00], // .
+    [0x00, 0x04, 0x08, 0x10, 0x20, 0x40, 0x00, 0x00], // /
+    [0x38, 0x44, 0x4c, 0x54, 0x64, 0x44, 0x38, 0x00], // 0
+    [0x10, 0x30, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // 1
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x20, 0x7c, 0x00], // 2
+    [0x7c, 0x08, 0x10, 0x08, 0x04, 0x44, 0x38, 0x00], // 3
+    [0x08, 0x18, 0x28, 0x48, 0x7c, 0x08, 0x08, 0x00], // 4
+    [0x7c, 0x40, 0x78, 0x04, 0x04, 0x44, 0x38, 0x00], // 5
+    [0x18, 0x20, 0x40, 0x78, 0x44, 0x44, 0x38, 0x00], // 6
+    [0x7c, 0x04, 0x08, 0x10, 0x20, 0x20, 0x20, 0x00], // 7
+    [0x38, 0x44, 0x44, 0x38, 0x44, 0x44, 0x38, 0x00], // 8
+    [0x38, 0x44, 0x44, 0x3c, 0x04, 0x08, 0x30, 0x00], // 9
+    [0x00, 0x30, 0x30, 0x00, 0x30, 0x30, 0x00, 0x00], // :
+    [0x00, 0x30, 0x30, 0x00, 0x30, 0x10, 0x20, 0x00], // ;
+    [0x08, 0x10, 0x20, 0x40, 0x20, 0x10, 0x08, 0x00], // <
+    [0x00, 0x00, 0x7c, 0x00, 0x7c, 0x00, 0x00, 0x00], // =
+    [0x20, 0x10, 0x08, 0x04, 0x08, 0x10, 0x20, 0x00], // >
+    [0x38, 0x44, 0x04, 0x08, 0x10, 0x00, 0x10, 0x00], // ?
+    [0x38, 0x44, 0x04, 0x34, 0x54, 0x54, 0x38, 0x00], // @
+    [0x38, 0x44, 0x44, 0x7c, 0x44, 0x44, 0x44, 0x00], // A
+    [0x78, 0x44, 0x44, 0x78, 0x44, 0x44, 0x78, 0x00], // B
+    [0x38, 0x44, 0x40, 0x40, 0x40, 0x44, 0x38, 0x00], // C
+    [0x70, 0x48, 0x44, 0x44, 0x44, 0x48, 0x70, 0x00], // D
+    [0x7c, 0x40, 0x40, 0x78, 0x40, 0x40, 0x7c, 0x00], // E
+    [0x7c, 0x40, 0x40, 0x78, 0x40, 0x40, 0x40, 0x00], // F
+    [0x38, 0x44, 0x40, 0x5c, 0x44, 0x44, 0x3c, 0x00], // G
+    [0x44, 0x44, 0x44, 0x7c, 0x44, 0x44, 0x44, 0x00], // H
+    [0x38, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // I
+    [0x1c, 0x08, 0x08, 0x08, 0x08, 0x48, 0x30, 0x00], // J
+    [0x44, 0x48, 0x50, 0x60, 0x50, 0x48, 0x44, 0x00], // K
+    [0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x7c, 0x00], // L
+    [0x44, 0x6c, 0x54, 0x54, 0x44, 0x44, 0x44, 0x00], // M
+    [0x44, 0x44, 0x64, 0x54, 0x4c, 0x44, 0x44, 0x00], // N
+    [0x38, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // O
+    [0x78, 0x44, 0x44, 0x78, 0x40, 0x40, 0x40, 0x00], // P
+    [0x38, 0x44, 0x44, 0x44, 0x54, 0x48, 0x34, 0x00], // Q
+    [0x78, 0x44, 0x44, 0x78, 0x50, 0x48, 0x44, 0x00], // R
+    [0x3c, 0x40, 0x40, 0x38, 0x04, 0x04, 0x78, 0x00], // S
+    [0x7c, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00], // T
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x38, 0x00], // U
+    [0x44, 0x44, 0x44, 0x44, 0x44, 0x28, 0x10, 0x00], // V
+    [0x44, 0x44, 0x44, 0x54, 0x54, 0x54, 0x28, 0x00], // W
+    [0x44, 0x44, 0x28, 0x10, 0x28, 0x44, 0x44, 0x00], // X
+    [0x44, 0x44, 0x44, 0x28, 0x10, 0x10, 0x10, 0x00], // Y
+    [0x7c, 0x04, 0x08, 0x10, 0x20, 0x40, 0x7c, 0x00], // Z
+    [0x38, 0x20, 0x20, 0x20, 0x20, 0x20, 0x38, 0x00], // [
+    [0x00, 0x40, 0x20, 0x10, 0x08, 0x04, 0x00, 0x00], // \
+    [0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x38, 0x00], // ]
+    [0x10, 0x28, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x00], // _
+    [0x20, 0x10, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x38, 0x04, 0x3c, 0x44, 0x3c, 0x00], // a
+    [0x40, 0x40, 0x58, 0x64, 0x44, 0x44, 0x78, 0x00], // b
+    [0x00, 0x00, 0x38, 0x40, 0x40, 0x44, 0x38, 0x00], // c
+    [0x04, 0x04, 0x34, 0x4c, 0x44, 0x44, 0x3c, 0x00], // d
+    [0x00, 0x00, 0x38, 0x44, 0x7c, 0x40, 0x38, 0x00], // e
+    [0x18, 0x24, 0x20, 0x70, 0x20, 0x20, 0x20, 0x00], // f
+    [0x00, 0x00, 0x3c, 0x44, 0x44, 0x3c, 0x04, 0x38], // g
+    [0x40, 0x40, 0x58, 0x64, 0x44, 0x44, 0x44, 0x00], // h
+    [0x10, 0x00, 0x30, 0x10, 0x10, 0x10, 0x38, 0x00], // i
+    [0x08, 0x00, 0x18, 0x08, 0x08, 0x08, 0x48, 0x30], // j
+    [0x40, 0x40, 0x48, 0x50, 0x60, 0x50, 0x48, 0x00], // k
+    [0x30, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00], // l
+    [0x00, 0x00, 0x68, 0x54, 0x54, 0x44, 0x44, 0x00], // m
+    [0x00, 0x00, 0x58, 0x64, 0x44, 0x44, 0x44, 0x00], // n
+    [0x00, 0x00, 0x38, 0x44, 0x44, 0x44, 0x38, 0x00], // o
+    [0x00, 0x00, 0x78, 0x44, 0x44, 0x78, 0x40, 0x40], // p
+    [0x00, 0x00, 0x3c, 0x44, 0x44, 0x3c, 0x04, 0x04], // q
+    [0x00, 0x00, 0x58, 0x64, 0x40, 0x40, 0x40, 0x00], // r
+    [0x00, 0x00, 0x38, 0x40, 0x38, 0x04, 0x78, 0x00], // s
+    [0x20, 0x20, 0x70, 0x20, 0x20, 0x24, 0x18, 0x00], // t
+    [0x00, 0x00, 0x44, 0x44, 0x44, 0x4c, 0x34, 0x00], // u
+    [0x00, 0x00, 0x44, 0x44, 0x44, 0x28, 0x10, 0x00], // v
+    [0x00, 0x00, 0x44, 0x44, 0x54, 0x54, 0x28, 0x00], // w
+    [0x00, 0x00, 0x44, 0x28, 0x10, 0x28, 0x44, 0x00], // x
+    [0x00, 0x00, 0x44, 0x44, 0x44, 0x3c, 0x04, 0x38], // y
+    [0x00, 0x00, 0x7c, 0x08, 0x10, 0x20, 0x7c, 0x00], // z
+    [0x08, 0x10, 0x10, 0x20, 0x10, 0x10, 0x08, 0x00], // {
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00], // |
+    [0x20, 0x10, 0x10, 0x08, 0x10, 0x10, 0x20, 0x00], // }
+    [0x00, 0x00, 0x20, 0x54, 0x08, 0x00, 0x00, 0x00], // ~
+];
@@ -0,0 +1,276 @@
+//! Automated grading: run a submission against scripted tests, within
+//! limits, and report what it got right.
+//!
+//! ```no_run
+//! use pact::grade::{grade, Check, Limits, Test};
+//! use pact::Register;
+//!
+//! let submission = std::fs::read("student.rim").unwrap();
+//! let test = Test {
+//!     name: "echo".to_string(),
+//!     input: b"hi".to_vec(),
+//!     checks: vec![Check::Halted, Check::Output(b"hi".to_vec()), Check::Register(Register::Rb, 0)],
+//! };
+//!
+//! let report = grade(&submission, &test, &Limits::default());
+//! println!("{report}");
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::cache::ProgramCache;
+use crate::console::{Buffer, Console};
+use crate::error::{RimError, RimResult};
+use crate::image::{Image, LoadLimits};
+use crate::{Register, Rim, Snapshot, Status};
+
+/// How much a submission may do before it's stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_steps: usize,
+    pub max_time: Option<Duration>,
+    /// The most output bytes kept; anything after is dropped.
+    pub max_output: usize,
+    /// Checked before the submission runs, and whenever it loads another
+    /// program.
+    pub load: LoadLimits,
+    /// When serving, the most requests handled at once; any more are
+    /// turned away with a 503.
+    pub max_concurrent: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: 1_000_000,
+            max_time: Some(Duration::from_secs(1)),
+            max_output: 64 * 1024,
+            load: LoadLimits::default(),
+            max_concurrent: 16,
+        }
+    }
+}
+
+/// A scripted run of a submission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Test {
+    pub name: String,
+    /// Fed to the keyboard, in order.
+    pub input: Vec<u8>,
+    pub checks: Vec<Check>,
+}
+
+/// Something a submission must have done by the end of a test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Check {
+    /// It halted, rather than faulting or running out of steps or time.
+    Halted,
+    /// It wrote exactly these bytes to the screen.
+    Output(Vec<u8>),
+    Register(Register, u8),
+    Memory(usize, u8),
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Halted => write!(f, "halted"),
+            Self::Output(output) => write!(f, "output = {:?}", String::from_utf8_lossy(output)),
+            Self::Register(register, value) => write!(f, "{register} = {value:#04x}"),
+            Self::Memory(addr, value) => write!(f, "mem[{addr:#05x}] = {value:#04x}"),
+        }
+    }
+}
+
+/// How a test run ended.
+#[derive(Debug)]
+pub enum Outcome {
+    Halted,
+    Faulted(RimError),
+    OutOfSteps,
+    OutOfTime,
+}
+
+impl Outcome {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Halted => "halted",
+            Self::Faulted(_) => "faulted",
+            Self::OutOfSteps => "out_of_steps",
+            Self::OutOfTime => "out_of_time",
+        }
+    }
+
+    pub fn error(&self) -> Option<&RimError> {
+        match self {
+            Self::Faulted(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Halted => write!(f, "halted"),
+            Self::Faulted(e) => write!(f, "faulted: {e}"),
+            Self::OutOfSteps => write!(f, "ran out of steps"),
+            Self::OutOfTime => write!(f, "ran out of time"),
+        }
+    }
+}
+
+/// The result of a test.
+#[derive(Debug)]
+pub struct Report {
+    pub name: String,
+    pub outcome: Outcome,
+    pub steps: usize,
+    pub elapsed: Duration,
+    pub output: Vec<u8>,
+    /// Whether output went over [`Limits::max_output`].
+    pub truncated: bool,
+    /// The final state, if the submission loaded at all.
+    pub snapshot: Option<Snapshot>,
+    pub passed: Vec<Check>,
+    pub failed: Vec<Check>,
+}
+
+impl Report {
+    /// Whether every check passed. Add [`Check::Halted`] to a test to also
+    /// require that the submission finished cleanly.
+    pub fn success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {} after {} steps", self.name, self.outcome, self.steps)?;
+        for check in &self.passed {
+            writeln!(f, "    pass {check}")?;
+        }
+
+        for check in &self.failed {
+            writeln!(f, "    FAIL {check}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs a program image against a test. A submission that doesn't load
+/// fails every check.
+pub fn grade(program: &[u8], test: &Test, limits: &Limits) -> Report {
+    let start = Instant::now();
+    grade_loaded(load(program, &limits.load), test, limits, start)
+}
+
+/// Like [`grade`], but loading the program through a cache, so grading
+/// the same submission again skips parsing it.
+pub fn grade_cached(cache: &ProgramCache, program: &[u8], test: &Test, limits: &Limits) -> Report {
+    let start = Instant::now();
+    grade_loaded(cache.load(program, &limits.load), test, limits, start)
+}
+
+fn grade_loaded(loaded: RimResult<Rim>, test: &Test, limits: &Limits, start: Instant) -> Report {
+    let mut report = Report {
+        name: test.name.clone(),
+        outcome: Outcome::Halted,
+        steps: 0,
+        elapsed: Duration::ZERO,
+        output: Vec::new(),
+        truncated: false,
+        snapshot: None,
+        passed: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    let mut rim = match loaded {
+        Ok(rim) => rim,
+        Err(e) => {
+            report.outcome = Outcome::Faulted(e);
+            report.failed = test.checks.clone();
+            return report;
+        }
+    };
+
+    let mut buffer = Buffer::new(test.input.clone());
+    buffer.set_limit(limits.max_output);
+    rim.set_console(Console::Buffer(buffer));
+
+    report.outcome = run(&mut rim, limits, start, &mut report.steps, &mut 0);
+    report.elapsed = start.elapsed();
+    report.snapshot = Some(rim.snapshot());
+
+    if let Console::Buffer(buffer) = rim.console() {
+        report.output = buffer.output().to_vec();
+        report.truncated = buffer.truncated();
+    }
+
+    for check in &test.checks {
+        let passed = match check {
+            Check::Halted => matches!(report.outcome, Outcome::Halted),
+            Check::Output(output) => report.output == *output,
+            Check::Register(register, value) => rim.registers()[*register as usize] == *value,
+            Check::Memory(addr, value) => rim.data().get(*addr) == Some(value),
+        };
+
+        if passed {
+            report.passed.push(check.clone());
+        } else {
+            report.failed.push(check.clone());
+        }
+    }
+
+    report
+}
+
+/// Loads a program image, as [`read_bytes`](crate::read_bytes) does,
+/// unless it's over the limits, which keep applying to it as it runs.
+pub fn load(program: &[u8], limits: &LoadLimits) -> RimResult<Rim> {
+    let image = Image::parse(program)?;
+    image.check_limits(limits)?;
+    for warning in &image.warnings {
+        log::warn!("{warning}");
+    }
+
+    let mut rim = image.into_rim();
+    rim.set_load_limits(*limits);
+    Ok(rim)
+}
+
+/// Runs each test in turn, parsing the program only once.
+pub fn grade_all(program: &[u8], tests: &[Test], limits: &Limits) -> Vec<Report> {
+    let cache = ProgramCache::new(1);
+    tests.iter().map(|test| grade_cached(&cache, program, test, limits)).collect()
+}
+
+/// Steps a machine until it halts or faults, or until a limit, counting the
+/// steps, and the instructions executed by those that didn't block on a
+/// mailbox or wait for a key.
+pub(crate) fn run(rim: &mut Rim, limits: &Limits, start: Instant, steps: &mut usize, instructions: &mut usize) -> Outcome {
+    // Checking the clock every step would dominate short instructions.
+    const CLOCK_INTERVAL: usize = 1024;
+
+    while *steps < limits.max_steps {
+        if steps.is_multiple_of(CLOCK_INTERVAL) && limits.max_time.is_some_and(|max| start.elapsed() > max) {
+            return Outcome::OutOfTime;
+        }
+
+        // Up to the next clock check, which a burst can't pass.
+        let max = (limits.max_steps - *steps).min(CLOCK_INTERVAL - *steps % CLOCK_INTERVAL);
+        let burst = rim.burst(max);
+        *steps += burst.steps;
+        // A burst ends after a step that blocked or waited, if any did.
+        *instructions += burst.steps - (rim.is_blocked() || rim.is_waiting()) as usize;
+        match burst.result {
+            Ok(Status::Running) => {}
+            Ok(Status::Halted) => return Outcome::Halted,
+            Err(e) => return Outcome::Faulted(e),
+        }
+    }
+
+    Outcome::OutOfSteps
+}
@@ -0,0 +1,248 @@
+//! The graphics device: a framebuffer with sprites on top, composited by the
+//! host.
+//!
+//! The device is extension device 1 of bank 1, and has video memory and a
+//! file of registers. Its functions are:
+//!
+//! | Function | Effect                                                   |
+//! |----------|----------------------------------------------------------|
+//! | 0        | Set the high byte of the video memory pointer            |
+//! | 1        | Set the low byte of the video memory pointer             |
+//! | 2        | Write `value` at the pointer, and advance it             |
+//! | 3        | Read the byte at the pointer into Ra, and advance it     |
+//! | 4        | Select register `value`                                  |
+//! | 5        | Write `value` to the selected register, and select the next |
+//! | 6        | Read the selected register into Ra, and select the next  |
+//! | 7        | Write 256 bytes from data memory from page `value` at the pointer, and advance it |
+//!
+//! Function 7 copies a block at once, or up to the end of data memory, so
+//! the framebuffer can be redrawn from data memory in four calls. Like a
+//! byte written with function 2, the block wraps around to the start of
+//! video memory.
+//!
+//! The first [`FRAMEBUFFER_LEN`] bytes of video memory are the framebuffer,
+//! row by row, with the leftmost pixels in the most significant bits of each
+//! byte. Its size is fixed, so more colors mean fewer pixels; the
+//! [`Mode`] is set by register [`MODE`]:
+//!
+//! | Mode | Colors | Pixels   |
+//! |------|--------|----------|
+//! | 0    | 2      | 128 x 64 |
+//! | 1    | 4      | 64 x 64  |
+//! | 2    | 16     | 64 x 32  |
+//!
+//! Pixels are indices into the palette, registers [`PALETTE`] onwards, one
+//! RGB332 color each (red in the top 3 bits, then green, then blue in the
+//! bottom 2). It starts out as [`DEFAULT_PALETTE`].
+//!
+//! The rest of video memory holds [`TILES`] 8x8 tiles, 8 bytes each, a bit
+//! per pixel. Registers `4 * n` to `4 * n + 3` hold sprite `n`'s x, y, tile,
+//! and attributes. Bit 0 of the attributes makes the sprite visible, and the
+//! high 4 bits are the color of its set pixels; its clear pixels are
+//! transparent. Sprites are drawn in order, so later ones are on top.
+
+pub const FRAMEBUFFER_LEN: usize = 1024;
+
+pub const TILES: usize = 128;
+pub const VRAM_LEN: usize = FRAMEBUFFER_LEN + TILES * 8;
+
+pub const SPRITES: usize = 8;
+/// The register holding the mode.
+pub const MODE: usize = SPRITES * 4;
+/// The first palette register.
+pub const PALETTE: usize = MODE + 1;
+pub const REGISTERS: usize = PALETTE + 16;
+
+/// Black, white, red, green, blue, yellow, cyan, magenta, gray, dark red,
+/// dark green, dark blue, orange, brown, dark gray, and light gray.
+pub const DEFAULT_PALETTE: [u8; 16] = [
+    0x00, 0xff, 0xe0, 0x1c, 0x03, 0xfc, 0x1f, 0xe3, 0x92, 0x80, 0x10, 0x02, 0xf0, 0x88, 0x49, 0xdb,
+];
+
+/// How the framebuffer's bytes are split into pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Mono,
+    Four,
+    Sixteen,
+}
+
+impl Mode {
+    /// Modes past 2 are treated as mode 0.
+    pub fn from_register(value: u8) -> Self {
+        match value {
+            1 => Self::Four,
+            2 => Self::Sixteen,
+            _ => Self::Mono,
+        }
+    }
+
+    pub fn bits(self) -> usize {
+        match self {
+            Self::Mono => 1,
+            Self::Four => 2,
+            Self::Sixteen => 4,
+        }
+    }
+
+    pub fn width(self) -> usize {
+        match self {
+            Self::Mono => 128,
+            Self::Four | Self::Sixteen => 64,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        FRAMEBUFFER_LEN * 8 / self.bits() / self.width()
+    }
+}
+
+/// A sprite's registers, decoded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub visible: bool,
+    pub color: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Graphics {
+    vram: Vec<u8>,
+    pointer: usize,
+    registers: [u8; REGISTERS],
+    selected: usize,
+}
+
+impl Default for Graphics {
+    fn default() -> Self {
+        let mut registers = [0; REGISTERS];
+        registers[PALETTE..].copy_from_slice(&DEFAULT_PALETTE);
+
+        Self {
+            vram: vec![0; VRAM_LEN],
+            pointer: 0,
+            registers,
+            selected: 0,
+        }
+    }
+}
+
+impl Graphics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    pub fn mode(&self) -> Mode {
+        Mode::from_register(self.registers[MODE])
+    }
+
+    pub fn width(&self) -> usize {
+        self.mode().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.mode().height()
+    }
+
+    /// A palette entry as red, green, and blue, each scaled to 0 to 255.
+    pub fn rgb(&self, index: u8) -> [u8; 3] {
+        let color = self.registers[PALETTE + (index as usize & 0xf)];
+        let (r, g, b) = (color >> 5, (color >> 2) & 7, color & 3);
+        let scale = |v: u8, max: u16| (v as u16 * 255 / max) as u8;
+        [scale(r, 7), scale(g, 7), scale(b, 3)]
+    }
+
+    pub fn sprite(&self, n: usize) -> Sprite {
+        let [x, y, tile, attr] = self.registers[n * 4..n * 4 + 4] else {
+            unreachable!()
+        };
+
+        Sprite {
+            x,
+            y,
+            tile,
+            visible: attr & 1 != 0,
+            color: attr >> 4,
+        }
+    }
+
+    /// The framebuffer's palette index at a position, in the current mode.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        let bits = self.mode().bits();
+        let bit = (y * self.width() + x) * bits;
+        (self.vram[bit / 8] >> (8 - bits - bit % 8)) & ((1 << bits) - 1)
+    }
+
+    /// Composites the sprites over the framebuffer, returning a palette
+    /// index for each pixel, row by row.
+    pub fn compose(&self) -> Vec<u8> {
+        let (width, height) = (self.width(), self.height());
+        let mut pixels: Vec<u8> = (0..width * height).map(|i| self.pixel(i % width, i / width)).collect();
+
+        for sprite in (0..SPRITES).map(|n| self.sprite(n)).filter(|sprite| sprite.visible) {
+            let tile = FRAMEBUFFER_LEN + (sprite.tile as usize % TILES) * 8;
+            for (dy, row) in self.vram[tile..tile + 8].iter().enumerate() {
+                for dx in 0..8 {
+                    let (x, y) = (sprite.x as usize + dx, sprite.y as usize + dy);
+                    if row & (0x80 >> dx) != 0 && x < width && y < height {
+                        pixels[y * width + x] = sprite.color;
+                    }
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// Like [`Graphics::compose`], but with colors looked up in the palette.
+    pub fn compose_rgb(&self) -> Vec<[u8; 3]> {
+        self.compose().into_iter().map(|index| self.rgb(index)).collect()
+    }
+
+    /// The most function 7 copies at once.
+    pub(crate) const BLOCK_LEN: usize = 256;
+
+    /// Writes `src` at the pointer, wrapping around, and advances it.
+    pub(crate) fn write_block(&mut self, src: &[u8]) {
+        let (first, rest) = src.split_at(src.len().min(VRAM_LEN - self.pointer));
+        crate::block::copy(&mut self.vram[self.pointer..self.pointer + first.len()], first);
+        crate::block::copy(&mut self.vram[..rest.len()], rest);
+        self.pointer = (self.pointer + src.len()) % VRAM_LEN;
+    }
+
+    pub(crate) fn io(&mut self, function: crate::helper::U3, value: u8) -> Option<u8> {
+        match function as u8 {
+            0 => self.pointer = ((value as usize) << 8 | (self.pointer & 0xff)) % VRAM_LEN,
+            1 => self.pointer = (self.pointer & !0xff | value as usize) % VRAM_LEN,
+            2 => {
+                self.vram[self.pointer] = value;
+                self.pointer = (self.pointer + 1) % VRAM_LEN;
+            }
+            3 => {
+                let byte = self.vram[self.pointer];
+                self.pointer = (self.pointer + 1) % VRAM_LEN;
+                return Some(byte);
+            }
+            4 => self.selected = value as usize % REGISTERS,
+            5 => {
+                self.registers[self.selected] = value;
+                self.selected = (self.selected + 1) % REGISTERS;
+            }
+            6 => {
+                let byte = self.registers[self.selected];
+                self.selected = (self.selected + 1) % REGISTERS;
+                return Some(byte);
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
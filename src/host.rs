@@ -0,0 +1,44 @@
+//! Devices the host provides, on extension banks past the built-in ones.
+//!
+//! A [`HostDevice`] attached with [`Rim::attach_device`](crate::Rim::attach_device)
+//! takes a device ID from 12 up, which is device `id % 4` of bank `id / 4`
+//! (see the [`image`](crate::image) module), and performs its functions for
+//! the program however it likes. When one fails, the step fails with a
+//! [`RimError::Device`](crate::error::RimError::Device) saying which
+//! device and function failed, and where, with the device's own error as
+//! its source.
+//!
+//! Devices are shared, so a machine's clones and forks use the same one.
+//!
+//! ```no_run
+//! use pact::helper::U3;
+//! use pact::host::{DeviceError, HostDevice};
+//!
+//! /// Reads the host's clock into Ra, in seconds mod 256.
+//! struct Clock;
+//!
+//! impl HostDevice for Clock {
+//!     fn io(&mut self, _function: U3, _value: u8) -> Result<Option<u8>, DeviceError> {
+//!         let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+//!         Ok(Some(now.as_secs() as u8))
+//!     }
+//! }
+//!
+//! let mut rim = pact::read_file("clock.rim").unwrap();
+//! rim.attach_device(12, Clock);
+//! rim.run().unwrap();
+//! ```
+
+use std::error::Error;
+
+use crate::helper::U3;
+
+/// The first device ID free for host devices.
+pub const FIRST_HOST_DEVICE: usize = 12;
+
+pub type DeviceError = Box<dyn Error + Send + Sync>;
+
+pub trait HostDevice: Send {
+    /// Performs a function, returning what to set Ra to, if anything.
+    fn io(&mut self, function: U3, value: u8) -> Result<Option<u8>, DeviceError>;
+}
@@ -0,0 +1,465 @@
+//! Program images, and the warnings found while loading them.
+//!
+//! A v1 image is [`MAGIC`](crate::MAGIC) followed by the program's
+//! instructions, one byte each. A v2 image starts with [`MAGIC_V2`], and is
+//! followed by sections, each a tag byte, a big-endian `u16` length, and
+//! that many bytes:
+//!
+//! | Tag | Section                                       |
+//! |-----|-----------------------------------------------|
+//! | 0   | End; anything after it is ignored             |
+//! | 1   | The program's instructions                    |
+//! | 2   | The initial contents of data memory           |
+//! | 3   | Metadata, as UTF-8 `key=value` lines          |
+//! | 4   | A bitmap of the devices the program requires  |
+//! | 5   | The instruction set level required, one byte  |
+//! | 6   | Annotations, as UTF-8 `addr=comment` lines    |
+//!
+//! Annotations are comments on instructions, kept from the source by
+//! `pact asm --annotate` so a distributed program can still explain itself
+//! when disassembled. Addresses are in decimal.
+//!
+//! Bit `n` of the device bitmap (bit `n % 8` of byte `n / 8`) stands for
+//! device ID `n`, which is `bank * 4 + device` (see [`Rim`]'s `ext` system
+//! call): IDs 0 to 3 are the standard devices, 4 is the disk, 5 is
+//! graphics, 6 is sound, 7 is the mailbox, and 8 is the counters.
+//!
+//! Images without a level section, and v1 images, are level 1 (see
+//! [`isa`](crate::isa)).
+//!
+//! Unknown sections are skipped, so newer images still load in older
+//! versions, minus whatever they added. A v2 image must have a code section.
+//!
+//! Either version may also be gzip- or zstd-compressed, usually named
+//! `.rim.gz` or `.rim.zst`, with the `gzip` or `zstd` feature enabled.
+//! Compression is detected from the content, not the file name.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use crate::addr::Addr;
+use crate::analysis::{analyze, Diagnostic};
+use crate::error::{LoadError, RimError, RimResult};
+use crate::isa::IsaLevel;
+use crate::{Device, Instruction, InstructionData, Rim, MAGIC, MAX_PROGRAM_LEN};
+
+pub const MAGIC_V2: u16 = 0x8bcb;
+
+/// The largest image accepted after decompression, so a small compressed
+/// file can't expand without bound.
+pub const MAX_IMAGE_LEN: usize = 1024 * 1024;
+
+pub const SECTION_END: u8 = 0;
+pub const SECTION_CODE: u8 = 1;
+pub const SECTION_DATA: u8 = 2;
+pub const SECTION_METADATA: u8 = 3;
+pub const SECTION_DEVICES: u8 = 4;
+pub const SECTION_ISA: u8 = 5;
+pub const SECTION_ANNOTATIONS: u8 = 6;
+
+/// The device ID of the disk.
+pub const DEVICE_DISK: usize = 4;
+/// The device ID of the graphics device.
+pub const DEVICE_GRAPHICS: usize = 5;
+/// The device ID of the sound device.
+pub const DEVICE_SOUND: usize = 6;
+/// The device ID of the mailbox.
+pub const DEVICE_MAILBOX: usize = 7;
+/// The device ID of the performance counters.
+pub const DEVICE_COUNTERS: usize = 8;
+
+/// Parses a device ID from a name, as given by [`device_name`], or a number.
+pub fn device_id(name: &str) -> Option<usize> {
+    match name {
+        "cpu" => Some(0),
+        "kbd" => Some(1),
+        "scr" => Some(2),
+        "mth" => Some(3),
+        "disk" => Some(DEVICE_DISK),
+        "gfx" => Some(DEVICE_GRAPHICS),
+        "snd" => Some(DEVICE_SOUND),
+        "mbox" => Some(DEVICE_MAILBOX),
+        "ctr" => Some(DEVICE_COUNTERS),
+        _ => name.parse().ok(),
+    }
+}
+
+/// A device's ID, by name where it has one, and by bank and device otherwise.
+pub fn device_name(id: usize) -> String {
+    match id {
+        0..=3 => Device::from(id as u8).to_string(),
+        DEVICE_DISK => "disk".to_string(),
+        DEVICE_GRAPHICS => "gfx".to_string(),
+        DEVICE_SOUND => "snd".to_string(),
+        DEVICE_MAILBOX => "mbox".to_string(),
+        DEVICE_COUNTERS => "ctr".to_string(),
+        _ => format!("bank {} device {}", id / 4, id % 4),
+    }
+}
+
+/// Something odd about an image that doesn't stop it from loading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    EmptyProgram,
+    /// Bytes after the end section.
+    TrailingBytes(usize),
+    UnknownSection(u8),
+    /// A later section replaced an earlier one with the same tag.
+    DuplicateSection(u8),
+    /// An instruction calling a reserved device function, at an address.
+    ReservedFunction(usize),
+    /// A jump, at an address, that can only go past the end of the program
+    /// when run from reset (see [`analysis`](crate::analysis)).
+    JumpPastEnd { pc: usize, target: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyProgram => write!(f, "program is empty, and will halt immediately"),
+            Self::TrailingBytes(len) => write!(f, "{len} trailing bytes after the end section ignored"),
+            Self::UnknownSection(tag) => write!(f, "unknown section {tag} skipped"),
+            Self::DuplicateSection(tag) => write!(f, "section {tag} appears more than once; the last one wins"),
+            Self::ReservedFunction(addr) => write!(f, "instruction at {addr:#05x} calls a reserved device function"),
+            Self::JumpPastEnd { pc, target } => {
+                write!(f, "jump at {pc:#05x} goes to {target:#05x}, past the end of the program, and will fault")
+            }
+        }
+    }
+}
+
+/// Caps on the programs a machine loads, so a host can turn away abusive
+/// submissions before running them. Each defaults to what the machine can
+/// hold anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadLimits {
+    /// The most instructions in any one program.
+    pub max_program_len: usize,
+    /// The most bytes in an image's data section.
+    pub max_data_len: usize,
+    /// The most programs loaded at once, counting the first, so this caps
+    /// how deep overlays and boot images loading each other can nest.
+    pub max_programs: usize,
+}
+
+impl Default for LoadLimits {
+    fn default() -> Self {
+        Self {
+            max_program_len: MAX_PROGRAM_LEN,
+            max_data_len: Addr::COUNT,
+            max_programs: usize::MAX,
+        }
+    }
+}
+
+impl LoadLimits {
+    pub(crate) fn check(limit: &'static str, value: usize, max: usize) -> RimResult<()> {
+        if value > max {
+            return Err(LoadError::OverLimit { limit, value, max }.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// A loaded image: its program, initial data, and any warnings.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub code: Vec<Instruction>,
+    /// At most 4096 bytes, loaded at the start of data memory.
+    pub data: Vec<u8>,
+    /// Like `title`, `author`, or `version`.
+    pub metadata: BTreeMap<String, String>,
+    /// Device IDs that must be present for the program to work.
+    pub required_devices: BTreeSet<usize>,
+    /// The instruction set level the program was written for.
+    pub isa: IsaLevel,
+    /// Comments on instructions, by address, to show when disassembling.
+    pub annotations: BTreeMap<usize, String>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Image {
+    pub fn new(code: Vec<Instruction>) -> Self {
+        Self {
+            code,
+            ..Default::default()
+        }
+    }
+
+    pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Self> {
+        let path = f.as_ref();
+        std::fs::read(path).map_err(RimError::from).and_then(|bytes| Self::parse(&bytes)).map_err(|e| e.in_file(path))
+    }
+
+    /// Parses a v1 or v2 image, decompressing it first if needed.
+    pub fn parse(bytes: &[u8]) -> RimResult<Self> {
+        let bytes = decompress(bytes)?;
+        let Some((&magic, rest)) = bytes.split_first_chunk::<2>() else {
+            return Err(LoadError::InvalidMagic.into());
+        };
+
+        let mut image = match u16::from_be_bytes(magic) {
+            MAGIC => Self::new(decode(rest)?),
+            MAGIC_V2 => Self::parse_sections(rest)?,
+            _ => return Err(LoadError::InvalidMagic.into()),
+        };
+
+        if image.code.is_empty() {
+            image.warnings.push(Warning::EmptyProgram);
+        }
+
+        for (addr, instruction) in image.code.iter().enumerate() {
+            if is_reserved(*instruction) {
+                image.warnings.push(Warning::ReservedFunction(addr));
+            }
+        }
+
+        for diagnostic in analyze(&image.code).diagnostics {
+            if let Diagnostic::JumpPastEnd { pc, target } = diagnostic {
+                image.warnings.push(Warning::JumpPastEnd { pc, target });
+            }
+        }
+
+        Ok(image)
+    }
+
+    fn parse_sections(mut bytes: &[u8]) -> RimResult<Self> {
+        // Offsets count the magic, to be offsets into the whole image.
+        let end = bytes.len() + 2;
+        let offset = |rest: &[u8]| end - rest.len();
+        let invalid = |tag, offset| LoadError::InvalidSection { tag, offset };
+        let mut image = Self::default();
+        let mut code = None;
+        let mut data = None;
+        let mut metadata = None;
+        let mut metadata_offset = 0;
+        let mut devices = None;
+        let mut isa = None;
+        let mut annotations = None;
+        let mut annotations_offset = 0;
+
+        while let Some((&[tag, a, b], rest)) = bytes.split_first_chunk::<3>() {
+            let header = offset(bytes);
+            let len = u16::from_be_bytes([a, b]) as usize;
+            let payload = rest.get(..len).ok_or(invalid(tag, header))?;
+            bytes = &rest[len..];
+
+            let slot = match tag {
+                SECTION_END => {
+                    if !bytes.is_empty() {
+                        image.warnings.push(Warning::TrailingBytes(bytes.len()));
+                    }
+
+                    bytes = &[];
+                    break;
+                }
+                SECTION_CODE => &mut code,
+                SECTION_DATA if len > 4096 => return Err(invalid(tag, header).into()),
+                SECTION_DATA => &mut data,
+                SECTION_METADATA => {
+                    metadata_offset = header;
+                    &mut metadata
+                }
+                SECTION_DEVICES => &mut devices,
+                SECTION_ISA if len != 1 => return Err(invalid(tag, header).into()),
+                SECTION_ISA => &mut isa,
+                SECTION_ANNOTATIONS => {
+                    annotations_offset = header;
+                    &mut annotations
+                }
+                _ => {
+                    image.warnings.push(Warning::UnknownSection(tag));
+                    continue;
+                }
+            };
+
+            if slot.replace(payload).is_some() {
+                image.warnings.push(Warning::DuplicateSection(tag));
+            }
+        }
+
+        if !bytes.is_empty() {
+            return Err(invalid(bytes[0], offset(bytes)).into());
+        }
+
+        image.code = decode(code.ok_or(invalid(SECTION_CODE, end))?)?;
+        image.data = data.unwrap_or_default().to_vec();
+        if let Some(&[level]) = isa {
+            image.isa = IsaLevel::try_from(level)?;
+        }
+
+        if let Some(metadata) = metadata {
+            let invalid = invalid(SECTION_METADATA, metadata_offset);
+            let metadata = std::str::from_utf8(metadata).map_err(|_| invalid.clone())?;
+            for line in metadata.lines() {
+                let (key, value) = line.split_once('=').ok_or(invalid.clone())?;
+                image.metadata.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        if let Some(annotations) = annotations {
+            let invalid = invalid(SECTION_ANNOTATIONS, annotations_offset);
+            let annotations = std::str::from_utf8(annotations).map_err(|_| invalid.clone())?;
+            for line in annotations.lines() {
+                let (addr, comment) = line.split_once('=').ok_or(invalid.clone())?;
+                let addr = addr.parse().map_err(|_| invalid.clone())?;
+                image.annotations.insert(addr, comment.to_string());
+            }
+        }
+
+        for (i, byte) in devices.unwrap_or_default().iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    image.required_devices.insert(i * 8 + bit);
+                }
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Encodes a v2 image, leaving out the data section if there's no data.
+    pub fn to_bytes(&self) -> RimResult<Vec<u8>> {
+        let mut bytes = MAGIC_V2.to_be_bytes().to_vec();
+
+        let code = self.code.iter().map(|instruction| instruction.encode()).collect::<RimResult<Vec<_>>>()?;
+        push_section(&mut bytes, SECTION_CODE, &code)?;
+
+        if !self.data.is_empty() {
+            push_section(&mut bytes, SECTION_DATA, &self.data)?;
+        }
+
+        if !self.metadata.is_empty() {
+            let mut metadata = String::new();
+            for (key, value) in &self.metadata {
+                if key.contains(['=', '\n']) || value.contains('\n') {
+                    return Err(LoadError::InvalidSection { tag: SECTION_METADATA, offset: bytes.len() }.into());
+                }
+
+                metadata.push_str(&format!("{key}={value}\n"));
+            }
+
+            push_section(&mut bytes, SECTION_METADATA, metadata.as_bytes())?;
+        }
+
+        if let Some(&last) = self.required_devices.last() {
+            let mut devices = vec![0; last / 8 + 1];
+            for &id in &self.required_devices {
+                devices[id / 8] |= 1 << (id % 8);
+            }
+
+            push_section(&mut bytes, SECTION_DEVICES, &devices)?;
+        }
+
+        if self.isa != IsaLevel::V1 {
+            push_section(&mut bytes, SECTION_ISA, &[self.isa as u8])?;
+        }
+
+        if !self.annotations.is_empty() {
+            let mut annotations = String::new();
+            for (addr, comment) in &self.annotations {
+                if comment.contains('\n') {
+                    return Err(LoadError::InvalidSection { tag: SECTION_ANNOTATIONS, offset: bytes.len() }.into());
+                }
+
+                annotations.push_str(&format!("{addr}={comment}\n"));
+            }
+
+            push_section(&mut bytes, SECTION_ANNOTATIONS, annotations.as_bytes())?;
+        }
+
+        push_section(&mut bytes, SECTION_END, &[])?;
+        Ok(bytes)
+    }
+
+    pub fn write_file<F: AsRef<Path>>(&self, f: F) -> RimResult<()> {
+        std::fs::write(&f, self.to_bytes()?).map_err(|e| RimError::from(e).in_file(f))
+    }
+
+    /// Checks the image's program and data against a host's limits.
+    pub fn check_limits(&self, limits: &LoadLimits) -> RimResult<()> {
+        LoadLimits::check("program length", self.code.len(), limits.max_program_len)?;
+        LoadLimits::check("data length", self.data.len(), limits.max_data_len)
+    }
+
+    /// The required devices that `rim` lacks.
+    pub fn missing_devices(&self, rim: &Rim) -> Vec<usize> {
+        self.required_devices.iter().copied().filter(|&id| !rim.has_device(id)).collect()
+    }
+
+    /// A machine ready to run the image, at its level. The warnings are
+    /// dropped.
+    pub fn into_rim(self) -> Rim {
+        let mut rim = Rim::new(self.code);
+        rim.set_isa(self.isa);
+        rim.data_mut()[..self.data.len()].copy_from_slice(&self.data);
+        rim
+    }
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+fn decompress(bytes: &[u8]) -> RimResult<Cow<'_, [u8]>> {
+    if bytes.starts_with(GZIP_MAGIC) {
+        #[cfg(feature = "gzip")]
+        return read_limited(flate2::read::GzDecoder::new(bytes)).map(Cow::Owned);
+        #[cfg(not(feature = "gzip"))]
+        return Err(LoadError::UnsupportedCompression("gzip").into());
+    }
+
+    if bytes.starts_with(ZSTD_MAGIC) {
+        #[cfg(feature = "zstd")]
+        return ruzstd::decoding::StreamingDecoder::new(bytes)
+            .map_err(|e| RimError::IoError(std::io::Error::other(e)))
+            .and_then(read_limited)
+            .map(Cow::Owned);
+        #[cfg(not(feature = "zstd"))]
+        return Err(LoadError::UnsupportedCompression("zstd").into());
+    }
+
+    Ok(Cow::Borrowed(bytes))
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn read_limited(reader: impl std::io::Read) -> RimResult<Vec<u8>> {
+    use std::io::Read;
+
+    let mut bytes = Vec::new();
+    reader.take(MAX_IMAGE_LEN as u64 + 1).read_to_end(&mut bytes)?;
+    if bytes.len() > MAX_IMAGE_LEN {
+        return Err(LoadError::ImageTooLarge.into());
+    }
+
+    Ok(bytes)
+}
+
+fn decode(bytes: &[u8]) -> RimResult<Vec<Instruction>> {
+    if bytes.len() > MAX_PROGRAM_LEN {
+        return Err(LoadError::ProgramTooLarge(bytes.len()).into());
+    }
+
+    Ok(bytes.iter().copied().map(Instruction::decode).collect())
+}
+
+fn push_section(bytes: &mut Vec<u8>, tag: u8, payload: &[u8]) -> RimResult<()> {
+    let len = u16::try_from(payload.len()).map_err(|_| LoadError::InvalidSection { tag, offset: bytes.len() })?;
+    bytes.push(tag);
+    bytes.extend_from_slice(&len.to_be_bytes());
+    bytes.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Whether an instruction calls a device function that does nothing yet.
+fn is_reserved(instruction: Instruction) -> bool {
+    match instruction.1 {
+        InstructionData::Io { device, function } => matches!(
+            (device, function as u8),
+            (Device::Kbd, 6 | 7)
+        ),
+        _ => false,
+    }
+}
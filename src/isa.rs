@@ -0,0 +1,116 @@
+//! Versions of the instruction set.
+//!
+//! Programs record the level they were written for in their image (see
+//! [`image`](crate::image)), and a machine loaded from one runs at that
+//! level, so an old program sees the instruction set it was written
+//! against even as new levels add to it. Images needing a level newer than
+//! this version of pact knows don't load.
+//!
+//! Level 1 is the original instruction set. Level 2 adds these instructions
+//! on extended opcode page 0 (see [`Rim::set_extension`]), each a byte with
+//! its operation in the low 3 bits, and the register it works on in the 2
+//! above them:
+//!
+//! | Operation | Instruction | Effect                                            |
+//! |-----------|-------------|---------------------------------------------------|
+//! | 0         | `shl r`     | Shift left, with carry set to the bit shifted out |
+//! | 1         | `shr r`     | Shift right, filling with 0                       |
+//! | 2         | `sar r`     | Shift right, keeping the sign bit                 |
+//! | 3         | `rol r`     | Rotate left, with carry set to the bit rotated    |
+//! | 4         | `ror r`     | Rotate right                                      |
+//! | 5         | `adc r`     | Add `r` and the carry flag to Ra                  |
+//! | 6         | `sbc r`     | Subtract `r` and the carry flag from Ra           |
+//!
+//! Shifts and rotates set zero, and sign from bit 7 of the result. Adding
+//! and subtracting with carry set the flags like `add` and `sub` do.
+//! Operation 7, and the top 3 bits, are reserved.
+//!
+//! Level 2 also adds system call 10 (`ioi cpu, 7` with Ra = 10), after
+//! which `ior` sends register Rb, or the one Ra selects again if Rb is past
+//! Rd. A program sending the same register over and over then doesn't have
+//! to load Ra to choose it each time. `ior`'s operands already fill the
+//! instruction, so there's no room to name the register there.
+//!
+//! And it adds system call 11, which waits for a key or an interrupt
+//! without spinning: see [`Rim::is_waiting`].
+
+use std::fmt;
+
+use crate::error::{LoadError, RimError, RimResult};
+use crate::{Rim, Status};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IsaLevel {
+    #[default]
+    V1 = 1,
+    V2 = 2,
+}
+
+impl IsaLevel {
+    /// The newest level this version of pact implements.
+    pub const LATEST: Self = Self::V2;
+}
+
+impl TryFrom<u8> for IsaLevel {
+    type Error = RimError;
+
+    fn try_from(level: u8) -> RimResult<Self> {
+        match level {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            _ => Err(LoadError::UnsupportedIsa(level).into()),
+        }
+    }
+}
+
+impl fmt::Display for IsaLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", *self as u8)
+    }
+}
+
+/// Executes an instruction from extended opcode page 0, under level 2.
+pub(crate) fn execute_v2(rim: &mut Rim, byte: u8) -> RimResult<Status> {
+    let register = (byte >> 3 & 0b11) as usize;
+    let value = rim.registers[register];
+    let carry = rim.flags.carry;
+
+    let (res, carry) = match byte & 0b111 {
+        0 => (value << 1, value & 0x80 != 0),
+        1 => (value >> 1, value & 1 != 0),
+        2 => (((value as i8) >> 1) as u8, value & 1 != 0),
+        3 => (value.rotate_left(1), value & 0x80 != 0),
+        4 => (value.rotate_right(1), value & 1 != 0),
+        5 | 6 => {
+            let ra = rim.registers[0];
+            let (sign, overflowed, res, carried) = if byte & 0b111 == 5 {
+                let exact = ra as i8 as i16 + value as i8 as i16 + carry as i16;
+                let (res, a) = ra.overflowing_add(value);
+                let (res, b) = res.overflowing_add(carry as u8);
+                (exact < 0, !(-128..=127).contains(&exact), res, a || b)
+            } else {
+                let exact = ra as i8 as i16 - value as i8 as i16 - carry as i16;
+                let (res, a) = ra.overflowing_sub(value);
+                let (res, b) = res.overflowing_sub(carry as u8);
+                (exact < 0, !(-128..=127).contains(&exact), res, a || b)
+            };
+
+            rim.check_overflow(overflowed)?;
+            rim.registers[0] = res;
+            rim.flags.sign = sign;
+            rim.flags.zero = res == 0;
+            rim.flags.carry = carried;
+            return Ok(Status::Running);
+        }
+        _ => {
+            log::warn!("reserved v2 instruction {byte:#04x} called at {:#05x}", rim.pc - 1);
+            return Ok(Status::Running);
+        }
+    };
+
+    rim.registers[register] = res;
+    rim.flags.sign = res & 0x80 != 0;
+    rim.flags.zero = res == 0;
+    rim.flags.carry = carry;
+    Ok(Status::Running)
+}
@@ -1,272 +1,1609 @@
 use std::{io::Read, path::Path, fs::File};
-use std::fmt::Debug;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::fmt::{Debug, Display};
+use std::time::{Duration, Instant};
 
+pub mod addr;
+pub mod analysis;
+pub mod asm;
+pub mod batch;
+pub mod bf;
+pub mod block;
+pub mod cache;
+pub mod cast;
+pub mod cfg;
+pub mod clipboard;
+mod codegen;
+pub mod config;
+pub mod conformance;
+pub mod console;
+#[cfg(feature = "serve")]
+pub mod control;
+pub mod counters;
+pub mod debug;
+pub mod disasm;
+pub mod disk;
+pub mod encoding;
 pub mod error;
+pub mod eval;
+pub mod explain;
+pub mod explore;
+pub mod fusion;
+#[cfg(feature = "gif")]
+pub mod gif;
+pub mod grade;
+pub mod graphics;
 pub mod helper;
+pub mod host;
+pub mod image;
+pub mod isa;
+pub mod mailbox;
+pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod microcode;
+pub mod minimize;
+pub mod package;
+#[cfg(feature = "pactc")]
+pub mod pactc;
+#[cfg(feature = "playground")]
+pub mod playground;
 pub mod prelude;
+pub mod profile;
+pub mod program;
+pub mod properties;
+pub mod runner;
+pub mod scheduler;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod screen;
+pub mod selftest;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(unix)]
+pub mod session;
+pub mod sound;
+pub mod state;
+pub mod symbols;
+pub mod taint;
+pub mod testing;
+pub mod trace;
 
-use error::{RimResult, RimError};
+use addr::Addr;
+use console::{Console, MouseEvent};
+use counters::{Counters, Latch};
+use disk::Disk;
+use encoding::Format;
+use fusion::Fusion;
+use error::{AsmError, LoadError, RimResult, RimError, RuntimeError};
+use graphics::Graphics;
 use helper::{U3, U4};
+use host::HostDevice;
+use image::LoadLimits;
+use isa::IsaLevel;
+use mailbox::{Mailbox, Port};
+use microcode::Microcode;
+use screen::{Present, Screen};
+use sound::Sound;
 
 pub const MAGIC: u16 = 0x8bca;
 
+/// The most instructions a program can have. Jump targets are 12 bits wide,
+/// so nothing past this could be jumped to.
+pub const MAX_PROGRAM_LEN: usize = 4096;
+
+/// Executes an instruction from an extended opcode page, given its byte, in
+/// place of the standard one. See [`Rim::set_extension`].
+pub type Extension = fn(&mut Rim, u8) -> RimResult<Status>;
+
+/// How long a program sleeps for per tick it asks for, under [`Rim::run`].
+pub const TICK: Duration = Duration::from_millis(10);
+
+/// How many steps [`Rim::run`] takes at a time with a
+/// [`console::Inbox`], between checking it for interrupts.
+pub const INBOX_BURST: usize = 10_000;
+
 #[inline]
 pub fn check_magic(signature: [u8; 2]) -> bool {
     ((signature[0] as u16) << 8) | signature[1] as u16 == MAGIC
 }
 
-pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Rim> {
-    let mut file = File::open(f)?;
+pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Rim> {
+    let path = f.as_ref();
+    let mut bytes = Vec::new();
+    File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)).map_err(|e| RimError::from(e).in_file(path))?;
+
+    read_bytes(&bytes).map_err(|e| e.in_file(path))
+}
+
+/// Loads a program from an in-memory image, as produced by [`to_bytes`] or
+/// [`Image::to_bytes`](image::Image::to_bytes). Warnings are logged; use
+/// [`Image::parse`](image::Image::parse) to inspect them instead.
+pub fn read_bytes(bytes: &[u8]) -> RimResult<Rim> {
+    let image = image::Image::parse(bytes)?;
+    for warning in &image.warnings {
+        log::warn!("{warning}");
+    }
+
+    Ok(image.into_rim())
+}
+
+/// Encodes a program into a v1 image, magic included.
+pub fn to_bytes(instructions: &[Instruction]) -> RimResult<Vec<u8>> {
+    let mut bytes = MAGIC.to_be_bytes().to_vec();
+    for instruction in instructions {
+        bytes.push(instruction.encode()?);
+    }
+
+    Ok(bytes)
+}
+
+pub fn write_file<F: AsRef<Path>>(f: F, instructions: &[Instruction]) -> RimResult<()> {
+    std::fs::write(&f, to_bytes(instructions)?).map_err(|e| RimError::from(e).in_file(f))
+}
+
+/// Where instructions are fetched from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Architecture {
+    /// Instructions live apart from data, and can't be modified.
+    #[default]
+    Harvard,
+    /// The executing program is copied to the start of data memory, and
+    /// each instruction is fetched and decoded from there, so programs can
+    /// modify themselves. Execution runs off the end of the program into
+    /// whatever follows, and halts at the end of memory.
+    VonNeumann,
+}
+
+/// What `Adi`, `Add`, and `Sub` do on signed overflow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// Wrap around silently.
+    #[default]
+    Wrapping,
+    /// Wrap around, and set the overflow flag; clear it otherwise.
+    Flagged,
+    /// Fault.
+    Faulting,
+}
+
+/// The machine's condition flags.
+///
+/// `Adi`, `Add`, and `Sub` set zero if their result is 0, and carry if it
+/// carried out of (or, for `Sub`, borrowed into) bit 7. They set sign if the
+/// exact result, with both operands taken as signed, is negative; unlike bit
+/// 7 of the wrapped result, this stays correct across signed overflow.
+/// Overflow is set on signed overflow, but only under
+/// [`Arithmetic::Flagged`].
+///
+/// So after `sub src, dest`, comparing `dest` against `src`:
+///
+/// | Jump          | Condition        | Taken if               |
+/// |---------------|------------------|------------------------|
+/// | `jne`         | !zero            | `dest != src`          |
+/// | `jg`          | !sign && !zero   | `dest > src`, signed   |
+/// | `jl`          | sign             | `dest < src`, signed   |
+/// | carry, any    | carry            | `dest < src`, unsigned |
+///
+/// A jump tests carry instead of its own condition if it follows the carry
+/// condition system call (`ioi cpu, 7` with Ra = 3). Swap the operands of
+/// `sub` to test `dest > src`, unsigned.
+///
+/// Mth function 6 reads the flags into Ra, and 7 sets them from a value,
+/// both using [`Flags::to_bits`]'s layout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+    sign: bool,
+    zero: bool,
+    carry: bool,
+    overflow: bool,
+}
+
+impl Flags {
+    pub fn sign(self) -> bool {
+        self.sign
+    }
+
+    pub fn zero(self) -> bool {
+        self.zero
+    }
+
+    pub fn carry(self) -> bool {
+        self.carry
+    }
+
+    pub fn overflow(self) -> bool {
+        self.overflow
+    }
+
+    /// Packs the flags into a byte: sign in bit 0, zero in bit 1, carry in
+    /// bit 2, and overflow in bit 3.
+    pub fn to_bits(self) -> u8 {
+        self.sign as u8
+            | (self.zero as u8) << 1
+            | (self.carry as u8) << 2
+            | (self.overflow as u8) << 3
+    }
+
+    /// The inverse of [`Flags::to_bits`], ignoring the high 4 bits.
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            sign: bits & 0b0001 != 0,
+            zero: bits & 0b0010 != 0,
+            carry: bits & 0b0100 != 0,
+            overflow: bits & 0b1000 != 0,
+        }
+    }
+}
+
+/// Formats the flags as `SZCO`, with a `-` for each flag that isn't set.
+impl Display for Flags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (set, c) in [(self.sign, 'S'), (self.zero, 'Z'), (self.carry, 'C'), (self.overflow, 'O')] {
+            write!(f, "{}", if set { c } else { '-' })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What an interrupt handler returns to.
+#[derive(Debug, Clone, Copy)]
+struct Interrupted {
+    snapshot: Snapshot,
+    bank: Option<u8>,
+    opcode_page: Option<u8>,
+    ior_source: Option<Register>,
+    carry_condition: bool,
+}
+
+/// The processor state of a machine at a point in time, without memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    pub slot: usize,
+    pub pc: usize,
+    pub registers: [u8; 4],
+    pub flags: Flags,
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [ra, rb, rc, rd] = self.registers;
+        write!(f, "pc={:04x} ra={ra:02x} rb={rb:02x} rc={rc:02x} rd={rd:02x} flags={}", self.pc, self.flags)
+    }
+}
+
+/// Counts of a machine's I/O, for billing or limiting it apart from steps.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IoStats {
+    /// Characters put on the screen.
+    pub screen_bytes: u64,
+    /// Keys read from the keyboard, not counting reads with no input.
+    pub keys_read: u64,
+    /// Disk bytes read, including boot images.
+    pub disk_read: u64,
+    pub disk_written: u64,
+    /// System calls, which hand control to the host.
+    pub syscalls: u64,
+}
+
+/// Whether a program can keep running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Halted,
+}
+
+/// How far [`Rim::burst`] got.
+#[derive(Debug)]
+pub struct Burst {
+    pub steps: usize,
+    /// What the last step returned.
+    pub result: RimResult<Status>,
+}
+
+/// A Rim program.
+///
+/// Code and data addresses are both 12 bits wide: the high 8 bits of
+/// an address come from Rd, and the low 4 from the instruction (or, for
+/// pointers, from memory, and for the CPU memory functions, a register).
+/// See [`Addr`] for the details. A jump lands on instruction `(Rd << 4) | addr`,
+/// and programs longer than 16 instructions must set Rd to the target's
+/// page before jumping across pages. Taking a jump to an address past the
+/// end of the program is a fault; running off the end halts. The host can
+/// give a program less data memory (see [`Rim::set_memory_len`]), and
+/// reaching past the end of it is a fault too.
+///
+/// Nothing a program does can panic the host: selecting a register that
+/// doesn't exist, dividing by zero, and the like are faults too.
+///
+/// A machine and all its devices are `Send` and `Sync`, so it can run on a
+/// worker thread, or be shared as an `Arc<Mutex<Rim>>` and inspected
+/// between steps. Devices shared between machines, like a
+/// [`Mailbox`], are handles that clone cheaply and lock internally.
+#[derive(Clone)]
+pub struct Rim {
+    programs: Vec<Arc<Vec<Instruction>>>,
+    load_limits: LoadLimits,
+    current: usize,
+    pc: usize,
+
+    registers: [u8; 4],
+    flags: Flags,
+    /// Shared with forks until either writes to it.
+    data: Arc<[u8; 4096]>,
+    /// How much of `data` the program can reach.
+    memory_len: usize,
+
+    architecture: Architecture,
+    arithmetic: Arithmetic,
+    isa: IsaLevel,
+    microcode: [Microcode; 8],
+    /// Whether any opcode's microcode was replaced, so pairs can't be fused.
+    custom_microcode: bool,
+    fusion: Fusion,
+    fused: Option<Arc<fusion::Table>>,
+
+    /// Whether the next jump tests the carry flag instead of its own condition.
+    carry_condition: bool,
+    /// The bank of the next I/O instruction, if an extension device.
+    bank: Option<u8>,
+    /// The opcode page the next instruction is decoded from, if extended.
+    opcode_page: Option<u8>,
+    /// The register `ior` sends, if it's been chosen with system call 10
+    /// rather than being the one Ra selects.
+    ior_source: Option<Register>,
+    extensions: BTreeMap<u8, Extension>,
+    host_devices: BTreeMap<usize, Arc<Mutex<dyn HostDevice>>>,
+    disk: Option<Disk>,
+    graphics: Option<Graphics>,
+    sound: Option<Sound>,
+    mailbox: Option<Port>,
+    /// Whether the last step waited on an empty mailbox queue.
+    blocked: bool,
+    /// Whether the last step waited for a key, with system call 11.
+    waiting: bool,
+    /// The slot and address of the interrupt handler, if the program set one.
+    interrupt_handler: Option<(usize, usize)>,
+    interrupted: Option<Interrupted>,
+    /// The same for the fault handler, and the code and address of the last
+    /// fault it handled.
+    fault_handler: Option<(usize, usize)>,
+    fault: Option<(u8, usize)>,
+    /// Ticks the program asked to sleep for, until the runner takes them.
+    sleep: Option<u8>,
+    console: Console,
+    /// The last mouse event the program took.
+    mouse: MouseEvent,
+    /// Device IDs the program may not use.
+    denied: BTreeSet<usize>,
+    io_stats: IoStats,
+    counters: Counters,
+    latch: Latch,
+    /// Pages of data memory shared with the host, and the addresses in them
+    /// the program has stored to since the host last looked.
+    shared: BTreeSet<u8>,
+    shared_writes: BTreeSet<usize>,
+
+    screen: Screen,
+    present: Present,
+    /// Reused to render the screen into, so presenting doesn't allocate.
+    frame: Vec<u8>,
+    last_present: Option<Instant>,
+}
+
+// Embedders rely on moving machines across threads, so losing this is a
+// breaking change.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Rim>();
+    assert_send_sync::<Mailbox>();
+    assert_send_sync::<scheduler::Scheduler>();
+    assert_send_sync::<RimError>();
+};
+
+impl Rim {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Self {
+            programs: vec![Arc::new(instructions)],
+            ..Default::default()
+        }
+    }
+
+    /// Loads another program into the next free slot, returning the slot.
+    /// Programs share registers and data memory, so a large program can be
+    /// split into stages that hand off to each other.
+    pub fn load(&mut self, instructions: Vec<Instruction>) -> RimResult<usize> {
+        if instructions.len() > MAX_PROGRAM_LEN {
+            return Err(LoadError::ProgramTooLarge(instructions.len()).into());
+        }
+
+        LoadLimits::check("program length", instructions.len(), self.load_limits.max_program_len)?;
+        LoadLimits::check("program count", self.programs.len() + 1, self.load_limits.max_programs)?;
+
+        self.programs.push(Arc::new(instructions));
+        Ok(self.programs.len() - 1)
+    }
+
+    /// Appends instructions to the executing program, so they run once pc
+    /// reaches them. Under the von Neumann architecture, they're also
+    /// written to data memory.
+    pub fn extend(&mut self, instructions: &[Instruction]) -> RimResult<()> {
+        let program = Arc::make_mut(&mut self.programs[self.current]);
+        let len = program.len() + instructions.len();
+        if len > MAX_PROGRAM_LEN {
+            return Err(LoadError::ProgramTooLarge(len).into());
+        }
+
+        let start = program.len();
+        program.extend_from_slice(instructions);
+
+        if self.architecture == Architecture::VonNeumann {
+            for (byte, instruction) in Arc::make_mut(&mut self.data)[start..len].iter_mut().zip(instructions) {
+                *byte = u8::from(*instruction);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_limits(&self) -> LoadLimits {
+        self.load_limits
+    }
+
+    /// Caps the programs loaded from now on, including by the `boot`
+    /// system call.
+    pub fn set_load_limits(&mut self, limits: LoadLimits) {
+        self.load_limits = limits;
+    }
+
+    /// Switches execution to the start of the program in `slot`. Under the
+    /// von Neumann architecture, this copies it into data memory.
+    pub fn switch(&mut self, slot: usize) -> RimResult<()> {
+        if slot >= self.programs.len() {
+            return Err(RuntimeError::NoSuchProgram(slot).into());
+        }
+
+        self.current = slot;
+        self.pc = 0;
+
+        if self.architecture == Architecture::VonNeumann {
+            self.copy_program_to_data();
+        }
+
+        Ok(())
+    }
+
+    pub fn architecture(&self) -> Architecture {
+        self.architecture
+    }
+
+    /// Switching to the von Neumann architecture copies the executing
+    /// program into data memory, overwriting its start.
+    pub fn set_architecture(&mut self, architecture: Architecture) {
+        self.architecture = architecture;
+
+        if architecture == Architecture::VonNeumann {
+            self.copy_program_to_data();
+        }
+    }
+
+    fn copy_program_to_data(&mut self) {
+        let program = &self.programs[self.current];
+        for (byte, instruction) in Arc::make_mut(&mut self.data).iter_mut().zip(program.iter()) {
+            *byte = u8::from(*instruction);
+        }
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+        self.flags.overflow = false;
+    }
+
+    pub fn isa(&self) -> IsaLevel {
+        self.isa
+    }
+
+    /// Sets the instruction set level to run programs at. Machines start at
+    /// [`IsaLevel::LATEST`], or the level an image asks for.
+    pub fn set_isa(&mut self, isa: IsaLevel) {
+        self.isa = isa;
+    }
+
+    /// Replaces what an opcode does. See [`microcode`].
+    pub fn set_microcode(&mut self, opcode: Opcode, microcode: Microcode) {
+        self.microcode[opcode as usize] = microcode;
+        self.custom_microcode = true;
+    }
+
+    pub fn microcode(&self, opcode: Opcode) -> Microcode {
+        self.microcode[opcode as usize]
+    }
+
+    /// Restores the standard semantics of every opcode.
+    pub fn reset_microcode(&mut self) {
+        self.microcode = microcode::DEFAULT;
+        self.custom_microcode = false;
+    }
+
+    pub fn fusion(&self) -> Fusion {
+        self.fusion
+    }
+
+    /// Chooses the pairs of instructions that can share a block in
+    /// [`Rim::burst`]. See [`fusion`].
+    pub fn set_fusion(&mut self, fusion: Fusion) {
+        self.fusion = fusion;
+    }
+
+    fn check_overflow(&mut self, overflowed: bool) -> RimResult<()> {
+        match self.arithmetic {
+            Arithmetic::Wrapping => {}
+            Arithmetic::Flagged => self.flags.overflow = overflowed,
+            Arithmetic::Faulting if overflowed => return Err(RuntimeError::Overflow(self.pc - 1).into()),
+            Arithmetic::Faulting => {}
+        }
+
+        Ok(())
+    }
+
+    /// Whether a jump with the given condition is taken, unless a carry
+    /// condition was requested, in which case the carry flag decides.
+    fn condition(&mut self, condition: bool) -> bool {
+        let taken = if std::mem::take(&mut self.carry_condition) {
+            self.flags.carry
+        } else {
+            condition
+        };
+
+        self.counters.jumps += 1;
+        self.counters.taken += taken as u64;
+        taken
+    }
+
+    /// Moves pc to a jump target, faulting if there's no instruction there.
+    fn jump(&mut self, target: usize) -> RimResult<()> {
+        let len = match self.architecture {
+            Architecture::Harvard => self.programs[self.current].len(),
+            Architecture::VonNeumann => self.memory_len,
+        };
+
+        if target >= len {
+            return Err(RuntimeError::JumpOutOfRange { target, pc: self.pc - 1 }.into());
+        }
+
+        self.pc = target;
+        Ok(())
+    }
+
+    /// The instruction at pc, if any.
+    pub fn next_instruction(&self) -> Option<Instruction> {
+        match self.architecture {
+            Architecture::Harvard => self.programs[self.current].get(self.pc).copied(),
+            Architecture::VonNeumann => self.data[..self.memory_len].get(self.pc).copied().map(Instruction::decode),
+        }
+    }
+
+    pub fn attach_disk(&mut self, disk: Disk) {
+        self.disk = Some(disk);
+    }
+
+    pub fn detach_disk(&mut self) -> Option<Disk> {
+        self.disk.take()
+    }
+
+    pub fn disk(&self) -> Option<&Disk> {
+        self.disk.as_ref()
+    }
+
+    pub fn attach_graphics(&mut self, graphics: Graphics) {
+        self.graphics = Some(graphics);
+    }
+
+    pub fn detach_graphics(&mut self) -> Option<Graphics> {
+        self.graphics.take()
+    }
+
+    pub fn graphics(&self) -> Option<&Graphics> {
+        self.graphics.as_ref()
+    }
+
+    pub fn graphics_mut(&mut self) -> Option<&mut Graphics> {
+        self.graphics.as_mut()
+    }
+
+    pub fn attach_sound(&mut self, sound: Sound) {
+        self.sound = Some(sound);
+    }
+
+    pub fn detach_sound(&mut self) -> Option<Sound> {
+        self.sound.take()
+    }
+
+    pub fn sound(&self) -> Option<&Sound> {
+        self.sound.as_ref()
+    }
+
+    pub fn sound_mut(&mut self) -> Option<&mut Sound> {
+        self.sound.as_mut()
+    }
+
+    /// Connects the machine to a mailbox, which clones of it attached to
+    /// other machines share.
+    pub fn attach_mailbox(&mut self, mailbox: Mailbox) {
+        self.mailbox = Some(Port::new(mailbox));
+    }
+
+    pub fn detach_mailbox(&mut self) -> Option<Mailbox> {
+        self.mailbox.take().map(|port| port.mailbox)
+    }
+
+    pub fn mailbox(&self) -> Option<&Mailbox> {
+        self.mailbox.as_ref().map(|port| &port.mailbox)
+    }
+
+    /// Jumps to the program's interrupt handler, unless it hasn't set one or
+    /// is already in it, returning whether it did. What the handler
+    /// interrupted is saved, and restored when it returns, along with the
+    /// registers and flags.
+    pub fn interrupt(&mut self) -> RimResult<bool> {
+        let Some((slot, addr)) = self.interrupt_handler else {
+            return Ok(false);
+        };
+
+        if self.interrupted.is_some() {
+            return Ok(false);
+        }
+
+        // An interrupt ends a wait, so the handler returns past it.
+        if std::mem::take(&mut self.waiting) {
+            self.pc += 1;
+        }
+
+        self.interrupted = Some(Interrupted {
+            snapshot: self.snapshot(),
+            bank: self.bank.take(),
+            opcode_page: self.opcode_page.take(),
+            ior_source: self.ior_source.take(),
+            carry_condition: std::mem::take(&mut self.carry_condition),
+        });
+
+        self.jump_to(slot, addr)?;
+        Ok(true)
+    }
+
+    /// Whether the program is in its interrupt handler.
+    pub fn in_interrupt(&self) -> bool {
+        self.interrupted.is_some()
+    }
+
+    /// Adds a page of extended instructions. After system call 9 selects
+    /// page `page`, the next instruction's byte, whatever it decodes to
+    /// normally, is passed to `extension` instead, after the program counter
+    /// has moved past it. So every 3-bit opcode stays as it is, and each page
+    /// has room for 256 more instructions. Calling a page with nothing in it
+    /// skips the instruction.
+    ///
+    /// Pages 0 to 127 are for versions of the instruction set (see
+    /// [`isa`]), and the rest for embedders. Pages a machine's level defines
+    /// can't be replaced.
+    pub fn set_extension(&mut self, page: u8, extension: Extension) {
+        self.extensions.insert(page, extension);
+    }
+
+    pub fn remove_extension(&mut self, page: u8) -> Option<Extension> {
+        self.extensions.remove(&page)
+    }
+
+    /// Attaches a device the host provides, by its ID (see the [`host`]
+    /// module). The built-in devices can't be replaced, so IDs below
+    /// [`host::FIRST_HOST_DEVICE`] are ignored.
+    pub fn attach_device(&mut self, id: usize, device: impl HostDevice + 'static) {
+        if id < host::FIRST_HOST_DEVICE {
+            log::warn!("can't attach a host device as built-in device {}", image::device_name(id));
+            return;
+        }
+
+        self.host_devices.insert(id, Arc::new(Mutex::new(device)));
+    }
+
+    pub fn detach_device(&mut self, id: usize) -> bool {
+        self.host_devices.remove(&id).is_some()
+    }
+
+    /// Sends a fault to the fault handler, if the program set one and can
+    /// recover from it. Handling a fault unsets the handler, so a fault in
+    /// the handler itself ends the program, unless it sets it again.
+    fn vector(&mut self, e: RimError) -> RimResult<Status> {
+        let (Some((slot, addr)), Some(code)) = (self.fault_handler, e.fault_code()) else {
+            return Err(e);
+        };
+
+        self.fault_handler = None;
+        self.fault = Some((code, self.pc.saturating_sub(1)));
+        self.bank = None;
+        self.opcode_page = None;
+        self.ior_source = None;
+        self.carry_condition = false;
+        self.jump_to(slot, addr)?;
+        Ok(Status::Running)
+    }
+
+    fn jump_to(&mut self, slot: usize, pc: usize) -> RimResult<()> {
+        if slot != self.current {
+            self.switch(slot)?;
+        }
+
+        self.pc = pc;
+        Ok(())
+    }
+
+    /// Whether the last step blocked receiving from an empty mailbox queue,
+    /// or waiting for the next frame, and so didn't advance. It's retried on
+    /// the next step.
+    pub fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+
+    /// Whether the last step waited for a key or an interrupt (system call
+    /// 11), and so didn't advance. Like a blocked receive, the wait is
+    /// retried on the next step, so runners can stop stepping the machine
+    /// until they've sent it keys or interrupted it.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// Whether a device is present, by its ID (see the [`image`] module).
+    pub fn has_device(&self, id: usize) -> bool {
+        match id {
+            0..=3 | image::DEVICE_COUNTERS => true,
+            image::DEVICE_DISK => self.disk.is_some(),
+            image::DEVICE_GRAPHICS => self.graphics.is_some(),
+            image::DEVICE_SOUND => self.sound.is_some(),
+            image::DEVICE_MAILBOX => self.mailbox.is_some(),
+            _ => self.host_devices.contains_key(&id),
+        }
+    }
+
+    /// Forbids the program from using a device, by its ID. Trying to is a
+    /// fault, so untrusted programs can be kept away from the host.
+    pub fn deny_device(&mut self, id: usize) {
+        self.denied.insert(id);
+    }
+
+    pub fn allow_device(&mut self, id: usize) {
+        self.denied.remove(&id);
+    }
+
+    pub fn is_allowed(&self, id: usize) -> bool {
+        !self.denied.contains(&id)
+    }
+
+    fn check_access(&self, id: usize) -> RimResult<()> {
+        if self.is_allowed(id) {
+            Ok(())
+        } else {
+            Err(RuntimeError::DeviceDenied { device: id, pc: self.pc - 1 }.into())
+        }
+    }
+
+    pub fn io_stats(&self) -> IoStats {
+        self.io_stats
+    }
+
+    pub fn reset_io_stats(&mut self) {
+        self.io_stats = IoStats::default();
+    }
+
+    /// What the machine has executed (see [`counters`]).
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    pub fn reset_counters(&mut self) {
+        self.counters = Counters::default();
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// The screen, for frontends to [take its dirty cells](Screen::take_dirty).
+    pub fn screen_mut(&mut self) -> &mut Screen {
+        &mut self.screen
+    }
+
+    pub fn present_mode(&self) -> Present {
+        self.present
+    }
+
+    pub fn set_present_mode(&mut self, present: Present) {
+        self.present = present;
+    }
+
+    /// Sends the whole screen to the console.
+    pub fn present(&mut self) {
+        self.frame.clear();
+        self.screen.render(&mut self.frame);
+        self.console.write(&self.frame);
+        self.last_present = Some(Instant::now());
+    }
+
+    /// How long until the next frame is due under [`Present::Rate`], for
+    /// runners to sleep through while the program waits for it.
+    pub fn until_next_frame(&self) -> Option<Duration> {
+        match self.present {
+            Present::Rate(interval) => Some(self.last_present.map_or(Duration::ZERO, |last| interval.saturating_sub(last.elapsed()))),
+            _ => None,
+        }
+    }
+
+    /// Waits for the next frame (screen function 7), presenting it if the
+    /// screen changed. Until it's due, the call is retried like a blocked
+    /// receive. Without a rate, every call is a frame.
+    fn wait_for_frame(&mut self) {
+        match self.until_next_frame() {
+            Some(wait) if !wait.is_zero() => {
+                self.pc -= 1;
+                self.blocked = true;
+            }
+            _ if self.present == Present::Immediate => {}
+            _ if self.screen.changed() => self.present(),
+            _ => self.last_present = Some(Instant::now()),
+        }
+    }
+
+    /// Shows a screen function's effect: right away under
+    /// [`Present::Immediate`], or in the next frame if one is due.
+    fn update_screen(&mut self, function: U3, value: u8) {
+        match self.present {
+            Present::Immediate => match function as u8 {
+                0 | 1 | 5 => {
+                    self.frame.clear();
+                    if function as u8 == 5 {
+                        self.frame.extend_from_slice(b"\x1b[2J");
+                    }
+                    self.screen.cursor_escape(&mut self.frame);
+                    self.console.write(&self.frame);
+                }
+                2 => self.console.write(&[value]),
+                _ => {}
+            },
+            Present::Manual => {}
+            Present::Rate(interval) => {
+                let due = self.last_present.is_none_or(|last| last.elapsed() >= interval);
+                if due && self.screen.changed() {
+                    self.present();
+                }
+            }
+        }
+    }
+
+    /// Replaces the keyboard and screen, returning the old ones.
+    pub fn set_console(&mut self, console: Console) -> Console {
+        std::mem::replace(&mut self.console, console)
+    }
+
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    /// The slot of the executing program.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Runs until the program halts, sleeping when it asks to.
+    ///
+    /// While it waits for a key, a machine with a [`console::Inbox`] parks
+    /// until a key is sent or an interrupt is asked for, and takes those
+    /// interrupts between bursts of [`INBOX_BURST`] steps. Other consoles
+    /// can't get keys while it runs, so it just sleeps a tick at a time.
+    pub fn run(&mut self) -> RimResult<()> {
+        let steps = if matches!(self.console, Console::Inbox(_)) { INBOX_BURST } else { usize::MAX };
+        loop {
+            if let Console::Inbox(inbox) = &self.console {
+                if self.waiting {
+                    inbox.wait();
+                }
+
+                if inbox.take_interrupt() {
+                    self.interrupt()?;
+                }
+            } else if self.waiting {
+                std::thread::sleep(TICK);
+            }
+
+            if self.burst(steps).result? == Status::Halted {
+                break;
+            }
+
+            if let Some(wait) = self.until_next_frame().filter(|_| self.blocked) {
+                std::thread::sleep(wait);
+            }
+
+            match self.take_sleep() {
+                Some(0) => std::thread::yield_now(),
+                Some(ticks) => std::thread::sleep(TICK * ticks as u32),
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the program for up to `budget` of wall-clock time, then returns
+    /// control, so a frontend can keep its event loop going while the
+    /// machine runs in the background. Calling it again picks up where it
+    /// left off.
+    ///
+    /// Like [`Rim::burst`], it also stops early after a step that halts,
+    /// faults, blocks, waits, or asks to sleep, without waiting itself, so
+    /// the caller can deliver input or check [`Rim::take_sleep`] before its
+    /// next frame. The clock is checked every few thousand steps, so the
+    /// budget can be overrun by about that many.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use pact::Status;
+    ///
+    /// let mut rim = pact::read_file("game.rim").unwrap();
+    /// loop {
+    ///     // Handle the window's events...
+    ///     if rim.run_for(Duration::from_millis(12)).result.unwrap() == Status::Halted {
+    ///         break;
+    ///     }
+    ///     // ...and draw the screen.
+    /// }
+    /// ```
+    pub fn run_for(&mut self, budget: Duration) -> Burst {
+        // Checking the clock every step would dominate short instructions.
+        const CLOCK_INTERVAL: usize = 4096;
+
+        let start = Instant::now();
+        let mut steps = 0;
+        loop {
+            let burst = self.burst(CLOCK_INTERVAL);
+            steps += burst.steps;
+
+            let stopped = burst.steps < CLOCK_INTERVAL || self.blocked || self.waiting || !matches!(burst.result, Ok(Status::Running));
+            if stopped || start.elapsed() >= budget {
+                return Burst { steps, result: burst.result };
+            }
+        }
+    }
+
+    /// The ticks the program last asked to sleep for, if it has since this
+    /// was last called. 0 means it's just yielding. Runners that step the
+    /// machine themselves can use this to stop running it for a while.
+    pub fn take_sleep(&mut self) -> Option<u8> {
+        self.sleep.take()
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> RimResult<Status> {
+        self.blocked = false;
+        self.waiting = false;
+        let result = self.execute();
+        self.counters.cycles += 1;
+        self.counters.instructions += !(self.blocked || self.waiting) as u64;
+
+        let status = match result {
+            Ok(status) => status,
+            Err(e) => return self.vector(e),
+        };
+        if status == Status::Halted && self.present != Present::Immediate && self.screen.changed() {
+            self.present();
+        }
+
+        Ok(status)
+    }
+
+    /// Executes up to `max_steps` instructions, exactly as that many calls
+    /// to [`Rim::step`] would, but faster, by executing basic blocks with
+    /// their dispatch skipped and jumping to targets resolved ahead of time
+    /// (see [`fusion`]). Stops early after a step that halts, faults, blocks
+    /// on a mailbox, waits for a key, or asks to sleep (when it hadn't
+    /// already), so the caller can deal with it as it would after a step.
+    pub fn burst(&mut self, max_steps: usize) -> Burst {
+        let mut steps = 0;
+        let sleeping = self.sleep.is_some();
+        let predecoded = self.architecture == Architecture::Harvard && !self.custom_microcode;
+
+        while steps < max_steps {
+            let block = if predecoded && self.opcode_page.is_none() {
+                let pc = self.pc;
+                fusion::table(self).block(pc).min(max_steps - steps)
+            } else {
+                0
+            };
+
+            let result = if block > 0 {
+                self.blocked = false;
+                self.waiting = false;
+                self.run_block(block, &mut steps)
+            } else {
+                steps += 1;
+                self.step()
+            };
+
+            match result {
+                Ok(Status::Running) if !self.blocked && !self.waiting && (sleeping || self.sleep.is_none()) => {}
+                result => return Burst { steps, result },
+            }
+        }
+
+        Burst { steps, result: Ok(Status::Running) }
+    }
+
+    /// Executes the first `len` instructions of the block at pc, which can
+    /// only end early with a fault.
+    fn run_block(&mut self, len: usize, steps: &mut usize) -> RimResult<Status> {
+        for _ in 0..len {
+            let instruction = self.programs[self.current][self.pc];
+            self.pc += 1;
+            *steps += 1;
+            let result = fusion::execute(self, instruction);
+            self.counters.cycles += 1;
+            self.counters.instructions += 1;
+            if let Err(e) = result {
+                return self.vector(e);
+            }
+        }
 
-    let mut signature = [0; 2];
-    file.read_exact(&mut signature)?;
-    if !check_magic(signature) {
-        return Err(RimError::InvalidMagic);
+        Ok(Status::Running)
     }
 
-    let mut instructions = Vec::new();
+    fn execute(&mut self) -> RimResult<Status> {
+        let Some(instruction) = self.next_instruction() else {
+            return Ok(Status::Halted);
+        };
 
-    let mut buf = [0u8; 12];
+        self.pc += 1;
 
-    while let Ok(read) = file.read(&mut buf) {
-        if read == 0 {
-            break;
+        if let Some(page) = self.opcode_page.take() {
+            return self.execute_extended(page, instruction.into());
         }
 
-        for &byte in &buf[0..read] {
-            let opcode: Opcode = byte.into();
-            let data = opcode.parse_data(byte & 0b1111_1000);
+        let microcode = self.microcode[instruction.0 as usize];
+        microcode(self, instruction.1)
+    }
+
+    fn execute_extended(&mut self, page: u8, byte: u8) -> RimResult<Status> {
+        if page == 0 && self.isa >= IsaLevel::V2 {
+            return isa::execute_v2(self, byte);
+        }
 
-            instructions.push(Instruction(opcode, data));
+        match self.extensions.get(&page) {
+            Some(extension) => extension(self, byte),
+            None => {
+                log::warn!("missing extended opcode page {page} called at {:#05x}", self.pc - 1);
+                Ok(Status::Running)
+            }
         }
     }
 
-    Ok(Rim {
-        instructions,
-        ..Default::default()
-    })
-}
+    /// The register a program selected by number, which it can't always
+    /// be trusted to keep in range.
+    fn register(&self, selector: u8) -> RimResult<u8> {
+        self.registers
+            .get(selector as usize)
+            .copied()
+            .ok_or(RuntimeError::InvalidRegister { register: selector, pc: self.pc - 1 }.into())
+    }
 
-/// A Rim program.
-#[derive(Clone)]
-pub struct Rim {
-    instructions: Vec<Instruction>,
-    pc: usize,
+    /// The executing program.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.programs[self.current]
+    }
 
-    registers: [u8; 4],
-    flags: [bool; 2],
-    data: [u8; 4096],
-}
+    /// The program in a slot, sharing its instructions with the machine.
+    pub fn program(&self, slot: usize) -> Option<program::Program> {
+        self.programs.get(slot).cloned().map(Into::into)
+    }
 
-impl Rim {
-    pub fn run(&mut self) -> RimResult<()> {
-        for instruction in self.instructions.clone() {
-            match instruction.0 {
-                Opcode::Adi => {
-                    let imm = instruction.1.as_imm();
-                    let res = self.registers[0].wrapping_add(imm);
-                    self.registers[0] = res;
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
 
-                    self.flags[0] = false;
-                    self.flags[1] = res == 0;
-                }
-                Opcode::Add => {
-                    let (is_id, src, dest) = instruction.1.as_reg();
-                    let (src, dest) = if is_id {
-                        (
-                            Register::from(self.registers[src as usize]) as usize,
-                            Register::from(self.registers[dest as usize]) as usize,
-                        )
-                    } else {
-                        (
-                            src as usize,
-                            dest as usize,
-                        )
-                    };
-
-                    let res = self.registers[dest].wrapping_add(self.registers[src]);
-                    self.registers[dest] = res;
-
-                    self.flags[0] = false;
-                    self.flags[1] = res == 0;
-                }
-                Opcode::Sub => {
-                    let (is_id, src, dest) = instruction.1.as_reg();
-                    let (src, dest) = if is_id {
-                        (
-                            Register::from(self.registers[src as usize]) as usize,
-                            Register::from(self.registers[dest as usize]) as usize,
-                        )
-                    } else {
-                        (
-                            src as usize,
-                            dest as usize,
-                        )
-                    };
-
-                    let (res, sign) = self.registers[dest].overflowing_sub(self.registers[src]);
-                    self.registers[dest as usize] = res;
-
-                    self.flags[0] = sign;
-                    self.flags[1] = res == 0;
-                }
-                Opcode::Jne => {
-                    let (is_ptr, addr) = instruction.1.as_mem();
-                    let mut addr = ((self.registers[3] as usize) << 4) | addr as usize;
-                    if is_ptr {
-                        addr = ((self.registers[3] as usize) << 4) | self.data[addr] as usize;
-                    }
+    pub fn registers(&self) -> [u8; 4] {
+        self.registers
+    }
 
-                    if !self.flags[1] {
-                        self.pc = addr;
-                    }
-                }
-                Opcode::Jg => {
-                    let (is_ptr, addr) = instruction.1.as_mem();
-                    let mut addr = ((self.registers[3] as usize) << 4) | addr as usize;
-                    if is_ptr {
-                        addr = ((self.registers[3] as usize) << 4) | self.data[addr] as usize;
-                    }
+    pub fn set_registers(&mut self, registers: [u8; 4]) {
+        self.registers = registers;
+    }
 
-                    if self.flags[0] {
-                        self.pc = addr;
-                    }
-                }
-                Opcode::Jl => {
-                    let (is_ptr, addr) = instruction.1.as_mem();
-                    let mut addr = ((self.registers[3] as usize) << 4) | addr as usize;
-                    if is_ptr {
-                        addr = ((self.registers[3] as usize) << 4) | self.data[addr] as usize;
-                    }
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
 
-                    if !self.flags[0] && !self.flags[1] {
-                        self.pc = addr;
-                    }
-                }
-                Opcode::Ioi => {
-                    let (device, function) = instruction.1.as_io();
-                    if self.io(device, function, self.registers[0])? {
-                        return Ok(());
-                    }
-                }
-                Opcode::Ior => {
-                    let (device, function) = instruction.1.as_io();
-                    if self.io(device, function, self.registers[self.registers[0] as usize])? {
-                        return Ok(());
-                    }
-                }
-            }
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+
+    /// An independent copy of the machine as it is now, which can run on
+    /// separately, for exploring different futures of a program. Memory and
+    /// programs are shared until one side writes to them, so forking is
+    /// cheap. Devices shared between machines, like a mailbox, stay shared.
+    pub fn fork(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            slot: self.current,
+            pc: self.pc,
+            registers: self.registers,
+            flags: self.flags,
+        }
+    }
+
+    pub fn data(&self) -> &[u8; 4096] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8; 4096] {
+        Arc::make_mut(&mut self.data)
+    }
+
+    /// How many bytes of data memory the program can reach.
+    pub fn memory_len(&self) -> usize {
+        self.memory_len
+    }
+
+    /// Gives the program only the first `len` bytes of data memory, rounded
+    /// up to a whole page, and at most all of it. Loads, stores, pointers,
+    /// and block transfers past them fault, and under the von Neumann
+    /// architecture, so does jumping past them. The host can still reach
+    /// all of it through [`data`](Self::data).
+    ///
+    /// ```
+    /// use pact::{asm, Rim};
+    ///
+    /// let mut rim = Rim::new(asm::assemble("
+    ///     li rd, 0x40
+    ///     ioi cpu, 4      ; data[0x400] = 0
+    /// ").unwrap());
+    /// rim.set_memory_len(1024);
+    /// assert_eq!(rim.run().unwrap_err().kind(), "memory_out_of_range");
+    /// ```
+    pub fn set_memory_len(&mut self, len: usize) {
+        self.memory_len = len.next_multiple_of(Addr::PAGE_SIZE).min(Addr::COUNT);
+    }
+
+    /// Starts editing data memory in a transaction, whose writes land only
+    /// if it's committed. See [`memory`].
+    pub fn memory_transaction(&mut self) -> memory::Transaction<'_> {
+        memory::Transaction::new(self)
+    }
+
+    /// Shares a page of data memory, the 16 bytes from `page << 4`, with the
+    /// host. Between steps, either side can read and write it, and the host
+    /// can see which bytes the program stored to, so it can exchange data
+    /// faster than a byte per I/O instruction.
+    pub fn share_page(&mut self, page: u8) {
+        self.shared.insert(page);
+    }
+
+    pub fn unshare_page(&mut self, page: u8) {
+        self.shared.remove(&page);
+        self.shared_writes.retain(|&addr| addr >> 4 != page as usize);
+    }
+
+    pub fn shared_page(&self, page: u8) -> Option<&[u8]> {
+        let start = Addr::new(page, 0).index();
+        self.shared.contains(&page).then(|| &self.data[start..start + Addr::PAGE_SIZE])
+    }
+
+    pub fn shared_page_mut(&mut self, page: u8) -> Option<&mut [u8]> {
+        let start = Addr::new(page, 0).index();
+        self.shared.contains(&page).then(|| &mut Arc::make_mut(&mut self.data)[start..start + Addr::PAGE_SIZE])
+    }
+
+    /// The addresses in shared pages the program stored to since this was
+    /// last called, in order.
+    pub fn take_shared_writes(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.shared_writes).into_iter().collect()
+    }
+
+    /// The address of an offset in Rd's page.
+    pub(crate) fn addr(&self, offset: u8) -> Addr {
+        Addr::new(self.registers[3], offset)
+    }
+
+    /// The byte at an address, faulting if it's past the end of memory.
+    pub(crate) fn read(&self, addr: Addr) -> RimResult<u8> {
+        self.check_memory(addr)?;
+        Ok(self.data[addr.index()])
+    }
+
+    fn check_memory(&self, addr: Addr) -> RimResult<()> {
+        if addr.index() >= self.memory_len {
+            return Err(RuntimeError::MemoryOutOfRange { addr: addr.index(), pc: self.pc - 1 }.into());
+        }
+
+        Ok(())
+    }
+
+    fn store(&mut self, addr: Addr, value: u8) -> RimResult<()> {
+        self.check_memory(addr)?;
+        Arc::make_mut(&mut self.data)[addr.index()] = value;
+        if self.shared.contains(&addr.page()) {
+            self.shared_writes.insert(addr.index());
         }
 
         Ok(())
     }
 
     fn io(&mut self, device: Device, function: U3, value: u8) -> RimResult<bool> {
+        if let Some(bank) = self.bank.take() {
+            return self.ext_io(bank, device, function, value);
+        }
+
+        self.check_access(device as usize)?;
+
         match device {
             Device::Cpu => match function as u8 {
                 0 => return Ok(true),
                 1 => {},
                 2 => self.registers[0] = 0,
-                3 => {
-                    let addr = ((self.registers[3] as usize) << 4) | value as usize;
-                    self.registers[0] = self.data[addr];
-                }
-                4 => {
-                    let addr = ((self.registers[3] as usize) << 4) | self.registers[0] as usize;
-                    self.data[addr] = value;
-                }
+                3 => self.registers[0] = self.read(self.addr(value))?,
+                4 => self.store(self.addr(self.registers[0]), value)?,
+                // Through a pointer: the byte at the offset is the offset
+                // to load from or store to, in the same page.
                 5 => {
-                    let addr = ((self.registers[3] as usize) << 4) | value as usize;
-                    let addr = ((self.registers[3] as usize) << 4) | addr;
-                    self.registers[0] = self.data[addr];
+                    let pointer = self.read(self.addr(value))?;
+                    self.registers[0] = self.read(self.addr(pointer))?;
                 }
                 6 => {
-                    let addr = ((self.registers[3] as usize) << 4) | self.registers[0] as usize;
-                    let addr = ((self.registers[3] as usize) << 4) | addr;
-                    self.data[addr] = value;
+                    let pointer = self.read(self.addr(self.registers[0]))?;
+                    self.store(self.addr(pointer), value)?;
+                }
+                7 => {
+                    self.io_stats.syscalls += 1;
+                    return self.sys(value);
                 }
-                7 => {},
                 _ => unreachable!()
             },
             Device::Kbd => match function as u8 {
-                0 => todo!(),
-                1 => todo!(),
-                2 => {},
-                3 => {},
-                4 => {},
-                5 => {},
-                6 => {},
-                7 => {},
-                _ => unreachable!()
-            },
-            Device::Scr => match function as u8 {
-                0 => print!("{}[{value};H", 27 as char),
-                1 => print!("{}[;{value}H", 27 as char),
-                2 => print!("{}", value as char),
-                3 => self.registers[0] = 0,
-                4 => self.registers[0] = 0,
-                5 => println!("{}[2J", 27 as char),
-                6 => {},
-                7 => {},
+                0 => {
+                    let key = self.console.read();
+                    self.io_stats.keys_read += key.is_some() as u64;
+                    self.registers[0] = key.unwrap_or(0);
+                }
+                1 => self.registers[0] = self.console.poll() as u8,
+                2 => {
+                    let event = self.console.read_mouse();
+                    self.registers[0] = event.map_or(0, |event| event.code());
+                    self.mouse = event.unwrap_or(self.mouse);
+                }
+                3 => self.registers[0] = self.mouse.row,
+                4 => self.registers[0] = self.mouse.col,
+                5 => self.registers[0] = self.console.poll_mouse() as u8,
+                6 | 7 => log::warn!("reserved keyboard function {} called at {:#05x}", function as u8, self.pc - 1),
                 _ => unreachable!()
             },
+            Device::Scr => {
+                match function as u8 {
+                    0 => self.screen.set_row(value),
+                    1 => self.screen.set_col(value),
+                    2 => {
+                        self.io_stats.screen_bytes += 1;
+                        self.screen.put(value);
+                    }
+                    3 => {
+                        let (row, col) = self.screen.cursor();
+                        self.registers[0] = self.screen.cell(row, col);
+                    }
+                    4 => self.registers[0] = 0,
+                    5 => self.screen.clear(),
+                    6 => {
+                        if self.present != Present::Immediate {
+                            self.present();
+                        }
+                    }
+                    7 => self.wait_for_frame(),
+                    _ => unreachable!()
+                }
+
+                self.update_screen(function, value);
+            }
             Device::Mth => match function as u8 {
                 0 => {
-                    let res = (self.registers[0] as u16).wrapping_mul(self.registers[value as usize] as u16);
+                    let res = (self.registers[0] as u16).wrapping_mul(self.register(value)? as u16);
                     self.registers[0] = res as u8;
                     self.registers[1] = (res >> 8) as u8;
 
-                    self.flags[1] = res == 0;
+                    self.flags.zero = res == 0;
                 }
                 1 => {
-                    let res = self.registers[0] / self.registers[value as usize];
+                    let divisor = self.register(value)?;
+                    if divisor == 0 {
+                        return Err(RuntimeError::DivisionByZero(self.pc - 1).into());
+                    }
+
+                    let res = self.registers[0] / divisor;
                     self.registers[0] = res;
 
-                    self.flags[1] = res == 0;
+                    self.flags.zero = res == 0;
                 }
                 2 => {
-                    let res = self.registers[0] & self.registers[0];
+                    let res = self.registers[0] & self.register(value)?;
                     self.registers[0] = res;
 
-                    self.flags[1] = res == 0;
+                    self.flags.zero = res == 0;
                 }
                 3 => {
-                    let res = self.registers[0] | self.registers[0];
+                    let res = self.registers[0] | self.register(value)?;
                     self.registers[0] = res;
 
-                    self.flags[1] = res == 0;
+                    self.flags.zero = res == 0;
                 }
                 4 => {
-                    let res = self.registers[0] ^ self.registers[0];
+                    let res = self.registers[0] ^ self.register(value)?;
                     self.registers[0] = res;
 
-                    self.flags[1] = res == 0;
+                    self.flags.zero = res == 0;
                 }
                 5 => {
                     let res = !self.registers[0];
                     self.registers[0] = res;
 
-                    self.flags[1] = res == 0;
+                    self.flags.zero = res == 0;
                 }
-                6 => {
-                    let mut res = 0;
+                6 => self.registers[0] = self.flags.to_bits(),
+                7 => {
+                    self.flags = Flags::from_bits(value);
+                    if self.arithmetic != Arithmetic::Flagged {
+                        self.flags.overflow = false;
+                    }
+                }
+                _ => unreachable!()
+            },
+        }
+
+        Ok(false)
+    }
+
+    /// A system call, selected by `value`, with its argument in Rb.
+    ///
+    /// | `value` | Call                                                    |
+    /// |---------|---------------------------------------------------------|
+    /// | 0       | Switch to the start of the program in slot Rb          |
+    /// | 1       | Load the boot image at disk sector Rb, and jump into it |
+    /// | 2       | Direct the next I/O instruction to extension bank Rb    |
+    /// | 3       | Make the next jump test the carry flag instead          |
+    /// | 4       | Set the interrupt handler to the start of page Rb       |
+    /// | 5       | Return from the interrupt handler                       |
+    /// | 6       | Sleep for Rb ticks, or yield if Rb is 0                 |
+    /// | 7       | Set the fault handler to the start of page Rb           |
+    /// | 8       | Get the last fault handled                              |
+    /// | 9       | Decode the next instruction from opcode page Rb         |
+    /// | 10      | Make `ior` send register Rb, or Ra's choice if Rb > 3   |
+    /// | 11      | Wait until a key is waiting, or an interrupt            |
+    ///
+    /// Other values are reserved, and do nothing. Calls 10 and 11 are new
+    /// in level 2 of the instruction set (see [`isa`]), and reserved before
+    /// it.
+    ///
+    /// Once a program sets a fault handler, the next fault it can recover
+    /// from (see [`RimError::fault_code`]) jumps there instead of ending the
+    /// run. Call 8 then sets Ra to the fault's code, and Rb and Rc to the
+    /// page and offset of the instruction that faulted.
+    fn sys(&mut self, value: u8) -> RimResult<bool> {
+        match value {
+            0 => self.switch(self.registers[1] as usize).map(|_| false),
+            1 => {
+                self.check_access(image::DEVICE_DISK)?;
+                let disk = self.disk.as_ref().ok_or(RuntimeError::NoDisk)?;
+                let instructions = disk.read_image(self.registers[1])?;
 
-                    if self.flags[0] {
-                        res |= 0b01;
+                // The image's length, and then the image.
+                let start = self.registers[1] as usize * disk::SECTOR_SIZE;
+                let len = &disk.bytes()[start..start + 2];
+                self.io_stats.disk_read += 2 + u16::from_be_bytes([len[0], len[1]]) as u64;
+
+                let slot = self.load(instructions)?;
+                self.switch(slot).map(|_| false)
+            }
+            2 => {
+                self.bank = Some(self.registers[1]);
+                Ok(false)
+            }
+            3 => {
+                self.carry_condition = true;
+                Ok(false)
+            }
+            4 => {
+                self.interrupt_handler = Some((self.current, Addr::new(self.registers[1], 0).index()));
+                Ok(false)
+            }
+            5 => {
+                match self.interrupted.take() {
+                    Some(interrupted) => {
+                        let snapshot = interrupted.snapshot;
+                        self.jump_to(snapshot.slot, snapshot.pc)?;
+                        self.registers = snapshot.registers;
+                        self.flags = snapshot.flags;
+                        self.bank = interrupted.bank;
+                        self.opcode_page = interrupted.opcode_page;
+                        self.ior_source = interrupted.ior_source;
+                        self.carry_condition = interrupted.carry_condition;
                     }
+                    None => log::warn!("return from interrupt at {:#05x} outside of one", self.pc - 1),
+                }
+
+                Ok(false)
+            }
+            6 => {
+                self.sleep = Some(self.registers[1]);
+                Ok(false)
+            }
+            7 => {
+                self.fault_handler = Some((self.current, Addr::new(self.registers[1], 0).index()));
+                Ok(false)
+            }
+            8 => {
+                let (code, pc) = self.fault.unwrap_or_default();
+                self.registers[0] = code;
+                self.registers[1] = (pc >> 4) as u8;
+                self.registers[2] = (pc & 0xf) as u8;
+                Ok(false)
+            }
+            9 => {
+                self.opcode_page = Some(self.registers[1]);
+                Ok(false)
+            }
+            10 if self.isa >= IsaLevel::V2 => {
+                let rb = self.registers[1];
+                self.ior_source = (rb < 4).then(|| Register::from(rb));
+                Ok(false)
+            }
+            11 if self.isa >= IsaLevel::V2 => {
+                if !self.console.poll() {
+                    // Retry the call next step, until there's a key.
+                    self.pc -= 1;
+                    self.waiting = true;
+                }
+
+                Ok(false)
+            }
+            _ => {
+                log::warn!("reserved system call {value} called at {:#05x}", self.pc - 1);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Copies a block between data memory from `page` and the disk
+    /// (functions 5 and 6) or video memory (function 7), which must be
+    /// attached. A block that runs past the end of memory is cut short
+    /// there, but one that starts past it faults.
+    fn block_io(&mut self, device: Device, function: U3, page: u8) -> RimResult<()> {
+        let start = Addr::new(page, 0);
+        self.check_memory(start)?;
+        let start = start.index();
+        let len = if device == Device::Cpu { disk::SECTOR_SIZE } else { graphics::Graphics::BLOCK_LEN };
+        let end = (start + len).min(self.memory_len);
+
+        match (device, function as u8) {
+            (Device::Cpu, 5) => {
+                let data = Arc::make_mut(&mut self.data);
+                self.disk.as_mut().unwrap().read_block(&mut data[start..end]);
+                self.io_stats.disk_read += (end - start) as u64;
+                for page in (start / Addr::PAGE_SIZE..end / Addr::PAGE_SIZE).filter(|&page| self.shared.contains(&(page as u8))) {
+                    self.shared_writes.extend(page * Addr::PAGE_SIZE..(page + 1) * Addr::PAGE_SIZE);
+                }
+            }
+            (Device::Cpu, _) => {
+                self.disk.as_mut().unwrap().write_block(&self.data[start..end]);
+                self.io_stats.disk_written += (end - start) as u64;
+            }
+            _ => self.graphics.as_mut().unwrap().write_block(&self.data[start..end]),
+        }
+
+        Ok(())
+    }
 
-                    if self.flags[1] {
-                        res |= 0b10;
+    /// I/O on an extension device. Bank 0 holds the standard devices, so
+    /// extension banks start at 1:
+    ///
+    /// | Bank | Device | Extension                |
+    /// |------|--------|--------------------------|
+    /// | 1    | 0      | [The disk](disk)         |
+    /// | 1    | 1      | [Graphics](graphics)     |
+    /// | 1    | 2      | [Sound](sound)           |
+    /// | 1    | 3      | [The mailbox](mailbox)   |
+    /// | 2    | 0      | [Counters](counters)     |
+    /// | 3 on | any    | [Host devices](host)     |
+    ///
+    /// Missing devices do nothing.
+    fn ext_io(&mut self, bank: u8, device: Device, function: U3, value: u8) -> RimResult<bool> {
+        let id = bank as usize * 4 + device as usize;
+        if bank != 0 {
+            self.check_access(id)?;
+        }
+
+        let res = match (bank, device) {
+            (0, _) => return self.io(device, function, value),
+            (1, Device::Cpu) if matches!(function as u8, 5 | 6) && self.disk.is_some() => {
+                self.block_io(device, function, value)?;
+                None
+            }
+            (1, Device::Kbd) if function as u8 == 7 && self.graphics.is_some() => {
+                self.block_io(device, function, value)?;
+                None
+            }
+            (1, Device::Cpu) => match self.disk.as_mut() {
+                Some(disk) => {
+                    match function as u8 {
+                        2 => self.io_stats.disk_read += 1,
+                        3 => self.io_stats.disk_written += 1,
+                        _ => {}
                     }
 
-                    self.registers[res];
+                    disk.io(function, value)
                 }
-                7 => {
-                    self.flags[0] = value & 0b01 != 0;
-                    self.flags[1] = value & 0b10 != 0;
+                None => {
+                    log::warn!("disk I/O at {:#05x} with no disk attached", self.pc - 1);
+                    None
+                }
+            },
+            (1, Device::Kbd) => match self.graphics.as_mut() {
+                Some(graphics) => graphics.io(function, value),
+                None => {
+                    log::warn!("graphics I/O at {:#05x} with no graphics attached", self.pc - 1);
+                    None
+                }
+            },
+            (1, Device::Scr) => match self.sound.as_mut() {
+                Some(sound) => sound.io(function, value),
+                None => {
+                    log::warn!("sound I/O at {:#05x} with no sound attached", self.pc - 1);
+                    None
+                }
+            },
+            (1, Device::Mth) => match self.mailbox.as_mut() {
+                Some(port) => match port.io(function, value) {
+                    Ok(res) => res,
+                    Err(()) => {
+                        // Retry the receive, prefix and all, next step.
+                        self.pc -= 1;
+                        self.bank = Some(bank);
+                        self.blocked = true;
+                        None
+                    }
+                },
+                None => {
+                    log::warn!("mailbox I/O at {:#05x} with no mailbox attached", self.pc - 1);
+                    None
                 }
-                _ => unreachable!()
             },
+            (2, Device::Cpu) => self.latch.io(&mut self.counters, function, value),
+            _ => match self.host_devices.get(&id) {
+                Some(host) => {
+                    let res = host.lock().unwrap_or_else(PoisonError::into_inner).io(function, value);
+                    res.map_err(|source| RimError::Device { device: id, function: function as u8, pc: self.pc - 1, source })?
+                }
+                None => {
+                    log::warn!("missing extension device {device} of bank {bank} called at {:#05x}", self.pc - 1);
+                    None
+                }
+            }
+        };
+
+        if let Some(res) = res {
+            self.registers[0] = res;
         }
 
         Ok(false)
@@ -275,28 +1612,189 @@ impl Rim {
 
 impl Default for Rim {
     fn default() -> Self {
-        Self { instructions: Default::default(), pc: Default::default(), registers: Default::default(), flags: [false; 2], data: [0; 4096] }
+        Self { programs: vec![Arc::default()], load_limits: LoadLimits::default(), current: 0, pc: Default::default(), registers: Default::default(), flags: Flags::default(), data: Arc::new([0; 4096]), memory_len: Addr::COUNT, architecture: Architecture::Harvard, arithmetic: Arithmetic::Wrapping, isa: IsaLevel::LATEST, microcode: microcode::DEFAULT, custom_microcode: false, fusion: Fusion::DEFAULT, fused: None, carry_condition: false, bank: None, opcode_page: None, ior_source: None, extensions: BTreeMap::new(), host_devices: BTreeMap::new(), disk: None, graphics: None, sound: None, mailbox: None, blocked: false, waiting: false, interrupt_handler: None, interrupted: None, fault_handler: None, fault: None, sleep: None, console: Console::default(), mouse: MouseEvent::default(), denied: BTreeSet::new(), io_stats: IoStats::default(), counters: Counters::default(), latch: Latch::default(), shared: BTreeSet::new(), shared_writes: BTreeSet::new(), screen: Screen::default(), present: Present::default(), frame: Vec::new(), last_present: None }
     }
 }
 
 impl Debug for Rim {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Rim").field("instructions", &self.instructions).field("pc", &self.pc).field("registers", &self.registers).field("flags", &self.flags).finish()
+        f.debug_struct("Rim").field("programs", &self.programs).field("current", &self.current).field("pc", &self.pc).field("registers", &self.registers).field("flags", &self.flags).finish()
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Instruction(pub Opcode, pub InstructionData);
 
+impl Instruction {
+    /// Decodes an instruction byte. Every byte is a valid instruction.
+    pub fn decode(byte: u8) -> Self {
+        let opcode = Opcode::from(byte);
+        Self(opcode, opcode.parse_data(byte))
+    }
+
+    /// Encodes this instruction, failing if the data doesn't match the
+    /// opcode's format or an operand doesn't fit in its field.
+    pub fn encode(self) -> RimResult<u8> {
+        if self.1.format() != Format::from(self.0) {
+            return Err(AsmError::InvalidInstruction(self).into());
+        }
+
+        let data = match self.1 {
+            InstructionData::Imm(imm) => encoding::IMM.put(imm),
+            data => Some(data.into()),
+        };
+
+        data.map(|data| encoding::OPCODE.pack(self.0 as u8) | data)
+            .ok_or(AsmError::InvalidInstruction(self).into())
+    }
+}
+
+impl Instruction {
+    /// The largest immediate a single `Adi` can add.
+    pub const MAX_IMM: u8 = encoding::IMM.max();
+
+    /// `Adi`, failing if `imm` doesn't fit in the 5-bit immediate.
+    pub fn adi(imm: u8) -> RimResult<Self> {
+        if imm > Self::MAX_IMM {
+            return Err(AsmError::ImmediateOutOfRange(imm).into());
+        }
+
+        Ok(Self(Opcode::Adi, InstructionData::Imm(imm)))
+    }
+
+    /// As many `Adi`s as it takes to add `imm`, which may be any byte.
+    pub fn adi_expanded(imm: u8) -> Vec<Self> {
+        let mut instructions = vec![Self(Opcode::Adi, InstructionData::Imm(Self::MAX_IMM)); (imm / Self::MAX_IMM) as usize];
+
+        let rest = imm % Self::MAX_IMM;
+        if rest != 0 || instructions.is_empty() {
+            instructions.push(Self(Opcode::Adi, InstructionData::Imm(rest)));
+        }
+
+        instructions
+    }
+
+    /// The shortest sequence loading `imm` into `dest`, built from `Adi`s
+    /// and doublings. Loading anything but Ra clobbers Ra, and the flags
+    /// are left in an unspecified state. Values of 128 and up overflow on
+    /// the way, so they fault under [`Arithmetic::Faulting`].
+    pub fn li(dest: Register, imm: u8) -> Vec<Self> {
+        let mut instructions = vec![Self::ioi(Device::Cpu, U3::B010)];
+
+        if imm != 0 {
+            let best = (0..8)
+                .filter(|shift| imm >> shift != 0)
+                .map(|shift| Self::load_shifted(imm, shift))
+                .min_by_key(Vec::len)
+                .unwrap_or_default();
+
+            instructions.extend(best);
+        }
+
+        if dest != Register::Ra {
+            instructions.push(Self::sub(dest, dest));
+            instructions.push(Self::add(Register::Ra, dest));
+        }
+
+        instructions
+    }
+
+    /// Loads `imm >> shift` into a zeroed Ra, shifts it back up by doubling,
+    /// then adds the low bits.
+    fn load_shifted(imm: u8, shift: u8) -> Vec<Self> {
+        let mut instructions = Self::adi_expanded(imm >> shift);
+        instructions.extend((0..shift).map(|_| Self::add(Register::Ra, Register::Ra)));
+
+        let low = imm & ((1 << shift) - 1);
+        if low != 0 {
+            instructions.extend(Self::adi_expanded(low));
+        }
+
+        instructions
+    }
+
+    pub fn add(src: Register, dest: Register) -> Self {
+        Self(Opcode::Add, InstructionData::Reg { is_id: false, src, dest })
+    }
+
+    pub fn add_id(src: Register, dest: Register) -> Self {
+        Self(Opcode::Add, InstructionData::Reg { is_id: true, src, dest })
+    }
+
+    pub fn sub(src: Register, dest: Register) -> Self {
+        Self(Opcode::Sub, InstructionData::Reg { is_id: false, src, dest })
+    }
+
+    pub fn sub_id(src: Register, dest: Register) -> Self {
+        Self(Opcode::Sub, InstructionData::Reg { is_id: true, src, dest })
+    }
+
+    pub fn jne(addr: U4) -> Self {
+        Self(Opcode::Jne, InstructionData::Mem { is_ptr: false, addr })
+    }
+
+    pub fn jne_ptr(addr: U4) -> Self {
+        Self(Opcode::Jne, InstructionData::Mem { is_ptr: true, addr })
+    }
+
+    pub fn jg(addr: U4) -> Self {
+        Self(Opcode::Jg, InstructionData::Mem { is_ptr: false, addr })
+    }
+
+    pub fn jg_ptr(addr: U4) -> Self {
+        Self(Opcode::Jg, InstructionData::Mem { is_ptr: true, addr })
+    }
+
+    pub fn jl(addr: U4) -> Self {
+        Self(Opcode::Jl, InstructionData::Mem { is_ptr: false, addr })
+    }
+
+    pub fn jl_ptr(addr: U4) -> Self {
+        Self(Opcode::Jl, InstructionData::Mem { is_ptr: true, addr })
+    }
+
+    pub fn ioi(device: Device, function: U3) -> Self {
+        Self(Opcode::Ioi, InstructionData::Io { device, function })
+    }
+
+    pub fn ior(device: Device, function: U3) -> Self {
+        Self(Opcode::Ior, InstructionData::Io { device, function })
+    }
+}
+
+impl From<u8> for Instruction {
+    fn from(byte: u8) -> Self {
+        Self::decode(byte)
+    }
+}
+
+/// Encodes an instruction, discarding any operand bits that don't fit. Use
+/// [`Instruction::encode`] to catch those instead.
 impl From<Instruction> for u8 {
     fn from(instruction: Instruction) -> Self {
-        let opcode = instruction.0 as u8;
+        let opcode = encoding::OPCODE.pack(instruction.0 as u8);
         let data: u8 = instruction.1.into();
 
         opcode | data
     }
 }
 
+/// Formats an instruction in assembler syntax.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = format!("{:?}", self.0).to_lowercase();
+
+        match self.1 {
+            InstructionData::Imm(imm) => write!(f, "{mnemonic} {imm}"),
+            InstructionData::Reg { is_id: false, src, dest } => write!(f, "{mnemonic} {src}, {dest}"),
+            InstructionData::Reg { is_id: true, src, dest } => write!(f, "{mnemonic} [{src}], [{dest}]"),
+            InstructionData::Mem { is_ptr: false, addr } => write!(f, "{mnemonic} {addr}"),
+            InstructionData::Mem { is_ptr: true, addr } => write!(f, "{mnemonic} [{addr}]"),
+            InstructionData::Io { device, function } => write!(f, "{mnemonic} {device}, {function}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Opcode {
@@ -311,53 +1809,34 @@ pub enum Opcode {
 }
 
 impl Opcode {
-    pub fn parse_data(&self, data: u8) -> InstructionData {
-        match self {
-            Opcode::Adi => {
-                let imm = data >> 3;
-                
-                InstructionData::Imm(imm)
-            }
-            Opcode::Add
-            | Opcode::Sub => {
-                let is_id = data & 0b0000_1000 != 0;
-                let src = Register::from(data >> 4);
-                let dest = Register::from(data >> 6);
-
-                InstructionData::Reg {
-                    is_id,
-                    src,
-                    dest,
-                }
-            }
-            Opcode::Jne
-            | Opcode::Jg
-            | Opcode::Jl => {
-                let is_ptr = data & 0b0000_1000 != 0;
-                let addr = U4::from(data >> 4);
-
-                InstructionData::Mem {
-                    is_ptr,
-                    addr,
-                }
-            }
-            Opcode::Ioi
-            | Opcode::Ior => {
-                let device = Device::from(data >> 3);
-                let function = U3::from(data >> 5);
+    /// Every opcode, in order.
+    pub const ALL: [Self; 8] = [Self::Adi, Self::Add, Self::Sub, Self::Jne, Self::Jg, Self::Jl, Self::Ioi, Self::Ior];
 
-                InstructionData::Io {
-                    device,
-                    function,
-                }
-            }
+    /// Decodes the operands of an instruction byte according to this
+    /// opcode's format. The opcode bits of `data` are ignored.
+    pub fn parse_data(&self, data: u8) -> InstructionData {
+        match Format::from(*self) {
+            Format::Imm => InstructionData::Imm(encoding::IMM.get(data)),
+            Format::Reg => InstructionData::Reg {
+                is_id: encoding::IS_ID.get(data) != 0,
+                src: Register::from(encoding::SRC.get(data)),
+                dest: Register::from(encoding::DEST.get(data)),
+            },
+            Format::Mem => InstructionData::Mem {
+                is_ptr: encoding::IS_PTR.get(data) != 0,
+                addr: U4::from(encoding::ADDR.get(data)),
+            },
+            Format::Io => InstructionData::Io {
+                device: Device::from(encoding::DEVICE.get(data)),
+                function: U3::from(encoding::FUNCTION.get(data)),
+            },
         }
     }
 }
 
 impl From<u8> for Opcode {
     fn from(opcode: u8) -> Self {
-        match opcode & 0b0000_0111 {
+        match encoding::OPCODE.get(opcode) {
             0b000 => Self::Adi,
             0b001 => Self::Add,
             0b010 => Self::Sub,
@@ -391,6 +1870,15 @@ pub enum InstructionData {
 }
 
 impl InstructionData {
+    pub fn format(self) -> Format {
+        match self {
+            Self::Imm(_) => Format::Imm,
+            Self::Reg { .. } => Format::Reg,
+            Self::Mem { .. } => Format::Mem,
+            Self::Io { .. } => Format::Io,
+        }
+    }
+
     pub fn as_imm(self) -> u8 {
         if let Self::Imm(imm) = self {
             imm
@@ -426,31 +1914,22 @@ impl InstructionData {
 
 impl From<InstructionData> for u8 {
     fn from(data: InstructionData) -> Self {
-        let mut byte = 0;
         match data {
-            InstructionData::Imm(imm) => byte |= imm << 3,
+            InstructionData::Imm(imm) => encoding::IMM.pack(imm),
             InstructionData::Reg { is_id, src, dest } => {
-                if is_id {
-                    byte |= 1 << 3;
-                }
-
-                byte |= (src as u8) << 4;
-                byte |= (dest as u8) << 6;
+                encoding::IS_ID.pack(is_id as u8)
+                    | encoding::SRC.pack(src as u8)
+                    | encoding::DEST.pack(dest as u8)
             }
             InstructionData::Mem { is_ptr, addr } => {
-                if is_ptr {
-                    byte |= 1 << 3;
-                }
-
-                byte |= (addr as u8) << 4;
+                encoding::IS_PTR.pack(is_ptr as u8)
+                    | encoding::ADDR.pack(addr as u8)
             }
             InstructionData::Io { device, function } => {
-                byte |= (device as u8) << 3;
-                byte |= (function as u8) << 5;
+                encoding::DEVICE.pack(device as u8)
+                    | encoding::FUNCTION.pack(function as u8)
             }
         }
-
-        byte
     }
 }
 
@@ -479,6 +1958,17 @@ impl From<u8> for Register {
     }
 }
 
+impl Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ra => write!(f, "ra"),
+            Self::Rb => write!(f, "rb"),
+            Self::Rc => write!(f, "rc"),
+            Self::Rd => write!(f, "rd"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
 pub enum Device {
@@ -499,3 +1989,14 @@ impl From<u8> for Device {
         }
     }
 }
+
+impl Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cpu => write!(f, "cpu"),
+            Self::Kbd => write!(f, "kbd"),
+            Self::Scr => write!(f, "scr"),
+            Self::Mth => write!(f, "mth"),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! The mailbox: slots and message queues shared between machines.
+//!
+//! The device is extension device 3 of bank 1. A [`Mailbox`] is a handle,
+//! so attaching clones of one to several machines, even on different
+//! threads, connects them. Its functions are:
+//!
+//! | Function | Effect                                                       |
+//! |----------|--------------------------------------------------------------|
+//! | 0        | Select slot `value`                                          |
+//! | 1        | Store `value` in the selected slot                           |
+//! | 2        | Load the selected slot into Ra                               |
+//! | 3        | Set the value compare-and-swap expects                       |
+//! | 4        | Compare and swap `value` into the slot                       |
+//! | 5        | Send `value` to the slot's queue                             |
+//! | 6        | Receive from the slot's queue into Ra, blocking while empty  |
+//!
+//! Compare and swap stores `value` only if the slot holds the expected
+//! value, and sets Ra to 1 if it did and 0 otherwise. Sending sets Ra to 1,
+//! or 0 if the queue was full and the message was dropped.
+//!
+//! The selected slot and expected value belong to each machine. Compare and
+//! swap is atomic, so machines can build locks on it.
+//!
+//! A blocked machine doesn't advance, retrying the receive each step, and
+//! [`Rim::is_blocked`](crate::Rim::is_blocked) reports it, so schedulers
+//! can set it aside until [`Mailbox::wait`] sees a message.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+pub const SLOTS: usize = 16;
+/// The most messages a slot's queue holds.
+pub const QUEUE_LEN: usize = 256;
+
+#[derive(Debug, Default)]
+struct State {
+    slots: [u8; SLOTS],
+    queues: [VecDeque<u8>; SLOTS],
+    /// Bumped on every send, for waiters to notice.
+    sent: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Mailbox {
+    shared: Arc<(Mutex<State>, Condvar)>,
+}
+
+impl Mailbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // Nothing can panic while holding the lock, so it's never poisoned.
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn slot(&self, slot: usize) -> u8 {
+        self.state().slots[slot % SLOTS]
+    }
+
+    pub fn set_slot(&self, slot: usize, value: u8) {
+        self.state().slots[slot % SLOTS] = value;
+    }
+
+    /// Stores `new` in a slot if it holds `expected`, returning whether it
+    /// did.
+    pub fn compare_and_swap(&self, slot: usize, expected: u8, new: u8) -> bool {
+        let mut state = self.state();
+        let slot = &mut state.slots[slot % SLOTS];
+        let swapped = *slot == expected;
+        if swapped {
+            *slot = new;
+        }
+
+        swapped
+    }
+
+    /// Queues a message on a slot, returning false if its queue is full.
+    pub fn send(&self, slot: usize, value: u8) -> bool {
+        let mut state = self.state();
+        let queue = &mut state.queues[slot % SLOTS];
+        if queue.len() == QUEUE_LEN {
+            return false;
+        }
+
+        queue.push_back(value);
+        state.sent += 1;
+        self.shared.1.notify_all();
+        true
+    }
+
+    pub fn receive(&self, slot: usize) -> Option<u8> {
+        self.state().queues[slot % SLOTS].pop_front()
+    }
+
+    /// How many messages are waiting in a slot's queue.
+    pub fn pending(&self, slot: usize) -> usize {
+        self.state().queues[slot % SLOTS].len()
+    }
+
+    /// Waits up to `timeout` for a message to be sent on any slot,
+    /// returning whether one was.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let state = self.state();
+        let sent = state.sent;
+        let (state, _) = self
+            .shared
+            .1
+            .wait_timeout_while(state, timeout, |state| state.sent == sent)
+            .unwrap_or_else(|e| e.into_inner());
+
+        state.sent != sent
+    }
+}
+
+/// A machine's connection to a mailbox.
+#[derive(Debug, Clone)]
+pub(crate) struct Port {
+    pub(crate) mailbox: Mailbox,
+    selected: usize,
+    expected: u8,
+}
+
+impl Port {
+    pub(crate) fn new(mailbox: Mailbox) -> Self {
+        Self {
+            mailbox,
+            selected: 0,
+            expected: 0,
+        }
+    }
+
+    /// Does a function, returning what to set Ra to, or `Err` if it has to
+    /// block.
+    pub(crate) fn io(&mut self, function: crate::helper::U3, value: u8) -> Result<Option<u8>, ()> {
+        Ok(match function as u8 {
+            0 => {
+                self.selected = value as usize % SLOTS;
+                None
+            }
+            1 => {
+                self.mailbox.set_slot(self.selected, value);
+                None
+            }
+            2 => Some(self.mailbox.slot(self.selected)),
+            3 => {
+                self.expected = value;
+                None
+            }
+            4 => Some(self.mailbox.compare_and_swap(self.selected, self.expected, value) as u8),
+            5 => Some(self.mailbox.send(self.selected, value) as u8),
+            6 => Some(self.mailbox.receive(self.selected).ok_or(())?),
+            _ => None,
+        })
+    }
+}
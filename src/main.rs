@@ -1,16 +1,1690 @@
-use pact::read_file;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use pact::addr::Addr;
+use pact::asm::Assembler;
+use pact::cfg::Cfg;
+use pact::config::RimConfig;
+use pact::debug::{Debugger, Session, Stop, Watch};
+use pact::disasm::{annotate, disassemble, disassemble_profiled, lines, render};
+use pact::disk::Disk;
+use pact::image::{device_id, device_name, Image};
+use pact::isa::IsaLevel;
+use pact::package::Package;
+use pact::profile::Profile;
+use pact::screen::Present;
+use pact::symbols::Symbols;
+use pact::state::State;
+use pact::taint::Taint;
+use pact::trace::{Recorder, Trace};
+use pact::error::RimError;
+use pact::{read_file, write_file, Architecture, Arithmetic, Rim, Status};
 use sarge::prelude::*;
 
+/// Prints warnings from the library to stderr.
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}: {}", record.level().as_str().to_lowercase(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
 fn main() {
+    let _ = log::set_logger(&Logger).map(|_| log::set_max_level(log::LevelFilter::Warn));
+
     let parser = ArgumentParser::new();
-    let files = parser.parse().expect("failed to parse arguments");
-    
-    if files.len() < 1 {
+    let output = parser.add::<String>(tag::both('o', "output"));
+    let symbols = parser.add::<String>(tag::both('s', "symbols"));
+    let disk = parser.add::<String>(tag::long("disk"));
+    let arithmetic = parser.add::<String>(tag::long("arithmetic"));
+    let format = parser.add::<String>(tag::long("format"));
+    let deny = parser.add::<String>(tag::long("deny"));
+    let screen = parser.add::<String>(tag::long("screen"));
+    let memory = parser.add::<String>(tag::long("memory"));
+    let expand_imm = parser.add::<bool>(tag::long("expand-imm"));
+    let strict_calls = parser.add::<bool>(tag::long("strict-calls"));
+    let annotate_comments = parser.add::<bool>(tag::long("annotate"));
+    let von_neumann = parser.add::<bool>(tag::long("von-neumann"));
+    let trace = parser.add::<bool>(tag::long("trace"));
+    let taint = parser.add::<bool>(tag::long("taint"));
+    let stats = parser.add::<bool>(tag::long("stats"));
+    let sound = parser.add::<bool>(tag::long("sound"));
+    let mouse = parser.add::<bool>(tag::long("mouse"));
+    let clipboard = parser.add::<bool>(tag::long("clipboard"));
+    let control = parser.add::<bool>(tag::long("control"));
+    let web = parser.add::<bool>(tag::long("web"));
+    let manifest = parser.add::<String>(tag::long("manifest"));
+    let record = parser.add::<String>(tag::long("record"));
+    let isa = parser.add::<String>(tag::long("isa"));
+    let max_steps = parser.add::<String>(tag::long("max-steps"));
+    let max_time = parser.add::<String>(tag::long("max-time"));
+    let max_program_len = parser.add::<String>(tag::long("max-program-len"));
+    let max_data_len = parser.add::<String>(tag::long("max-data-len"));
+    let max_programs = parser.add::<String>(tag::long("max-programs"));
+    let max_concurrent = parser.add::<String>(tag::long("max-concurrent"));
+    let old_build = parser.add::<String>(tag::long("old"));
+    let new_build = parser.add::<String>(tag::long("new"));
+    let args = parser.parse().or_exit("failed to parse arguments");
+
+    if args.is_empty() {
+        panic!("not enough input");
+    }
+
+    let (command, files) = match args[0].as_str() {
+        "run" | "asm" | "bf" | "pactc" | "check" | "disasm" | "graph" | "profile" | "debug" | "repl" | "disk" | "conformance" | "properties" | "serve" | "trace-view" | "bench-dir" | "explain" | "isa" | "selftest" | "minimize" | "compare" | "session" | "pack" => (args[0].as_str(), &args[1..]),
+        _ => ("run", &args[..]),
+    };
+
+    if command == "conformance" {
+        conformance(files.first().map(Path::new));
+        return;
+    }
+
+    if command == "selftest" {
+        if let Ok(path) = output.get() {
+            let program = pact::selftest::program().or_exit("failed to build self-test");
+            write_file(path, &program).or_exit("failed to write file");
+            return;
+        }
+
+        let report = pact::selftest::run();
+        print!("{}", String::from_utf8_lossy(&report.output));
+        if !pact::selftest::passed(&report) {
+            eprintln!("self-test failed: {report}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if command == "isa" {
+        match format.get().as_deref() {
+            Ok("md") | Err(_) => print!("{}", pact::encoding::to_markdown()),
+            Ok("json") => print!("{}", pact::encoding::to_json()),
+            Ok(other) => panic!("unknown table format `{other}`, expected md or json"),
+        }
+        return;
+    }
+
+    if command == "explain" {
+        let input = files.join(" ");
+        let byte = pact::explain::parse(&input).unwrap_or_else(|e| fail_in_source("failed to parse instruction", "<input>", &input, &e));
+        print!("{}", pact::explain::explain(byte));
+        return;
+    }
+
+    if command == "properties" {
+        let cases = files.first().map_or(pact::properties::DEFAULT_CASES, |cases| {
+            cases.parse().unwrap_or_else(|_| panic!("invalid case count `{cases}`"))
+        });
+        properties(cases);
+        return;
+    }
+
+    // For bench-dir, serve, and minimize.
+    let limits = || {
+        let mut limits = pact::grade::Limits::default();
+        let limit = |arg: &Result<String, _>, what: &str| {
+            arg.as_ref().ok().map(|n| parse_number(n).unwrap_or_else(|| panic!("invalid {what} limit `{n}`")))
+        };
+
+        if let Some(steps) = limit(&max_steps.get(), "step") {
+            limits.max_steps = steps;
+        }
+
+        if let Some(ms) = limit(&max_time.get(), "time") {
+            limits.max_time = (ms != 0).then(|| std::time::Duration::from_millis(ms as u64));
+        }
+
+        if let Some(len) = limit(&max_program_len.get(), "program length") {
+            limits.load.max_program_len = len;
+        }
+
+        if let Some(len) = limit(&max_data_len.get(), "data length") {
+            limits.load.max_data_len = len;
+        }
+
+        if let Some(programs) = limit(&max_programs.get(), "program count") {
+            limits.load.max_programs = programs;
+        }
+
+        if let Some(requests) = limit(&max_concurrent.get(), "concurrent request") {
+            limits.max_concurrent = requests;
+        }
+
+        limits
+    };
+
+    if command == "serve" {
+        let addr = files.first().map_or("127.0.0.1:8080", String::as_str);
+        serve(addr, limits(), control.get().unwrap_or(false), web.get().unwrap_or(false));
+        return;
+    }
+
+    // The options that set up a machine, for `session start` to pass on to
+    // the pact hosting the session.
+    let machine_options: Vec<String> = [
+        ("--disk", disk.get_keep().ok()),
+        ("--arithmetic", arithmetic.get_keep().ok()),
+        ("--screen", screen.get_keep().ok()),
+        ("--deny", deny.get_keep().ok()),
+        ("--memory", memory.get_keep().ok()),
+    ]
+    .into_iter()
+    .filter_map(|(flag, value)| Some([flag.to_string(), value?]))
+    .flatten()
+    .chain(von_neumann.get_keep().unwrap_or(false).then(|| "--von-neumann".to_string()))
+    .collect();
+
+    let disk_path = disk.get().ok();
+    let record = record.get().ok();
+    let present = match screen.get().as_deref() {
+        Ok("immediate") | Err(_) => Present::Immediate,
+        Ok("manual") => Present::Manual,
+        Ok(fps) => match fps.parse() {
+            Ok(fps) => Present::fps(fps),
+            Err(_) => panic!("unknown screen mode `{fps}`, expected immediate, manual, or a frame rate"),
+        },
+    };
+    let denied: Vec<usize> = match deny.get() {
+        Ok(devices) => devices
+            .split(',')
+            .map(|name| device_id(name.trim()).unwrap_or_else(|| panic!("unknown device `{name}`")))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let von_neumann = von_neumann.get().unwrap_or(false);
+    let memory_len = memory.get().ok().map(|len| parse_number(&len).unwrap_or_else(|| panic!("invalid memory size `{len}`")));
+    let arithmetic = match arithmetic.get().as_deref() {
+        Ok("wrap") | Err(_) => Arithmetic::Wrapping,
+        Ok("flag") => Arithmetic::Flagged,
+        Ok("fault") => Arithmetic::Faulting,
+        Ok(other) => panic!("unknown arithmetic mode `{other}`, expected wrap, flag, or fault"),
+    };
+    // Owned, so bench-dir can make a fresh config for each program.
+    let config = {
+        let disk_path = disk_path.clone();
+        move || {
+            let mut config = RimConfig::new().arithmetic(arithmetic).present(present);
+            if let Some(path) = &disk_path {
+                config = config.disk(Disk::read_file(path).or_exit("failed to read disk"));
+            }
+
+            if let Some(len) = memory_len {
+                config = config.memory_len(len);
+            }
+
+            if von_neumann {
+                config = config.architecture(Architecture::VonNeumann);
+            }
+
+            for &id in &denied {
+                config = config.deny(id);
+            }
+
+            config
+        }
+    };
+    let configure = |rim: &mut Rim| config().apply(rim);
+
+    if command == "bench-dir" {
+        let Some(dir) = files.first() else {
+            panic!("not enough input");
+        };
+
+        let entries = pact::batch::Batch::new().config(config.clone()).limits(limits()).run_dir(dir).or_exit("failed to run programs");
+        match format.get().as_deref() {
+            Ok("csv") | Err(_) => print!("{}", pact::batch::to_csv(&entries)),
+            Ok("json") => print!("{}", pact::batch::to_json(&entries)),
+            Ok(other) => panic!("unknown report format `{other}`, expected csv or json"),
+        }
+        return;
+    }
+
+    if command == "session" {
+        session(files, &machine_options, &configure);
+        return;
+    }
+
+    if command == "repl" {
+        let mut rim = Rim::default();
+        configure(&mut rim);
+        repl(rim);
+        return;
+    }
+
+    let Some(file) = files.first() else {
         panic!("not enough input");
+    };
+
+    let symbols_path = symbols.get().unwrap_or_else(|_| {
+        Symbols::path_for(file).to_string_lossy().into_owned()
+    });
+
+    match command {
+        "asm" => {
+            let source = std::fs::read_to_string(file).or_exit(&format!("failed to read `{file}`"));
+            let (instructions, symbols, annotations) = Assembler::new()
+                .expand_immediates(expand_imm.get().unwrap_or(false))
+                .strict_calls(strict_calls.get().unwrap_or(false))
+                .assemble_annotated(&source)
+                .unwrap_or_else(|e| fail_in_source("failed to assemble program", file, &source, &e));
+
+            let output = output.get().unwrap_or_else(|_| {
+                Path::new(file).with_extension("rim").to_string_lossy().into_owned()
+            });
+
+            // Level 1 programs stay readable by older versions of pact, unless
+            // they carry annotations.
+            let annotate_comments = annotate_comments.get().unwrap_or(false);
+            let level = match isa.get().as_deref() {
+                Ok(level) => level
+                    .trim_start_matches('v')
+                    .parse::<u8>()
+                    .ok()
+                    .and_then(|level| IsaLevel::try_from(level).ok())
+                    .unwrap_or_else(|| panic!("unknown instruction set level `{level}`")),
+                Err(_) => IsaLevel::V1,
+            };
+            if level == IsaLevel::V1 && !annotate_comments {
+                write_file(&output, &instructions).or_exit("failed to write file");
+            } else {
+                let mut image = Image::new(instructions);
+                image.isa = level;
+                if annotate_comments {
+                    image.annotations = annotations;
+                }
+                image.write_file(&output).or_exit("failed to write file");
+            }
+            symbols
+                .write_file(Symbols::path_for(&output))
+                .or_exit("failed to write symbols");
+        }
+        "bf" => {
+            let source = std::fs::read_to_string(file).or_exit(&format!("failed to read `{file}`"));
+            let instructions = pact::bf::compile(&source)
+                .unwrap_or_else(|e| fail_in_source("failed to compile program", file, &source, &e));
+            let output = output.get().unwrap_or_else(|_| {
+                Path::new(file).with_extension("rim").to_string_lossy().into_owned()
+            });
+
+            write_file(&output, &instructions).or_exit("failed to write file");
+        }
+        "pactc" => {
+            let source = std::fs::read_to_string(file).or_exit(&format!("failed to read `{file}`"));
+            let output = output.get().unwrap_or_else(|_| {
+                Path::new(file).with_extension("rim").to_string_lossy().into_owned()
+            });
+
+            pactc(file, &source, &output);
+        }
+        "check" => {
+            let program = read_file(file).or_exit("failed to read file").program(0).unwrap_or_default();
+            for diagnostic in program.diagnostics() {
+                println!("warning: {diagnostic}");
+            }
+
+            if program.diagnostics().is_empty() {
+                println!("no problems found");
+            }
+        }
+        "minimize" => {
+            // Fuzzer artifacts are usually bare instruction bytes, without
+            // an image's magic.
+            let bytes = std::fs::read(file).or_exit("failed to read file");
+            let program = match Image::parse(&bytes) {
+                Ok(image) => image.code,
+                Err(_) => bytes.into_iter().map(pact::Instruction::decode).collect(),
+            };
+
+            // No time limit, so the behavior doesn't depend on the host.
+            let limits = pact::grade::Limits { max_time: None, ..limits() };
+
+            let behavior = pact::minimize::Behavior::observe(&program, &limits);
+            let small = pact::minimize::minimize_behavior(&program, &limits);
+            eprintln!("minimized {} instructions to {}, which {behavior}", program.len(), small.len());
+
+            let output = output.get().unwrap_or_else(|_| {
+                Path::new(file).with_extension("min.rim").to_string_lossy().into_owned()
+            });
+            write_file(&output, &small).or_exit("failed to write file");
+            print!("{}", disassemble(&small, &Symbols::new()));
+        }
+        "compare" => {
+            let (Ok(old), Ok(new)) = (old_build.get(), new_build.get()) else {
+                panic!("compare needs both --old and --new builds");
+            };
+
+            compare(&old, &new, file);
+        }
+        "disasm" => {
+            let image = Image::read_file(file).or_exit("failed to read file");
+            for warning in &image.warnings {
+                log::warn!("{warning}");
+            }
+
+            let symbols = load_symbols(&symbols_path);
+            let mut lines = lines(&image.code, &symbols);
+            annotate(&mut lines, &image.annotations);
+            print!("{}", render(&lines));
+        }
+        "graph" => {
+            let program = read_file(file).or_exit("failed to read file").program(0).unwrap_or_default();
+            let symbols = load_symbols(&symbols_path);
+            match format.get().as_deref() {
+                Ok("dot") | Err(_) => print!("{}", program.cfg().to_dot(program.instructions(), &symbols)),
+                Ok("mermaid") => print!("{}", program.cfg().to_mermaid(program.instructions(), &symbols)),
+                Ok(other) => panic!("unknown graph format `{other}`, expected dot or mermaid"),
+            }
+        }
+        "profile" => {
+            let mut rim = read_file(file).or_exit("failed to read file");
+            configure(&mut rim);
+            let profile = Profile::run(&mut rim).or_exit("failed to run program");
+
+            // The program's screen output shares stdout, so reports can be
+            // sent to a file instead.
+            let symbols = load_symbols(&symbols_path);
+            let instructions = rim.instructions();
+            let counts = profile.counts(0);
+            let report = match format.get().as_deref() {
+                Ok("asm") | Err(_) => disassemble_profiled(instructions, &symbols, counts),
+                Ok("dot") => Cfg::build(instructions).to_dot_profiled(instructions, &symbols, counts),
+                Ok("mermaid") => Cfg::build(instructions).to_mermaid_profiled(instructions, &symbols, counts),
+                Ok(other) => panic!("unknown profile format `{other}`, expected asm, dot, or mermaid"),
+            };
+
+            match output.get() {
+                Ok(path) => std::fs::write(path, report).or_exit("failed to write file"),
+                Err(_) => print!("{report}"),
+            }
+        }
+        "trace-view" => {
+            let trace = Trace::open(std::fs::File::open(file).or_exit("failed to read file")).or_exit("failed to read trace");
+            trace_view(trace, file);
+        }
+        "debug" => {
+            let mut rim = read_file(file).or_exit("failed to read file");
+            configure(&mut rim);
+            let mut debugger = Debugger::new(rim, load_symbols(&symbols_path));
+            let session_path = Session::path_for(file);
+            let mut resumed = false;
+            if session_path.exists() {
+                let session = Session::read_file(&session_path).or_exit("failed to read debugger session");
+                resumed = debugger.resume(&session);
+                if resumed {
+                    println!(
+                        "restored {} breakpoints, {} watchpoints, and {} watches from {}",
+                        session.breakpoints.len(),
+                        session.watchpoints.len(),
+                        session.watches.len(),
+                        session_path.display()
+                    );
+                } else {
+                    println!("{} is for a different build of the program; ignoring it", session_path.display());
+                }
+            }
+
+            debug(&mut debugger);
+
+            // Only overwrite a session for another build if there's
+            // something to replace it with.
+            let session = debugger.session();
+            if resumed || !session.breakpoints.is_empty() || !session.watchpoints.is_empty() || !session.watches.is_empty() {
+                session.write_file(&session_path).or_exit("failed to save debugger session");
+            }
+        }
+        "disk" => {
+            // Packs boot images one after another, starting at sector 0.
+            let mut disk = Disk::default();
+            let mut sector = 0;
+            for image in &files[1..] {
+                let rim = read_file(image).or_exit("failed to read file");
+                // The boot system call can only reach the first 256 sectors.
+                let Ok(start) = u8::try_from(sector) else {
+                    panic!("disk full: `{image}` would start at sector {sector}, past 255");
+                };
+
+                println!("{sector:3}: {image}");
+                sector += disk
+                    .write_image(start, rim.instructions())
+                    .or_exit("failed to write boot image");
+            }
+
+            disk.write_file(file).or_exit("failed to write disk");
+        }
+        "pack" => {
+            // Lays out the files one after another, starting at sector 0.
+            let Some(program) = files.get(1) else {
+                panic!("not enough input");
+            };
+
+            let mut package = Package::new(Image::read_file(program).or_exit("failed to read file"));
+            if let Ok(path) = manifest.get() {
+                let text = std::fs::read_to_string(&path).or_exit(&format!("failed to read `{path}`"));
+                for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+                    let Some((key, value)) = line.split_once('=') else {
+                        panic!("invalid manifest line `{line}`, expected `key=value`");
+                    };
+                    package.manifest.insert(key.trim().to_string(), value.trim().to_string());
+                }
+            }
+
+            for path in &files[2..] {
+                let bytes = std::fs::read(path).or_exit(&format!("failed to read `{path}`"));
+                let name = Path::new(path).file_name().map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                let sector = package.add_file(&name, bytes).or_exit("failed to add file");
+                println!("{sector:3}: {name}");
+            }
+
+            // Check the manifest the way it'll be read.
+            let bytes = package.to_bytes().or_exit("failed to build package");
+            Package::parse(&bytes).or_exit("invalid manifest");
+            std::fs::write(file, bytes).or_exit("failed to write file");
+        }
+        _ => {
+            // Any further files are loaded as overlays, in order. Packages
+            // bring their own disk and devices.
+            let bytes = std::fs::read(file).or_exit("failed to read file");
+            let (image, package) = if bytes.starts_with(&pact::package::MAGIC) {
+                let package = Package::parse(&bytes).map_err(|e| e.in_file(file)).or_exit("failed to read package");
+                (package.image.clone(), Some(package))
+            } else {
+                (Image::parse(&bytes).map_err(|e| e.in_file(file)).or_exit("failed to read file"), None)
+            };
+            for warning in &image.warnings {
+                log::warn!("{warning}");
+            }
+
+            let mut required = image.required_devices.clone();
+            let mut rim = image.into_rim();
+            for overlay in &files[1..] {
+                let overlay = read_file(overlay).or_exit("failed to read file");
+                rim.load(overlay.instructions().to_vec()).or_exit("failed to load overlay");
+            }
+
+            if let Some(package) = &package {
+                required = package.required_devices().or_exit("failed to read package");
+                package.config().or_exit("failed to read package").apply(&mut rim);
+            }
+            configure(&mut rim);
+            if clipboard.get().unwrap_or(false) || required.contains(&pact::clipboard::CLIPBOARD_DEVICE) {
+                attach_clipboard(&mut rim);
+            }
+            // Kept until the program's done, when dropping it puts the
+            // terminal back.
+            let _terminal = mouse.get().unwrap_or(false).then(|| {
+                let terminal = pact::console::Crossterm::start().or_exit("failed to capture the mouse");
+                rim.set_console(terminal.console());
+                terminal
+            });
+            let sound = sound.get().unwrap_or(false);
+            if sound && rim.sound().is_none() {
+                rim.attach_sound(pact::sound::Sound::new());
+            }
+            let missing: Vec<_> = required.into_iter().filter(|&id| !rim.has_device(id)).map(device_name).collect();
+            if !missing.is_empty() {
+                panic!("program requires devices that aren't attached: {}", missing.join(", "));
+            }
+            if trace.get().unwrap_or(false) {
+                // Traces go to stderr, to keep them apart from screen output.
+                loop {
+                    let snapshot = rim.snapshot();
+                    if let Some(next) = rim.next_instruction() {
+                        eprintln!("{snapshot} {next}");
+                    }
+
+                    if rim.step().or_exit("failed to run program") == Status::Halted {
+                        break;
+                    }
+                }
+            } else if taint.get().unwrap_or(false) {
+                let taint = Taint::run(&mut rim).or_exit("failed to run program");
+                for report in taint.reports() {
+                    eprintln!("warning: {report}");
+                }
+            } else if let Some(path) = record.as_ref().filter(|path| path.ends_with(".gif")) {
+                record_gif(&mut rim, path);
+            } else if let Some(path) = record.as_ref().filter(|path| path.ends_with(".cast")) {
+                let file = std::fs::File::create(path).or_exit("failed to create recording");
+                let mut recorder = pact::cast::Recorder::new(std::io::BufWriter::new(file));
+                let res = recorder.run(&mut rim);
+                recorder.finish().or_exit("failed to write recording");
+                res.or_exit("failed to run program");
+            } else if let Some(path) = &record {
+                let file = std::fs::File::create(path).or_exit("failed to create trace");
+                let mut recorder = Recorder::new(std::io::BufWriter::new(file));
+                let res = recorder.run(&mut rim);
+                // Keep what was recorded up to a fault, to see how it got there.
+                recorder.finish().or_exit("failed to write trace");
+                res.or_exit("failed to run program");
+            } else if sound {
+                play(&mut rim);
+            } else if stats.get().unwrap_or(false) {
+                run_with_stats(&mut rim);
+            } else {
+                rim.run().or_exit("failed to run program");
+            }
+
+            if let (Some(path), Some(disk)) = (&disk_path, rim.disk()) {
+                disk.write_file(path).or_exit("failed to write disk");
+            }
+        }
+    }
+}
+
+/// Runs a program like [`Rim::run`], then prints how much it executed and
+/// how fast to stderr (see [`pact::counters`]).
+fn run_with_stats(rim: &mut Rim) {
+    let start = std::time::Instant::now();
+
+    // In bursts, the way `Rim::run` does, so the stats are for the same
+    // fused, batched loop as a normal run.
+    run_in_bursts(rim, usize::MAX, |_| {});
+
+    let elapsed = start.elapsed();
+    let counters = rim.counters();
+    let instructions = counters.instructions;
+    // So the stats come after the program's output.
+    let _ = std::io::stdout().flush();
+    eprintln!("instructions: {instructions}");
+    eprintln!("cycles: {}", counters.cycles);
+    eprintln!("jumps: {} ({} taken)", counters.jumps, counters.taken);
+    eprintln!("wall time: {elapsed:?}");
+    eprintln!("MIPS: {:.2}", instructions as f64 / elapsed.as_secs_f64() / 1e6);
+}
+
+/// Runs a program like [`Rim::run`], playing its sound device through the
+/// default audio output.
+#[cfg(feature = "audio")]
+fn play(rim: &mut Rim) {
+    let mut speaker = pact::sound::Speaker::new().unwrap_or_else(|e| panic!("failed to open audio output: {e}"));
+
+    // Short bursts, so notes change close to when the program changes them.
+    run_in_bursts(rim, 1000, |rim| {
+        if let Some(sound) = rim.sound_mut() {
+            sound.mix(&mut speaker);
+        }
+    });
+}
+
+#[cfg(not(feature = "audio"))]
+fn play(_rim: &mut Rim) {
+    panic!("pact was built without the `audio` feature");
+}
+
+/// Runs a program in bursts of up to `steps` until it halts, waiting and
+/// sleeping between them as [`Rim::run`] does, and calling `each` after
+/// each one.
+fn run_in_bursts(rim: &mut Rim, steps: usize, mut each: impl FnMut(&mut Rim)) {
+    while rim.burst(steps).result.or_exit("failed to run program") == Status::Running {
+        each(rim);
+
+        if rim.is_waiting() {
+            std::thread::sleep(pact::TICK);
+        }
+
+        if let Some(wait) = rim.until_next_frame().filter(|_| rim.is_blocked()) {
+            std::thread::sleep(wait);
+        }
+
+        match rim.take_sleep() {
+            Some(0) => std::thread::yield_now(),
+            Some(ticks) => std::thread::sleep(pact::TICK * ticks as u32),
+            None => {}
+        }
+    }
+
+    each(rim);
+}
+
+/// Checks each instruction property against `cases` random states,
+/// exiting with an error if any fail.
+fn properties(cases: usize) {
+    let properties = pact::properties::PROPERTIES;
+    // Some properties make reserved calls on purpose.
+    log::set_max_level(log::LevelFilter::Error);
+
+    let mut failed = 0;
+    for property in properties {
+        match property.check(cases, 0) {
+            Ok(()) => println!("pass {}", property.name),
+            Err(failure) => {
+                failed += 1;
+                println!("FAIL {failure}");
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed", properties.len() - failed);
+    if failed != 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Runs the bundled conformance suite, or the cases in `dir`, exiting with
+/// an error if any fail.
+fn conformance(dir: Option<&Path>) {
+    let cases: Vec<(String, Vec<u8>, String)> = match dir {
+        Some(dir) => {
+            let mut programs: Vec<_> = std::fs::read_dir(dir)
+                .or_exit("failed to read directory")
+                .map(|entry| entry.or_exit("failed to read directory").path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rim"))
+                .collect();
+            programs.sort();
+
+            programs
+                .into_iter()
+                .map(|path| {
+                    let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                    let program = std::fs::read(&path).or_exit("failed to read file");
+                    let expected = std::fs::read_to_string(path.with_extension("expect"))
+                        .or_exit("failed to read expectation");
+
+                    (name, program, expected)
+                })
+                .collect()
+        }
+        None => pact::conformance::CASES
+            .iter()
+            .map(|case| (case.name.to_string(), case.program.to_vec(), case.expected.to_string()))
+            .collect(),
+    };
+
+    let mut failed = 0;
+    for (name, program, expected) in &cases {
+        match pact::conformance::check(program, expected) {
+            Ok(mismatches) if mismatches.is_empty() => println!("pass {name}"),
+            Ok(mismatches) => {
+                failed += 1;
+                println!("FAIL {name}");
+                for mismatch in mismatches {
+                    println!("    {mismatch}");
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {name}: {e}");
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed", cases.len() - failed);
+    if failed != 0 {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "serve")]
+fn serve(addr: &str, limits: pact::grade::Limits, control: bool, web: bool) {
+    println!("listening on {addr}");
+    if control {
+        pact::control::serve(addr, limits).or_exit("failed to serve");
+    } else if web {
+        serve_web(addr, limits);
+    } else {
+        pact::serve::serve(addr, limits).or_exit("failed to serve");
+    }
+}
+
+#[cfg(not(feature = "serve"))]
+fn serve(_addr: &str, _limits: pact::grade::Limits, _control: bool, _web: bool) {
+    panic!("pact was built without the `serve` feature");
+}
+
+#[cfg(feature = "playground")]
+fn serve_web(addr: &str, limits: pact::grade::Limits) {
+    pact::playground::serve(addr, limits).or_exit("failed to serve");
+}
+
+#[cfg(all(feature = "serve", not(feature = "playground")))]
+fn serve_web(_addr: &str, _limits: pact::grade::Limits) {
+    panic!("pact was built without the `playground` feature");
+}
+
+#[cfg(feature = "clipboard")]
+fn attach_clipboard(rim: &mut Rim) {
+    use pact::clipboard::{Clipboard, System, CLIPBOARD_DEVICE};
+
+    let system = System::new().unwrap_or_else(|e| panic!("failed to open the clipboard: {e}"));
+    rim.attach_device(CLIPBOARD_DEVICE, Clipboard::new(system));
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn attach_clipboard(_rim: &mut Rim) {
+    panic!("pact was built without the `clipboard` feature");
+}
+
+#[cfg(feature = "gif")]
+fn record_gif(rim: &mut Rim, path: &str) {
+    let file = std::fs::File::create(path).or_exit("failed to create recording");
+    let mut recorder = pact::gif::Recorder::new(std::io::BufWriter::new(file));
+    let res = recorder.run(rim);
+    recorder.finish().or_exit("failed to write recording");
+    res.or_exit("failed to run program");
+}
+
+#[cfg(not(feature = "gif"))]
+fn record_gif(_rim: &mut Rim, _path: &str) {
+    panic!("pact was built without the `gif` feature");
+}
+
+#[cfg(feature = "pactc")]
+fn pactc(file: &str, source: &str, output: &str) {
+    let instructions = pact::pactc::compile(source)
+        .unwrap_or_else(|e| fail_in_source("failed to compile program", file, source, &e));
+    write_file(output, &instructions).or_exit("failed to write file");
+}
+
+#[cfg(not(feature = "pactc"))]
+fn pactc(_file: &str, _source: &str, _output: &str) {
+    panic!("pact was built without the `pactc` feature");
+}
+
+/// Ends the program with an error and what caused it, rather than
+/// panicking with its debug form.
+trait OrExit<T> {
+    fn or_exit(self, context: &str) -> T;
+}
+
+impl<T, E: std::error::Error> OrExit<T> for Result<T, E> {
+    fn or_exit(self, context: &str) -> T {
+        self.unwrap_or_else(|e| fail(context, &e))
+    }
+}
+
+fn fail(context: &str, error: &dyn std::error::Error) -> ! {
+    pact::console::Crossterm::restore();
+    let _ = std::io::stdout().flush();
+    eprintln!("error: {context}: {}", chain(error));
+    std::process::exit(1);
+}
+
+/// An error followed by each of its causes.
+fn chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(&format!(": {error}"));
+        source = error.source();
     }
 
-    let file = &files[0];
+    message
+}
+
+/// Fails with an error from compiling a source file, showing the line it's
+/// on with the `diagnostics` feature.
+#[cfg(feature = "diagnostics")]
+fn fail_in_source(context: &str, path: &str, source: &str, error: &RimError) -> ! {
+    let RimError::Asm(pact::error::AsmError::Source { line, message }) = error.innermost() else {
+        fail(context, error);
+    };
+    let Some(text) = line.checked_sub(1).and_then(|i| source.lines().nth(i)) else {
+        fail(context, error);
+    };
+
+    let number = line.to_string();
+    let gutter = " ".repeat(number.len());
+    let code = text.trim();
+    let indent = text.len() - text.trim_start().len();
+
+    eprintln!("error: {message}");
+    eprintln!("{gutter}--> {path}:{line}");
+    eprintln!("{gutter} |");
+    eprintln!("{number} | {}", text.trim_end());
+    eprintln!("{gutter} | {}{}", &text[..indent], "^".repeat(code.chars().count()));
+    std::process::exit(1);
+}
 
-    let mut rim = read_file(file).expect("failed to read file");
-    rim.run().expect("failed to run program");
+#[cfg(not(feature = "diagnostics"))]
+fn fail_in_source(context: &str, _path: &str, _source: &str, error: &RimError) -> ! {
+    fail(context, error)
+}
+
+/// Loads a symbol file if it exists, since most binaries won't have one.
+fn load_symbols(path: &str) -> Symbols {
+    if Path::new(path).exists() {
+        Symbols::read_file(path).or_exit("failed to read symbols")
+    } else {
+        Symbols::new()
+    }
+}
+
+fn debug(debugger: &mut Debugger) {
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("(pact) ");
+        let _ = std::io::stdout().flush();
+
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+
+        let mut words = line.split_whitespace();
+        let (Some(command), arg) = (words.next(), words.next()) else {
+            continue;
+        };
+
+        match (command, arg) {
+            ("q" | "quit", _) => break,
+            ("s" | "step", _) => {
+                match debugger.step() {
+                    Ok(Status::Running) => print_state(debugger.rim(), debugger.symbols()),
+                    Ok(Status::Halted) => println!("program halted"),
+                    Err(e) => println!("error: {e}"),
+                }
+                print_watches(debugger);
+            }
+            ("c" | "continue", _) => {
+                match debugger.cont() {
+                    Ok(Stop::Breakpoint(_)) => print_state(debugger.rim(), debugger.symbols()),
+                    Ok(stop @ Stop::Watchpoint { .. }) => {
+                        print_watchpoint(stop);
+                        print_state(debugger.rim(), debugger.symbols());
+                    }
+                    Ok(Stop::Halted) => println!("program halted"),
+                    Err(e) => println!("error: {e}"),
+                }
+                print_watches(debugger);
+            }
+            ("w" | "watch", Some(expr)) => match Watch::parse(expr) {
+                Some(watch) if debugger.add_watch(watch) => println!("{watch} = {}", watch.eval(debugger.rim())),
+                Some(watch) => println!("already watching {watch}"),
+                None => println!("error: expected pc, a register, mem[addr], flags, or flags.<name>"),
+            },
+            ("w" | "watch", None) => print_watches(debugger),
+            ("unwatch", Some(expr)) => match Watch::parse(expr) {
+                Some(watch) if debugger.remove_watch(watch) => println!("stopped watching {watch}"),
+                Some(watch) => println!("not watching {watch}"),
+                None => println!("error: expected pc, a register, mem[addr], flags, or flags.<name>"),
+            },
+            ("b" | "break", Some(target)) => match debugger.resolve(target) {
+                Ok(addr) => {
+                    debugger.add_breakpoint(addr);
+                    println!("breakpoint at {addr:04x}");
+                }
+                Err(e) => println!("error: {e}"),
+            },
+            ("d" | "delete", Some(target)) => match debugger.resolve(target) {
+                Ok(addr) if debugger.remove_breakpoint(addr) => println!("deleted breakpoint at {addr:04x}"),
+                Ok(addr) => println!("no breakpoint at {addr:04x}"),
+                Err(e) => println!("error: {e}"),
+            },
+            ("wp" | "watchpoint", Some(addr)) => match parse_number(addr).and_then(Addr::from_index) {
+                Some(addr) if debugger.add_watchpoint(addr) => println!("watchpoint at mem[{addr}]"),
+                Some(addr) => println!("already a watchpoint at mem[{addr}]"),
+                None => println!("error: expected an address below 4096"),
+            },
+            ("wp" | "watchpoint", None) => {
+                for addr in debugger.watchpoints() {
+                    println!("mem[{addr}] = {}", Watch::Memory(addr).eval(debugger.rim()));
+                }
+            }
+            ("unwatchpoint", Some(addr)) => match parse_number(addr).and_then(Addr::from_index) {
+                Some(addr) if debugger.remove_watchpoint(addr) => println!("deleted watchpoint at mem[{addr}]"),
+                Some(addr) => println!("no watchpoint at mem[{addr}]"),
+                None => println!("error: expected an address below 4096"),
+            },
+            ("b" | "break", None) => {
+                for addr in debugger.breakpoints() {
+                    println!("{addr:04x} {}", debugger.symbols().label(addr).unwrap_or_default());
+                }
+            }
+            ("r" | "regs", _) => print_state(debugger.rim(), debugger.symbols()),
+            ("l" | "list", _) => print!("{}", disassemble(debugger.rim().instructions(), debugger.symbols())),
+            ("source", Some(path)) => source(debugger, path),
+            ("save", Some(path)) => match State::capture(debugger.rim()).write_file(path) {
+                Ok(()) => println!("saved to {path}"),
+                Err(e) => println!("error: {}", chain(&e)),
+            },
+            ("load", Some(path)) => match State::read_file(path) {
+                Ok(state) => {
+                    state.restore(debugger.rim_mut());
+                    print_state(debugger.rim(), debugger.symbols());
+                    print_watches(debugger);
+                }
+                Err(e) => println!("error: {}", chain(&e)),
+            },
+            _ => println!("commands: step, continue, break [target], delete <target>, watchpoint [addr], unwatchpoint <addr>, regs, list, watch [expr], unwatch <expr>, source <script>, save <file>, load <file>, quit"),
+        }
+    }
+}
+
+/// How wide the line-at-a-time trace viewer's timeline is.
+const TIMELINE_WIDTH: usize = 60;
+
+/// Browses a recorded trace full-screen, or a line at a time without a
+/// terminal to draw on.
+fn trace_view(trace: Trace<std::fs::File>, path: &str) {
+    use std::io::IsTerminal;
+
+    if std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+        TraceView::new(trace, path).run().or_exit("failed to draw the trace view");
+    } else {
+        trace_prompt(trace);
+    }
+}
+
+/// The full-screen trace viewer: a timeline to scrub through with the
+/// arrow keys or the mouse, the registers and a page of memory at the
+/// step it's on, and the steps that wrote to an address searched for.
+struct TraceView {
+    trace: Trace<std::fs::File>,
+    path: String,
+    at: u64,
+    /// The address of the first row of memory shown.
+    memory: usize,
+    /// The address last searched for, and the steps that wrote to it.
+    search: Option<(Addr, Vec<u64>)>,
+    /// What's being typed, and for which command.
+    prompt: Option<(Prompt, String)>,
+    /// An error or result, shown until the next key.
+    message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    Goto,
+    Memory,
+    Writes,
+}
+
+/// How many rows of memory the trace viewer shows.
+const MEMORY_ROWS: usize = 16;
+
+/// The row of the screen the timeline is drawn on.
+const TIMELINE_ROW: u16 = 1;
+
+/// Puts the terminal back however the viewer leaves, even by panicking.
+struct RawScreen;
+
+impl RawScreen {
+    fn enter() -> std::io::Result<Self> {
+        use crossterm::{cursor, event, terminal};
+
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(std::io::stdout(), terminal::EnterAlternateScreen, event::EnableMouseCapture, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawScreen {
+    fn drop(&mut self) {
+        use crossterm::{cursor, event, terminal};
+
+        let _ = crossterm::execute!(std::io::stdout(), cursor::Show, event::DisableMouseCapture, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl TraceView {
+    fn new(trace: Trace<std::fs::File>, path: &str) -> Self {
+        Self { trace, path: path.to_string(), at: 0, memory: 0, search: None, prompt: None, message: String::new() }
+    }
+
+    fn run(mut self) -> std::io::Result<()> {
+        use crossterm::event::{self, Event, KeyEventKind};
+
+        let _screen = RawScreen::enter()?;
+        loop {
+            self.draw()?;
+            let keep_going = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => self.key(key),
+                Event::Mouse(mouse) => self.mouse(mouse).map(|()| true)?,
+                _ => true,
+            };
+
+            if !keep_going {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handles a key, returning false to quit.
+    fn key(&mut self, key: crossterm::event::KeyEvent) -> bool {
+        use crossterm::event::{KeyCode, KeyModifiers};
+
+        if let Some((prompt, mut text)) = self.prompt.take() {
+            match key.code {
+                KeyCode::Enter => self.answer(prompt, &text),
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    text.pop();
+                    self.prompt = Some((prompt, text));
+                }
+                KeyCode::Char(c) => {
+                    text.push(c);
+                    self.prompt = Some((prompt, text));
+                }
+                _ => self.prompt = Some((prompt, text)),
+            }
+
+            return true;
+        }
+
+        self.message.clear();
+        let len = self.trace.len();
+        let jump = (len / 50).max(1);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Right | KeyCode::Char('l') => self.at = (self.at + 1).min(len),
+            KeyCode::Left | KeyCode::Char('h') => self.at = self.at.saturating_sub(1),
+            KeyCode::PageDown => self.at = (self.at + jump).min(len),
+            KeyCode::PageUp => self.at = self.at.saturating_sub(jump),
+            KeyCode::Home => self.at = 0,
+            KeyCode::End => self.at = len,
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::Char('g') => self.prompt = Some((Prompt::Goto, String::new())),
+            KeyCode::Char('m') => self.prompt = Some((Prompt::Memory, String::new())),
+            KeyCode::Char('w') => self.prompt = Some((Prompt::Writes, String::new())),
+            KeyCode::Char('n') => self.next_write(true),
+            KeyCode::Char('N') => self.next_write(false),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn answer(&mut self, prompt: Prompt, text: &str) {
+        let Some(n) = parse_number(text.trim()) else {
+            self.message = format!("expected a number, not `{text}`");
+            return;
+        };
+
+        match prompt {
+            Prompt::Goto => self.at = (n as u64).min(self.trace.len()),
+            Prompt::Memory | Prompt::Writes => {
+                let Some(addr) = Addr::from_index(n) else {
+                    self.message = "expected an address below 4096".to_string();
+                    return;
+                };
+
+                self.show(addr);
+                if prompt == Prompt::Writes {
+                    match self.trace.writes_to(addr) {
+                        Ok(steps) => {
+                            // Jump to the first write from here on, if there is one.
+                            if let Some(&next) = steps.iter().find(|&&step| step >= self.at) {
+                                self.at = next;
+                            }
+
+                            self.message = format!("{} writes to {addr}", steps.len());
+                            self.search = Some((addr, steps));
+                        }
+                        Err(e) => self.message = e.to_string(),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves to the next write found after this step, or the last before it.
+    fn next_write(&mut self, forward: bool) {
+        let Some((_, steps)) = &self.search else {
+            self.message = "search for writes first, with w".to_string();
+            return;
+        };
+
+        let next = if forward {
+            steps.iter().find(|&&step| step > self.at)
+        } else {
+            steps.iter().rev().find(|&&step| step < self.at)
+        };
+
+        match next {
+            Some(&step) => self.at = step,
+            None => self.message = "no more writes that way".to_string(),
+        }
+    }
+
+    fn scroll(&mut self, rows: isize) {
+        let last = Addr::COUNT - MEMORY_ROWS * Addr::PAGE_SIZE;
+        self.memory = self.memory.saturating_add_signed(rows * Addr::PAGE_SIZE as isize).min(last);
+    }
+
+    /// Scrolls memory so that `addr`'s row is shown.
+    fn show(&mut self, addr: Addr) {
+        let row = addr.index() & !(Addr::PAGE_SIZE - 1);
+        if !(self.memory..self.memory + MEMORY_ROWS * Addr::PAGE_SIZE).contains(&row) {
+            self.memory = 0;
+            self.scroll((row / Addr::PAGE_SIZE) as isize - MEMORY_ROWS as isize / 2);
+        }
+    }
+
+    /// Scrubs to where the timeline is clicked or dragged, and scrolls
+    /// memory with the wheel.
+    fn mouse(&mut self, mouse: crossterm::event::MouseEvent) -> std::io::Result<()> {
+        use crossterm::event::{MouseButton, MouseEventKind};
+
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left) if mouse.row == TIMELINE_ROW => {
+                let width = crossterm::terminal::size()?.0.saturating_sub(2).max(2) as u64;
+                let column = (mouse.column as u64).saturating_sub(1).min(width - 1);
+                self.at = column * self.trace.len() / (width - 1);
+            }
+            MouseEventKind::ScrollDown => self.scroll(1),
+            MouseEventKind::ScrollUp => self.scroll(-1),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self) -> std::io::Result<()> {
+        use crossterm::cursor::MoveTo;
+        use crossterm::style::{Attribute, Print, SetAttribute};
+        use crossterm::terminal::{self, Clear, ClearType};
+        use crossterm::queue;
+
+        let (width, height) = terminal::size()?;
+        let len = self.trace.len();
+        let mut out = std::io::stdout().lock();
+        let mut row = 0;
+
+        let title = format!(" {}  step {}/{len}", self.path, self.at);
+        queue!(out, MoveTo(0, 0), SetAttribute(Attribute::Reverse), Print(format!("{title:<width$}", width = width as usize)), SetAttribute(Attribute::Reset))?;
+        row += 1;
+
+        // The timeline, with the searched-for writes marked.
+        let inner = (width as usize).saturating_sub(2).max(2);
+        let column = |step: u64| (step * (inner as u64 - 1) / len.max(1)) as usize;
+        let mut timeline = vec!['-'; inner];
+        for &mark in self.search.iter().flat_map(|(_, steps)| steps) {
+            timeline[column(mark)] = '*';
+        }
+        timeline[column(self.at)] = '|';
+        Self::line(&mut out, &mut row, &format!("[{}]", timeline.into_iter().collect::<String>()))?;
+        Self::line(&mut out, &mut row, "")?;
+
+        let (frame, step) = match self.trace.frame(self.at) {
+            Ok(frame) => (frame, self.trace.step(self.at).ok()),
+            Err(e) => {
+                Self::line(&mut out, &mut row, &format!("error: {e}"))?;
+                queue!(out, Clear(ClearType::FromCursorDown))?;
+                return out.flush();
+            }
+        };
+
+        match &step {
+            Some(step) => Self::line(&mut out, &mut row, &format!("{} {}", frame.snapshot, step.instruction))?,
+            None => Self::line(&mut out, &mut row, &format!("{} (end)", frame.snapshot))?,
+        }
+        let writes: Vec<_> = step.iter().flat_map(|step| &step.writes).map(|(addr, value)| format!("{addr}={value:02x}")).collect();
+        Self::line(&mut out, &mut row, &format!("writes: {}", if writes.is_empty() { "none".to_string() } else { writes.join(" ") }))?;
+        Self::line(&mut out, &mut row, "")?;
+
+        // Memory, with this step's writes reversed and the searched-for
+        // address underlined.
+        let header: String = (0..Addr::PAGE_SIZE).map(|offset| format!(" {offset:2x}")).collect();
+        Self::line(&mut out, &mut row, &format!("       {header}"))?;
+        let written: Vec<Addr> = step.iter().flat_map(|step| &step.writes).map(|&(addr, _)| addr).collect();
+        let searched = self.search.as_ref().map(|&(addr, _)| addr);
+        let rows = (height as usize).saturating_sub(row as usize + 2).min(MEMORY_ROWS);
+        for start in (self.memory..).step_by(Addr::PAGE_SIZE).take(rows) {
+            queue!(out, MoveTo(0, row), Print(format!("  {start:04x}:")))?;
+            for (offset, byte) in frame.data[start..start + Addr::PAGE_SIZE].iter().enumerate() {
+                let addr = Addr::from_index(start + offset).expect("the row is in memory");
+                let attribute = if written.contains(&addr) {
+                    Attribute::Reverse
+                } else if searched == Some(addr) {
+                    Attribute::Underlined
+                } else {
+                    Attribute::Reset
+                };
+                queue!(out, Print(" "), SetAttribute(attribute), Print(format!("{byte:02x}")), SetAttribute(Attribute::Reset))?;
+            }
+            queue!(out, Clear(ClearType::UntilNewLine))?;
+            row += 1;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+
+        let status = match &self.prompt {
+            Some((Prompt::Goto, text)) => format!("go to step: {text}"),
+            Some((Prompt::Memory, text)) => format!("show address: {text}"),
+            Some((Prompt::Writes, text)) => format!("find writes to: {text}"),
+            None if !self.message.is_empty() => self.message.clone(),
+            None => "←/→ step  PgUp/PgDn jump  Home/End  ↑/↓ memory  g go to  m address  w writes  n/N next/prev write  q quit".to_string(),
+        };
+        queue!(out, MoveTo(0, height.saturating_sub(1)), Print(status.chars().take(width as usize).collect::<String>()), Clear(ClearType::UntilNewLine))?;
+        out.flush()
+    }
+
+    /// Draws a line of text at `row`, and moves on to the next.
+    fn line(out: &mut impl Write, row: &mut u16, text: &str) -> std::io::Result<()> {
+        use crossterm::cursor::MoveTo;
+        use crossterm::style::Print;
+        use crossterm::terminal::{Clear, ClearType};
+
+        crossterm::queue!(out, MoveTo(0, *row), Print(text), Clear(ClearType::UntilNewLine))?;
+        *row += 1;
+        Ok(())
+    }
+}
+
+/// Browses a recorded trace a command at a line, for when there's no
+/// terminal to draw the full-screen viewer on.
+fn trace_prompt(mut trace: Trace<std::fs::File>) {
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    let len = trace.len();
+    let mut at = 0;
+    // Steps found by the last search, marked on the timeline.
+    let mut marks = Vec::new();
+
+    print_frame(&mut trace, at, &marks);
+    loop {
+        print!("(trace) ");
+        let _ = std::io::stdout().flush();
+
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+
+        let mut words = line.split_whitespace();
+        let (Some(command), arg) = (words.next(), words.next()) else {
+            continue;
+        };
+        let count = arg.and_then(parse_number).unwrap_or(1) as u64;
+
+        match (command, arg) {
+            ("q" | "quit", _) => break,
+            ("n" | "next", _) => at = (at + count).min(len),
+            ("p" | "prev", _) => at = at.saturating_sub(count),
+            ("g" | "goto", Some(_)) => at = count.min(len),
+            ("e" | "end", _) => at = len,
+            ("m" | "mem", Some(_)) => {
+                match trace.frame(at) {
+                    Ok(frame) => {
+                        // The 16-byte row holding the address.
+                        let start = count as usize & !0xf;
+                        match frame.data.get(start..start + 16) {
+                            Some(row) => {
+                                let bytes: Vec<_> = row.iter().map(|byte| format!("{byte:02x}")).collect();
+                                println!("{start:04x}: {}", bytes.join(" "));
+                            }
+                            None => println!("error: expected an address below 4096"),
+                        }
+                    }
+                    Err(e) => println!("error: {e}"),
+                }
+                continue;
+            }
+            ("w" | "writes", Some(_)) => {
+                let Some(addr) = pact::addr::Addr::from_index(count as usize) else {
+                    println!("error: expected an address below 4096");
+                    continue;
+                };
+
+                marks = match trace.writes_to(addr) {
+                    Ok(steps) => steps,
+                    Err(e) => {
+                        println!("error: {e}");
+                        continue;
+                    }
+                };
+
+                for &step in &marks {
+                    let value = trace.step(step).ok().and_then(|step| step.writes.into_iter().find(|&(written, _)| written == addr));
+                    println!("{step:>10}: {:02x}", value.map_or(0, |(_, value)| value));
+                }
+                println!("{} writes to {addr}", marks.len());
+
+                // Jump to the next write after here, if there is one.
+                if let Some(&next) = marks.iter().find(|&&step| step >= at) {
+                    at = next;
+                }
+            }
+            ("r" | "regs", _) => {}
+            _ => {
+                println!("commands: next [n], prev [n], goto <step>, end, regs, mem <addr>, writes <addr>, quit");
+                continue;
+            }
+        }
+
+        print_frame(&mut trace, at, &marks);
+    }
+}
+
+/// Prints the timeline, with the position and any marks on it, and the
+/// state before step `at`.
+fn print_frame(trace: &mut Trace<std::fs::File>, at: u64, marks: &[u64]) {
+    let len = trace.len();
+    let column = |step: u64| (step * (TIMELINE_WIDTH as u64 - 1) / len.max(1)) as usize;
+
+    let mut timeline = vec!['-'; TIMELINE_WIDTH];
+    for &mark in marks {
+        timeline[column(mark)] = '*';
+    }
+    timeline[column(at)] = '|';
+    println!("[{}] {at}/{len}", timeline.into_iter().collect::<String>());
+
+    let frame = match trace.frame(at) {
+        Ok(frame) => frame,
+        Err(e) => return println!("error: {e}"),
+    };
+
+    match trace.step(at) {
+        Ok(step) => println!("{} {}", frame.snapshot, step.instruction),
+        Err(_) => println!("{} (end)", frame.snapshot),
+    }
+}
+
+/// Runs a program under two builds of pact, recording a trace from each,
+/// and prints the first step where they differ.
+fn compare(old: &str, new: &str, file: &str) {
+    let record = |build: &str, name: &str| {
+        let path = std::env::temp_dir().join(format!("pact-compare-{}-{name}.trace", std::process::id()));
+        // A program that faults still leaves its trace, so the build's exit
+        // status doesn't matter.
+        std::process::Command::new(build)
+            .arg("run")
+            .arg(file)
+            .arg("--record")
+            .arg(&path)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .or_exit(&format!("failed to run `{build}`"));
+
+        let trace = std::fs::File::open(&path).map_err(RimError::from).and_then(Trace::open);
+        (trace.or_exit(&format!("failed to read the trace from `{build}`")), path)
+    };
+
+    let (mut old_trace, old_path) = record(old, "old");
+    let (mut new_trace, new_path) = record(new, "new");
+    let divergence = old_trace.diverge(&mut new_trace);
+    let _ = std::fs::remove_file(old_path);
+    let _ = std::fs::remove_file(new_path);
+
+    let Some(divergence) = divergence.or_exit("failed to read traces") else {
+        println!("both builds ran the same {} steps", old_trace.len());
+        return;
+    };
+
+    println!("first divergence at step {}, from {}", divergence.step, divergence.before.snapshot);
+    for (build, instruction, after) in [
+        (old, divergence.instructions.0, &divergence.after.0),
+        (new, divergence.instructions.1, &divergence.after.1),
+    ] {
+        match (instruction, after) {
+            (Some(instruction), Some(after)) => println!("  {build}: {instruction} -> {}", after.snapshot),
+            _ => println!("  {build}: (ended)"),
+        }
+    }
+
+    if let (Some(old), Some(new)) = &divergence.after {
+        for (addr, (a, b)) in old.data.iter().zip(new.data.iter()).enumerate().filter(|(_, (a, b))| a != b) {
+            println!("  mem[{addr:#05x}]: {a:#04x} vs {b:#04x}");
+        }
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Manages background sessions (see [`pact::session`]).
+#[cfg(unix)]
+fn session(args: &[String], options: &[String], configure: &dyn Fn(&mut Rim)) {
+    use pact::session::{self, request};
+
+    let (Some(action), rest) = (args.first(), args.get(1..).unwrap_or_default()) else {
+        panic!("expected a session command: start, list, attach, send, status, screen, output, or stop");
+    };
+    let name = |i: usize| rest.get(i).map(String::as_str).unwrap_or_else(|| panic!("not enough input"));
+    let reply = |name: &str, command: &str, payload: &[u8]| {
+        let reply = request(name, command, payload).or_exit(&format!("failed to reach session `{name}`"));
+        if let Some(message) = reply.strip_prefix("error: ") {
+            eprintln!("error: {message}");
+            std::process::exit(1);
+        }
+
+        reply
+    };
+
+    match action.as_str() {
+        "start" => {
+            let file = name(0);
+            let name = rest.get(1).cloned().unwrap_or_else(|| {
+                Path::new(file).file_stem().map_or("pact".into(), |stem| stem.to_string_lossy().into_owned())
+            });
+
+            // The session is hosted by another pact, with the same options,
+            // that outlives this one.
+            std::process::Command::new(std::env::current_exe().or_exit("failed to find pact"))
+                .args(["session", "host", file, &name])
+                .args(options)
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn()
+                .or_exit("failed to start session");
+
+            for _ in 0..100 {
+                if session::list().contains(&name) {
+                    println!("started session `{name}`");
+                    return;
+                }
+
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            }
+
+            eprintln!("error: session `{name}` didn't start");
+            std::process::exit(1);
+        }
+        "host" => {
+            let mut rim = read_file(name(0)).or_exit("failed to read file");
+            configure(&mut rim);
+            session::host(rim, name(1)).or_exit("failed to host session");
+        }
+        "list" => {
+            for name in session::list() {
+                println!("{name}");
+            }
+        }
+        "send" => {
+            reply(name(0), "send", rest[1..].join(" ").as_bytes());
+        }
+        "attach" => {
+            let name = name(0).to_string();
+            print!("{}", reply(&name, "screen", &[]));
+            println!("-- attached to `{name}`; end input to detach --");
+
+            // Output is passed on as it comes, while lines are sent.
+            let poller = name.clone();
+            std::thread::spawn(move || loop {
+                match request(&poller, "output", &[]) {
+                    Ok(output) => {
+                        print!("{output}");
+                        let _ = std::io::stdout().flush();
+                    }
+                    Err(_) => std::process::exit(0),
+                }
+
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            });
+
+            for line in std::io::stdin().lock().lines() {
+                let line = line.or_exit("failed to read input");
+                reply(&name, "send", format!("{line}\n").as_bytes());
+            }
+        }
+        command @ ("status" | "screen" | "output" | "stop") => print!("{}", reply(name(0), command, &[])),
+        other => panic!("unknown session command `{other}`"),
+    }
+}
+
+#[cfg(not(unix))]
+fn session(_args: &[String], _options: &[String], _configure: &dyn Fn(&mut Rim)) {
+    panic!("sessions need Unix sockets, which this platform doesn't have");
+}
+
+/// How long a line may run in the REPL, so an accidental loop can't hang it.
+const REPL_STEPS: usize = 100_000;
+
+/// Assembles and runs each line as it's typed, against the same machine.
+/// Lines are appended to its program, so jumps can reach earlier ones.
+fn repl(mut rim: Rim) {
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    let assembler = Assembler::new();
+
+    loop {
+        print!("> ");
+        let _ = std::io::stdout().flush();
+
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) => continue,
+            (Some(":q" | ":quit"), _) => break,
+            (Some(":reset"), _) => {
+                let architecture = rim.architecture();
+                let arithmetic = rim.arithmetic();
+                rim = Rim::default();
+                rim.set_architecture(architecture);
+                rim.set_arithmetic(arithmetic);
+                print_state(&rim, &Symbols::new());
+                continue;
+            }
+            (Some(":mem"), Some(addr)) => {
+                match parse_number(addr).and_then(|addr| rim.data().get(addr).map(|byte| (addr, byte))) {
+                    Some((addr, byte)) => println!("{addr:04x}: {byte:02x}"),
+                    None => println!("error: expected an address below 4096"),
+                }
+                continue;
+            }
+            (Some(word), _) if word.starts_with(':') => {
+                println!("commands: :mem <addr>, :reset, :quit, or any instruction");
+                continue;
+            }
+            _ => {}
+        }
+
+        let instructions = match assembler.assemble(&line) {
+            Ok(instructions) => instructions,
+            Err(e) => {
+                println!("error: {e}");
+                continue;
+            }
+        };
+
+        if let Err(e) = rim.extend(&instructions) {
+            println!("error: {e}");
+            continue;
+        }
+
+        let mut steps = 0;
+        while rim.pc() < rim.instructions().len() {
+            if steps == REPL_STEPS {
+                println!("stopped after {REPL_STEPS} steps");
+                break;
+            }
+
+            steps += 1;
+            match rim.step() {
+                Ok(Status::Running) => {}
+                Ok(Status::Halted) => {
+                    println!("program halted");
+                    break;
+                }
+                Err(e) => {
+                    println!("error: {e}");
+                    break;
+                }
+            }
+        }
+
+        print_state(&rim, &Symbols::new());
+    }
+}
+
+/// Runs a debugger script against the program, from where it is: a Rhai
+/// script if it ends in `.rhai`, and a line script otherwise.
+#[cfg(feature = "script")]
+fn source(debugger: &mut Debugger, path: &str) {
+    use pact::script::{Ended, Script};
+
+    let ended = if path.ends_with(".rhai") {
+        #[cfg(feature = "rhai")]
+        {
+            pact::script::rhai::run_file(path, debugger, &mut std::io::stdout())
+        }
+        #[cfg(not(feature = "rhai"))]
+        {
+            println!("error: pact was built without the `rhai` feature");
+            return;
+        }
+    } else {
+        Script::read_file(path, debugger).and_then(|script| script.run(debugger, &mut std::io::stdout()))
+    };
+
+    match ended {
+        Ok(Ended::Stopped(Stop::Halted)) => println!("program halted"),
+        Ok(Ended::Stopped(Stop::Breakpoint(_)) | Ended::Action(_)) => print_state(debugger.rim(), debugger.symbols()),
+        Ok(Ended::Stopped(stop @ Stop::Watchpoint { .. })) => {
+            print_watchpoint(stop);
+            print_state(debugger.rim(), debugger.symbols());
+        }
+        Ok(Ended::Limit) => {
+            println!("step limit reached");
+            print_state(debugger.rim(), debugger.symbols());
+        }
+        Err(e) => println!("error: {}", chain(&e)),
+    }
+}
+
+#[cfg(not(feature = "script"))]
+fn source(_: &mut Debugger, _: &str) {
+    println!("error: pact was built without the `script` feature");
+}
+
+/// Says which watchpoint stopped the program, and how it changed.
+fn print_watchpoint(stop: Stop) {
+    if let Stop::Watchpoint { addr, old, new } = stop {
+        println!("watchpoint mem[{addr}]: {old:#04x} -> {new:#04x}");
+    }
+}
+
+/// Shows each watch's value, one per line.
+fn print_watches(debugger: &Debugger) {
+    for watch in debugger.watches() {
+        println!("  {watch} = {}", watch.eval(debugger.rim()));
+    }
+}
+
+fn print_state(rim: &Rim, symbols: &Symbols) {
+    let snapshot = rim.snapshot();
+    let pc = snapshot.pc;
+
+    let next = rim
+        .next_instruction()
+        .map(|instruction| instruction.to_string())
+        .unwrap_or_default();
+
+    match symbols.label(pc) {
+        Some(label) => println!("{snapshot} <{label}> {next}"),
+        None => println!("{snapshot} {next}"),
+    }
 }
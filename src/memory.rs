@@ -0,0 +1,91 @@
+//! Editing data memory in transactions, which either all land or none do.
+//!
+//! A [`Transaction`] holds writes back until it's committed, and reads
+//! through it see them, so a debugger can try out a patch, or a test
+//! fixture set up memory piece by piece, then back out of it by rolling
+//! back or just dropping the transaction. The machine can't run while one
+//! is open, since it borrows the machine.
+//!
+//! ```no_run
+//! use pact::addr::Addr;
+//!
+//! let mut rim = pact::Rim::default();
+//! let mut transaction = rim.memory_transaction();
+//! transaction.write(Addr::new(0x1f, 0), 42);
+//! transaction.write_all(Addr::new(0x20, 0), b"hello");
+//! transaction.commit();
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::addr::Addr;
+use crate::Rim;
+
+/// Writes to a machine's data memory, held back until committed.
+#[must_use = "a transaction is rolled back unless it's committed"]
+pub struct Transaction<'a> {
+    rim: &'a mut Rim,
+    writes: BTreeMap<Addr, u8>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(rim: &'a mut Rim) -> Self {
+        Self { rim, writes: BTreeMap::new() }
+    }
+
+    /// The machine, as it is before the transaction.
+    pub fn rim(&self) -> &Rim {
+        self.rim
+    }
+
+    /// A byte, as it will be if the transaction is committed.
+    pub fn read(&self, addr: Addr) -> u8 {
+        self.writes.get(&addr).copied().unwrap_or(self.rim.data()[addr.index()])
+    }
+
+    pub fn write(&mut self, addr: Addr, value: u8) {
+        self.writes.insert(addr, value);
+    }
+
+    /// Writes bytes from an address on, stopping at the end of memory.
+    /// Returns how many were written.
+    pub fn write_all(&mut self, start: Addr, bytes: &[u8]) -> usize {
+        let len = bytes.len().min(Addr::COUNT - start.index());
+        for (i, &byte) in bytes[..len].iter().enumerate() {
+            self.writes.insert(Addr::from_index(start.index() + i).unwrap(), byte);
+        }
+
+        len
+    }
+
+    /// Forgets the pending write to an address, if there is one.
+    pub fn revert(&mut self, addr: Addr) {
+        self.writes.remove(&addr);
+    }
+
+    /// The writes that would change memory, in address order, with each
+    /// byte's old and new value.
+    pub fn changes(&self) -> impl Iterator<Item = (Addr, u8, u8)> + '_ {
+        let data = self.rim.data();
+        self.writes
+            .iter()
+            .map(|(&addr, &new)| (addr, data[addr.index()], new))
+            .filter(|(_, old, new)| old != new)
+    }
+
+    /// Makes every write at once, returning how many changed memory.
+    pub fn commit(self) -> usize {
+        let changed = self.changes().count();
+        if changed > 0 {
+            let data = self.rim.data_mut();
+            for (addr, value) in self.writes {
+                data[addr.index()] = value;
+            }
+        }
+
+        changed
+    }
+
+    /// Drops every write, as dropping the transaction does.
+    pub fn rollback(self) {}
+}
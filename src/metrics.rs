@@ -0,0 +1,78 @@
+//! Counters for long-lived embedders, like `pact serve`.
+//!
+//! A [`Metrics`] handle is cheap to share between threads. Its `Display`
+//! impl writes the Prometheus text format.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::grade::Outcome;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    programs: AtomicU64,
+    instructions: AtomicU64,
+    /// Runs that didn't halt, by error kind, or `out_of_steps` or
+    /// `out_of_time`.
+    faults: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a finished run.
+    pub fn record(&self, steps: usize, outcome: &Outcome) {
+        self.programs.fetch_add(1, Ordering::Relaxed);
+        self.instructions.fetch_add(steps as u64, Ordering::Relaxed);
+
+        let kind = match outcome {
+            Outcome::Halted => return,
+            Outcome::Faulted(e) => e.kind(),
+            Outcome::OutOfSteps => "out_of_steps",
+            Outcome::OutOfTime => "out_of_time",
+        };
+
+        let mut faults = self.faults.lock().unwrap_or_else(|e| e.into_inner());
+        *faults.entry(kind).or_default() += 1;
+    }
+
+    pub fn programs(&self) -> u64 {
+        self.programs.load(Ordering::Relaxed)
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions.load(Ordering::Relaxed)
+    }
+
+    pub fn faults(&self) -> BTreeMap<&'static str, u64> {
+        self.faults.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The mean number of steps per run, or 0 before any have finished.
+    pub fn average_fuel(&self) -> f64 {
+        match self.programs() {
+            0 => 0.0,
+            programs => self.instructions() as f64 / programs as f64,
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# TYPE pact_programs_run counter")?;
+        writeln!(f, "pact_programs_run {}", self.programs())?;
+        writeln!(f, "# TYPE pact_instructions_executed counter")?;
+        writeln!(f, "pact_instructions_executed {}", self.instructions())?;
+        writeln!(f, "# TYPE pact_faults counter")?;
+        for (kind, count) in self.faults() {
+            writeln!(f, "pact_faults{{kind=\"{kind}\"}} {count}")?;
+        }
+
+        writeln!(f, "# TYPE pact_fuel_used_average gauge")?;
+        writeln!(f, "pact_fuel_used_average {}", self.average_fuel())
+    }
+}
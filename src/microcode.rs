@@ -0,0 +1,166 @@
+//! What each instruction does, as a table embedders can change.
+//!
+//! A machine looks up each instruction's opcode in its table of
+//! [`Microcode`], and calls what it finds with the instruction's operands,
+//! after moving the program counter past it. [`DEFAULT`] holds the standard
+//! semantics, one function per opcode, so a replacement can wrap the
+//! original:
+//!
+//! ```no_run
+//! use pact::microcode;
+//! use pact::{InstructionData, Opcode, Rim, Status};
+//! use pact::error::RimResult;
+//!
+//! fn traced_adi(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+//!     eprintln!("adi {} at {:#05x}", data.as_imm(), rim.pc() - 1);
+//!     microcode::adi(rim, data)
+//! }
+//!
+//! let mut rim = Rim::default();
+//! rim.set_microcode(Opcode::Adi, traced_adi);
+//! ```
+//!
+//! Extended instructions (see [`Rim::set_extension`]) don't go through the
+//! table.
+
+use crate::error::RimResult;
+use crate::{InstructionData, Opcode, Register, Rim, Status};
+
+/// Executes an instruction, given its operands.
+pub type Microcode = fn(&mut Rim, InstructionData) -> RimResult<Status>;
+
+/// The standard semantics, indexed by opcode.
+pub const DEFAULT: [Microcode; 8] = [adi, add, sub, jne, jg, jl, ioi, ior];
+
+/// The default for an opcode.
+pub fn default(opcode: Opcode) -> Microcode {
+    DEFAULT[opcode as usize]
+}
+
+pub fn adi(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    let imm = data.as_imm();
+    let (res, carry) = rim.registers[0].overflowing_add(imm);
+    rim.check_overflow((rim.registers[0] as i8).overflowing_add(imm as i8).1)?;
+    let sign = (rim.registers[0] as i8 as i16 + imm as i16) < 0;
+    rim.registers[0] = res;
+
+    rim.flags.sign = sign;
+    rim.flags.zero = res == 0;
+    rim.flags.carry = carry;
+    Ok(Status::Running)
+}
+
+/// The registers an `Add` or `Sub` works on, as `(src, dest)`.
+fn operands(rim: &Rim, data: InstructionData) -> (usize, usize) {
+    let (is_id, src, dest) = data.as_reg();
+    if is_id {
+        (
+            Register::from(rim.registers[src as usize]) as usize,
+            Register::from(rim.registers[dest as usize]) as usize,
+        )
+    } else {
+        (src as usize, dest as usize)
+    }
+}
+
+pub fn add(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    let (src, dest) = operands(rim, data);
+
+    let (res, carry) = rim.registers[dest].overflowing_add(rim.registers[src]);
+    rim.check_overflow((rim.registers[dest] as i8).overflowing_add(rim.registers[src] as i8).1)?;
+    let sign = (rim.registers[dest] as i8 as i16 + rim.registers[src] as i8 as i16) < 0;
+    rim.registers[dest] = res;
+
+    rim.flags.sign = sign;
+    rim.flags.zero = res == 0;
+    rim.flags.carry = carry;
+    Ok(Status::Running)
+}
+
+pub fn sub(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    let (src, dest) = operands(rim, data);
+
+    let (res, borrow) = rim.registers[dest].overflowing_sub(rim.registers[src]);
+    rim.check_overflow((rim.registers[dest] as i8).overflowing_sub(rim.registers[src] as i8).1)?;
+    let sign = (rim.registers[dest] as i8 as i16 - rim.registers[src] as i8 as i16) < 0;
+    rim.registers[dest] = res;
+
+    rim.flags.sign = sign;
+    rim.flags.zero = res == 0;
+    rim.flags.carry = borrow;
+    Ok(Status::Running)
+}
+
+/// Jumps to an instruction's target if `condition` holds, or carry does
+/// after the carry condition system call.
+fn jump_if(rim: &mut Rim, data: InstructionData, condition: bool) -> RimResult<Status> {
+    let (is_ptr, addr) = data.as_mem();
+    let mut addr = rim.addr(addr as u8);
+    if is_ptr {
+        addr = rim.addr(rim.read(addr)?);
+    }
+
+    if rim.condition(condition) {
+        rim.jump(addr.index())?;
+    }
+
+    Ok(Status::Running)
+}
+
+/// Whether a jump's own condition holds.
+fn holds(rim: &Rim, opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Jne => !rim.flags.zero,
+        Opcode::Jg => !rim.flags.sign && !rim.flags.zero,
+        _ => rim.flags.sign,
+    }
+}
+
+pub fn jne(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    jump_if(rim, data, holds(rim, Opcode::Jne))
+}
+
+pub fn jg(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    jump_if(rim, data, holds(rim, Opcode::Jg))
+}
+
+pub fn jl(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    jump_if(rim, data, holds(rim, Opcode::Jl))
+}
+
+/// A jump whose target was resolved, and checked to be in the program,
+/// ahead of time (see [`Rim::burst`]).
+#[inline(always)]
+pub(crate) fn jump_resolved(rim: &mut Rim, opcode: Opcode, target: usize) -> Status {
+    let condition = holds(rim, opcode);
+    if rim.condition(condition) {
+        rim.pc = target;
+    }
+
+    Status::Running
+}
+
+pub fn ioi(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    let (device, function) = data.as_io();
+    match rim.io(device, function, rim.registers[0])? {
+        true => Ok(Status::Halted),
+        false => Ok(Status::Running),
+    }
+}
+
+/// Passes the register Ra selects to a device, or the one chosen with
+/// system call 10. Selectors aren't masked like register operands are, so
+/// one past Rd is an
+/// [`InvalidRegister`](crate::error::RuntimeError::InvalidRegister) fault.
+pub fn ior(rim: &mut Rim, data: InstructionData) -> RimResult<Status> {
+    let (device, function) = data.as_io();
+    let value = match rim.ior_source {
+        Some(register) => rim.registers[register as usize],
+        None => rim.register(rim.registers[0])?,
+    };
+
+    match rim.io(device, function, value)? {
+        true => Ok(Status::Halted),
+        false => Ok(Status::Running),
+    }
+}
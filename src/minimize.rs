@@ -0,0 +1,104 @@
+//! Shrinking a program to a small one that still behaves the same way, so
+//! a bug report can carry a tiny reproducer.
+//!
+//! [`minimize`] is delta debugging over instructions: it removes ever
+//! smaller chunks of the program while what's left stays interesting, until
+//! no single instruction can go. Removing instructions moves the ones after
+//! them, jump targets included, so a reproducer isn't always a subsequence
+//! that makes sense on its own, only one that still does the thing.
+//!
+//! What counts as interesting is up to the caller. [`minimize_behavior`]
+//! keeps the [`Behavior`] of the original, which is how a fuzz target's
+//! crash is usually reduced:
+//!
+//! ```no_run
+//! use pact::grade::Limits;
+//! use pact::minimize::{minimize_behavior, Behavior};
+//!
+//! let crash = std::fs::read("crash-1234").unwrap();
+//! let program: Vec<_> = crash.into_iter().map(pact::Instruction::decode).collect();
+//! let limits = Limits { max_time: None, ..Limits::default() };
+//!
+//! let small = minimize_behavior(&program, &limits);
+//! println!("{} -> {} instructions, which {}", program.len(), small.len(), Behavior::observe(&small, &limits));
+//! ```
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::grade::Limits;
+use crate::runner::Runner;
+use crate::{Instruction, Rim};
+
+/// How a run ended, as far as minimizing is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Behavior {
+    /// The [`Outcome`](crate::grade::Outcome)'s kind, or `panicked` if the
+    /// machine itself panicked.
+    pub outcome: &'static str,
+    /// The kind of error it faulted with, if it did.
+    pub error: Option<&'static str>,
+}
+
+impl Behavior {
+    /// Runs a program on a fresh machine, with no input, and sees how it
+    /// ends. A time limit makes this depend on the host's speed, so leave
+    /// it out where that matters.
+    pub fn observe(program: &[Instruction], limits: &Limits) -> Self {
+        let run = panic::catch_unwind(AssertUnwindSafe(|| Runner::new(Rim::new(program.to_vec())).limits(*limits).execute()));
+
+        match run {
+            Ok(report) => Self {
+                outcome: report.termination.kind(),
+                error: report.termination.error().map(|e| e.kind()),
+            },
+            Err(_) => Self { outcome: "panicked", error: None },
+        }
+    }
+}
+
+impl fmt::Display for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error {
+            Some(error) => write!(f, "{} ({error})", self.outcome),
+            None => write!(f, "{}", self.outcome),
+        }
+    }
+}
+
+/// Removes as much of a program as it can while `interesting` holds of
+/// what's left, which it's assumed to of the whole program.
+pub fn minimize(program: &[Instruction], mut interesting: impl FnMut(&[Instruction]) -> bool) -> Vec<Instruction> {
+    let mut current = program.to_vec();
+    let mut chunks = 2;
+
+    while current.len() >= 2 {
+        let size = current.len().div_ceil(chunks);
+        let reduced = (0..current.len()).step_by(size).find_map(|start| {
+            let end = (start + size).min(current.len());
+            let candidate = [&current[..start], &current[end..]].concat();
+            interesting(&candidate).then_some(candidate)
+        });
+
+        match reduced {
+            Some(candidate) => {
+                current = candidate;
+                chunks = (chunks - 1).max(2);
+            }
+            None if chunks >= current.len() => break,
+            None => chunks = (chunks * 2).min(current.len()),
+        }
+    }
+
+    if current.len() == 1 && interesting(&[]) {
+        current.clear();
+    }
+
+    current
+}
+
+/// Minimizes a program, keeping the [`Behavior`] it has under `limits`.
+pub fn minimize_behavior(program: &[Instruction], limits: &Limits) -> Vec<Instruction> {
+    let behavior = Behavior::observe(program, limits);
+    minimize(program, |candidate| Behavior::observe(candidate, limits) == behavior)
+}
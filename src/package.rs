@@ -0,0 +1,316 @@
+//! Packages: a program, the files it reads from the disk, and what it
+//! needs to run, in one file to share.
+//!
+//! A package starts with [`MAGIC`], and is followed by entries, each a tag
+//! byte, a big-endian `u32` length, and that many bytes:
+//!
+//! | Tag | Entry                                                        |
+//! |-----|--------------------------------------------------------------|
+//! | 0   | End; anything after it is ignored                            |
+//! | 1   | The manifest, as UTF-8 `key=value` lines                     |
+//! | 2   | The program, as an [image](crate::image)                     |
+//! | 3   | A file on the disk: its sector, a name length byte, the name, and its contents |
+//!
+//! A package must have a program, and has at most one manifest. Unknown
+//! entries are skipped. Besides free-form keys like `title` and `author`,
+//! the manifest may have:
+//!
+//! - `devices`: the devices to attach, as names or IDs separated by commas,
+//!   on top of those the image requires
+//! - `screen`: `immediate`, `manual`, or a frame rate, as for `pact run
+//!   --screen`
+//! - `disk_sectors`: the smallest the disk may be, for programs that write
+//!   past their files
+//!
+//! Each file starts at the beginning of its sector, so the program can seek
+//! straight to it. If there are any files, or `disk_sectors` is set, the
+//! machine gets a disk holding them, which isn't saved back to the package.
+//! Graphics, sound, and the mailbox are attached if they're required; host
+//! devices are up to whoever runs the package.
+//!
+//! `pact pack` makes packages, and `pact run` runs them like images:
+//!
+//! ```
+//! use pact::image::{Image, DEVICE_DISK};
+//! use pact::package::Package;
+//!
+//! let program = pact::asm::assemble("
+//!     li rb, 1
+//!     li ra, 2
+//!     ioi cpu, 7      ; the next I/O goes to bank 1
+//!     ioi cpu, 0      ; seek to the start of sector Ra
+//!     li rb, 1
+//!     li ra, 2
+//!     ioi cpu, 7
+//!     ioi cpu, 2      ; read a byte
+//!     ioi cpu, 0
+//! ").unwrap();
+//!
+//! let mut package = Package::new(Image::new(program));
+//! package.add_file("header", vec![0xff; 300]).unwrap();
+//! assert_eq!(package.add_file("level", b"hi".to_vec()).unwrap(), 2);
+//! package.manifest.insert("title".to_string(), "Demo".to_string());
+//!
+//! let package = Package::parse(&package.to_bytes().unwrap()).unwrap();
+//! assert!(package.required_devices().unwrap().contains(&DEVICE_DISK));
+//!
+//! let mut rim = package.into_rim().unwrap();
+//! rim.run().unwrap();
+//! assert_eq!(rim.registers()[0], b'h');
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::config::RimConfig;
+use crate::disk::{Disk, SECTOR_SIZE};
+use crate::error::{LoadError, RimError, RimResult};
+use crate::graphics::Graphics;
+use crate::image::{device_id, Image, LoadLimits, DEVICE_DISK, DEVICE_GRAPHICS, DEVICE_MAILBOX, DEVICE_SOUND};
+use crate::mailbox::Mailbox;
+use crate::screen::Present;
+use crate::sound::Sound;
+use crate::Rim;
+
+pub const MAGIC: [u8; 6] = *b"RIMPKG";
+
+pub const ENTRY_END: u8 = 0;
+pub const ENTRY_MANIFEST: u8 = 1;
+pub const ENTRY_PROGRAM: u8 = 2;
+pub const ENTRY_FILE: u8 = 3;
+
+/// A file on a package's disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    /// At most 255 bytes long.
+    pub name: String,
+    /// The sector it starts at.
+    pub sector: u8,
+    pub bytes: Vec<u8>,
+}
+
+impl File {
+    /// How many sectors it takes up.
+    pub fn sectors(&self) -> usize {
+        self.bytes.len().div_ceil(SECTOR_SIZE)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub image: Image,
+    pub manifest: BTreeMap<String, String>,
+    pub files: Vec<File>,
+}
+
+impl Package {
+    pub fn new(image: Image) -> Self {
+        Self {
+            image,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a file after the last one on the disk, returning the sector it
+    /// starts at.
+    pub fn add_file(&mut self, name: &str, bytes: Vec<u8>) -> RimResult<u8> {
+        check_name(name)?;
+        let sector = self.files.iter().map(|file| file.sector as usize + file.sectors().max(1)).max().unwrap_or(0);
+        let sector = u8::try_from(sector).map_err(|_| LoadError::OverLimit { limit: "first sector of a file", value: sector, max: 255 })?;
+        self.files.push(File { name: name.to_string(), sector, bytes });
+        Ok(sector)
+    }
+
+    pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Self> {
+        let path = f.as_ref();
+        std::fs::read(path).map_err(RimError::from).and_then(|bytes| Self::parse(&bytes)).map_err(|e| e.in_file(path))
+    }
+
+    /// Parses a package, checking its manifest.
+    pub fn parse(bytes: &[u8]) -> RimResult<Self> {
+        let mut rest = bytes.strip_prefix(&MAGIC).ok_or(LoadError::InvalidMagic)?;
+        let offset = |rest: &[u8]| bytes.len() - rest.len();
+        let invalid = |tag, offset| LoadError::InvalidPackage { tag, offset };
+        let mut image = None;
+        let mut manifest = None;
+        let mut files = Vec::new();
+
+        while let Some((&[tag, a, b, c, d], after)) = rest.split_first_chunk::<5>() {
+            let header = offset(rest);
+            let len = u32::from_be_bytes([a, b, c, d]) as usize;
+            let payload = after.get(..len).ok_or(invalid(tag, header))?;
+            rest = &after[len..];
+
+            match tag {
+                ENTRY_END => {
+                    rest = &[];
+                    break;
+                }
+                ENTRY_MANIFEST if manifest.is_some() => return Err(invalid(tag, header).into()),
+                ENTRY_MANIFEST => {
+                    let text = std::str::from_utf8(payload).map_err(|_| invalid(tag, header))?;
+                    let mut entries = BTreeMap::new();
+                    for line in text.lines() {
+                        let (key, value) = line.split_once('=').ok_or(invalid(tag, header))?;
+                        entries.insert(key.to_string(), value.to_string());
+                    }
+
+                    manifest = Some((entries, header));
+                }
+                ENTRY_PROGRAM if image.is_some() => return Err(invalid(tag, header).into()),
+                ENTRY_PROGRAM => image = Some(Image::parse(payload)?),
+                ENTRY_FILE => {
+                    let Some((&[sector, name_len], payload)) = payload.split_first_chunk::<2>() else {
+                        return Err(invalid(tag, header).into());
+                    };
+
+                    let name = payload.get(..name_len as usize).and_then(|name| std::str::from_utf8(name).ok()).ok_or(invalid(tag, header))?;
+                    let bytes = payload[name_len as usize..].to_vec();
+                    files.push(File { name: name.to_string(), sector, bytes });
+                }
+                _ => {}
+            }
+        }
+
+        if !rest.is_empty() {
+            return Err(invalid(rest[0], offset(rest)).into());
+        }
+
+        let (manifest, manifest_offset) = manifest.unwrap_or_default();
+        let package = Self {
+            image: image.ok_or(invalid(ENTRY_PROGRAM, bytes.len()))?,
+            manifest,
+            files,
+        };
+
+        // Catch a bad manifest now, not when it's run.
+        if package.required_devices().is_err() || package.present().is_err() || package.disk_sectors().is_err() {
+            return Err(invalid(ENTRY_MANIFEST, manifest_offset).into());
+        }
+
+        Ok(package)
+    }
+
+    pub fn to_bytes(&self) -> RimResult<Vec<u8>> {
+        let mut bytes = MAGIC.to_vec();
+        let mut entry = |tag: u8, payload: &[u8]| {
+            bytes.push(tag);
+            bytes.extend((payload.len() as u32).to_be_bytes());
+            bytes.extend(payload);
+        };
+
+        if !self.manifest.is_empty() {
+            let manifest: String = self.manifest.iter().map(|(key, value)| format!("{key}={value}\n")).collect();
+            entry(ENTRY_MANIFEST, manifest.as_bytes());
+        }
+
+        entry(ENTRY_PROGRAM, &self.image.to_bytes()?);
+        for file in &self.files {
+            check_name(&file.name)?;
+            let mut payload = vec![file.sector, file.name.len() as u8];
+            payload.extend(file.name.as_bytes());
+            payload.extend(&file.bytes);
+            entry(ENTRY_FILE, &payload);
+        }
+
+        entry(ENTRY_END, &[]);
+        Ok(bytes)
+    }
+
+    pub fn write_file<F: AsRef<Path>>(&self, f: F) -> RimResult<()> {
+        let bytes = self.to_bytes()?;
+        std::fs::write(&f, bytes).map_err(|e| RimError::from(e).in_file(f))
+    }
+
+    /// The devices the image requires, those the manifest lists, and the
+    /// disk if there is one.
+    pub fn required_devices(&self) -> RimResult<BTreeSet<usize>> {
+        let mut devices = self.image.required_devices.clone();
+        if let Some(names) = self.manifest.get("devices") {
+            for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                devices.insert(device_id(name).ok_or(self.invalid_manifest())?);
+            }
+        }
+
+        if !self.files.is_empty() || self.disk_sectors()?.is_some() {
+            devices.insert(DEVICE_DISK);
+        }
+
+        Ok(devices)
+    }
+
+    /// How the screen is presented, if the manifest says.
+    pub fn present(&self) -> RimResult<Option<Present>> {
+        let Some(screen) = self.manifest.get("screen") else {
+            return Ok(None);
+        };
+
+        Ok(Some(match screen.as_str() {
+            "immediate" => Present::Immediate,
+            "manual" => Present::Manual,
+            fps => Present::fps(fps.parse().map_err(|_| self.invalid_manifest())?),
+        }))
+    }
+
+    fn disk_sectors(&self) -> RimResult<Option<usize>> {
+        self.manifest.get("disk_sectors").map(|sectors| sectors.parse().map_err(|_| self.invalid_manifest())).transpose()
+    }
+
+    /// The disk, with every file written at its sector, if there are any
+    /// files or the manifest asks for one.
+    pub fn disk(&self) -> RimResult<Option<Disk>> {
+        let min = self.disk_sectors()?;
+        if self.files.is_empty() && min.is_none() {
+            return Ok(None);
+        }
+
+        let sectors = self.files.iter().map(|file| file.sector as usize + file.sectors()).chain(min).max().unwrap_or(0);
+        let mut bytes = vec![0; sectors * SECTOR_SIZE];
+        for file in &self.files {
+            let start = file.sector as usize * SECTOR_SIZE;
+            bytes[start..start + file.bytes.len()].copy_from_slice(&file.bytes);
+        }
+
+        Ok(Some(Disk::new(bytes)))
+    }
+
+    /// A config with the package's disk, screen, and built-in devices.
+    pub fn config(&self) -> RimResult<RimConfig> {
+        let mut config = RimConfig::new();
+        if let Some(disk) = self.disk()? {
+            config = config.disk(disk);
+        }
+
+        if let Some(present) = self.present()? {
+            config = config.present(present);
+        }
+
+        for id in self.required_devices()? {
+            config = match id {
+                DEVICE_GRAPHICS => config.graphics(Graphics::new()),
+                DEVICE_SOUND => config.sound(Sound::new()),
+                DEVICE_MAILBOX => config.mailbox(Mailbox::new()),
+                _ => config,
+            };
+        }
+
+        Ok(config)
+    }
+
+    /// A machine ready to run the package, with its config applied. The
+    /// image's warnings are dropped.
+    pub fn into_rim(self) -> RimResult<Rim> {
+        let config = self.config()?;
+        let mut rim = self.image.into_rim();
+        config.apply(&mut rim);
+        Ok(rim)
+    }
+
+    fn invalid_manifest(&self) -> RimError {
+        LoadError::InvalidPackage { tag: ENTRY_MANIFEST, offset: 0 }.into()
+    }
+}
+
+fn check_name(name: &str) -> RimResult<()> {
+    LoadLimits::check("file name length", name.len(), u8::MAX as usize)
+}
@@ -0,0 +1,527 @@
+//! pactc, a compiler from a tiny C-like language to Rim programs.
+//!
+//! Programs are statements, run top to bottom, over byte variables:
+//!
+//! ```text
+//! var n = getc() - '0';   // declare before use; starts at 0 without `=`
+//! var i;
+//! while (i < n) {
+//!     putc('*');
+//!     i = i + 1;
+//! }
+//! if (n == 0) { putc('0'); } else { halt; }
+//! ```
+//!
+//! Expressions are numbers, characters, variables, `getc()`, which reads a
+//! key, or 0 if there's none, and `+`, `-`, unary `-`, and parentheses.
+//! Arithmetic wraps. Conditions compare two expressions with `==`, `!=`,
+//! `<`, `>`, `<=`, or `>=`, unsigned, or test one for being nonzero.
+//! `putc(e);` writes a byte to the screen, and `halt;` stops. Comments
+//! start with `//`.
+//!
+//! Variable `n`, in order of declaration, lives at data address `n << 4 |
+//! 1`, so at most 256 can be declared, fewer if expressions need memory to
+//! hold what doesn't fit in registers. Rb and Rc hold values, allocated as
+//! expressions need them; Ra is the accumulator, and Rd addresses memory
+//! and holds jumps' pages.
+
+use std::collections::HashMap;
+
+use crate::codegen::Code;
+use crate::error::{AsmError, RimError, RimResult};
+use crate::helper::U3;
+use crate::{Device, Instruction, Register};
+
+/// Compiles a program, failing on syntax errors, undeclared variables, or
+/// if it's too big.
+pub fn compile(source: &str) -> RimResult<Vec<Instruction>> {
+    let tokens = lex(source)?;
+    let mut parser = Parser { tokens, pos: 0, vars: HashMap::new() };
+
+    let mut program = Vec::new();
+    while parser.peek().is_some() {
+        program.push(parser.statement()?);
+    }
+
+    let mut compiler = Compiler {
+        code: Code::new(),
+        next_temp: parser.vars.len(),
+        line: 0,
+    };
+
+    compiler.block(&program)?;
+    compiler.code.push(Instruction::ioi(Device::Cpu, U3::B000));
+    compiler.code.link()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(u8),
+    Ident(String),
+    Punct(&'static str),
+}
+
+const PUNCTUATION: &[&str] = &["==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "(", ")", "{", "}", ";"];
+
+fn lex(source: &str) -> RimResult<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let err = |message: String| RimError::Asm(AsmError::Source { line: line_no, message });
+        let mut rest = line.split("//").next().unwrap_or_default().trim_start();
+
+        while !rest.is_empty() {
+            let token = if let Some(punct) = PUNCTUATION.iter().find(|punct| rest.starts_with(**punct)) {
+                rest = &rest[punct.len()..];
+                Token::Punct(punct)
+            } else if let Some(quoted) = rest.strip_prefix('\'') {
+                let mut chars = quoted.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), Some('\'')) if c.is_ascii() => {
+                        rest = chars.as_str();
+                        Token::Number(c as u8)
+                    }
+                    _ => return Err(err("invalid character literal".to_string())),
+                }
+            } else {
+                let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(err(format!("unexpected `{}`", rest.chars().next().unwrap_or_default())));
+                }
+
+                let (word, after) = rest.split_at(len);
+                rest = after;
+                if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    Token::Number(word.parse().map_err(|_| err(format!("invalid number `{word}`")))?)
+                } else {
+                    Token::Ident(word.to_string())
+                }
+            };
+
+            tokens.push((line_no, token));
+            rest = rest.trim_start();
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(u8),
+    /// A variable, by slot.
+    Var(usize),
+    Getc,
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+impl Expr {
+    /// Whether it can be loaded straight into Ra.
+    fn is_leaf(&self) -> bool {
+        !matches!(self, Self::Binary(..))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cond {
+    left: Expr,
+    cmp: Cmp,
+    right: Expr,
+}
+
+/// A statement, and the line it starts on.
+type Line = (usize, Stmt);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Assign(usize, Expr),
+    Putc(Expr),
+    Halt,
+    If { cond: Cond, then: Vec<Line>, otherwise: Vec<Line> },
+    While { cond: Cond, body: Vec<Line> },
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    vars: HashMap<String, usize>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |&(line, _)| line)
+    }
+
+    fn err(&self, message: String) -> RimError {
+        AsmError::Source { line: self.line(), message }.into()
+    }
+
+    fn next(&mut self) -> RimResult<Token> {
+        let token = self.peek().cloned().ok_or_else(|| self.err("unexpected end of program".to_string()))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Punct(p)) if *p == punct);
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> RimResult<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.err(format!("expected `{punct}`")))
+        }
+    }
+
+    fn ident(&mut self) -> RimResult<String> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            _ => {
+                self.pos -= 1;
+                Err(self.err("expected a name".to_string()))
+            }
+        }
+    }
+
+    fn var(&self, name: &str) -> RimResult<usize> {
+        self.vars
+            .get(name)
+            .copied()
+            .ok_or_else(|| self.err(format!("undeclared variable `{name}`")))
+    }
+
+    fn block(&mut self) -> RimResult<Vec<Line>> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            statements.push(self.statement()?);
+        }
+
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> RimResult<Line> {
+        let line = self.line();
+        let name = self.ident()?;
+
+        let statement = match name.as_str() {
+            "var" => {
+                let name = self.ident()?;
+                let slot = self.vars.len();
+                if slot > u8::MAX as usize {
+                    return Err(self.err("too many variables".to_string()));
+                }
+
+                if self.vars.insert(name.clone(), slot).is_some() {
+                    return Err(self.err(format!("variable `{name}` declared twice")));
+                }
+
+                let value = if self.eat("=") { self.expr()? } else { Expr::Number(0) };
+                Stmt::Assign(slot, value)
+            }
+            "putc" => {
+                self.expect("(")?;
+                let value = self.expr()?;
+                self.expect(")")?;
+                Stmt::Putc(value)
+            }
+            "halt" => Stmt::Halt,
+            "if" => {
+                let cond = self.cond()?;
+                let then = self.block()?;
+                let otherwise = match self.peek() {
+                    Some(Token::Ident(word)) if word == "else" => {
+                        self.pos += 1;
+                        match self.peek() {
+                            Some(Token::Ident(word)) if word == "if" => vec![self.statement()?],
+                            _ => self.block()?,
+                        }
+                    }
+                    _ => Vec::new(),
+                };
+
+                return Ok((line, Stmt::If { cond, then, otherwise }));
+            }
+            "while" => {
+                let cond = self.cond()?;
+                let body = self.block()?;
+                return Ok((line, Stmt::While { cond, body }));
+            }
+            _ => {
+                let slot = self.var(&name)?;
+                self.expect("=")?;
+                Stmt::Assign(slot, self.expr()?)
+            }
+        };
+
+        self.expect(";")?;
+        Ok((line, statement))
+    }
+
+    fn cond(&mut self) -> RimResult<Cond> {
+        self.expect("(")?;
+        let left = self.expr()?;
+
+        let cmp = [("==", Cmp::Eq), ("!=", Cmp::Ne), ("<=", Cmp::Le), (">=", Cmp::Ge), ("<", Cmp::Lt), (">", Cmp::Gt)]
+            .into_iter()
+            .find(|(punct, _)| self.eat(punct))
+            .map(|(_, cmp)| cmp);
+
+        let cond = match cmp {
+            Some(cmp) => Cond { left, cmp, right: self.expr()? },
+            None => Cond { left, cmp: Cmp::Ne, right: Expr::Number(0) },
+        };
+
+        self.expect(")")?;
+        Ok(cond)
+    }
+
+    fn expr(&mut self) -> RimResult<Expr> {
+        let mut expr = self.term()?;
+        loop {
+            let op = if self.eat("+") {
+                Op::Add
+            } else if self.eat("-") {
+                Op::Sub
+            } else {
+                return Ok(expr);
+            };
+
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> RimResult<Expr> {
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+
+        if self.eat("-") {
+            return Ok(Expr::Binary(Box::new(Expr::Number(0)), Op::Sub, Box::new(self.term()?)));
+        }
+
+        match self.next()? {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Ident(name) if name == "getc" => {
+                self.expect("(")?;
+                self.expect(")")?;
+                Ok(Expr::Getc)
+            }
+            Token::Ident(name) => self.var(&name).map(Expr::Var),
+            Token::Punct(punct) => {
+                self.pos -= 1;
+                Err(self.err(format!("unexpected `{punct}`")))
+            }
+        }
+    }
+}
+
+/// What a condition's comparison leaves in the flags, once Rc is
+/// subtracted from Rb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flag {
+    NotZero,
+    /// Rb is less than Rc, unsigned.
+    Carry,
+}
+
+struct Compiler {
+    code: Code,
+    /// The next free slot for holding values in memory.
+    next_temp: usize,
+    /// The line being compiled, for errors.
+    line: usize,
+}
+
+impl Compiler {
+    fn block(&mut self, statements: &[Line]) -> RimResult<()> {
+        for (line, statement) in statements {
+            self.line = *line;
+            self.statement(statement)?;
+        }
+
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> RimResult<()> {
+        match statement {
+            Stmt::Assign(slot, value) => {
+                self.eval(value, Register::Rb, &[Register::Rc])?;
+                self.store(Register::Rb, *slot);
+            }
+            Stmt::Putc(value) => {
+                self.eval(value, Register::Rb, &[Register::Rc])?;
+                self.code.push(Instruction::sub(Register::Ra, Register::Ra));
+                self.code.push(Instruction::add(Register::Rb, Register::Ra));
+                self.code.push(Instruction::ioi(Device::Scr, U3::B010));
+            }
+            Stmt::Halt => self.code.push(Instruction::ioi(Device::Cpu, U3::B000)),
+            Stmt::If { cond, then, otherwise } => {
+                let (other, end) = (self.code.label(), self.code.label());
+                self.branch_unless(cond, other)?;
+                self.block(then)?;
+                self.code.jump(end);
+                self.code.place(other);
+                self.block(otherwise)?;
+                self.code.place(end);
+            }
+            Stmt::While { cond, body } => {
+                let (top, end) = (self.code.label(), self.code.label());
+                self.code.place(top);
+                self.branch_unless(cond, end)?;
+                self.block(body)?;
+                self.code.jump(top);
+                self.code.place(end);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Jumps to a label if a condition doesn't hold.
+    fn branch_unless(&mut self, cond: &Cond, label: usize) -> RimResult<()> {
+        // Greater-than comparisons are less-than ones with the sides
+        // swapped.
+        let (left, right) = match cond.cmp {
+            Cmp::Gt | Cmp::Le => (&cond.right, &cond.left),
+            _ => (&cond.left, &cond.right),
+        };
+
+        self.eval(left, Register::Rb, &[Register::Rc])?;
+        self.eval(right, Register::Rc, &[])?;
+
+        match cond.cmp {
+            Cmp::Eq => self.branch_if(Flag::NotZero, label),
+            Cmp::Ne => self.branch_if_not(Flag::NotZero, label),
+            Cmp::Lt | Cmp::Gt => self.branch_if_not(Flag::Carry, label),
+            Cmp::Ge | Cmp::Le => self.branch_if(Flag::Carry, label),
+        }
+
+        Ok(())
+    }
+
+    /// Compares Rb to Rc, jumping to a label if the flag is set.
+    fn branch_if(&mut self, flag: Flag, label: usize) {
+        self.code.load_page(label);
+        if flag == Flag::Carry {
+            self.code.extend(Instruction::li(Register::Ra, 3));
+            self.code.push(Instruction::ioi(Device::Cpu, U3::B111));
+        }
+
+        self.code.push(Instruction::sub(Register::Rc, Register::Rb));
+        self.code.jne(label);
+    }
+
+    fn branch_if_not(&mut self, flag: Flag, label: usize) {
+        let skip = self.code.label();
+        self.branch_if(flag, skip);
+        self.code.jump(label);
+        self.code.place(skip);
+    }
+
+    /// Evaluates an expression into `dest`, which must be Rb or Rc, using
+    /// only it, `free`, Ra, Rd, and memory.
+    fn eval(&mut self, expr: &Expr, dest: Register, free: &[Register]) -> RimResult<()> {
+        let Expr::Binary(left, op, right) = expr else {
+            self.load_leaf(expr);
+            self.code.push(Instruction::sub(dest, dest));
+            self.code.push(Instruction::add(Register::Ra, dest));
+            return Ok(());
+        };
+
+        // Adding a complex expression to a simple one is quicker done the
+        // other way around.
+        let (left, right) = match op {
+            Op::Add if left.is_leaf() => (right, left),
+            _ => (left, right),
+        };
+
+        let apply = match op {
+            Op::Add => Instruction::add,
+            Op::Sub => Instruction::sub,
+        };
+
+        if right.is_leaf() {
+            self.eval(left, dest, free)?;
+            self.load_leaf(right);
+            self.code.push(apply(Register::Ra, dest));
+        } else if let Some((&other, rest)) = free.split_first() {
+            let mut scratch = vec![dest];
+            scratch.extend_from_slice(rest);
+            self.eval(right, other, &scratch)?;
+            self.eval(left, dest, rest)?;
+            self.code.push(apply(other, dest));
+        } else {
+            // Out of registers: hold the right side in memory meanwhile.
+            self.eval(right, dest, &[])?;
+            let temp = self.next_temp;
+            if temp > u8::MAX as usize {
+                return Err(AsmError::Source { line: self.line, message: "out of memory for variables".to_string() }.into());
+            }
+
+            self.next_temp += 1;
+            let offset = self.store(dest, temp);
+            self.eval(left, dest, &[])?;
+            self.load(temp, offset);
+            self.code.push(apply(Register::Ra, dest));
+            self.next_temp -= 1;
+        }
+
+        Ok(())
+    }
+
+    /// Loads an expression with no operators into Ra.
+    fn load_leaf(&mut self, expr: &Expr) {
+        match *expr {
+            Expr::Number(n) => self.code.extend(Instruction::li(Register::Ra, n)),
+            Expr::Var(slot) => self.load(slot, 1),
+            Expr::Getc => self.code.push(Instruction::ioi(Device::Kbd, U3::B000)),
+            Expr::Binary(..) => unreachable!(),
+        }
+    }
+
+    /// Loads byte `offset` of a slot's page into Ra.
+    fn load(&mut self, slot: usize, offset: u8) {
+        self.code.extend(Instruction::li(Register::Rd, slot as u8));
+        self.code.extend(Instruction::li(Register::Ra, offset));
+        self.code.push(Instruction::ioi(Device::Cpu, U3::B011));
+    }
+
+    /// Stores Rb or Rc in a slot, returning the offset it went to: the
+    /// register's number, since it's selected by Ra and stored there.
+    fn store(&mut self, register: Register, slot: usize) -> u8 {
+        self.code.extend(Instruction::li(Register::Rd, slot as u8));
+        self.code.extend(Instruction::li(Register::Ra, register as u8));
+        self.code.push(Instruction::ior(Device::Cpu, U3::B100));
+        register as u8
+    }
+}
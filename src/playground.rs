@@ -0,0 +1,110 @@
+//! A web playground: an editor, a screen, and the registers, in a page
+//! served alongside the [HTTP API](crate::serve).
+//!
+//! `pact serve --web` serves the page at `GET /`, which assembles, steps,
+//! and runs programs through the [control protocol](crate::control). Its
+//! requests are posted to `POST /control?session=<id>`, one JSON-RPC
+//! request per body, and answered with a JSON array of the messages a
+//! connection would have been sent, so notifications arrive with the
+//! response they followed.
+//!
+//! Each session ID gets a machine of its own, kept between requests. Past
+//! [`MAX_SESSIONS`], the one used longest ago is dropped, so a client that
+//! comes back after that starts over. Every other route is the API's, and
+//! requests share a pool of [`Limits::max_concurrent`] workers the same way.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::cache::ProgramCache;
+use crate::control::Control;
+use crate::error::{RimError, RimResult};
+use crate::grade::Limits;
+use crate::metrics::Metrics;
+use crate::serve::MAX_BODY;
+
+/// The page itself, with its styles and scripts inline.
+pub const INDEX: &str = include_str!("playground/index.html");
+
+/// How many sessions are kept at once.
+pub const MAX_SESSIONS: usize = 64;
+
+/// The sessions' machines, and when each was last used.
+type Sessions = Mutex<HashMap<String, (Arc<Mutex<Control>>, Instant)>>;
+
+/// Serves the playground and the API on `addr` until the process exits,
+/// handling requests on a pool of threads, within `limits`.
+pub fn serve(addr: &str, limits: Limits) -> RimResult<()> {
+    let server = Server::http(addr).map_err(|e| RimError::IoError(std::io::Error::other(e)))?;
+    let (metrics, cache, sessions) = (Metrics::default(), ProgramCache::default(), Sessions::default());
+
+    crate::serve::pool(&server, limits.max_concurrent, move |request| {
+        handle(request, &limits, &metrics, &cache, &sessions)
+    });
+
+    Ok(())
+}
+
+fn handle(mut request: Request, limits: &Limits, metrics: &Metrics, cache: &ProgramCache, sessions: &Sessions) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    let (status, content_type, body) = match (request.method(), path) {
+        (Method::Get, "/") => (200, "text/html; charset=utf-8", INDEX.to_string()),
+        (Method::Post, "/control") => {
+            let session = query.split('&').find_map(|pair| pair.strip_prefix("session="));
+            let mut body = String::new();
+            let read = request.as_reader().take(MAX_BODY as u64 + 1).read_to_string(&mut body);
+
+            let (status, messages) = match (session, read) {
+                (None, _) | (Some(""), _) => (400, json!({ "error": "expected a `session`" })),
+                (_, Ok(_)) if body.len() > MAX_BODY => (413, json!({ "error": format!("request body is over {MAX_BODY} bytes") })),
+                (Some(session), Ok(_)) => {
+                    let control = open(sessions, session, limits);
+                    let mut control = control.lock().unwrap_or_else(PoisonError::into_inner);
+                    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| control.handle(&body))) {
+                        Ok(messages) => (200, json!(messages)),
+                        Err(_) => {
+                            // Whatever it was doing is gone, so start it over.
+                            *control = Control::new(*limits);
+                            (500, json!({ "error": "the machine panicked" }))
+                        }
+                    }
+                }
+                (_, Err(e)) => (400, json!({ "error": e.to_string() })),
+            };
+
+            (status, "application/json", messages.to_string())
+        }
+        (_, "/" | "/control") => (405, "application/json", json!({ "error": "method not allowed" }).to_string()),
+        _ => return crate::serve::handle(request, limits, metrics, cache),
+    };
+
+    let header = Header::from_bytes("Content-Type", content_type).expect("header should be valid");
+    let _ = request.respond(Response::from_string(body).with_status_code(status).with_header(header));
+}
+
+/// Finds a session's machine, making it if it's new.
+fn open(sessions: &Sessions, session: &str, limits: &Limits) -> Arc<Mutex<Control>> {
+    let mut sessions = sessions.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((control, used)) = sessions.get_mut(session) {
+        *used = Instant::now();
+        return control.clone();
+    }
+
+    if sessions.len() >= MAX_SESSIONS {
+        let oldest = sessions.iter().min_by_key(|(_, (_, used))| *used).map(|(id, _)| id.clone());
+        if let Some(oldest) = oldest {
+            sessions.remove(&oldest);
+        }
+    }
+
+    let control = Arc::new(Mutex::new(Control::new(*limits)));
+    sessions.insert(session.to_string(), (control.clone(), Instant::now()));
+    control
+}
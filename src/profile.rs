@@ -0,0 +1,61 @@
+//! Execution counts, for finding where a program spends its time.
+
+use crate::error::RimResult;
+use crate::{Rim, Status};
+
+/// How many times each instruction ran, per program slot.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Profile {
+    counts: Vec<Vec<u64>>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `rim` until it halts, counting every instruction.
+    pub fn run(rim: &mut Rim) -> RimResult<Self> {
+        let mut profile = Self::new();
+        while profile.step(rim)? == Status::Running {}
+
+        Ok(profile)
+    }
+
+    /// Steps `rim` once, counting the instruction it executes.
+    pub fn step(&mut self, rim: &mut Rim) -> RimResult<Status> {
+        if rim.next_instruction().is_some() {
+            self.record(rim.current(), rim.pc());
+        }
+
+        rim.step()
+    }
+
+    pub fn record(&mut self, slot: usize, pc: usize) {
+        if self.counts.len() <= slot {
+            self.counts.resize(slot + 1, Vec::new());
+        }
+
+        let counts = &mut self.counts[slot];
+        if counts.len() <= pc {
+            counts.resize(pc + 1, 0);
+        }
+
+        counts[pc] += 1;
+    }
+
+    pub fn count(&self, slot: usize, pc: usize) -> u64 {
+        self.counts(slot).get(pc).copied().unwrap_or(0)
+    }
+
+    /// The counts for a program slot, indexed by address. Addresses past the
+    /// end were never run.
+    pub fn counts(&self, slot: usize) -> &[u64] {
+        self.counts.get(slot).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The total number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().flatten().sum()
+    }
+}
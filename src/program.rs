@@ -0,0 +1,137 @@
+//! A program, with the facts tooling keeps asking about it.
+//!
+//! A [`Program`] wraps a machine's decoded instructions, shared rather than
+//! copied, and works out its [`Analysis`] and [`Cfg`] the first time
+//! either is asked for, keeping them for its clones too. A validator, an
+//! optimizer, and a visualizer looking at the same program then agree on
+//! its blocks and jump targets, and only pay for finding them once.
+//!
+//! ```no_run
+//! let rim = pact::read_file("prog.rim").unwrap();
+//! let program = rim.program(0).unwrap();
+//! println!("{} instructions in {} blocks", program.len(), program.basic_blocks().len());
+//! for device in program.devices_used() {
+//!     println!("uses {device}");
+//! }
+//! ```
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, OnceLock};
+
+use crate::analysis::{self, Analysis, Diagnostic};
+use crate::cfg::{self, BasicBlock, Cfg};
+use crate::{Device, Instruction, InstructionData};
+
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    instructions: Arc<Vec<Instruction>>,
+    analysis: Arc<OnceLock<Analysis>>,
+    cfg: Arc<OnceLock<Cfg>>,
+}
+
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.instructions == other.instructions
+    }
+}
+
+impl Eq for Program {}
+
+impl From<Vec<Instruction>> for Program {
+    fn from(instructions: Vec<Instruction>) -> Self {
+        Self::new(instructions)
+    }
+}
+
+impl From<Arc<Vec<Instruction>>> for Program {
+    fn from(instructions: Arc<Vec<Instruction>>) -> Self {
+        Self { instructions, ..Default::default() }
+    }
+}
+
+impl AsRef<[Instruction]> for Program {
+    fn as_ref(&self) -> &[Instruction] {
+        &self.instructions
+    }
+}
+
+impl<'a> IntoIterator for &'a Program {
+    type Item = &'a Instruction;
+    type IntoIter = std::slice::Iter<'a, Instruction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Arc::new(instructions).into()
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn get(&self, pc: usize) -> Option<Instruction> {
+        self.instructions.get(pc).copied()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Instruction> {
+        self.instructions.iter()
+    }
+
+    /// Where execution starts, which is always address 0: a machine starts
+    /// there, and switching programs goes to the start of the new one.
+    pub fn entry(&self) -> usize {
+        0
+    }
+
+    /// What [`analysis::analyze`] finds, from reset.
+    pub fn analysis(&self) -> &Analysis {
+        self.analysis.get_or_init(|| analysis::analyze(&self.instructions))
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.analysis().diagnostics
+    }
+
+    /// The control-flow graph, which assumes each jump stays in its own page.
+    pub fn cfg(&self) -> &Cfg {
+        self.cfg.get_or_init(|| Cfg::build(&self.instructions))
+    }
+
+    pub fn basic_blocks(&self) -> &[BasicBlock] {
+        &self.cfg().blocks
+    }
+
+    /// The addresses in the program that non-pointer jumps go to, under the
+    /// same assumption as [`cfg`](Self::cfg).
+    pub fn jump_targets(&self) -> BTreeSet<usize> {
+        self.iter()
+            .enumerate()
+            .filter_map(|(pc, &instruction)| cfg::static_target(pc, instruction))
+            .filter(|&target| target < self.len())
+            .collect()
+    }
+
+    /// The devices the program's I/O instructions name. Devices reached
+    /// through an extended bank, like the disk, are chosen at runtime, so
+    /// they show up as the device the instruction names.
+    pub fn devices_used(&self) -> BTreeSet<Device> {
+        self.iter()
+            .filter_map(|instruction| match instruction.1 {
+                InstructionData::Io { device, .. } => Some(device),
+                _ => None,
+            })
+            .collect()
+    }
+}
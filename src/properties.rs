@@ -0,0 +1,433 @@
+//! Properties of each instruction's semantics, checked against randomized
+//! machine states.
+//!
+//! Where the conformance suite runs whole programs from known states, each
+//! property here takes a random instruction of one opcode, runs it with
+//! one [`Rim::step`] from random registers, flags, and memory, and checks
+//! the result against a definition: that `sub` sets carry when `dest < src`
+//! unsigned, that a `sub` undoes an `add`, that a jump lands when its
+//! condition holds. A failure names the seed it came from, which
+//! reproduces it.
+//!
+//! ```no_run
+//! for property in pact::properties::PROPERTIES {
+//!     if let Err(failure) = property.check(1000, 0) {
+//!         eprintln!("{failure}");
+//!     }
+//! }
+//! ```
+
+use std::fmt::{self, Display};
+
+use crate::addr::Addr;
+use crate::error::{RimError, RuntimeError};
+use crate::fusion::Fusion;
+use crate::helper::U3;
+use crate::isa::IsaLevel;
+use crate::{Arithmetic, Device, Flags, Instruction, InstructionData, Opcode, Register, Rim, Status, MAX_PROGRAM_LEN};
+
+/// How many states `pact properties` checks each property against.
+pub const DEFAULT_CASES: usize = 2000;
+
+/// A property of an opcode.
+#[derive(Debug, Clone, Copy)]
+pub struct Property {
+    pub name: &'static str,
+    pub opcode: Opcode,
+    check: fn(&mut Rng) -> Result<(), String>,
+}
+
+/// A state a property didn't hold in.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub property: &'static str,
+    /// Passing this to [`Property::check_seed`] reproduces the failure.
+    pub seed: u64,
+    pub message: String,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (seed {}): {}", self.property, self.seed, self.message)
+    }
+}
+
+impl Property {
+    /// Checks the property against `cases` states, starting from `seed`,
+    /// stopping at the first it doesn't hold in.
+    pub fn check(&self, cases: usize, seed: u64) -> Result<(), Failure> {
+        (0..cases as u64).try_for_each(|i| self.check_seed(seed.wrapping_add(i)))
+    }
+
+    /// Checks the property against the one state `seed` generates.
+    pub fn check_seed(&self, seed: u64) -> Result<(), Failure> {
+        (self.check)(&mut Rng::new(seed)).map_err(|message| Failure { property: self.name, seed, message })
+    }
+}
+
+macro_rules! property {
+    ($name:literal, $opcode:ident, $check:ident) => {
+        Property { name: $name, opcode: Opcode::$opcode, check: $check }
+    };
+}
+
+pub const PROPERTIES: &[Property] = &[
+    property!("adi adds its immediate to Ra", Adi, adi_adds),
+    property!("add adds src to dest", Add, add_adds),
+    property!("indirect add is add of the registers named", Add, add_indirect),
+    property!("sub sets flags by comparing dest to src", Sub, sub_compares),
+    property!("sub then add restores dest", Sub, sub_add_restores),
+    property!("sub r, r clears r", Sub, sub_clears),
+    property!("bursts run as steps do", Sub, bursts_match_steps),
+    property!("jne jumps unless zero", Jne, jne_jumps),
+    property!("jg jumps if neither sign nor zero", Jg, jg_jumps),
+    property!("jl jumps if sign", Jl, jl_jumps),
+    property!("setting the flags then reading them back keeps the low bits", Ioi, flags_round_trip),
+    property!("cpu 4 and cpu 3 store and load in Rd's page", Ioi, memory_in_page),
+    property!("cpu 6 and cpu 5 store and load through a pointer", Ioi, memory_through_pointer),
+    property!("ior reads the register Ra selects", Ior, ior_selects),
+    property!("ior faults on every selector past Rd", Ior, ior_checks_selectors),
+    property!("ior sends the register system call 10 chose, from level 2", Ior, ior_sends_chosen),
+];
+
+/// A small xorshift generator, so that seeds reproduce everywhere.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Spread nearby seeds apart, and keep the state nonzero.
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn byte(&mut self) -> u8 {
+        (self.next() >> 32) as u8
+    }
+
+    fn bool(&mut self) -> bool {
+        self.next() & 1 != 0
+    }
+
+    /// A random instruction with the given opcode.
+    fn instruction(&mut self, opcode: Opcode) -> Instruction {
+        Instruction::decode(self.byte() & !0b111 | opcode as u8)
+    }
+}
+
+/// A machine about to run `program` from random registers and flags, with
+/// either wrapping or flagged arithmetic.
+fn machine(rng: &mut Rng, program: Vec<Instruction>) -> Rim {
+    let mut rim = Rim::new(program);
+    rim.set_arithmetic(if rng.bool() { Arithmetic::Flagged } else { Arithmetic::Wrapping });
+    rim.set_registers([rng.byte(), rng.byte(), rng.byte(), rng.byte()]);
+    rim.set_flags(Flags::from_bits(rng.byte()));
+    rim
+}
+
+fn step(rim: &mut Rim) -> Result<Status, String> {
+    rim.step().map_err(|e| format!("faulted: {e}"))
+}
+
+fn expect<T: PartialEq + fmt::Debug>(what: &str, actual: T, expected: T) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("{what} was {actual:?}, expected {expected:?}"))
+    }
+}
+
+/// Checks the flags an addition or subtraction leaves, given its exact
+/// signed result, and whether it carried or borrowed.
+fn expect_arithmetic_flags(rim: &Rim, before: Flags, exact: i16, carry: bool) -> Result<(), String> {
+    let flags = rim.flags();
+    expect("sign", flags.sign(), exact < 0)?;
+    expect("zero", flags.zero(), exact as u8 == 0)?;
+    expect("carry", flags.carry(), carry)?;
+
+    let overflow = match rim.arithmetic() {
+        Arithmetic::Flagged => !(-128..=127).contains(&exact),
+        _ => before.overflow(),
+    };
+    expect("overflow", flags.overflow(), overflow)
+}
+
+fn adi_adds(rng: &mut Rng) -> Result<(), String> {
+    let instruction = rng.instruction(Opcode::Adi);
+    let imm = instruction.1.as_imm();
+    let mut rim = machine(rng, vec![instruction]);
+    let (before, flags) = (rim.registers(), rim.flags());
+
+    step(&mut rim)?;
+    let mut expected = before;
+    expected[0] = before[0].wrapping_add(imm);
+    expect("registers", rim.registers(), expected)?;
+    expect("pc", rim.pc(), 1)?;
+    expect_arithmetic_flags(&rim, flags, before[0] as i8 as i16 + imm as i16, before[0].checked_add(imm).is_none())
+}
+
+fn add_adds(rng: &mut Rng) -> Result<(), String> {
+    let instruction = Instruction::add(Register::from(rng.byte()), Register::from(rng.byte()));
+    let (_, src, dest) = instruction.1.as_reg();
+    let mut rim = machine(rng, vec![instruction]);
+    let (before, flags) = (rim.registers(), rim.flags());
+    let (a, b) = (before[dest as usize], before[src as usize]);
+
+    step(&mut rim)?;
+    let mut expected = before;
+    expected[dest as usize] = a.wrapping_add(b);
+    expect("registers", rim.registers(), expected)?;
+    expect_arithmetic_flags(&rim, flags, a as i8 as i16 + b as i8 as i16, a.checked_add(b).is_none())
+}
+
+fn add_indirect(rng: &mut Rng) -> Result<(), String> {
+    let (src, dest) = (Register::from(rng.byte()), Register::from(rng.byte()));
+    let mut indirect = machine(rng, vec![Instruction(Opcode::Add, InstructionData::Reg { is_id: true, src, dest })]);
+    let registers = indirect.registers();
+
+    let named = |register: Register| Register::from(registers[register as usize]);
+    let mut direct = Rim::new(vec![Instruction::add(named(src), named(dest))]);
+    direct.set_arithmetic(indirect.arithmetic());
+    direct.set_registers(registers);
+    direct.set_flags(indirect.flags());
+
+    step(&mut indirect)?;
+    step(&mut direct)?;
+    expect("registers", indirect.registers(), direct.registers())?;
+    expect("flags", indirect.flags(), direct.flags())
+}
+
+fn sub_compares(rng: &mut Rng) -> Result<(), String> {
+    let instruction = Instruction::sub(Register::from(rng.byte()), Register::from(rng.byte()));
+    let (_, src, dest) = instruction.1.as_reg();
+    let mut rim = machine(rng, vec![instruction]);
+    let (before, flags) = (rim.registers(), rim.flags());
+    let (a, b) = (before[dest as usize], before[src as usize]);
+
+    step(&mut rim)?;
+    let mut expected = before;
+    expected[dest as usize] = a.wrapping_sub(b);
+    expect("registers", rim.registers(), expected)?;
+    expect_arithmetic_flags(&rim, flags, a as i8 as i16 - b as i8 as i16, a < b)
+}
+
+fn sub_add_restores(rng: &mut Rng) -> Result<(), String> {
+    let src = Register::from(rng.byte());
+    let dest = Register::from(src as u8 + 1 + rng.byte() % 3);
+    let mut rim = machine(rng, vec![Instruction::sub(src, dest), Instruction::add(src, dest)]);
+    let before = rim.registers();
+
+    step(&mut rim)?;
+    step(&mut rim)?;
+    expect("registers", rim.registers(), before)
+}
+
+fn sub_clears(rng: &mut Rng) -> Result<(), String> {
+    let register = Register::from(rng.byte());
+    let mut rim = machine(rng, vec![Instruction::sub(register, register)]);
+
+    step(&mut rim)?;
+    expect("register", rim.registers()[register as usize], 0)?;
+    let flags = rim.flags();
+    expect("flags", (flags.sign(), flags.zero(), flags.carry()), (false, true, false))
+}
+
+/// Runs a random program of the opcodes that can be fused or resolved, once
+/// by steps and once by bursts of random lengths with blocks as long as they
+/// can be, and checks that they end up in the same place.
+fn bursts_match_steps(rng: &mut Rng) -> Result<(), String> {
+    const MAX_STEPS: usize = 256;
+    const OPCODES: [Opcode; 6] = [Opcode::Adi, Opcode::Add, Opcode::Sub, Opcode::Jne, Opcode::Jg, Opcode::Jl];
+
+    let len = 2 + rng.byte() as usize % 48;
+    let program = (0..len)
+        .map(|_| {
+            let opcode = OPCODES[rng.byte() as usize % OPCODES.len()];
+            rng.instruction(opcode)
+        })
+        .collect();
+    let mut stepped = machine(rng, program);
+    if rng.byte().is_multiple_of(4) {
+        stepped.set_arithmetic(Arithmetic::Faulting);
+    }
+    stepped.data_mut().iter_mut().for_each(|byte| *byte = rng.byte());
+    // Jump targets are resolved for Rd as it is from reset.
+    if rng.bool() {
+        let [ra, rb, rc, _] = stepped.registers();
+        stepped.set_registers([ra, rb, rc, 0]);
+    }
+    let mut burst = stepped.fork();
+    burst.set_fusion(Fusion::ALL);
+
+    let (mut steps, mut stepped_result) = (0, Ok(Status::Running));
+    while steps < MAX_STEPS && matches!(stepped_result, Ok(Status::Running)) {
+        steps += 1;
+        stepped_result = stepped.step();
+    }
+
+    let (mut bursts, mut burst_result) = (0, Ok(Status::Running));
+    while bursts < MAX_STEPS && matches!(burst_result, Ok(Status::Running)) {
+        let result = burst.burst((1 + rng.byte() as usize % 8).min(MAX_STEPS - bursts));
+        bursts += result.steps;
+        burst_result = result.result;
+    }
+
+    expect("steps", bursts, steps)?;
+    expect("result", format!("{burst_result:?}"), format!("{stepped_result:?}"))?;
+    expect("state", burst.snapshot(), stepped.snapshot())
+}
+
+/// Runs a random jump with the given opcode, from random memory, checking
+/// it lands exactly when `condition` holds of the flags.
+fn jumps(rng: &mut Rng, opcode: Opcode, condition: fn(Flags) -> bool) -> Result<(), String> {
+    let instruction = rng.instruction(opcode);
+    let (is_ptr, addr) = instruction.1.as_mem();
+
+    let mut program = vec![Instruction(Opcode::Adi, InstructionData::Imm(0)); MAX_PROGRAM_LEN];
+    program[0] = instruction;
+    let mut rim = machine(rng, program);
+    rim.data_mut().iter_mut().for_each(|byte| *byte = rng.byte());
+    let (before, flags) = (rim.registers(), rim.flags());
+
+    let target = Addr::new(before[3], addr as u8);
+    let target = if is_ptr { target.with_offset(rim.data()[target.index()]) } else { target }.index();
+
+    step(&mut rim)?;
+    expect("pc", rim.pc(), if condition(flags) { target } else { 1 })?;
+    expect("registers", rim.registers(), before)?;
+    expect("flags", rim.flags(), flags)
+}
+
+fn jne_jumps(rng: &mut Rng) -> Result<(), String> {
+    jumps(rng, Opcode::Jne, |flags| !flags.zero())
+}
+
+fn jg_jumps(rng: &mut Rng) -> Result<(), String> {
+    jumps(rng, Opcode::Jg, |flags| !flags.sign() && !flags.zero())
+}
+
+fn jl_jumps(rng: &mut Rng) -> Result<(), String> {
+    jumps(rng, Opcode::Jl, |flags| flags.sign())
+}
+
+/// The bits of a value Mth function 7 keeps as flags.
+fn kept_flags(rim: &Rim) -> u8 {
+    // Overflow only sticks when it's being flagged.
+    if rim.arithmetic() == Arithmetic::Flagged { 0b1111 } else { 0b0111 }
+}
+
+fn flags_round_trip(rng: &mut Rng) -> Result<(), String> {
+    let mut rim = machine(rng, vec![Instruction::ioi(Device::Mth, U3::B111), Instruction::ioi(Device::Mth, U3::B110)]);
+    let value = rim.registers()[0];
+
+    step(&mut rim)?;
+    step(&mut rim)?;
+    expect("Ra", rim.registers()[0], value & kept_flags(&rim))
+}
+
+fn memory_in_page(rng: &mut Rng) -> Result<(), String> {
+    let mut rim = machine(rng, vec![Instruction::ioi(Device::Cpu, U3::B100), Instruction::ioi(Device::Cpu, U3::B011)]);
+    // The edges of memory, as often as anywhere else.
+    let page = [0, 0xff, rng.byte()][rng.byte() as usize % 3];
+    let mut registers = rim.registers();
+    registers[3] = page;
+    rim.set_registers(registers);
+    let value = registers[0];
+
+    step(&mut rim)?;
+    let addr = Addr::new(page, value).index();
+    let mut expected = [0; Addr::COUNT];
+    expected[addr] = value;
+    if let Some(wrong) = (0..expected.len()).find(|&i| rim.data()[i] != expected[i]) {
+        return Err(format!("storing {value} with Rd = {page} wrote {wrong:#05x}, expected {addr:#05x}"));
+    }
+
+    step(&mut rim)?;
+    expect("registers", rim.registers(), registers)
+}
+
+fn memory_through_pointer(rng: &mut Rng) -> Result<(), String> {
+    let mut rim = machine(rng, vec![Instruction::ioi(Device::Cpu, U3::B110), Instruction::ioi(Device::Cpu, U3::B101)]);
+    rim.data_mut().iter_mut().for_each(|byte| *byte = rng.byte());
+    let [value, .., page] = rim.registers();
+
+    let mut expected = *rim.data();
+    let pointer = Addr::new(page, value);
+    expected[pointer.with_offset(expected[pointer.index()]).index()] = value;
+
+    step(&mut rim)?;
+    if rim.data() != &expected {
+        return Err(format!("storing {value} through {pointer} with Rd = {page} didn't land on what it pointed to"));
+    }
+
+    // The store may have overwritten the pointer itself.
+    let loaded = expected[pointer.with_offset(expected[pointer.index()]).index()];
+    step(&mut rim)?;
+    expect("Ra", rim.registers()[0], loaded)
+}
+
+fn ior_selects(rng: &mut Rng) -> Result<(), String> {
+    // Setting the flags takes the value as it is, where most Mth functions
+    // select a register with it.
+    let mut rim = machine(rng, vec![Instruction::ior(Device::Mth, U3::B111)]);
+    let before = rim.registers();
+    let selector = before[0];
+
+    if selector as usize >= before.len() {
+        return match rim.step() {
+            Err(RimError::Runtime(RuntimeError::InvalidRegister { register, .. })) => expect("faulting register", register, selector),
+            other => Err(format!("selector {selector} gave {other:?}, expected an invalid register fault")),
+        };
+    }
+
+    step(&mut rim)?;
+    expect("registers", rim.registers(), before)?;
+    expect("flags", rim.flags().to_bits(), before[selector as usize] & kept_flags(&rim))
+}
+
+fn ior_checks_selectors(rng: &mut Rng) -> Result<(), String> {
+    let (rb, rc, rd) = (rng.byte(), rng.byte(), rng.byte());
+
+    // Every selector, with the same other registers.
+    for selector in 0..=255 {
+        let mut rim = Rim::new(vec![Instruction::ior(Device::Mth, U3::B111)]);
+        rim.set_registers([selector, rb, rc, rd]);
+
+        match rim.step() {
+            Ok(_) if selector < 4 => {}
+            Err(RimError::Runtime(RuntimeError::InvalidRegister { register, pc: 0 })) if selector >= 4 && register == selector => {}
+            other => return Err(format!("selector {selector} gave {other:?}")),
+        }
+    }
+
+    Ok(())
+}
+
+fn ior_sends_chosen(rng: &mut Rng) -> Result<(), String> {
+    let program = vec![Instruction::ioi(Device::Cpu, U3::B111), Instruction::ior(Device::Mth, U3::B111)];
+    let mut rim = machine(rng, program);
+    let isa = if rng.bool() { IsaLevel::V1 } else { IsaLevel::V2 };
+    rim.set_isa(isa);
+    let mut registers = rim.registers();
+    registers[0] = 10;
+    rim.set_registers(registers);
+
+    step(&mut rim)?;
+    let chosen = registers[1];
+    // Before level 2 the call is reserved, and past Rd it goes back to Ra
+    // selecting, so either way Ra selects 10, which is past Rd.
+    if isa == IsaLevel::V1 || chosen > 3 {
+        return match rim.step() {
+            Err(RimError::Runtime(RuntimeError::InvalidRegister { register: 10, .. })) => Ok(()),
+            other => Err(format!("choosing {chosen} under {isa} gave {other:?}, expected an invalid register fault")),
+        };
+    }
+
+    step(&mut rim)?;
+    expect("flags", rim.flags().to_bits(), registers[chosen as usize] & kept_flags(&rim))
+}
@@ -0,0 +1,137 @@
+//! Running a program unattended from start to finish, for embedding.
+//!
+//! A [`Runner`] bundles a machine with its [`RimConfig`], scripted input,
+//! and [`Limits`], and [`Runner::execute`] runs it and
+//! reports everything about how it went, so embedders don't each write the
+//! same loop.
+//!
+//! ```no_run
+//! use pact::runner::Runner;
+//!
+//! let image = std::fs::read("echo.rim").unwrap();
+//! let report = Runner::from_image(&image).unwrap().input("hi").execute();
+//! println!("{report}");
+//! assert_eq!(report.output, b"hi");
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::config::RimConfig;
+use crate::console::{Buffer, Console, MouseEvent};
+use crate::disk::Disk;
+use crate::error::RimResult;
+use crate::grade::{self, Limits, Outcome};
+use crate::{read_bytes, IoStats, Rim, Snapshot};
+
+/// A machine ready to run, and how.
+pub struct Runner {
+    rim: Rim,
+    input: Vec<u8>,
+    mouse: Vec<MouseEvent>,
+    limits: Limits,
+}
+
+impl Runner {
+    pub fn new(rim: Rim) -> Self {
+        Self { rim, input: Vec::new(), mouse: Vec::new(), limits: Limits::default() }
+    }
+
+    /// Loads a program image, as [`read_bytes`] does.
+    pub fn from_image(bytes: &[u8]) -> RimResult<Self> {
+        read_bytes(bytes).map(Self::new)
+    }
+
+    /// Sets the machine up, keeping anything the config leaves alone. The
+    /// console is always replaced by the scripted input.
+    pub fn config(mut self, config: RimConfig) -> Self {
+        config.apply(&mut self.rim);
+        self
+    }
+
+    /// Sets the keys fed to the keyboard, in order.
+    pub fn input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    /// Sets the mouse events fed to the keyboard, in order.
+    pub fn mouse(mut self, events: impl Into<Vec<MouseEvent>>) -> Self {
+        self.mouse = events.into();
+        self
+    }
+
+    /// Sets the limits, of which [`Limits::load`] applies to the programs
+    /// the machine loads as it runs, but not to the one it has.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Runs the program until it halts, faults, or reaches a limit.
+    pub fn execute(mut self) -> RunReport {
+        let start = Instant::now();
+        let mut buffer = Buffer::new(self.input);
+        buffer.push_mouse(&self.mouse);
+        buffer.set_limit(self.limits.max_output);
+        self.rim.set_console(Console::Buffer(buffer));
+        self.rim.set_load_limits(self.limits.load);
+
+        let (mut steps, mut instructions) = (0, 0);
+        let termination = grade::run(&mut self.rim, &self.limits, start, &mut steps, &mut instructions);
+        let elapsed = start.elapsed();
+
+        let (output, truncated) = match self.rim.console() {
+            Console::Buffer(buffer) => (buffer.output().to_vec(), buffer.truncated()),
+            _ => (Vec::new(), false),
+        };
+
+        RunReport {
+            termination,
+            steps,
+            instructions,
+            elapsed,
+            output,
+            truncated,
+            screen: self.rim.screen().text(),
+            io: self.rim.io_stats(),
+            snapshot: self.rim.snapshot(),
+            data: Box::new(*self.rim.data()),
+            disk: self.rim.detach_disk(),
+        }
+    }
+}
+
+/// Everything about how a run went.
+#[derive(Debug)]
+pub struct RunReport {
+    pub termination: Outcome,
+    pub steps: usize,
+    /// Steps that executed an instruction, rather than blocking on a
+    /// mailbox or waiting for a key.
+    pub instructions: usize,
+    pub elapsed: Duration,
+    /// What the program wrote to the screen, as it wrote it.
+    pub output: Vec<u8>,
+    /// Whether output went over [`Limits::max_output`].
+    pub truncated: bool,
+    /// What the screen finally showed, one line per row.
+    pub screen: String,
+    pub io: IoStats,
+    pub snapshot: Snapshot,
+    pub data: Box<[u8; 4096]>,
+    /// The disk, with whatever the program wrote to it.
+    pub disk: Option<Disk>,
+}
+
+impl RunReport {
+    pub fn halted(&self) -> bool {
+        matches!(self.termination, Outcome::Halted)
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} after {} steps in {:.2?}: {}", self.termination, self.steps, self.elapsed, self.snapshot)
+    }
+}
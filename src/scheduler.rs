@@ -0,0 +1,205 @@
+//! Running several machines in turn, as a simple multitasking system.
+//!
+//! A [`Scheduler`] gives each machine a slice of steps, round-robin. At the
+//! start of each of its slices, a machine gets a timer interrupt (see
+//! [`Rim::interrupt`]), so a program that sets a handler can see time pass.
+//! A machine blocked on a mailbox gets no interrupts, and just retries its
+//! receive each slice. A machine that sleeps or yields (see
+//! [`Rim::take_sleep`]) ends its slice there, and isn't run again until that
+//! many ticks have passed, a tick being a slice of any machine. One that
+//! waits for a key (see [`Rim::is_waiting`]) isn't run again until it has
+//! one.
+//!
+//! The machines share one console: keys go to the machine in focus, and
+//! everything the machines write is passed to it at the end of each slice.
+
+use crate::console::{Buffer, Console};
+use crate::error::RimError;
+use crate::{Rim, Status};
+
+/// How far a machine has got.
+#[derive(Debug)]
+pub enum State {
+    Ready,
+    /// Asleep until a tick.
+    Sleeping(u64),
+    /// Waiting on a mailbox at the end of its last slice.
+    Blocked,
+    /// Waiting for a key.
+    Waiting,
+    Halted,
+    Faulted(RimError),
+}
+
+impl State {
+    /// Whether the machine is done, and won't be run again.
+    pub fn is_done(&self) -> bool {
+        matches!(self, Self::Halted | Self::Faulted(_))
+    }
+}
+
+struct Task {
+    rim: Rim,
+    state: State,
+    /// Whether its last slice blocked before doing anything.
+    stalled: bool,
+}
+
+pub struct Scheduler {
+    tasks: Vec<Task>,
+    /// Steps per slice.
+    slice: usize,
+    next: usize,
+    /// Slices so far, including idle ones.
+    ticks: u64,
+    focus: usize,
+    console: Console,
+}
+
+impl Scheduler {
+    /// A scheduler running each machine for up to `slice` steps at a time,
+    /// with the terminal as its console.
+    pub fn new(slice: usize) -> Self {
+        Self {
+            tasks: Vec::new(),
+            slice: slice.max(1),
+            next: 0,
+            ticks: 0,
+            focus: 0,
+            console: Console::default(),
+        }
+    }
+
+    /// Replaces the shared console, returning the old one.
+    pub fn set_console(&mut self, console: Console) -> Console {
+        std::mem::replace(&mut self.console, console)
+    }
+
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    /// Adds a machine, returning its ID. Its console is replaced, so it
+    /// reads and writes through the scheduler's.
+    pub fn spawn(&mut self, mut rim: Rim) -> usize {
+        rim.set_console(Console::Buffer(Buffer::default()));
+        self.tasks.push(Task { rim, state: State::Ready, stalled: false });
+        self.tasks.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn machine(&self, id: usize) -> Option<&Rim> {
+        self.tasks.get(id).map(|task| &task.rim)
+    }
+
+    pub fn machine_mut(&mut self, id: usize) -> Option<&mut Rim> {
+        self.tasks.get_mut(id).map(|task| &mut task.rim)
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn state(&self, id: usize) -> Option<&State> {
+        self.tasks.get(id).map(|task| &task.state)
+    }
+
+    /// The machine keys go to.
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    pub fn set_focus(&mut self, id: usize) {
+        self.focus = id;
+    }
+
+    /// Types keys on the focused machine's keyboard.
+    pub fn send_keys(&mut self, keys: &[u8]) {
+        let Some(task) = self.tasks.get_mut(self.focus) else {
+            return;
+        };
+
+        if let Console::Buffer(buffer) = task.rim.console_mut() {
+            buffer.push_input(keys);
+            // If it was waiting for keys, it can carry on.
+            task.stalled = false;
+        }
+    }
+
+    /// Runs the next machine that can run for a slice, returning its ID, or
+    /// `None` if they're all done or asleep.
+    pub fn run_slice(&mut self) -> Option<usize> {
+        self.ticks += 1;
+
+        let len = self.tasks.len();
+        let ticks = self.ticks;
+        let id = (0..len).map(|i| (self.next + i) % len).find(|&id| match self.tasks[id].state {
+            State::Sleeping(until) => until <= ticks,
+            State::Waiting => self.tasks[id].rim.console().poll(),
+            ref state => !state.is_done(),
+        })?;
+        self.next = (id + 1) % len;
+
+        let task = &mut self.tasks[id];
+        let interrupt = !matches!(task.state, State::Blocked);
+        (task.state, task.stalled) = match Self::slice(&mut task.rim, self.slice, interrupt, ticks) {
+            Ok(res) => res,
+            Err(e) => (State::Faulted(e), false),
+        };
+
+        if let Console::Buffer(buffer) = task.rim.console_mut() {
+            let output = buffer.take_output();
+            self.console.write(&output);
+        }
+
+        Some(id)
+    }
+
+    /// Runs a machine for a slice, returning how far it got, and whether it
+    /// blocked straight away.
+    fn slice(rim: &mut Rim, steps: usize, interrupt: bool, ticks: u64) -> Result<(State, bool), RimError> {
+        if interrupt {
+            rim.interrupt()?;
+        }
+
+        for i in 0..steps {
+            if rim.step()? == Status::Halted {
+                return Ok((State::Halted, false));
+            }
+
+            if rim.is_blocked() {
+                return Ok((State::Blocked, i == 0));
+            }
+
+            // Only keys can wake it, so it's stuck until some are sent.
+            if rim.is_waiting() {
+                return Ok((State::Waiting, true));
+            }
+
+            if let Some(sleep) = rim.take_sleep() {
+                return Ok((State::Sleeping(ticks + 1 + sleep as u64), false));
+            }
+        }
+
+        Ok((State::Ready, false))
+    }
+
+    /// Runs slices until every machine is done, or the rest are all stuck
+    /// waiting on each other or for keys. In that case, sending them a
+    /// message through their mailbox, or keys, and running again continues
+    /// them.
+    pub fn run(&mut self) {
+        while self.tasks.iter().any(|task| !task.state.is_done() && !task.stalled) {
+            for _ in 0..self.tasks.len() {
+                self.run_slice();
+            }
+        }
+    }
+}
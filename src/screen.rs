@@ -0,0 +1,272 @@
+//! The screen: a grid of character cells, and when to show it.
+//!
+//! The screen has the following functions:
+//!
+//! | Function | Effect                                                   |
+//! |----------|----------------------------------------------------------|
+//! | 0        | Move the cursor to row `value`                           |
+//! | 1        | Move the cursor to column `value`                        |
+//! | 2        | Put character `value` at the cursor, and advance it      |
+//! | 3        | Read the character at the cursor into Ra                 |
+//! | 4        | Reserved for reading attributes; sets Ra to 0            |
+//! | 5        | Clear the screen, leaving the cursor where it is         |
+//! | 6        | Present the screen, under [`Present::Manual`] or a rate  |
+//! | 7        | Wait for the next frame, and present it                  |
+//!
+//! Rows and columns count from 0, and moving past the edge clamps to it.
+//! Putting a character past the end of a row wraps to the next, `\n` moves
+//! to the start of the next row, and `\r` to the start of this one. Moving
+//! down from the last row scrolls the screen up.
+//!
+//! Reading the screen back lets a character-based game check what's at a
+//! cell, to detect collisions, without keeping its own copy in data memory:
+//! move the cursor there, and read. Blank cells read as 0. Function 4 is
+//! kept for reading a cell's attributes, once cells have them.
+//!
+//! ```
+//! let program = pact::asm::assemble("
+//!     li ra, 3
+//!     ioi scr, 0      ; row 3
+//!     li ra, 7
+//!     ioi scr, 1      ; column 7
+//!     li ra, 64
+//!     ioi scr, 2      ; '@', moving the cursor on
+//!     li ra, 7
+//!     ioi scr, 1      ; back to column 7
+//!     ioi scr, 3
+//!     ioi cpu, 0
+//! ").unwrap();
+//!
+//! let mut rim = pact::Rim::new(program);
+//! rim.run().unwrap();
+//! assert_eq!(rim.registers()[0], b'@');
+//! ```
+//!
+//! Under [`Present::Immediate`], every change is sent to the console as it
+//! happens, as ANSI escapes. Otherwise, changes only reach the console as
+//! whole frames, so nothing is seen half-drawn, and captured output only
+//! depends on when the program presents.
+//!
+//! Under [`Present::Rate`], function 7 waits until a frame is due, so a
+//! game can draw, wait, and draw the next frame at a steady pace however
+//! fast the machine runs. Frames it presents only go out if the screen
+//! changed. Under [`Present::Manual`], every wait is a frame, and under
+//! [`Present::Immediate`], where there are no frames, it does nothing.
+//!
+//! Graphical frontends can instead draw from the [`Screen`] directly,
+//! redrawing only the cells [`Screen::take_dirty`] reports.
+
+use std::io::Write;
+use std::time::Duration;
+
+pub const WIDTH: usize = 80;
+pub const HEIGHT: usize = 25;
+
+/// When the screen is sent to the console.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Present {
+    /// As each change happens.
+    #[default]
+    Immediate,
+    /// Whenever the program presents, and when it halts.
+    Manual,
+    /// At most once per interval, when the screen has changed, as well as
+    /// whenever the program presents, and when it halts.
+    Rate(Duration),
+}
+
+impl Present {
+    /// Presents at most `fps` times a second.
+    pub fn fps(fps: u32) -> Self {
+        Self::Rate(Duration::from_secs(1) / fps.max(1))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,
+    row: usize,
+    col: usize,
+    /// Whether anything changed since the last present.
+    changed: bool,
+    /// Cells changed since the last [`Screen::take_dirty`], as indices and
+    /// as a set.
+    dirty: Vec<usize>,
+    is_dirty: Vec<bool>,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new(WIDTH, HEIGHT)
+    }
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        Self {
+            width,
+            height,
+            cells: vec![0; width * height],
+            row: 0,
+            col: 0,
+            changed: false,
+            dirty: Vec::with_capacity(width * height),
+            is_dirty: vec![false; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The cursor's row and column.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    /// The character at a cell, or 0 if it's blank or out of range.
+    pub fn cell(&self, row: usize, col: usize) -> u8 {
+        if row < self.height && col < self.width {
+            self.cells[row * self.width + col]
+        } else {
+            0
+        }
+    }
+
+    /// Every cell, row by row, with blank cells as 0.
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Each row as text, with blank cells as spaces.
+    pub fn rows(&self) -> impl Iterator<Item = String> + '_ {
+        self.cells
+            .chunks(self.width)
+            .map(|row| row.iter().map(|&c| if c == 0 { ' ' } else { c as char }).collect())
+    }
+
+    /// The whole screen as text, one line per row.
+    pub fn text(&self) -> String {
+        self.rows().collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    /// The cells that changed since this was last called, as rows and
+    /// columns in order, so a frontend can redraw only those.
+    pub fn take_dirty(&mut self) -> Vec<(usize, usize)> {
+        self.dirty.sort_unstable();
+
+        // Drained rather than taken, so the capacity for every cell stays
+        // and changing a cell never allocates.
+        let width = self.width;
+        let is_dirty = &mut self.is_dirty;
+        self.dirty
+            .drain(..)
+            .map(|i| {
+                is_dirty[i] = false;
+                (i / width, i % width)
+            })
+            .collect()
+    }
+
+    fn set(&mut self, i: usize, c: u8) {
+        if self.cells[i] != c {
+            self.cells[i] = c;
+            if !std::mem::replace(&mut self.is_dirty[i], true) {
+                self.dirty.push(i);
+            }
+        }
+    }
+
+    /// Fills a new screen with saved cells, and puts the cursor back.
+    pub(crate) fn restore(&mut self, cells: &[u8], (row, col): (usize, usize)) {
+        self.changed = true;
+        for (i, &c) in cells.iter().enumerate().take(self.cells.len()) {
+            self.set(i, c);
+        }
+
+        self.row = row.min(self.height - 1);
+        self.col = col.min(self.width - 1);
+    }
+
+    pub(crate) fn set_row(&mut self, row: u8) {
+        self.row = (row as usize).min(self.height - 1);
+    }
+
+    pub(crate) fn set_col(&mut self, col: u8) {
+        self.col = (col as usize).min(self.width - 1);
+    }
+
+    pub(crate) fn put(&mut self, c: u8) {
+        self.changed = true;
+
+        match c {
+            b'\n' => {
+                self.col = 0;
+                self.down();
+            }
+            b'\r' => self.col = 0,
+            _ => {
+                self.set(self.row * self.width + self.col, c);
+                self.col += 1;
+                if self.col == self.width {
+                    self.col = 0;
+                    self.down();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.changed = true;
+        for i in 0..self.cells.len() {
+            self.set(i, 0);
+        }
+    }
+
+    fn down(&mut self) {
+        if self.row + 1 < self.height {
+            self.row += 1;
+        } else {
+            for i in 0..self.cells.len() {
+                let below = self.cells.get(i + self.width).copied().unwrap_or(0);
+                self.set(i, below);
+            }
+        }
+    }
+
+    /// Appends the escape that moves the terminal's cursor to this one.
+    pub(crate) fn cursor_escape(&self, out: &mut Vec<u8>) {
+        let _ = write!(out, "\x1b[{};{}H", self.row + 1, self.col + 1);
+    }
+
+    /// Appends what redraws the whole screen on a terminal, and marks it
+    /// unchanged. Like [`Screen::rows`], without a string per row.
+    pub(crate) fn render(&mut self, out: &mut Vec<u8>) {
+        self.changed = false;
+
+        out.extend_from_slice(b"\x1b[H");
+        for (i, row) in self.cells.chunks(self.width).enumerate() {
+            if i != 0 {
+                out.extend_from_slice(b"\r\n");
+            }
+
+            for &c in row {
+                let mut utf8 = [0; 4];
+                out.extend_from_slice(if c == 0 { b" " } else { (c as char).encode_utf8(&mut utf8).as_bytes() });
+            }
+        }
+
+        self.cursor_escape(out);
+    }
+}
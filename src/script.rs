@@ -0,0 +1,185 @@
+//! Debugger scripts: actions to take each time the program reaches an
+//! address, for inspection that would be tedious to repeat by hand.
+//!
+//! A script has a directive per line, and `#` starts a comment:
+//!
+//! ```text
+//! # Log Ra and Rb to a CSV each time the loop comes round.
+//! on main_loop log loop.csv ra rb
+//! on 0x012 print mem[0x01f] flags.zero
+//! on done stop
+//! limit 1000000
+//! ```
+//!
+//! `on <target> <action>` runs an action just before the instruction at a
+//! label or address executes, where the action is:
+//!
+//! - `print <watch>...`, which prints each [`Watch`]'s value;
+//! - `log <file> <watch>...`, which appends a row to a CSV file, with the
+//!   number of the hit and each value as a number, under a header naming
+//!   them; or
+//! - `stop`, which stops the script, leaving the program where it is.
+//!
+//! `limit <steps>` stops the script after that many steps. Otherwise, it
+//! runs until the program halts, a `stop` action, a breakpoint, or a
+//! watchpoint.
+//!
+//! An address can have any number of actions, which run in order.
+//!
+//! With the `rhai` feature, scripts can be written in Rhai instead, for
+//! conditions and arithmetic; see the `rhai` module.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::debug::{Debugger, Stop, Watch};
+use crate::error::{AsmError, RimError, RimResult};
+
+#[cfg(feature = "rhai")]
+pub mod rhai;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Print(Vec<Watch>),
+    /// The index of a log in [`Script::logs`], and what to write to it.
+    Log(usize, Vec<Watch>),
+    Stop,
+}
+
+/// Why [`Script::run`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ended {
+    Stopped(Stop),
+    /// A `stop` action, at an address.
+    Action(usize),
+    Limit,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    hooks: BTreeMap<usize, Vec<Action>>,
+    /// The files logged to, and the watches in their header.
+    logs: Vec<(String, Vec<Watch>)>,
+    limit: Option<u64>,
+}
+
+impl Script {
+    /// Parses a script, resolving its targets against a debugger's symbols.
+    pub fn parse(text: &str, debugger: &Debugger) -> RimResult<Self> {
+        let mut script = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let Some(directive) = words.next() else {
+                continue;
+            };
+
+            let err = |message: &str| AsmError::Source { line: i + 1, message: message.to_string() };
+            match directive {
+                "on" => {
+                    let target = words.next().ok_or_else(|| err("expected a label or address"))?;
+                    let addr = debugger.resolve(target).map_err(|_| err(&format!("unknown label `{target}`")))?;
+                    let action = words.next().ok_or_else(|| err("expected print, log, or stop"))?;
+                    let watches = |words: std::str::SplitWhitespace| {
+                        words
+                            .map(|expr| Watch::parse(expr).ok_or_else(|| err(&format!("can't watch `{expr}`"))))
+                            .collect::<Result<Vec<_>, _>>()
+                    };
+
+                    let action = match action {
+                        "print" => Action::Print(watches(words)?),
+                        "log" => {
+                            let file = words.next().ok_or_else(|| err("expected a file to log to"))?.to_string();
+                            let watches = watches(words)?;
+                            let log = match script.logs.iter().position(|(f, _)| *f == file) {
+                                Some(log) if script.logs[log].1 != watches => {
+                                    return Err(err("a file has to be logged with the same watches each time").into());
+                                }
+                                Some(log) => log,
+                                None => {
+                                    script.logs.push((file, watches.clone()));
+                                    script.logs.len() - 1
+                                }
+                            };
+                            Action::Log(log, watches)
+                        }
+                        "stop" => Action::Stop,
+                        _ => return Err(err("expected print, log, or stop").into()),
+                    };
+                    script.hooks.entry(addr).or_default().push(action);
+                }
+                "limit" => {
+                    let limit = words.next().and_then(|n| n.parse().ok()).ok_or_else(|| err("expected a number of steps"))?;
+                    script.limit = Some(limit);
+                }
+                _ => return Err(err(&format!("unknown directive `{directive}`")).into()),
+            }
+        }
+
+        Ok(script)
+    }
+
+    pub fn read_file<F: AsRef<Path>>(f: F, debugger: &Debugger) -> RimResult<Self> {
+        let path = f.as_ref();
+        std::fs::read_to_string(path)
+            .map_err(RimError::from)
+            .and_then(|text| Self::parse(&text, debugger))
+            .map_err(|e| e.in_file(path))
+    }
+
+    /// Runs the debugger's program, taking the script's actions as it goes
+    /// and printing to `out`. Log files are created, or overwritten, first.
+    pub fn run(&self, debugger: &mut Debugger, out: &mut impl Write) -> RimResult<Ended> {
+        let mut logs = Vec::new();
+        for (path, watches) in &self.logs {
+            let mut log = File::create(path).map(BufWriter::new).map_err(|e| RimError::from(e).in_file(path))?;
+            let header: Vec<_> = watches.iter().map(Watch::to_string).collect();
+            writeln!(log, "hit,{}", header.join(",")).map_err(|e| RimError::from(e).in_file(path))?;
+            logs.push((path, log, 0u64));
+        }
+
+        let mut steps = 0;
+        let ended = loop {
+            let pc = debugger.rim().pc();
+            for action in self.hooks.get(&pc).into_iter().flatten() {
+                let rim = debugger.rim();
+                match action {
+                    Action::Print(watches) => {
+                        let values: Vec<_> = watches.iter().map(|watch| format!("{watch} = {}", watch.eval(rim))).collect();
+                        writeln!(out, "{pc:#05x}: {}", values.join(", "))?;
+                    }
+                    Action::Log(log, watches) => {
+                        let (path, file, hits) = &mut logs[*log];
+                        *hits += 1;
+                        let values: Vec<_> = watches.iter().map(|watch| watch.value(rim).to_string()).collect();
+                        writeln!(file, "{hits},{}", values.join(",")).map_err(|e| RimError::from(e).in_file(path))?;
+                    }
+                    Action::Stop => return finish(logs, Ended::Action(pc)),
+                }
+            }
+
+            if self.limit.is_some_and(|limit| steps >= limit) {
+                break Ended::Limit;
+            }
+
+            steps += 1;
+            if let Some(stop) = debugger.step_checked()? {
+                break Ended::Stopped(stop);
+            }
+        };
+
+        finish(logs, ended)
+    }
+}
+
+/// Flushes the logs, so errors writing them aren't lost on drop.
+fn finish(logs: Vec<(&String, BufWriter<File>, u64)>, ended: Ended) -> RimResult<Ended> {
+    for (path, mut log, _) in logs {
+        log.flush().map_err(|e| RimError::from(e).in_file(path))?;
+    }
+
+    Ok(ended)
+}
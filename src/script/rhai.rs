@@ -0,0 +1,194 @@
+//! Debugger scripts in [Rhai](https://rhai.rs), for inspection the line
+//! scripts can't express, like conditions, sums, or formatting.
+//!
+//! A script runs once to register hooks, then the program runs, calling
+//! each hook with the machine just before the instruction at its address
+//! executes:
+//!
+//! ```rhai
+//! // Log Ra and Rb to a CSV each time the loop comes round, until Rb is 0.
+//! on("main_loop", |m| {
+//!     log("loop.csv", [m.ra, m.rb]);
+//!     if m.rb == 0 { stop(); }
+//! });
+//! on(0x012, |m| print(`mem[0x01f] = ${m.mem(0x01f)}, zero = ${m.get("flags.zero")}`));
+//! limit(1000000);
+//! ```
+//!
+//! The functions are:
+//!
+//! - `on(target, hook)`, where the target is a label, or an address as a
+//!   number or a string;
+//! - `log(file, values)`, which appends an array of values to a CSV file
+//!   as a row;
+//! - `stop()`, which stops the script once the hook returns, leaving the
+//!   program where it is; and
+//! - `limit(steps)`, which stops the script after that many steps.
+//!
+//! Hooks get the machine, with `pc`, `ra` to `rd`, and `flags` (as
+//! [`Flags::to_bits`](crate::Flags::to_bits) lays them out), `mem(addr)`
+//! for a byte of data memory, and `get(watch)` for the value of any
+//! [`Watch`]. Otherwise, the script ends the same ways as a line script
+//! (see [`Ended`]), and `print` goes where its prints do.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use ::rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, Position, AST};
+
+use crate::debug::{Debugger, Watch};
+use crate::error::{AsmError, RimError, RimResult};
+use crate::symbols::Symbols;
+use crate::Rim;
+
+use super::Ended;
+
+/// What the script's functions set up and ask for.
+#[derive(Default)]
+struct State {
+    hooks: BTreeMap<usize, Vec<FnPtr>>,
+    logs: BTreeMap<String, BufWriter<File>>,
+    /// Printed since it was last passed on.
+    printed: String,
+    stopped: bool,
+    limit: Option<u64>,
+}
+
+/// The machine, as a hook sees it.
+#[derive(Clone)]
+struct Machine(Rim);
+
+/// Runs a script against the debugger's program, from where it is,
+/// printing to `out`.
+pub fn run(source: &str, debugger: &mut Debugger, out: &mut impl Write) -> RimResult<Ended> {
+    let state = Rc::new(RefCell::new(State::default()));
+    let engine = engine(&state, debugger.symbols().clone());
+    let ast = engine.compile(source).map_err(|e| error(e.1, e.0.to_string()))?;
+    engine.run_ast(&ast).map_err(|e| eval_error(&e))?;
+    out.write_all(std::mem::take(&mut state.borrow_mut().printed).as_bytes())?;
+
+    let mut steps = 0;
+    let ended = 'run: loop {
+        let pc = debugger.rim().pc();
+        let hooks = state.borrow().hooks.get(&pc).cloned();
+        for hook in hooks.into_iter().flatten() {
+            let called = call(&engine, &ast, &hook, debugger.rim());
+            out.write_all(std::mem::take(&mut state.borrow_mut().printed).as_bytes())?;
+            called?;
+            if state.borrow().stopped {
+                break 'run Ended::Action(pc);
+            }
+        }
+
+        if state.borrow().limit.is_some_and(|limit| steps >= limit) {
+            break Ended::Limit;
+        }
+
+        steps += 1;
+        if let Some(stop) = debugger.step_checked()? {
+            break Ended::Stopped(stop);
+        }
+    };
+
+    for (path, log) in &mut state.borrow_mut().logs {
+        log.flush().map_err(|e| RimError::from(e).in_file(path))?;
+    }
+
+    Ok(ended)
+}
+
+pub fn run_file<F: AsRef<Path>>(f: F, debugger: &mut Debugger, out: &mut impl Write) -> RimResult<Ended> {
+    let path = f.as_ref();
+    std::fs::read_to_string(path)
+        .map_err(RimError::from)
+        .and_then(|source| run(&source, debugger, out))
+        .map_err(|e| e.in_file(path))
+}
+
+fn call(engine: &Engine, ast: &AST, hook: &FnPtr, rim: &Rim) -> RimResult<()> {
+    hook.call::<Dynamic>(engine, ast, (Machine(rim.clone()),)).map(drop).map_err(|e| eval_error(&e))
+}
+
+fn engine(state: &Rc<RefCell<State>>, symbols: Symbols) -> Engine {
+    let mut engine = Engine::new();
+
+    let printed = state.clone();
+    engine.on_print(move |text| {
+        let mut state = printed.borrow_mut();
+        state.printed.push_str(text);
+        state.printed.push('\n');
+    });
+
+    let hooks = state.clone();
+    engine.register_fn("on", move |target: &str, hook: FnPtr| -> Result<(), Box<EvalAltResult>> {
+        let addr = symbols.addr(target).or_else(|| parse_number(target)).ok_or(format!("unknown label `{target}`"))?;
+        hooks.borrow_mut().hooks.entry(addr).or_default().push(hook);
+        Ok(())
+    });
+
+    let hooks = state.clone();
+    engine.register_fn("on", move |addr: i64, hook: FnPtr| -> Result<(), Box<EvalAltResult>> {
+        let addr = usize::try_from(addr).map_err(|_| format!("no address {addr}"))?;
+        hooks.borrow_mut().hooks.entry(addr).or_default().push(hook);
+        Ok(())
+    });
+
+    let logs = state.clone();
+    engine.register_fn("log", move |path: &str, values: Array| -> Result<(), Box<EvalAltResult>> {
+        let mut state = logs.borrow_mut();
+        if !state.logs.contains_key(path) {
+            let file = File::create(path).map_err(|e| format!("failed to create `{path}`: {e}"))?;
+            state.logs.insert(path.to_string(), BufWriter::new(file));
+        }
+
+        let row: Vec<_> = values.iter().map(ToString::to_string).collect();
+        let log = state.logs.get_mut(path).expect("the log was just created");
+        writeln!(log, "{}", row.join(",")).map_err(|e| format!("failed to write to `{path}`: {e}"))?;
+        Ok(())
+    });
+
+    let stopped = state.clone();
+    engine.register_fn("stop", move || stopped.borrow_mut().stopped = true);
+
+    let limit = state.clone();
+    engine.register_fn("limit", move |steps: i64| limit.borrow_mut().limit = Some(steps.max(0) as u64));
+
+    engine
+        .register_type_with_name::<Machine>("Machine")
+        .register_get("pc", |m: &mut Machine| m.0.pc() as i64)
+        .register_get("ra", |m: &mut Machine| m.0.registers()[0] as i64)
+        .register_get("rb", |m: &mut Machine| m.0.registers()[1] as i64)
+        .register_get("rc", |m: &mut Machine| m.0.registers()[2] as i64)
+        .register_get("rd", |m: &mut Machine| m.0.registers()[3] as i64)
+        .register_get("flags", |m: &mut Machine| m.0.flags().to_bits() as i64)
+        .register_fn("mem", |m: &mut Machine, addr: i64| -> Result<i64, Box<EvalAltResult>> {
+            let byte = usize::try_from(addr).ok().and_then(|addr| m.0.data().get(addr));
+            byte.map(|&byte| byte as i64).ok_or(format!("no address {addr}").into())
+        })
+        .register_fn("get", |m: &mut Machine, expr: &str| -> Result<i64, Box<EvalAltResult>> {
+            let watch = Watch::parse(expr).ok_or(format!("can't watch `{expr}`"))?;
+            Ok(watch.value(&m.0) as i64)
+        });
+
+    engine
+}
+
+/// A decimal or `0x`-prefixed hexadecimal address.
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn eval_error(e: &EvalAltResult) -> RimError {
+    error(e.position(), e.to_string())
+}
+
+fn error(position: Position, message: String) -> RimError {
+    AsmError::Source { line: position.line().unwrap_or(0), message }.into()
+}
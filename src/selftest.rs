@@ -0,0 +1,303 @@
+//! A built-in self-test program, for checking a build, port, or embedding
+//! of the machine.
+//!
+//! The program runs a series of checks covering every opcode, the
+//! standard devices, and the flags, each leaving a value in Ra that's
+//! compared to what it should be. A failing check writes `FAIL` and its
+//! name to the screen on a line of its own, and the program ends by writing
+//! `PASS` if every check passed, or `FAILED` if any didn't, then halts. A
+//! host without a screen can read the result from data memory instead:
+//! [`RESULT_ADDR`] ends up 0 on a pass and 1 otherwise.
+//!
+//! The keyboard checks expect [`INPUT`] waiting to be read, and the
+//! arithmetic checks expect wrapping arithmetic (see
+//! [`Arithmetic`](crate::Arithmetic)). Extension devices and level 2
+//! instructions aren't covered, since a machine needn't have them.
+//!
+//! ```
+//! let report = pact::selftest::run();
+//! assert!(pact::selftest::passed(&report), "{}", String::from_utf8_lossy(&report.output));
+//! ```
+
+use crate::addr::Addr;
+use crate::codegen::Code;
+use crate::error::RimResult;
+use crate::helper::U3;
+use crate::runner::{RunReport, Runner};
+use crate::{Device, Instruction, InstructionData, Opcode, Register, Rim};
+
+/// The keys the keyboard checks expect to read.
+pub const INPUT: &[u8] = b"k";
+
+/// The data address of the result: 0 if every check passed.
+pub const RESULT_ADDR: Addr = Addr::new(RESULT_PAGE, 1);
+
+const RESULT_PAGE: u8 = 0xff;
+/// Where the memory checks store to.
+const SCRATCH_PAGE: u8 = 0xfe;
+
+/// A check: the code to run, and what it must leave in Ra.
+struct Check {
+    name: &'static str,
+    expected: u8,
+    run: fn(&mut Code),
+}
+
+const CHECKS: &[Check] = &[
+    Check { name: "adi", expected: 36, run: |code| {
+        code.push(zero_ra());
+        code.push(adi(5));
+        code.push(adi(31));
+    }},
+    Check { name: "adi carry", expected: 0b0100, run: |code| {
+        code.extend(Instruction::li(Register::Ra, 0xf0));
+        code.push(adi(0x1f));
+        code.push(read_flags());
+    }},
+    Check { name: "adi zero", expected: 0b0110, run: |code| {
+        code.extend(Instruction::li(Register::Ra, 0xff));
+        code.push(adi(1));
+        code.push(read_flags());
+    }},
+    Check { name: "add", expected: 42, run: |code| {
+        code.extend(Instruction::li(Register::Rb, 20));
+        code.extend(Instruction::li(Register::Ra, 22));
+        code.push(Instruction::add(Register::Rb, Register::Ra));
+    }},
+    Check { name: "add id", expected: 7, run: |code| {
+        // Rb names Rc, and Ra names itself.
+        code.extend(Instruction::li(Register::Rc, 7));
+        code.extend(Instruction::li(Register::Rb, 2));
+        code.push(zero_ra());
+        code.push(Instruction::add_id(Register::Rb, Register::Ra));
+    }},
+    Check { name: "sub", expected: 22, run: |code| {
+        code.extend(Instruction::li(Register::Rb, 8));
+        code.extend(Instruction::li(Register::Ra, 30));
+        code.push(Instruction::sub(Register::Rb, Register::Ra));
+    }},
+    Check { name: "sub sign", expected: 0b0101, run: |code| {
+        code.extend(Instruction::li(Register::Rb, 1));
+        code.push(zero_ra());
+        code.push(Instruction::sub(Register::Rb, Register::Ra));
+        code.push(read_flags());
+    }},
+    Check { name: "sub zero", expected: 0b0010, run: |code| {
+        code.extend(Instruction::li(Register::Rb, 9));
+        code.extend(Instruction::li(Register::Ra, 9));
+        code.push(Instruction::sub(Register::Rb, Register::Ra));
+        code.push(read_flags());
+    }},
+    Check { name: "jne taken", expected: 1, run: |code| {
+        branch(code, Opcode::Jne, |code| code.push(adi(1)));
+    }},
+    Check { name: "jne not taken", expected: 10, run: |code| {
+        branch(code, Opcode::Jne, |code| code.push(adi(0)));
+    }},
+    Check { name: "jg taken", expected: 1, run: |code| {
+        branch(code, Opcode::Jg, |code| code.push(adi(1)));
+    }},
+    Check { name: "jg not taken", expected: 10, run: |code| {
+        branch(code, Opcode::Jg, |code| code.push(adi(0)));
+    }},
+    Check { name: "jl taken", expected: 0xff, run: |code| {
+        code.extend(Instruction::li(Register::Rb, 1));
+        branch(code, Opcode::Jl, |code| code.push(Instruction::sub(Register::Rb, Register::Ra)));
+    }},
+    Check { name: "jl not taken", expected: 11, run: |code| {
+        branch(code, Opcode::Jl, |code| code.push(adi(1)));
+    }},
+    Check { name: "carry condition", expected: 2, run: |code| {
+        // Arms the condition, then sets zero and carry, so only a jump
+        // testing carry is taken.
+        code.extend(Instruction::li(Register::Ra, 3));
+        code.push(Instruction::ioi(Device::Cpu, U3::B111));
+        code.extend(Instruction::li(Register::Rc, 0b0110));
+        branch(code, Opcode::Jne, |code| {
+            code.extend(Instruction::li(Register::Ra, 2));
+            code.push(Instruction::ior(Device::Mth, U3::B111));
+        });
+    }},
+    Check { name: "nop", expected: 3, run: |code| {
+        code.extend(Instruction::li(Register::Ra, 3));
+        code.push(Instruction::ioi(Device::Cpu, U3::B001));
+    }},
+    Check { name: "store load", expected: 0x5a, run: |code| {
+        store(code, 0x5a);
+        code.extend(Instruction::li(Register::Ra, 0x0a));
+        code.push(Instruction::ioi(Device::Cpu, U3::B011));
+    }},
+    Check { name: "store register", expected: 0x21, run: |code| {
+        store_rb(code, 0x21);
+        code.extend(Instruction::li(Register::Ra, 1));
+        code.push(Instruction::ioi(Device::Cpu, U3::B011));
+    }},
+    Check { name: "load indirect", expected: 0x73, run: |code| {
+        store_rb(code, 3);
+        store(code, 0x73);
+        code.extend(Instruction::li(Register::Ra, 1));
+        code.push(Instruction::ioi(Device::Cpu, U3::B101));
+    }},
+    Check { name: "store indirect", expected: 0x33, run: |code| {
+        // Stores Rb through the pointer at offset 1, to offset 4.
+        store_rb(code, 4);
+        code.extend(Instruction::li(Register::Rb, 0x33));
+        code.extend(Instruction::li(Register::Ra, 1));
+        code.push(Instruction::ior(Device::Cpu, U3::B110));
+        code.extend(Instruction::li(Register::Ra, 4));
+        code.push(Instruction::ioi(Device::Cpu, U3::B011));
+    }},
+    Check { name: "mul low", expected: 0x90, run: |code| {
+        code.extend(Instruction::li(Register::Rc, 200));
+        code.extend(Instruction::li(Register::Ra, 2));
+        code.push(Instruction::ioi(Device::Mth, U3::B000));
+    }},
+    Check { name: "mul high", expected: 1, run: |code| {
+        code.extend(Instruction::li(Register::Rc, 200));
+        code.extend(Instruction::li(Register::Ra, 2));
+        code.push(Instruction::ioi(Device::Mth, U3::B000));
+        code.push(zero_ra());
+        code.push(Instruction::add(Register::Rb, Register::Ra));
+    }},
+    Check { name: "div", expected: 1, run: |code| {
+        code.extend(Instruction::li(Register::Rc, 2));
+        code.extend(Instruction::li(Register::Ra, 2));
+        code.push(Instruction::ioi(Device::Mth, U3::B001));
+    }},
+    Check { name: "and", expected: 2, run: |code| mth(code, 0b1110, U3::B010) },
+    Check { name: "or", expected: 0x42, run: |code| mth(code, 0x40, U3::B011) },
+    Check { name: "xor", expected: 0xfd, run: |code| mth(code, 0xff, U3::B100) },
+    Check { name: "not", expected: 0xf0, run: |code| {
+        code.extend(Instruction::li(Register::Ra, 0x0f));
+        code.push(Instruction::ioi(Device::Mth, U3::B101));
+    }},
+    Check { name: "flags", expected: 0b0101, run: |code| {
+        code.extend(Instruction::li(Register::Ra, 0b0101));
+        code.push(Instruction::ioi(Device::Mth, U3::B111));
+        code.push(read_flags());
+    }},
+    Check { name: "kbd poll", expected: 1, run: |code| {
+        code.push(Instruction::ioi(Device::Kbd, U3::B001));
+    }},
+    Check { name: "kbd read", expected: INPUT[0], run: |code| {
+        code.push(Instruction::ioi(Device::Kbd, U3::B000));
+    }},
+    Check { name: "kbd empty", expected: 0, run: |code| {
+        code.extend(Instruction::li(Register::Ra, 5));
+        code.push(Instruction::ioi(Device::Kbd, U3::B000));
+    }},
+];
+
+/// The self-test program.
+pub fn program() -> RimResult<Vec<Instruction>> {
+    let mut code = Code::new();
+    code.push(Instruction::ioi(Device::Scr, U3::B101));
+
+    for check in CHECKS {
+        let (fail, next) = (code.label(), code.label());
+        (check.run)(&mut code);
+
+        // Rb -= expected sets zero only if they match.
+        code.push(Instruction::sub(Register::Rb, Register::Rb));
+        code.push(Instruction::add(Register::Ra, Register::Rb));
+        code.load_page(fail);
+        code.extend(Instruction::li(Register::Ra, check.expected));
+        code.push(Instruction::sub(Register::Ra, Register::Rb));
+        code.jne(fail);
+        code.jump(next);
+
+        code.place(fail);
+        print(&mut code, &format!("FAIL {}\n", check.name));
+        code.extend(Instruction::li(Register::Rd, RESULT_PAGE));
+        code.extend(Instruction::li(Register::Rb, 1));
+        code.extend(Instruction::li(Register::Ra, RESULT_ADDR.offset()));
+        code.push(Instruction::ior(Device::Cpu, U3::B100));
+        code.place(next);
+    }
+
+    let failed = code.label();
+    code.extend(Instruction::li(Register::Rd, RESULT_PAGE));
+    code.extend(Instruction::li(Register::Ra, RESULT_ADDR.offset()));
+    code.push(Instruction::ioi(Device::Cpu, U3::B011));
+    code.push(Instruction::sub(Register::Rb, Register::Rb));
+    code.push(Instruction::add(Register::Ra, Register::Rb));
+    code.load_page(failed);
+    code.push(Instruction::sub(Register::Ra, Register::Ra));
+    code.push(Instruction::add(Register::Rb, Register::Ra));
+    code.jne(failed);
+    print(&mut code, "PASS\n");
+    code.push(Instruction::ioi(Device::Cpu, U3::B000));
+
+    code.place(failed);
+    print(&mut code, "FAILED\n");
+    code.push(Instruction::ioi(Device::Cpu, U3::B000));
+    code.link()
+}
+
+/// Runs the self-test on a fresh machine, with [`INPUT`] waiting.
+pub fn run() -> RunReport {
+    // The checks are fixed, so they always fit.
+    let program = program().expect("the self-test is too long");
+    Runner::new(Rim::new(program)).input(INPUT).execute()
+}
+
+/// Whether a self-test run halted having passed.
+pub fn passed(report: &RunReport) -> bool {
+    report.halted() && report.data[RESULT_ADDR.index()] == 0 && report.output.ends_with(b"PASS\n")
+}
+
+fn zero_ra() -> Instruction {
+    Instruction::ioi(Device::Cpu, U3::B010)
+}
+
+fn adi(imm: u8) -> Instruction {
+    Instruction(Opcode::Adi, InstructionData::Imm(imm))
+}
+
+fn read_flags() -> Instruction {
+    Instruction::ioi(Device::Mth, U3::B110)
+}
+
+/// Zeroes Ra, runs `set` to set the flags, and branches past an `adi 10`,
+/// so Ra ends up 10 more if the branch isn't taken.
+fn branch(code: &mut Code, opcode: Opcode, set: impl FnOnce(&mut Code)) {
+    let skip = code.label();
+    code.load_page(skip);
+    code.push(zero_ra());
+    set(code);
+    code.branch(opcode, skip);
+    code.push(adi(10));
+    code.place(skip);
+}
+
+/// Stores a value in the scratch page at the offset of its low 4 bits,
+/// since `ioi` stores Ra at the offset in Ra, leaving Rd on the page.
+fn store(code: &mut Code, value: u8) {
+    code.extend(Instruction::li(Register::Rd, SCRATCH_PAGE));
+    code.extend(Instruction::li(Register::Ra, value));
+    code.push(Instruction::ioi(Device::Cpu, U3::B100));
+}
+
+/// Stores a value at offset 1 of the scratch page, through Rb, which
+/// `ior` picks when Ra is 1, leaving Rd on the page.
+fn store_rb(code: &mut Code, value: u8) {
+    code.extend(Instruction::li(Register::Rd, SCRATCH_PAGE));
+    code.extend(Instruction::li(Register::Rb, value));
+    code.extend(Instruction::li(Register::Ra, 1));
+    code.push(Instruction::ior(Device::Cpu, U3::B100));
+}
+
+/// Runs a Mth function on 2 and Rc, which Ra = 2 selects.
+fn mth(code: &mut Code, rc: u8, function: U3) {
+    code.extend(Instruction::li(Register::Rc, rc));
+    code.extend(Instruction::li(Register::Ra, 2));
+    code.push(Instruction::ioi(Device::Mth, function));
+}
+
+/// Writes text to the screen.
+fn print(code: &mut Code, text: &str) {
+    for byte in text.bytes() {
+        code.extend(Instruction::li(Register::Ra, byte));
+        code.push(Instruction::ioi(Device::Scr, U3::B010));
+    }
+}
@@ -0,0 +1,202 @@
+//! An HTTP/JSON API for running programs, for backing a web playground.
+//!
+//! `POST /run` takes a JSON object with either `source`, assembler source,
+//! or `image`, a program image as an array of bytes, and optionally:
+//!
+//! - `input`: a string fed to the keyboard
+//! - `max_steps`, `max_output`, and `max_time_ms`: tighter limits than the
+//!   server's, which can't be raised
+//!
+//! It responds with the outcome (`halted`, `faulted`, `out_of_steps`, or
+//! `out_of_time`), the error if any, the steps taken, the screen output, and
+//! the final `pc`, `registers`, and `flags`. Bad requests get a 400 with an
+//! `error` message, as do programs over the server's
+//! [`LoadLimits`](crate::image::LoadLimits), before they run.
+//!
+//! Images are loaded through a [`ProgramCache`], so a playground running
+//! the same program again, or many clients running the same example,
+//! don't have it parsed each time.
+//!
+//! `GET /metrics` reports [`Metrics`] for every run so far.
+//!
+//! Requests are handled by [`Limits::max_concurrent`] worker threads, with
+//! as many again waiting their turn. Past that, requests get a 503 until
+//! the workers catch up.
+//!
+//! With the `playground` feature, `pact serve --web` also serves a web UI
+//! for these; see [`playground`](crate::playground).
+
+use std::io::Read;
+use std::sync::mpsc::{Receiver, TrySendError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::cache::ProgramCache;
+use crate::error::{LoadError, RimError, RimResult};
+use crate::grade::{self, Limits, Outcome, Test};
+use crate::metrics::Metrics;
+
+/// The largest request body accepted, in bytes.
+pub const MAX_BODY: usize = 64 * 1024;
+
+/// Serves the API on `addr` until the process exits, handling requests on
+/// a pool of threads, within `limits`.
+pub fn serve(addr: &str, limits: Limits) -> RimResult<()> {
+    serve_with_metrics(addr, limits, Arc::default())
+}
+
+/// Like [`serve`], but counting runs in `metrics`, so an embedder can read
+/// them too.
+pub fn serve_with_metrics(addr: &str, limits: Limits, metrics: Arc<Metrics>) -> RimResult<()> {
+    let server = Server::http(addr).map_err(|e| RimError::IoError(std::io::Error::other(e)))?;
+    let cache = Arc::new(ProgramCache::default());
+
+    pool(&server, limits.max_concurrent, move |request| handle(request, &limits, &metrics, &cache));
+    Ok(())
+}
+
+/// Hands `server`'s requests to `workers` threads running `handle`, with
+/// as many again queued, and answers any more with a 503.
+pub(crate) fn pool(server: &Server, workers: usize, handle: impl Fn(Request) + Send + Sync + 'static) {
+    let (queue, requests) = std::sync::mpsc::sync_channel(workers);
+    let requests = Arc::new(Mutex::new(requests));
+    let handle = Arc::new(handle);
+    for _ in 0..workers {
+        let (requests, handle) = (requests.clone(), handle.clone());
+        std::thread::spawn(move || work(&requests, &*handle));
+    }
+
+    for request in server.incoming_requests() {
+        if let Err(TrySendError::Full(request) | TrySendError::Disconnected(request)) = queue.try_send(request) {
+            let header = Header::from_bytes("Content-Type", "application/json").expect("header should be valid");
+            let body = error("the server is busy; try again later".to_string()).to_string();
+            let _ = request.respond(Response::from_string(body).with_status_code(503).with_header(header));
+        }
+    }
+}
+
+fn work(requests: &Mutex<Receiver<Request>>, handle: &dyn Fn(Request)) {
+    loop {
+        let request = requests.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        let Ok(request) = request else {
+            return;
+        };
+
+        // A panic in one request shouldn't cost the pool its worker.
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handle(request)));
+    }
+}
+
+pub(crate) fn handle(mut request: Request, limits: &Limits, metrics: &Metrics, cache: &ProgramCache) {
+    if (request.method(), request.url()) == (&Method::Get, "/metrics") {
+        let header = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").expect("header should be valid");
+        let _ = request.respond(Response::from_string(metrics.to_string()).with_header(header));
+        return;
+    }
+
+    let (status, body) = match (request.method(), request.url()) {
+        (Method::Post, "/run") => {
+            let mut body = Vec::new();
+            let read = request.as_reader().take(MAX_BODY as u64 + 1).read_to_end(&mut body);
+
+            match read {
+                Ok(_) if body.len() > MAX_BODY => (413, error(format!("request body is over {MAX_BODY} bytes"))),
+                // A panic in the machine shouldn't take the connection with it.
+                Ok(_) => match std::panic::catch_unwind(|| run(&body, limits, metrics, cache)) {
+                    Ok(Ok(response)) => (200, response),
+                    Ok(Err(message)) => (400, error(message)),
+                    Err(_) => (500, error("the machine panicked".to_string())),
+                },
+                Err(e) => (400, error(e.to_string())),
+            }
+        }
+        (_, "/run") => (405, error("expected POST".to_string())),
+        _ => (404, error("not found".to_string())),
+    };
+
+    let header = Header::from_bytes("Content-Type", "application/json").expect("header should be valid");
+    let response = Response::from_string(body.to_string()).with_status_code(status).with_header(header);
+    let _ = request.respond(response);
+}
+
+fn error(message: String) -> Value {
+    json!({ "error": message })
+}
+
+fn run(body: &[u8], limits: &Limits, metrics: &Metrics, cache: &ProgramCache) -> Result<Value, String> {
+    let request: Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+
+    let program = match (request.get("source"), request.get("image")) {
+        (Some(Value::String(source)), None) => crate::asm::assemble_str(source).map_err(|e| e.to_string())?,
+        (None, Some(Value::Array(image))) => image
+            .iter()
+            .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+            .collect::<Option<_>>()
+            .ok_or("`image` must be an array of bytes")?,
+        _ => return Err("expected exactly one of `source` or `image`".to_string()),
+    };
+
+    let input = match request.get("input") {
+        Some(Value::String(input)) => input.as_bytes().to_vec(),
+        Some(_) => return Err("`input` must be a string".to_string()),
+        None => Vec::new(),
+    };
+
+    let limit = |key: &str, max: usize| -> Result<usize, String> {
+        match request.get(key) {
+            Some(value) => value
+                .as_u64()
+                .map(|value| (value as usize).min(max))
+                .ok_or(format!("`{key}` must be a non-negative integer")),
+            None => Ok(max),
+        }
+    };
+
+    let max_time = limits.max_time.map(|max| max.as_millis() as usize).unwrap_or(usize::MAX);
+    let limits = Limits {
+        max_steps: limit("max_steps", limits.max_steps)?,
+        max_time: Some(Duration::from_millis(limit("max_time_ms", max_time)? as u64)),
+        max_output: limit("max_output", limits.max_output)?,
+        ..*limits
+    };
+
+    // Turned away, rather than reported as a fault like any other image
+    // that doesn't load.
+    if let Err(RimError::Load(e @ LoadError::OverLimit { .. })) = cache.load(&program, &limits.load) {
+        return Err(e.to_string());
+    }
+
+    let test = Test {
+        name: String::new(),
+        input,
+        checks: Vec::new(),
+    };
+
+    let report = grade::grade_cached(cache, &program, &test, &limits);
+    metrics.record(report.steps, &report.outcome);
+    let (outcome, error) = match &report.outcome {
+        Outcome::Halted => ("halted", None),
+        Outcome::Faulted(e) => ("faulted", Some(e.to_string())),
+        Outcome::OutOfSteps => ("out_of_steps", None),
+        Outcome::OutOfTime => ("out_of_time", None),
+    };
+
+    let mut response = json!({
+        "outcome": outcome,
+        "error": error,
+        "steps": report.steps,
+        "output": String::from_utf8_lossy(&report.output),
+        "truncated": report.truncated,
+    });
+
+    if let Some(snapshot) = report.snapshot {
+        response["pc"] = json!(snapshot.pc);
+        response["registers"] = json!(snapshot.registers);
+        response["flags"] = json!(snapshot.flags.to_string());
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,281 @@
+//! Long-running machines that outlive the terminal that started them,
+//! managed over a local socket, like `tmux` sessions.
+//!
+//! [`host`] runs a machine in the background, and listens on a Unix
+//! socket named after the session, in [`dir`]. Any process of the same user
+//! can then connect and [`request`] one of these commands, with a payload
+//! after it:
+//!
+//! | Command  | Effect                                                     |
+//! |----------|------------------------------------------------------------|
+//! | `send`   | Feed the payload to the keyboard                           |
+//! | `status` | Report whether it's running, and the machine's registers   |
+//! | `screen` | Show the screen, one line per row                          |
+//! | `output` | Take what the program wrote since output was last taken    |
+//! | `stop`   | End the session                                            |
+//!
+//! A request is the command, a newline, and the payload, up to where the
+//! client shuts down its side of the connection. The reply is text, and
+//! starts with `error: ` if the request failed.
+//!
+//! The sockets' directory is only open to the user who made it, and
+//! connections from other users are turned away, so nobody else can drive
+//! a session. A client has [`REQUEST_TIMEOUT`] to send its request, so one
+//! that never finishes can't hold the session up.
+//!
+//! The session keeps running after its program halts or faults, so what
+//! it left behind can still be looked at, until it's stopped. `pact
+//! session` starts, lists, and attaches to sessions from the command line.
+
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::console::{Buffer, Console};
+use crate::error::{RimError, RimResult};
+use crate::{Rim, Status, TICK};
+
+/// How much output a session keeps for `output`, dropping the oldest past
+/// it.
+pub const MAX_OUTPUT: usize = 64 * 1024;
+
+/// How long a client has to send its request, and to take the reply.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the machine runs between checks for requests.
+const SLICE: Duration = Duration::from_millis(10);
+
+/// Where sessions' sockets are: under `$XDG_RUNTIME_DIR` if it's set, and
+/// otherwise a directory of the user's own in the temporary directory.
+pub fn dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime) if !runtime.is_empty() => PathBuf::from(runtime).join("pact-sessions"),
+        _ => std::env::temp_dir().join(format!("pact-sessions-{}", uid())),
+    }
+}
+
+/// Makes [`dir`] if it isn't there, refusing to use it if anyone but this
+/// user owns it or can get into it.
+fn make_dir() -> RimResult<PathBuf> {
+    let dir = dir();
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+
+    let metadata = std::fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid() || metadata.mode() & 0o077 != 0 {
+        return Err(RimError::IoError(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("`{}` isn't private to this user", dir.display()),
+        )));
+    }
+
+    Ok(dir)
+}
+
+fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions, and can't fail.
+    unsafe { libc::getuid() }
+}
+
+/// The user on the other end of a connection.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len are valid for writes, and len is cred's size.
+    let result = unsafe {
+        let cred = (&raw mut cred).cast();
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED, cred, &mut len)
+    };
+
+    if result == 0 {
+        Ok(cred.uid)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// The user on the other end of a connection.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: uid and gid are valid for writes.
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == 0 {
+        Ok(uid)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// The socket of a session.
+pub fn path(name: &str) -> PathBuf {
+    dir().join(format!("{name}.sock"))
+}
+
+/// The names of the sessions that are listening.
+pub fn list() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir()) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_stem()?.to_str()?.to_string();
+            // Sockets left behind by sessions that died don't count.
+            (path.extension()? == "sock" && UnixStream::connect(&path).is_ok()).then_some(name)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Sends a session a command, returning its reply.
+pub fn request(name: &str, command: &str, payload: &[u8]) -> RimResult<String> {
+    let mut stream = UnixStream::connect(path(name))?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.write_all(command.as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.write_all(payload)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// A session's machine, and how its run ended, if it has.
+struct Hosted {
+    rim: Rim,
+    ended: Option<String>,
+    output: Vec<u8>,
+    stopped: bool,
+}
+
+/// Runs a machine as a session until it's sent `stop`. Its console is
+/// replaced by one the session feeds.
+pub fn host(mut rim: Rim, name: &str) -> RimResult<()> {
+    let path = path(name);
+    make_dir()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(RimError::IoError(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            format!("session `{name}` is already running"),
+        )));
+    }
+
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+
+    rim.set_console(Console::Buffer(Buffer::new(Vec::new())));
+    let hosted = Arc::new(Mutex::new(Hosted { rim, ended: None, output: Vec::new(), stopped: false }));
+    let runner = {
+        let hosted = hosted.clone();
+        std::thread::spawn(move || run(&hosted))
+    };
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        if respond(stream, &hosted) {
+            break;
+        }
+    }
+
+    drop(listener);
+    let _ = runner.join();
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// Runs the machine a slice at a time, until the session is stopped.
+fn run(hosted: &Mutex<Hosted>) {
+    loop {
+        let mut guard = hosted.lock().unwrap_or_else(PoisonError::into_inner);
+        let hosted = &mut *guard;
+        if hosted.stopped {
+            return;
+        }
+
+        if hosted.ended.is_some() {
+            drop(guard);
+            std::thread::sleep(TICK);
+            continue;
+        }
+
+        let burst = hosted.rim.run_for(SLICE);
+        if let Console::Buffer(buffer) = hosted.rim.console_mut() {
+            hosted.output.extend(buffer.take_output());
+            let excess = hosted.output.len().saturating_sub(MAX_OUTPUT);
+            hosted.output.drain(..excess);
+        }
+
+        match burst.result {
+            Ok(Status::Running) => {}
+            Ok(Status::Halted) => hosted.ended = Some("halted".to_string()),
+            Err(e) => hosted.ended = Some(format!("faulted: {e}")),
+        }
+
+        let idle = hosted.rim.is_waiting() || hosted.rim.is_blocked();
+        let sleep = hosted.rim.take_sleep();
+        drop(guard);
+
+        match sleep {
+            Some(0) => std::thread::yield_now(),
+            Some(ticks) => std::thread::sleep(TICK * ticks as u32),
+            None if idle => std::thread::sleep(TICK),
+            None => {}
+        }
+    }
+}
+
+/// Handles a request, returning whether it stopped the session.
+fn respond(mut stream: UnixStream, hosted: &Mutex<Hosted>) -> bool {
+    if peer_uid(&stream).ok() != Some(uid()) {
+        let _ = stream.write_all(b"error: the session belongs to another user");
+        return false;
+    }
+
+    let mut request = Vec::new();
+    let timeouts = stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(REQUEST_TIMEOUT)));
+    if timeouts.is_err() || stream.read_to_end(&mut request).is_err() {
+        return false;
+    }
+
+    let (command, payload) = match request.iter().position(|&b| b == b'\n') {
+        Some(end) => (&request[..end], &request[end + 1..]),
+        None => (&request[..], &[][..]),
+    };
+
+    let mut hosted = hosted.lock().unwrap_or_else(PoisonError::into_inner);
+    let reply = match command {
+        b"send" => match hosted.rim.console_mut() {
+            Console::Buffer(buffer) => {
+                buffer.push_input(payload);
+                String::new()
+            }
+            _ => "error: the console isn't the session's".to_string(),
+        },
+        b"status" => {
+            let state = hosted.ended.clone().unwrap_or_else(|| "running".to_string());
+            format!("{state}\n{}\n", hosted.rim.snapshot())
+        }
+        b"screen" => format!("{}\n", hosted.rim.screen().text()),
+        b"output" => String::from_utf8_lossy(&std::mem::take(&mut hosted.output)).into_owned(),
+        b"stop" => {
+            hosted.stopped = true;
+            String::new()
+        }
+        command => format!("error: unknown command `{}`", String::from_utf8_lossy(command)),
+    };
+
+    let _ = stream.write_all(reply.as_bytes());
+    hosted.stopped
+}
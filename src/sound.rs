@@ -0,0 +1,259 @@
+//! The sound device: two square-wave channels and a noise channel.
+//!
+//! The device is extension device 2 of bank 1, and has a file of registers.
+//! Its functions are:
+//!
+//! | Function | Effect                                                      |
+//! |----------|-------------------------------------------------------------|
+//! | 0        | Select register `value`                                     |
+//! | 1        | Write `value` to the selected register, and select the next |
+//! | 2        | Read the selected register into Ra, and select the next     |
+//!
+//! Registers `3 * n` to `3 * n + 2` hold channel `n`'s frequency in hertz,
+//! low byte first, and its volume, from 0 to 15. Channels 0 and 1 are square
+//! waves, and channel 2 is noise, whose frequency is how often it changes. A
+//! frequency of 0 is silent.
+//!
+//! The device only holds the channels' settings: turning them into sound is
+//! up to a [`Mixer`], which the host feeds with [`Sound::mix`]. [`Synth`] is
+//! a mixer that renders samples, for playing through whatever audio output
+//! the host has, and with the `audio` feature, `Speaker` plays them through
+//! the default output device, as `pact run --sound` does:
+//!
+//! ```no_run
+//! # #[cfg(feature = "audio")] {
+//! use pact::sound::{Sound, Speaker};
+//! use pact::Status;
+//!
+//! let mut rim = pact::read_file("tune.rim").unwrap();
+//! rim.attach_sound(Sound::new());
+//! let mut speaker = Speaker::new().unwrap();
+//! while rim.burst(1000).result.unwrap() == Status::Running {
+//!     rim.sound_mut().unwrap().mix(&mut speaker);
+//! }
+//! # }
+//! ```
+
+#[cfg(feature = "audio")]
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[cfg(feature = "audio")]
+use crate::host::DeviceError;
+
+pub const CHANNELS: usize = 3;
+pub const REGISTERS: usize = CHANNELS * 3;
+
+/// Volumes range from 0 to this.
+pub const MAX_VOLUME: u8 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wave {
+    Square,
+    Noise,
+}
+
+/// A channel's settings, decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    pub wave: Wave,
+    pub frequency: u16,
+    pub volume: u8,
+}
+
+/// Something that turns channel settings into sound.
+pub trait Mixer {
+    /// Called with the new settings whenever the program changes them.
+    fn update(&mut self, channels: &[Channel; CHANNELS]);
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Sound {
+    registers: [u8; REGISTERS],
+    selected: usize,
+    /// Whether anything changed since the last mix.
+    changed: bool,
+}
+
+impl Sound {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn channel(&self, n: usize) -> Channel {
+        let [lo, hi, volume] = self.registers[n * 3..n * 3 + 3] else {
+            unreachable!()
+        };
+
+        Channel {
+            wave: if n == CHANNELS - 1 { Wave::Noise } else { Wave::Square },
+            frequency: u16::from_le_bytes([lo, hi]),
+            volume: volume.min(MAX_VOLUME),
+        }
+    }
+
+    pub fn channels(&self) -> [Channel; CHANNELS] {
+        std::array::from_fn(|n| self.channel(n))
+    }
+
+    /// Passes the channels to `mixer` if they changed since the last call.
+    pub fn mix(&mut self, mixer: &mut impl Mixer) {
+        if std::mem::take(&mut self.changed) {
+            mixer.update(&self.channels());
+        }
+    }
+
+    pub(crate) fn io(&mut self, function: crate::helper::U3, value: u8) -> Option<u8> {
+        match function as u8 {
+            0 => self.selected = value as usize % REGISTERS,
+            1 => {
+                self.changed |= self.registers[self.selected] != value;
+                self.registers[self.selected] = value;
+                self.selected = (self.selected + 1) % REGISTERS;
+            }
+            2 => {
+                let byte = self.registers[self.selected];
+                self.selected = (self.selected + 1) % REGISTERS;
+                return Some(byte);
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+/// A mixer that renders mono samples from -1 to 1, taking one per call to
+/// [`Iterator::next`], so it can back an audio library's sample source.
+#[derive(Debug, Clone)]
+pub struct Synth {
+    sample_rate: u32,
+    channels: [Channel; CHANNELS],
+    /// How far through its period each channel is, from 0 to 1.
+    phases: [f32; CHANNELS],
+    /// The noise channel's linear-feedback shift register.
+    lfsr: u16,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            channels: Sound::new().channels(),
+            phases: [0.0; CHANNELS],
+            lfsr: 1,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Fills `out` with the next samples.
+    pub fn render(&mut self, out: &mut [f32]) {
+        for sample in out {
+            *sample = self.sample();
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        let mut mix = 0.0;
+
+        for (channel, phase) in self.channels.iter().zip(&mut self.phases) {
+            if channel.frequency == 0 {
+                continue;
+            }
+
+            *phase += channel.frequency as f32 / self.sample_rate as f32;
+            let wrapped = *phase >= 1.0;
+            *phase = phase.fract();
+
+            let high = match channel.wave {
+                Wave::Square => *phase < 0.5,
+                Wave::Noise => {
+                    if wrapped {
+                        let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+                    }
+
+                    self.lfsr & 1 != 0
+                }
+            };
+
+            let level = channel.volume as f32 / MAX_VOLUME as f32;
+            mix += if high { level } else { -level };
+        }
+
+        mix / CHANNELS as f32
+    }
+}
+
+impl Mixer for Synth {
+    fn update(&mut self, channels: &[Channel; CHANNELS]) {
+        self.channels = *channels;
+    }
+}
+
+impl Iterator for Synth {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.sample())
+    }
+}
+
+/// The host's default audio output, playing a [`Synth`].
+#[cfg(feature = "audio")]
+pub struct Speaker {
+    synth: Arc<Mutex<Synth>>,
+    /// Plays for as long as it's kept.
+    _stream: cpal::Stream,
+}
+
+#[cfg(feature = "audio")]
+impl Speaker {
+    pub fn new() -> Result<Self, DeviceError> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+        use cpal::SampleFormat;
+
+        let device = cpal::default_host().default_output_device().ok_or("no audio output device")?;
+        let supported = device.default_output_config()?;
+        let config = supported.config();
+        let synth = Arc::new(Mutex::new(Synth::new(config.sample_rate.0)));
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => stream::<f32>(&device, &config, synth.clone())?,
+            SampleFormat::I16 => stream::<i16>(&device, &config, synth.clone())?,
+            SampleFormat::U16 => stream::<u16>(&device, &config, synth.clone())?,
+            format => return Err(format!("unsupported sample format `{format}`").into()),
+        };
+        stream.play()?;
+
+        Ok(Self { synth, _stream: stream })
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Mixer for Speaker {
+    fn update(&mut self, channels: &[Channel; CHANNELS]) {
+        self.synth.lock().unwrap_or_else(PoisonError::into_inner).update(channels);
+    }
+}
+
+/// An output stream of `T`, with the same sample on each of its channels.
+#[cfg(feature = "audio")]
+fn stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, synth: Arc<Mutex<Synth>>) -> Result<cpal::Stream, DeviceError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    use cpal::traits::DeviceTrait;
+
+    let channels = config.channels.max(1) as usize;
+    let fill = move |out: &mut [T], _: &cpal::OutputCallbackInfo| {
+        let mut synth = synth.lock().unwrap_or_else(PoisonError::into_inner);
+        for frame in out.chunks_mut(channels) {
+            frame.fill(T::from_sample(synth.sample()));
+        }
+    };
+
+    Ok(device.build_output_stream(config, fill, |e| log::warn!("audio output failed: {e}"), None)?)
+}
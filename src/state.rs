@@ -0,0 +1,538 @@
+//! Save states: a machine's whole state, as a `.rimstate` file that later
+//! versions of pact keep loading.
+//!
+//! A state is `"RSTA"`, a version byte, and sections, each a tag byte, a
+//! big-endian `u32` length, and that many bytes. All numbers are
+//! big-endian, and an optional value is a 0 byte, or a 1 followed by it:
+//!
+//! | Tag  | Section                                                        |
+//! |------|----------------------------------------------------------------|
+//! | 1    | A program's instructions, once per slot, in order              |
+//! | 2    | The CPU: slot (u32), pc (u16), registers (4), and flags        |
+//! | 3    | Data memory, all 4096 bytes                                    |
+//! | 4    | The architecture, arithmetic, and instruction set level        |
+//! | 5    | Pending system calls: carry condition, then optional bank,     |
+//! |      | opcode page, `ior` source, and sleep                           |
+//! | 6    | Optional interrupt handler and fault handler, each slot (u32)  |
+//! |      | and address (u16); the last fault's code and address; and what |
+//! |      | the interrupt handler returns to, as a CPU section followed by |
+//! |      | a pending section                                              |
+//! | 0x81 | The screen: width, height, cursor row and column (u16 each),   |
+//! |      | then its cells                                                 |
+//!
+//! Only the machine is saved, not what the host set up around it: devices,
+//! the console, microcode, memory size, denied devices, and shared pages
+//! stay as they are on the machine a state is restored into, so set those
+//! up first, as for any other program.
+//!
+//! Newer states load in older versions as long as they only add sections.
+//! Tags with the high bit set are optional, and skipped by versions that
+//! don't know them; an unknown tag without it fails to load, since the
+//! machine would run differently without it. A state missing a section
+//! this version knows gets its default, except for the first three, which
+//! are required. Changing what an existing section holds bumps
+//! [`VERSION`], and adds a migration from the previous version's sections,
+//! so states from any earlier version load in any later one.
+//!
+//! ```no_run
+//! use pact::state::State;
+//!
+//! let mut rim = pact::read_file("game.rim").unwrap();
+//! rim.run().unwrap();
+//! State::capture(&rim).write_file("game.rimstate").unwrap();
+//!
+//! let mut later = pact::Rim::default();
+//! State::read_file("game.rimstate").unwrap().restore(&mut later);
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::{LoadError, RimError, RimResult};
+use crate::isa::IsaLevel;
+use crate::screen::Screen;
+use crate::{Architecture, Arithmetic, Flags, Instruction, Interrupted, Register, Rim, Snapshot, MAX_PROGRAM_LEN};
+
+const MAGIC: &[u8; 4] = b"RSTA";
+
+/// Turns the sections of one version into the next one's: the first turns
+/// version 1 into version 2, and so on.
+type Migration = fn(&mut Vec<Section>) -> RimResult<()>;
+
+const MIGRATIONS: &[Migration] = &[];
+
+/// The version of the format written, and the newest one read.
+pub const VERSION: u8 = MIGRATIONS.len() as u8 + 1;
+
+pub const SECTION_CODE: u8 = 1;
+pub const SECTION_CPU: u8 = 2;
+pub const SECTION_DATA: u8 = 3;
+pub const SECTION_MODE: u8 = 4;
+pub const SECTION_PENDING: u8 = 5;
+pub const SECTION_HANDLERS: u8 = 6;
+pub const SECTION_SCREEN: u8 = 0x81;
+
+/// Sections with this bit set in their tag can be skipped.
+pub const OPTIONAL: u8 = 0x80;
+
+/// System calls that affect the next instruction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Pending {
+    carry_condition: bool,
+    bank: Option<u8>,
+    opcode_page: Option<u8>,
+    ior_source: Option<Register>,
+    sleep: Option<u8>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Handlers {
+    interrupt: Option<(usize, usize)>,
+    fault: Option<(usize, usize)>,
+    last_fault: Option<(u8, usize)>,
+    interrupted: Option<(Snapshot, Pending)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SavedScreen {
+    width: usize,
+    height: usize,
+    cursor: (usize, usize),
+    cells: Vec<u8>,
+}
+
+/// A machine's state, captured from one machine to restore into another,
+/// or the same one later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    programs: Vec<Vec<Instruction>>,
+    snapshot: Snapshot,
+    data: Box<[u8; 4096]>,
+    architecture: Architecture,
+    arithmetic: Arithmetic,
+    isa: IsaLevel,
+    pending: Pending,
+    handlers: Handlers,
+    screen: Option<SavedScreen>,
+    version: u8,
+    skipped: Vec<u8>,
+}
+
+struct Section {
+    tag: u8,
+    /// Of its header, in the file.
+    offset: usize,
+    payload: Vec<u8>,
+}
+
+impl State {
+    pub fn capture(rim: &Rim) -> Self {
+        let screen = rim.screen();
+        Self {
+            programs: rim.programs.iter().map(|program| program.to_vec()).collect(),
+            snapshot: rim.snapshot(),
+            data: Box::new(*rim.data()),
+            architecture: rim.architecture,
+            arithmetic: rim.arithmetic,
+            isa: rim.isa,
+            pending: Pending {
+                carry_condition: rim.carry_condition,
+                bank: rim.bank,
+                opcode_page: rim.opcode_page,
+                ior_source: rim.ior_source,
+                sleep: rim.sleep,
+            },
+            handlers: Handlers {
+                interrupt: rim.interrupt_handler,
+                fault: rim.fault_handler,
+                last_fault: rim.fault,
+                interrupted: rim.interrupted.map(|interrupted| {
+                    let pending = Pending {
+                        carry_condition: interrupted.carry_condition,
+                        bank: interrupted.bank,
+                        opcode_page: interrupted.opcode_page,
+                        ior_source: interrupted.ior_source,
+                        sleep: None,
+                    };
+                    (interrupted.snapshot, pending)
+                }),
+            },
+            screen: Some(SavedScreen {
+                width: screen.width(),
+                height: screen.height(),
+                cursor: screen.cursor(),
+                cells: screen.cells().to_vec(),
+            }),
+            version: VERSION,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Puts a machine in this state. Its devices, console, and other setup
+    /// are kept, and so is its screen if the state has none.
+    pub fn restore(&self, rim: &mut Rim) {
+        rim.programs = self.programs.iter().map(|program| Arc::new(program.clone())).collect();
+        rim.current = self.snapshot.slot;
+        rim.pc = self.snapshot.pc;
+        rim.registers = self.snapshot.registers;
+        rim.flags = self.snapshot.flags;
+        rim.data = Arc::new(*self.data);
+
+        // Set directly, since the data already holds whatever switching
+        // architectures would have copied into it.
+        rim.architecture = self.architecture;
+        rim.arithmetic = self.arithmetic;
+        rim.isa = self.isa;
+
+        rim.carry_condition = self.pending.carry_condition;
+        rim.bank = self.pending.bank;
+        rim.opcode_page = self.pending.opcode_page;
+        rim.ior_source = self.pending.ior_source;
+        rim.sleep = self.pending.sleep;
+        rim.blocked = false;
+        rim.waiting = false;
+
+        rim.interrupt_handler = self.handlers.interrupt;
+        rim.fault_handler = self.handlers.fault;
+        rim.fault = self.handlers.last_fault;
+        rim.interrupted = self.handlers.interrupted.map(|(snapshot, pending)| Interrupted {
+            snapshot,
+            bank: pending.bank,
+            opcode_page: pending.opcode_page,
+            ior_source: pending.ior_source,
+            carry_condition: pending.carry_condition,
+        });
+
+        if let Some(saved) = &self.screen {
+            let mut screen = Screen::new(saved.width, saved.height);
+            screen.restore(&saved.cells, saved.cursor);
+            *rim.screen_mut() = screen;
+        }
+    }
+
+    /// A new machine in this state, with no devices attached.
+    pub fn to_rim(&self) -> Rim {
+        let mut rim = Rim::default();
+        self.restore(&mut rim);
+        rim
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot
+    }
+
+    /// The version of the format this state was read from, which is
+    /// [`VERSION`] for a captured one.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The tags of the optional sections that were skipped when reading it,
+    /// which a newer version of pact would have used.
+    pub fn skipped(&self) -> &[u8] {
+        &self.skipped
+    }
+
+    pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Self> {
+        let path = f.as_ref();
+        std::fs::read(path).map_err(RimError::from).and_then(|bytes| Self::parse(&bytes)).map_err(|e| e.in_file(path))
+    }
+
+    pub fn write_file<F: AsRef<Path>>(&self, f: F) -> RimResult<()> {
+        let path = f.as_ref();
+        std::fs::write(path, self.to_bytes()).map_err(|e| RimError::from(e).in_file(path))
+    }
+
+    /// Parses a state of this version or any earlier one.
+    pub fn parse(bytes: &[u8]) -> RimResult<Self> {
+        let Some((magic, mut rest)) = bytes.split_first_chunk::<4>() else {
+            return Err(LoadError::InvalidMagic.into());
+        };
+        let Some((&version, after)) = rest.split_first().filter(|_| magic == MAGIC) else {
+            return Err(LoadError::InvalidMagic.into());
+        };
+        if version == 0 || version > VERSION {
+            return Err(LoadError::UnsupportedState(version).into());
+        }
+        rest = after;
+
+        let mut sections = Vec::new();
+        while !rest.is_empty() {
+            let offset = bytes.len() - rest.len();
+            let invalid = LoadError::InvalidSection { tag: rest[0], offset };
+            let Some((&[tag, a, b, c, d], after)) = rest.split_first_chunk::<5>() else {
+                return Err(invalid.into());
+            };
+            let len = u32::from_be_bytes([a, b, c, d]) as usize;
+            let payload = after.get(..len).ok_or(invalid)?;
+            sections.push(Section { tag, offset, payload: payload.to_vec() });
+            rest = &after[len..];
+        }
+
+        for migration in &MIGRATIONS[version as usize - 1..] {
+            migration(&mut sections)?;
+        }
+
+        let mut state = Self::from_sections(sections, bytes.len())?;
+        state.version = version;
+        Ok(state)
+    }
+
+    fn from_sections(sections: Vec<Section>, end: usize) -> RimResult<Self> {
+        let mut programs = Vec::new();
+        let mut cpu = None;
+        let mut data = None;
+        let mut mode = None;
+        let mut pending = Pending::default();
+        let mut handlers = Handlers::default();
+        let mut handlers_offset = end;
+        let mut screen = None;
+        let mut skipped = Vec::new();
+
+        for section in &sections {
+            let mut reader = Reader { section, at: 0 };
+            match section.tag {
+                SECTION_CODE if section.payload.len() > MAX_PROGRAM_LEN => {
+                    return Err(LoadError::ProgramTooLarge(section.payload.len()).into());
+                }
+                SECTION_CODE => programs.push(reader.take_rest().iter().copied().map(Instruction::decode).collect()),
+                SECTION_CPU => cpu = Some((reader.snapshot()?, section.offset)),
+                SECTION_DATA => {
+                    data = Some(Box::new(reader.bytes::<4096>()?));
+                }
+                SECTION_MODE => {
+                    let architecture = match reader.u8()? {
+                        0 => Architecture::Harvard,
+                        1 => Architecture::VonNeumann,
+                        _ => return Err(reader.invalid()),
+                    };
+                    let arithmetic = match reader.u8()? {
+                        0 => Arithmetic::Wrapping,
+                        1 => Arithmetic::Flagged,
+                        2 => Arithmetic::Faulting,
+                        _ => return Err(reader.invalid()),
+                    };
+                    mode = Some((architecture, arithmetic, IsaLevel::try_from(reader.u8()?)?));
+                }
+                SECTION_PENDING => pending = reader.pending()?,
+                SECTION_HANDLERS => {
+                    handlers_offset = section.offset;
+                    handlers = Handlers {
+                        interrupt: reader.option(|r| Ok((r.u32()?, r.u16()?)))?,
+                        fault: reader.option(|r| Ok((r.u32()?, r.u16()?)))?,
+                        last_fault: reader.option(|r| Ok((r.u8()?, r.u16()?)))?,
+                        interrupted: reader.option(|r| Ok((r.snapshot()?, r.pending()?)))?,
+                    };
+                }
+                SECTION_SCREEN => {
+                    let (width, height) = (reader.u16()?, reader.u16()?);
+                    let cursor = (reader.u16()?, reader.u16()?);
+                    let cells = reader.take_rest().to_vec();
+                    if width == 0 || height == 0 || cells.len() != width * height || cursor.0 >= height || cursor.1 >= width {
+                        return Err(reader.invalid());
+                    }
+                    screen = Some(SavedScreen { width, height, cursor, cells });
+                }
+                tag if tag & OPTIONAL != 0 => {
+                    skipped.push(tag);
+                    continue;
+                }
+                tag => return Err(LoadError::InvalidSection { tag, offset: section.offset }.into()),
+            }
+
+            if !reader.rest().is_empty() {
+                return Err(reader.invalid());
+            }
+        }
+
+        let missing = |tag| LoadError::InvalidSection { tag, offset: end };
+        let (snapshot, cpu_offset) = cpu.ok_or(missing(SECTION_CPU))?;
+        let data = data.ok_or(missing(SECTION_DATA))?;
+        if programs.is_empty() {
+            return Err(missing(SECTION_CODE).into());
+        }
+
+        // Slots can't be checked until every program is in.
+        let slots = programs.len();
+        if snapshot.slot >= slots {
+            return Err(LoadError::InvalidSection { tag: SECTION_CPU, offset: cpu_offset }.into());
+        }
+
+        let handler_slots = [handlers.interrupt, handlers.fault].into_iter().flatten().map(|(slot, _)| slot);
+        let interrupted_slot = handlers.interrupted.map(|(snapshot, _)| snapshot.slot);
+        if handler_slots.chain(interrupted_slot).any(|slot| slot >= slots) {
+            return Err(LoadError::InvalidSection { tag: SECTION_HANDLERS, offset: handlers_offset }.into());
+        }
+
+        let (architecture, arithmetic, isa) = mode.unwrap_or((Architecture::Harvard, Arithmetic::Wrapping, IsaLevel::LATEST));
+        Ok(Self {
+            programs,
+            snapshot,
+            data,
+            architecture,
+            arithmetic,
+            isa,
+            pending,
+            handlers,
+            screen,
+            version: VERSION,
+            skipped,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        for program in &self.programs {
+            let code: Vec<u8> = program.iter().map(|&instruction| u8::from(instruction)).collect();
+            push_section(&mut bytes, SECTION_CODE, &code);
+        }
+
+        let mut cpu = Vec::new();
+        push_snapshot(&mut cpu, self.snapshot);
+        push_section(&mut bytes, SECTION_CPU, &cpu);
+        push_section(&mut bytes, SECTION_DATA, &*self.data);
+
+        let architecture = match self.architecture {
+            Architecture::Harvard => 0,
+            Architecture::VonNeumann => 1,
+        };
+        let arithmetic = match self.arithmetic {
+            Arithmetic::Wrapping => 0,
+            Arithmetic::Flagged => 1,
+            Arithmetic::Faulting => 2,
+        };
+        push_section(&mut bytes, SECTION_MODE, &[architecture, arithmetic, self.isa as u8]);
+
+        let mut pending = Vec::new();
+        push_pending(&mut pending, self.pending);
+        push_section(&mut bytes, SECTION_PENDING, &pending);
+
+        let mut handlers = Vec::new();
+        for handler in [self.handlers.interrupt, self.handlers.fault] {
+            push_option(&mut handlers, handler, |out, (slot, addr)| {
+                out.extend_from_slice(&(slot as u32).to_be_bytes());
+                out.extend_from_slice(&(addr as u16).to_be_bytes());
+            });
+        }
+        push_option(&mut handlers, self.handlers.last_fault, |out, (code, addr)| {
+            out.push(code);
+            out.extend_from_slice(&(addr as u16).to_be_bytes());
+        });
+        push_option(&mut handlers, self.handlers.interrupted, |out, (snapshot, pending)| {
+            push_snapshot(out, snapshot);
+            push_pending(out, pending);
+        });
+        push_section(&mut bytes, SECTION_HANDLERS, &handlers);
+
+        if let Some(screen) = &self.screen {
+            let mut payload = Vec::new();
+            for n in [screen.width, screen.height, screen.cursor.0, screen.cursor.1] {
+                payload.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            payload.extend_from_slice(&screen.cells);
+            push_section(&mut bytes, SECTION_SCREEN, &payload);
+        }
+
+        bytes
+    }
+}
+
+fn push_section(bytes: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    bytes.push(tag);
+    bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(payload);
+}
+
+fn push_option<T>(out: &mut Vec<u8>, value: Option<T>, push: impl FnOnce(&mut Vec<u8>, T)) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            push(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+fn push_snapshot(out: &mut Vec<u8>, snapshot: Snapshot) {
+    out.extend_from_slice(&(snapshot.slot as u32).to_be_bytes());
+    out.extend_from_slice(&(snapshot.pc as u16).to_be_bytes());
+    out.extend_from_slice(&snapshot.registers);
+    out.push(snapshot.flags.to_bits());
+}
+
+fn push_pending(out: &mut Vec<u8>, pending: Pending) {
+    out.push(pending.carry_condition as u8);
+    for value in [pending.bank, pending.opcode_page, pending.ior_source.map(|register| register as u8), pending.sleep] {
+        push_option(out, value, |out, value| out.push(value));
+    }
+}
+
+/// Reads a section's payload, failing with the section's tag and offset.
+struct Reader<'a> {
+    section: &'a Section,
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn invalid(&self) -> RimError {
+        LoadError::InvalidSection { tag: self.section.tag, offset: self.section.offset }.into()
+    }
+
+    fn rest(&self) -> &[u8] {
+        &self.section.payload[self.at..]
+    }
+
+    fn take_rest(&mut self) -> &[u8] {
+        let rest = &self.section.payload[self.at..];
+        self.at = self.section.payload.len();
+        rest
+    }
+
+    fn bytes<const N: usize>(&mut self) -> RimResult<[u8; N]> {
+        let bytes = *self.rest().first_chunk::<N>().ok_or_else(|| self.invalid())?;
+        self.at += N;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> RimResult<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> RimResult<usize> {
+        Ok(u16::from_be_bytes(self.bytes()?) as usize)
+    }
+
+    fn u32(&mut self) -> RimResult<usize> {
+        Ok(u32::from_be_bytes(self.bytes()?) as usize)
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> RimResult<T>) -> RimResult<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            _ => Err(self.invalid()),
+        }
+    }
+
+    fn snapshot(&mut self) -> RimResult<Snapshot> {
+        Ok(Snapshot {
+            slot: self.u32()?,
+            pc: self.u16()?,
+            registers: self.bytes()?,
+            flags: Flags::from_bits(self.u8()?),
+        })
+    }
+
+    fn pending(&mut self) -> RimResult<Pending> {
+        let carry_condition = self.u8()? != 0;
+        Ok(Pending {
+            carry_condition,
+            bank: self.option(Self::u8)?,
+            opcode_page: self.option(Self::u8)?,
+            ior_source: self.option(|r| r.u8().map(Register::from))?,
+            sleep: self.option(Self::u8)?,
+        })
+    }
+}
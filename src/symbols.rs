@@ -0,0 +1,94 @@
+//! Symbol tables, mapping labels to instruction addresses.
+//!
+//! Symbol files hold one `address label` pair per line, with the address in
+//! hexadecimal, e.g. `0012 main_loop`.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::error::{LoadError, RimError, RimResult};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Symbols {
+    labels: BTreeMap<String, usize>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a label, returning its previous address if it was already defined.
+    pub fn insert(&mut self, label: impl Into<String>, addr: usize) -> Option<usize> {
+        self.labels.insert(label.into(), addr)
+    }
+
+    /// The address of a label.
+    pub fn addr(&self, label: &str) -> Option<usize> {
+        self.labels.get(label).copied()
+    }
+
+    /// The first label (alphabetically) at an address.
+    pub fn label(&self, addr: usize) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(_, &a)| a == addr)
+            .map(|(label, _)| label.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// All labels and their addresses, in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.labels.iter().map(|(label, &addr)| (label.as_str(), addr))
+    }
+
+    pub fn parse(text: &str) -> RimResult<Self> {
+        let mut symbols = Self::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = || LoadError::InvalidSymbols(i + 1);
+            let (addr, label) = line.split_once(char::is_whitespace).ok_or_else(err)?;
+            let addr = usize::from_str_radix(addr, 16).map_err(|_| err())?;
+            symbols.insert(label.trim(), addr);
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn read_file<F: AsRef<Path>>(f: F) -> RimResult<Self> {
+        let path = f.as_ref();
+        std::fs::read_to_string(path).map_err(RimError::from).and_then(|text| Self::parse(&text)).map_err(|e| e.in_file(path))
+    }
+
+    pub fn write_file<F: AsRef<Path>>(&self, f: F) -> RimResult<()> {
+        std::fs::write(&f, self.to_string()).map_err(|e| RimError::from(e).in_file(f))
+    }
+
+    /// Where the symbols for a binary live: alongside it, with a `.sym`
+    /// extension.
+    pub fn path_for<F: AsRef<Path>>(binary: F) -> PathBuf {
+        binary.as_ref().with_extension("sym")
+    }
+}
+
+impl Display for Symbols {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sorted: Vec<_> = self.iter().collect();
+        sorted.sort_by_key(|&(label, addr)| (addr, label));
+
+        for (label, addr) in sorted {
+            writeln!(f, "{addr:04x} {label}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+//! Taint tracking: following untrusted input through a program.
+//!
+//! A [`Taint`] steps a machine like [`Profile`](crate::profile::Profile)
+//! does, keeping a shadow bit for each register, flag set, and byte of data
+//! memory that says whether it came from input. Keys read from the
+//! keyboard, and bytes read from the disk, are tainted, and taint spreads
+//! through arithmetic, loads, and stores. Whenever tainted data decides
+//! where a jump goes, or is written to a device, that's reported, since
+//! it's where input could take control of a program or leak out of it.
+//!
+//! Only data is tracked, not control: a value picked by branching on a
+//! tainted flag isn't tainted.
+
+use std::fmt;
+
+use crate::addr::Addr;
+use crate::error::RimResult;
+use crate::{image, Device, InstructionData, Opcode, Register, Rim, Status};
+
+/// Where tainted data ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    /// A jump's target. Its condition doesn't count.
+    Jump,
+    /// The value written to a device, by ID.
+    Device(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub slot: usize,
+    pub pc: usize,
+    pub sink: Sink,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sink {
+            Sink::Jump => write!(f, "tainted jump target at {}:{:#05x}", self.slot, self.pc),
+            Sink::Device(id) => write!(
+                f,
+                "tainted data written to {} at {}:{:#05x}",
+                image::device_name(id),
+                self.slot,
+                self.pc
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Taint {
+    registers: [bool; 4],
+    flags: bool,
+    data: Box<[bool; 4096]>,
+    /// Whether anything tainted was put on the screen, which the program
+    /// can read back.
+    screen: bool,
+    reports: Vec<Report>,
+}
+
+impl Default for Taint {
+    fn default() -> Self {
+        Self {
+            registers: [false; 4],
+            flags: false,
+            data: Box::new([false; 4096]),
+            screen: false,
+            reports: Vec::new(),
+        }
+    }
+}
+
+impl Taint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `rim` until it halts, tracking taint.
+    pub fn run(rim: &mut Rim) -> RimResult<Self> {
+        let mut taint = Self::new();
+        while taint.step(rim)? == Status::Running {}
+
+        Ok(taint)
+    }
+
+    pub fn is_register_tainted(&self, register: Register) -> bool {
+        self.registers[register as usize]
+    }
+
+    /// Marks a register as input, or not.
+    pub fn set_register_tainted(&mut self, register: Register, tainted: bool) {
+        self.registers[register as usize] = tainted;
+    }
+
+    pub fn is_data_tainted(&self, addr: usize) -> bool {
+        self.data[addr % 4096]
+    }
+
+    /// Marks a byte of data memory as input, or not, like a buffer the host
+    /// filled.
+    pub fn set_data_tainted(&mut self, addr: usize, tainted: bool) {
+        self.data[addr % 4096] = tainted;
+    }
+
+    /// Everything reported so far, in order.
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    pub fn take_reports(&mut self) -> Vec<Report> {
+        std::mem::take(&mut self.reports)
+    }
+
+    /// Steps `rim` once, following taint through the instruction it
+    /// executes.
+    pub fn step(&mut self, rim: &mut Rim) -> RimResult<Status> {
+        // Extended instructions are up to their embedders, so aren't
+        // followed.
+        let Some(instruction) = rim.next_instruction().filter(|_| rim.opcode_page.is_none()) else {
+            return rim.step();
+        };
+
+        let (slot, pc, registers, bank, ior_source) = (rim.current(), rim.pc(), rim.registers(), rim.bank, rim.ior_source);
+        let handling = rim.fault_handler.is_some();
+        let status = rim.step()?;
+
+        // A receive or system call that has to wait, or a fault the program handled, never
+        // finished the instruction. Handlers are unset as they're entered.
+        if rim.is_blocked() || rim.is_waiting() || handling && rim.fault_handler.is_none() {
+            return Ok(status);
+        }
+
+        let t = self.registers;
+        let sink = match instruction.1 {
+            InstructionData::Imm(_) => {
+                self.flags = t[0];
+                None
+            }
+            InstructionData::Reg { is_id, src, dest } => {
+                let (src, dest, selectors) = if is_id {
+                    (
+                        Register::from(registers[src as usize]) as usize,
+                        Register::from(registers[dest as usize]) as usize,
+                        t[src as usize] || t[dest as usize],
+                    )
+                } else {
+                    (src as usize, dest as usize, false)
+                };
+
+                // Subtracting a register from itself always gives 0.
+                let cleared = instruction.0 == Opcode::Sub && src == dest && !selectors;
+                self.registers[dest] = (t[dest] || t[src] || selectors) && !cleared;
+                self.flags = self.registers[dest];
+                None
+            }
+            InstructionData::Mem { is_ptr, addr } => {
+                let addr = Addr::new(registers[3], addr as u8).index();
+                (t[3] || is_ptr && self.data[addr]).then_some(Sink::Jump)
+            }
+            InstructionData::Io { device, function } => {
+                let (value, tainted) = match instruction.0 {
+                    Opcode::Ioi => (registers[0], t[0]),
+                    _ => match ior_source {
+                        Some(register) => (registers[register as usize], t[register as usize]),
+                        // Bad selectors fault, unless the microcode was replaced.
+                        None => {
+                            let selected = (registers[0] as usize).min(3);
+                            (registers[selected], t[selected] || t[0])
+                        }
+                    },
+                };
+
+                match bank.filter(|&bank| bank != 0) {
+                    Some(bank) => {
+                        let id = bank as usize * 4 + device as usize;
+                        // Only what's read from the disk is input.
+                        let reads = match id {
+                            image::DEVICE_DISK => matches!(function as u8, 2 | 4),
+                            image::DEVICE_GRAPHICS => matches!(function as u8, 3 | 6),
+                            image::DEVICE_SOUND => function as u8 == 2,
+                            image::DEVICE_MAILBOX => matches!(function as u8, 2 | 4..=6),
+                            _ => false,
+                        };
+
+                        if reads && rim.has_device(id) {
+                            self.registers[0] = id == image::DEVICE_DISK && function as u8 == 2;
+                        }
+
+                        tainted.then_some(Sink::Device(id))
+                    }
+                    None => self.io(rim, device, function as u8, registers, value, tainted),
+                }
+            }
+        };
+
+        if let Some(sink) = sink {
+            self.reports.push(Report { slot, pc, sink });
+        }
+
+        Ok(status)
+    }
+
+    /// Follows taint through standard I/O of `value`, given the registers
+    /// before it, returning where it went if it's reported.
+    fn io(
+        &mut self,
+        rim: &Rim,
+        device: Device,
+        function: u8,
+        registers: [u8; 4],
+        value: u8,
+        tainted: bool,
+    ) -> Option<Sink> {
+        let d = |offset| Addr::new(registers[3], offset).index();
+        let t = self.registers;
+
+        match (device, function) {
+            (Device::Cpu, 2) => self.registers[0] = false,
+            (Device::Cpu, 3) => self.registers[0] = self.data[d(value)],
+            (Device::Cpu, 4) => self.data[d(registers[0])] = tainted,
+            (Device::Cpu, 5) => self.registers[0] = self.data[d(rim.data[d(value)])],
+            (Device::Cpu, 6) => self.data[d(rim.data[d(registers[0])])] = tainted,
+            (Device::Cpu, 7) => {
+                // Reading the last fault.
+                if value == 8 {
+                    self.registers[..3].fill(false);
+                }
+
+                // System calls' arguments decide where programs go, too.
+                return (tainted || t[1]).then_some(Sink::Device(Device::Cpu as usize));
+            }
+            (Device::Kbd, _) => self.registers[0] = true,
+            (Device::Scr, 0..=2) if tainted => {
+                self.screen |= function == 2;
+                return Some(Sink::Device(Device::Scr as usize));
+            }
+            (Device::Scr, 3) => self.registers[0] = self.screen,
+            (Device::Scr, 4) => self.registers[0] = false,
+            (Device::Mth, 0) => {
+                self.registers[0] = t[0] || tainted;
+                self.registers[1] = self.registers[0];
+                self.flags = self.registers[0];
+            }
+            (Device::Mth, 1..=4) => {
+                self.registers[0] = t[0] || tainted;
+                self.flags = self.registers[0];
+            }
+            (Device::Mth, 5) => self.flags = t[0],
+            (Device::Mth, 6) => self.registers[0] = self.flags,
+            (Device::Mth, 7) => self.flags = tainted,
+            _ => {}
+        }
+
+        None
+    }
+}
@@ -0,0 +1,295 @@
+//! Testing programs from Rust, with assertions the guest makes itself.
+//!
+//! The assertion device is host device 0 of bank 3 (ID [`ASSERT_DEVICE`],
+//! see the [`host`](crate::host) module), and has these functions:
+//!
+//! | Function | Effect                                                   |
+//! |----------|----------------------------------------------------------|
+//! | 0        | Set the expected value to `value`                        |
+//! | 1        | Fail unless `value` is the expected value                |
+//! | 2        | Set the code later failures report to `value`            |
+//! | 3        | Fail with code `value`                                   |
+//!
+//! A failure is a [`RimError::Device`] whose source is an
+//! [`AssertionFailed`], so it stops the program like any device failure.
+//! [`check`] runs a program with the device attached, and turns a failed
+//! assertion into a panic naming it and where it was made, so an assembly
+//! unit test is a `#[test]` that calls it.
+//!
+//! With the value in Rc, an assertion is the `ext` system call selecting
+//! bank 3 and an `ior` passing Rc, which Ra still selects:
+//!
+//! ```text
+//!     li rb, 3
+//!     li ra, 2
+//!     ioi cpu, 7      ; the next I/O goes to bank 3
+//!     ior cpu, 0      ; expect Rc
+//! ```
+//!
+//! [`rim_test!`](crate::rim_test) declares a whole test at once: a
+//! program in assembler source, how to run it, and the registers, memory,
+//! and output it must leave behind.
+//!
+//! ```
+//! use pact::{asm, testing, Rim};
+//!
+//! let program = asm::assemble("
+//!     li rc, 5
+//!     li rb, 3
+//!     li ra, 2
+//!     ioi cpu, 7
+//!     ior cpu, 0      ; expect 5
+//!     li rb, 3
+//!     li ra, 2
+//!     ioi cpu, 7
+//!     ior cpu, 1      ; Rc is 5
+//! ").unwrap();
+//! testing::check(Rim::new(program));
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+use crate::asm::Assembler;
+use crate::error::RimError;
+use crate::grade::{Check, Limits, Outcome};
+use crate::helper::U3;
+use crate::host::{DeviceError, HostDevice, FIRST_HOST_DEVICE};
+use crate::runner::{RunReport, Runner};
+use crate::{Register, Rim};
+
+/// The device ID of the assertion device.
+pub const ASSERT_DEVICE: usize = FIRST_HOST_DEVICE;
+
+/// The assertion device.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Assertions {
+    expected: u8,
+    code: u8,
+}
+
+impl HostDevice for Assertions {
+    fn io(&mut self, function: U3, value: u8) -> Result<Option<u8>, DeviceError> {
+        match function as u8 {
+            0 => self.expected = value,
+            1 if value != self.expected => {
+                return Err(Box::new(AssertionFailed {
+                    code: self.code,
+                    values: Some((self.expected, value)),
+                }));
+            }
+            2 => self.code = value,
+            3 => return Err(Box::new(AssertionFailed { code: value, values: None })),
+            _ => {}
+        }
+
+        Ok(None)
+    }
+}
+
+/// A failed guest assertion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssertionFailed {
+    pub code: u8,
+    /// What was expected and found, if it compared them.
+    pub values: Option<(u8, u8)>,
+}
+
+impl fmt::Display for AssertionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "assertion {} failed", self.code)?;
+        if let Some((expected, found)) = self.values {
+            write!(f, ": expected {expected:#04x}, found {found:#04x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for AssertionFailed {}
+
+/// A runner for a machine with the assertion device attached.
+pub fn runner(mut rim: Rim) -> Runner {
+    rim.attach_device(ASSERT_DEVICE, Assertions::default());
+    Runner::new(rim)
+}
+
+/// The guest assertion that failed in a run, and the pc it was made at.
+pub fn failed_assertion(report: &RunReport) -> Option<(usize, &AssertionFailed)> {
+    match report.termination.error()? {
+        RimError::Device { pc, source, .. } => source.downcast_ref().map(|failed| (*pc, failed)),
+        _ => None,
+    }
+}
+
+/// Panics unless a run halted, saying which guest assertion failed and
+/// where if one did.
+#[track_caller]
+pub fn assert_halted(report: &RunReport) {
+    if let Some((pc, failed)) = failed_assertion(report) {
+        panic!("guest {failed} at {pc:#05x}");
+    }
+
+    if !matches!(report.termination, Outcome::Halted) {
+        panic!("program {report}");
+    }
+}
+
+/// Runs a program with the assertion device attached and the default
+/// limits, and panics unless it halts.
+#[track_caller]
+pub fn check(rim: Rim) -> RunReport {
+    let report = runner(rim).execute();
+    assert_halted(&report);
+    report
+}
+
+/// An assembly unit test: a program, how to run it, and what it must
+/// leave behind. It always has to halt.
+#[derive(Debug, Clone)]
+pub struct RimTest {
+    source: String,
+    input: Vec<u8>,
+    limits: Limits,
+    checks: Vec<Check>,
+}
+
+impl RimTest {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            input: Vec::new(),
+            limits: Limits::default(),
+            checks: Vec::new(),
+        }
+    }
+
+    /// Sets the keys fed to the keyboard, in order.
+    pub fn input(mut self, input: impl AsRef<[u8]>) -> Self {
+        self.input = input.as_ref().to_vec();
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.limits.max_steps = max_steps;
+        self
+    }
+
+    pub fn register(mut self, register: Register, value: u8) -> Self {
+        self.checks.push(Check::Register(register, value));
+        self
+    }
+
+    pub fn ra(self, value: u8) -> Self {
+        self.register(Register::Ra, value)
+    }
+
+    pub fn rb(self, value: u8) -> Self {
+        self.register(Register::Rb, value)
+    }
+
+    pub fn rc(self, value: u8) -> Self {
+        self.register(Register::Rc, value)
+    }
+
+    pub fn rd(self, value: u8) -> Self {
+        self.register(Register::Rd, value)
+    }
+
+    /// Requires the data byte at `addr` to be `value`.
+    pub fn mem(mut self, addr: usize, value: u8) -> Self {
+        self.checks.push(Check::Memory(addr, value));
+        self
+    }
+
+    /// Requires the program to write exactly this to the screen.
+    pub fn output(mut self, output: impl AsRef<[u8]>) -> Self {
+        self.checks.push(Check::Output(output.as_ref().to_vec()));
+        self
+    }
+
+    /// Assembles and runs the program with the assertion device attached,
+    /// panicking if it doesn't assemble or halt, or with every check it
+    /// fails.
+    #[track_caller]
+    pub fn run(self) -> RunReport {
+        let program = Assembler::new().assemble(&self.source).unwrap_or_else(|e| panic!("program doesn't assemble: {e}"));
+        let report = runner(Rim::new(program)).input(self.input).limits(self.limits).execute();
+        assert_halted(&report);
+
+        let failed: Vec<String> = self
+            .checks
+            .iter()
+            .filter_map(|check| {
+                let found = match *check {
+                    Check::Register(register, value) => {
+                        let found = report.snapshot.registers[register as usize];
+                        (found != value).then(|| format!("{found:#04x}"))
+                    }
+                    Check::Memory(addr, value) => {
+                        let found = report.data.get(addr).copied();
+                        (found != Some(value)).then(|| found.map_or("nothing".to_string(), |found| format!("{found:#04x}")))
+                    }
+                    Check::Output(ref output) => {
+                        (report.output != *output).then(|| format!("{:?}", String::from_utf8_lossy(&report.output)))
+                    }
+                    Check::Halted => None,
+                };
+
+                found.map(|found| format!("expected {check}, found {found}"))
+            })
+            .collect();
+
+        if !failed.is_empty() {
+            panic!("{}", failed.join("\n"));
+        }
+
+        report
+    }
+}
+
+/// Declares `#[test]`s that assemble a program, run it, and check what it
+/// left behind, one [`RimTest`] each. After the source come any of
+/// `input`, `max_steps`, and the checks `ra` to `rd`, `mem[addr]`, and
+/// `output`, each as `key: value`:
+///
+/// ```
+/// pact::rim_test! {
+///     fn adds() {
+///         "
+///             adi 20
+///             adi 22
+///             ioi cpu, 0
+///         ",
+///         max_steps: 10,
+///         ra: 42,
+///     }
+///
+///     fn echoes() {
+///         "
+///             ioi kbd, 0
+///             ioi scr, 2
+///         ",
+///         input: "x",
+///         output: "x",
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! rim_test {
+    ($(#[$meta:meta])* fn $name:ident() { $source:expr $(, $key:ident $([$index:expr])?: $value:expr)* $(,)? } $($rest:tt)*) => {
+        $(#[$meta])*
+        #[test]
+        fn $name() {
+            $crate::testing::RimTest::new($source)$(.$key($($index,)? $value))*.run();
+        }
+
+        $crate::rim_test! { $($rest)* }
+    };
+    () => {};
+}
@@ -0,0 +1,538 @@
+//! Recorded execution traces, which can be read back from any step without
+//! replaying the run up to it.
+//!
+//! A [`Recorder`] steps a machine and writes what each step changed. The
+//! steps are grouped into blocks, each starting with a keyframe of the
+//! whole machine state, and an index of the blocks goes at the end, so a
+//! [`Trace`] finds the state before step 1,200,000 by seeking to its block
+//! and replaying at most a block's worth of steps. Steps are stored as
+//! deltas, so most take 2 or 3 bytes. Two traces of the same program, such
+//! as from two builds of pact, can be compared with [`Trace::diverge`]. All
+//! numbers are big-endian:
+//!
+//! ```text
+//! header:   "RTRC", version (1), steps per block (u32)
+//! block:    first step (u64), steps (u32), bytes after this (u32),
+//!           keyframe, steps
+//! keyframe: slot (u32), pc (u16), registers (4), flags, memory
+//! memory:   runs of (length, byte), with lengths of 1 to 255
+//! step:     instruction, changes, then the changed fields in order:
+//!           pc (u16) unless it just moved on one, each changed register,
+//!           flags, slot (u32), and memory as a count (u16) of (address
+//!           (u16), byte) pairs
+//! changes:  bit 0 pc, bits 1 to 4 Ra to Rd, 5 flags, 6 slot, 7 memory
+//! index:    blocks (u32), then each block's first step (u64) and offset
+//!           (u64), then the index's offset (u64), and "RIDX"
+//! ```
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use pact::trace::Trace;
+//!
+//! let mut trace = Trace::open(File::open("run.trace").unwrap()).unwrap();
+//! let frame = trace.frame(1_200_000).unwrap();
+//! println!("{}", frame.snapshot);
+//! ```
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use crate::addr::Addr;
+use crate::error::{LoadError, RimResult};
+use crate::{Flags, Instruction, Rim, Snapshot, Status};
+
+const MAGIC: &[u8; 4] = b"RTRC";
+const INDEX_MAGIC: &[u8; 4] = b"RIDX";
+const VERSION: u8 = 1;
+
+/// How many steps a [`Recorder`] puts in a block, by default.
+pub const DEFAULT_BLOCK_LEN: u32 = 4096;
+
+const PC: u8 = 1;
+const FLAGS: u8 = 1 << 5;
+const SLOT: u8 = 1 << 6;
+const MEMORY: u8 = 1 << 7;
+
+/// The machine's state between two steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub snapshot: Snapshot,
+    pub data: Box<[u8; 4096]>,
+}
+
+impl Frame {
+    fn of(rim: &Rim) -> Self {
+        Self { snapshot: rim.snapshot(), data: Box::new(*rim.data()) }
+    }
+}
+
+/// What one step did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub instruction: Instruction,
+    /// The addresses it stored to, and what it stored.
+    pub writes: Vec<(Addr, u8)>,
+    changes: u8,
+    pc: usize,
+    registers: [u8; 4],
+    flags: Flags,
+    slot: usize,
+}
+
+impl Step {
+    fn apply(&self, frame: &mut Frame) {
+        let snapshot = &mut frame.snapshot;
+        snapshot.pc = if self.changes & PC != 0 { self.pc } else { snapshot.pc + 1 };
+        for (i, register) in snapshot.registers.iter_mut().enumerate() {
+            if self.changes & 1 << (i + 1) != 0 {
+                *register = self.registers[i];
+            }
+        }
+
+        if self.changes & FLAGS != 0 {
+            snapshot.flags = self.flags;
+        }
+
+        if self.changes & SLOT != 0 {
+            snapshot.slot = self.slot;
+        }
+
+        for &(addr, value) in &self.writes {
+            frame.data[addr.index()] = value;
+        }
+    }
+}
+
+/// Steps a machine, writing a trace of what each step did.
+pub struct Recorder<W: Write> {
+    out: W,
+    offset: u64,
+    block_len: u32,
+    /// The block being filled: its keyframe and steps.
+    block: Vec<u8>,
+    first: u64,
+    steps: u64,
+    index: Vec<(u64, u64)>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            offset: 0,
+            block_len: DEFAULT_BLOCK_LEN,
+            block: Vec::new(),
+            first: 0,
+            steps: 0,
+            index: Vec::new(),
+        }
+    }
+
+    /// Sets how many steps go in each block: fewer make seeking faster, and
+    /// the trace bigger.
+    pub fn block_len(mut self, block_len: u32) -> Self {
+        self.block_len = block_len.max(1);
+        self
+    }
+
+    /// How many steps have been recorded.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Steps the machine, and records the step. A step that fails isn't
+    /// recorded.
+    pub fn step(&mut self, rim: &mut Rim) -> RimResult<Status> {
+        if self.block.is_empty() {
+            self.start_block(rim);
+        }
+
+        let before = rim.snapshot();
+        let instruction = rim.next_instruction().unwrap_or(Instruction::decode(0));
+        // Holding on to memory makes the first store copy it, so an
+        // untouched step costs nothing to check.
+        let data = Arc::clone(&rim.data);
+        let status = rim.step()?;
+        let after = rim.snapshot();
+
+        let mut changes = 0;
+        let mut fields = Vec::new();
+        if after.pc != before.pc + 1 {
+            changes |= PC;
+            fields.extend((after.pc as u16).to_be_bytes());
+        }
+
+        for i in 0..4 {
+            if after.registers[i] != before.registers[i] {
+                changes |= 1 << (i + 1);
+                fields.push(after.registers[i]);
+            }
+        }
+
+        if after.flags != before.flags {
+            changes |= FLAGS;
+            fields.push(after.flags.to_bits());
+        }
+
+        if after.slot != before.slot {
+            changes |= SLOT;
+            fields.extend((after.slot as u32).to_be_bytes());
+        }
+
+        if !Arc::ptr_eq(&data, &rim.data) {
+            let writes: Vec<_> = (0..Addr::COUNT).filter(|&i| data[i] != rim.data[i]).collect();
+            if !writes.is_empty() {
+                changes |= MEMORY;
+                fields.extend((writes.len() as u16).to_be_bytes());
+                for i in writes {
+                    fields.extend((i as u16).to_be_bytes());
+                    fields.push(rim.data[i]);
+                }
+            }
+        }
+
+        self.block.push(instruction.into());
+        self.block.push(changes);
+        self.block.extend(fields);
+        self.steps += 1;
+
+        if self.steps - self.first == self.block_len as u64 {
+            self.flush()?;
+        }
+
+        Ok(status)
+    }
+
+    /// Steps the machine until it halts or faults, recording every step.
+    pub fn run(&mut self, rim: &mut Rim) -> RimResult<()> {
+        while self.step(rim)? == Status::Running {}
+        Ok(())
+    }
+
+    /// Writes the last block and the index, returning the writer.
+    pub fn finish(mut self) -> RimResult<W> {
+        self.flush()?;
+        if self.offset == 0 {
+            self.write_header()?;
+        }
+
+        let index_offset = self.offset;
+        let mut index = (self.index.len() as u32).to_be_bytes().to_vec();
+        for &(first, offset) in &self.index {
+            index.extend(first.to_be_bytes());
+            index.extend(offset.to_be_bytes());
+        }
+
+        index.extend(index_offset.to_be_bytes());
+        index.extend(INDEX_MAGIC);
+        self.out.write_all(&index)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn start_block(&mut self, rim: &Rim) {
+        let Frame { snapshot, data } = Frame::of(rim);
+        self.first = self.steps;
+        self.block.extend((snapshot.slot as u32).to_be_bytes());
+        self.block.extend((snapshot.pc as u16).to_be_bytes());
+        self.block.extend(snapshot.registers);
+        self.block.push(snapshot.flags.to_bits());
+
+        for run in data.chunk_by(|a, b| a == b) {
+            for part in run.chunks(255) {
+                self.block.extend([part.len() as u8, part[0]]);
+            }
+        }
+    }
+
+    fn write_header(&mut self) -> RimResult<()> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        header.extend(self.block_len.to_be_bytes());
+        self.out.write_all(&header)?;
+        self.offset += header.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> RimResult<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        if self.offset == 0 {
+            self.write_header()?;
+        }
+
+        let mut header = self.first.to_be_bytes().to_vec();
+        header.extend(((self.steps - self.first) as u32).to_be_bytes());
+        header.extend((self.block.len() as u32).to_be_bytes());
+        self.out.write_all(&header)?;
+        self.out.write_all(&self.block)?;
+
+        self.index.push((self.first, self.offset));
+        self.offset += (header.len() + self.block.len()) as u64;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+/// Where two traces first differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The first step that did something different in each trace, or that
+    /// only one of them has.
+    pub step: u64,
+    /// The state before the step, as the first trace has it. The traces
+    /// agree on it unless they start differently, at step 0.
+    pub before: Frame,
+    /// The instruction each trace ran, if it got that far.
+    pub instructions: (Option<Instruction>, Option<Instruction>),
+    /// The state after the step in each trace, if it got that far.
+    pub after: (Option<Frame>, Option<Frame>),
+}
+
+/// A block of a trace, read back.
+struct Block {
+    first: u64,
+    keyframe: Frame,
+    steps: Vec<Step>,
+}
+
+/// A recorded trace, read back.
+pub struct Trace<R: Read + Seek> {
+    input: R,
+    /// Each block's first step and offset.
+    index: Vec<(u64, u64)>,
+    len: u64,
+    /// The last block read.
+    cached: Option<Block>,
+}
+
+impl<R: Read + Seek> Trace<R> {
+    /// Reads a trace's header and index.
+    pub fn open(mut input: R) -> RimResult<Self> {
+        let mut header = [0; 9];
+        input.seek(SeekFrom::Start(0))?;
+        input.read_exact(&mut header).map_err(|_| LoadError::InvalidTrace)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(LoadError::InvalidTrace.into());
+        }
+
+        let mut footer = [0; 12];
+        input.seek(SeekFrom::End(-12)).map_err(|_| LoadError::InvalidTrace)?;
+        input.read_exact(&mut footer)?;
+        if &footer[8..] != INDEX_MAGIC {
+            return Err(LoadError::InvalidTrace.into());
+        }
+
+        input.seek(SeekFrom::Start(u64::from_be_bytes(footer[..8].try_into().unwrap())))?;
+        let blocks = read_u32(&mut input)?;
+        let mut index = Vec::new();
+        for _ in 0..blocks {
+            index.push((read_u64(&mut input)?, read_u64(&mut input)?));
+        }
+
+        let mut trace = Self { input, index, len: 0, cached: None };
+        if let Some(last) = trace.index.len().checked_sub(1) {
+            let block = trace.block(last)?;
+            trace.len = block.first + block.steps.len() as u64;
+        }
+
+        Ok(trace)
+    }
+
+    /// How many steps were recorded.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The state before a step, or after the last if `step` is the trace's
+    /// length.
+    pub fn frame(&mut self, step: u64) -> RimResult<Frame> {
+        if step > self.len || self.index.is_empty() {
+            return Err(LoadError::InvalidTrace.into());
+        }
+
+        let block = self.block(self.block_of(step))?;
+        let mut frame = block.keyframe.clone();
+        for step in &block.steps[..(step - block.first) as usize] {
+            step.apply(&mut frame);
+        }
+
+        Ok(frame)
+    }
+
+    /// What a step did.
+    pub fn step(&mut self, step: u64) -> RimResult<Step> {
+        if step >= self.len {
+            return Err(LoadError::InvalidTrace.into());
+        }
+
+        let block = self.block(self.block_of(step))?;
+        Ok(block.steps[(step - block.first) as usize].clone())
+    }
+
+    /// The first step where this trace and another differ, if they do,
+    /// such as two builds' traces of the same program.
+    pub fn diverge<S: Read + Seek>(&mut self, other: &mut Trace<S>) -> RimResult<Option<Divergence>> {
+        let shared = self.len.min(other.len);
+        let mut at = (shared > 0 && self.frame(0)? != other.frame(0)?).then_some(0);
+        if at.is_none() {
+            for step in 0..shared {
+                if self.step(step)? != other.step(step)? {
+                    at = Some(step);
+                    break;
+                }
+            }
+        }
+
+        let Some(step) = at.or((self.len != other.len).then_some(shared)) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Divergence {
+            step,
+            before: self.frame(step).or_else(|_| other.frame(step))?,
+            instructions: (self.step(step).ok().map(|s| s.instruction), other.step(step).ok().map(|s| s.instruction)),
+            after: (self.frame(step + 1).ok(), other.frame(step + 1).ok()),
+        }))
+    }
+
+    /// The steps that stored to an address, in order.
+    pub fn writes_to(&mut self, addr: Addr) -> RimResult<Vec<u64>> {
+        let mut found = Vec::new();
+        for i in 0..self.index.len() {
+            let block = self.block(i)?;
+            for (n, step) in block.steps.iter().enumerate() {
+                if step.writes.iter().any(|&(written, _)| written == addr) {
+                    found.push(block.first + n as u64);
+                }
+            }
+        }
+
+        Ok(found)
+    }
+
+    fn block_of(&self, step: u64) -> usize {
+        self.index.partition_point(|&(first, _)| first <= step).saturating_sub(1)
+    }
+
+    fn block(&mut self, i: usize) -> RimResult<&Block> {
+        let (first, offset) = self.index[i];
+        if self.cached.as_ref().is_none_or(|block| block.first != first) {
+            self.input.seek(SeekFrom::Start(offset))?;
+            self.cached = Some(read_block(&mut self.input)?);
+        }
+
+        Ok(self.cached.as_ref().unwrap())
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> RimResult<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes).map_err(|_| LoadError::InvalidTrace)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> RimResult<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes).map_err(|_| LoadError::InvalidTrace)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_block(input: &mut impl Read) -> RimResult<Block> {
+    let first = read_u64(input)?;
+    let count = read_u32(input)?;
+    let mut bytes = vec![0; read_u32(input)? as usize];
+    input.read_exact(&mut bytes).map_err(|_| LoadError::InvalidTrace)?;
+
+    let mut bytes = Bytes(&bytes);
+    let mut keyframe = Frame {
+        snapshot: Snapshot {
+            slot: bytes.u32()? as usize,
+            pc: bytes.u16()? as usize,
+            registers: [bytes.u8()?, bytes.u8()?, bytes.u8()?, bytes.u8()?],
+            flags: Flags::from_bits(bytes.u8()?),
+        },
+        data: Box::new([0; 4096]),
+    };
+
+    let mut filled = 0;
+    while filled < Addr::COUNT {
+        let (len, value) = (bytes.u8()? as usize, bytes.u8()?);
+        let run = keyframe.data.get_mut(filled..filled + len).filter(|run| !run.is_empty());
+        run.ok_or(LoadError::InvalidTrace)?.fill(value);
+        filled += len;
+    }
+
+    let mut steps = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let instruction = Instruction::decode(bytes.u8()?);
+        let changes = bytes.u8()?;
+        let mut step = Step {
+            instruction,
+            writes: Vec::new(),
+            changes,
+            pc: 0,
+            registers: [0; 4],
+            flags: Flags::default(),
+            slot: 0,
+        };
+
+        if changes & PC != 0 {
+            step.pc = bytes.u16()? as usize;
+        }
+
+        for i in 0..4 {
+            if changes & 1 << (i + 1) != 0 {
+                step.registers[i] = bytes.u8()?;
+            }
+        }
+
+        if changes & FLAGS != 0 {
+            step.flags = Flags::from_bits(bytes.u8()?);
+        }
+
+        if changes & SLOT != 0 {
+            step.slot = bytes.u32()? as usize;
+        }
+
+        if changes & MEMORY != 0 {
+            for _ in 0..bytes.u16()? {
+                let addr = Addr::from_index(bytes.u16()? as usize).ok_or(LoadError::InvalidTrace)?;
+                step.writes.push((addr, bytes.u8()?));
+            }
+        }
+
+        steps.push(step);
+    }
+
+    Ok(Block { first, keyframe, steps })
+}
+
+/// A block's bytes, being decoded.
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn take<const N: usize>(&mut self) -> RimResult<[u8; N]> {
+        let (taken, rest) = self.0.split_first_chunk().ok_or(LoadError::InvalidTrace)?;
+        self.0 = rest;
+        Ok(*taken)
+    }
+
+    fn u8(&mut self) -> RimResult<u8> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    fn u16(&mut self) -> RimResult<u16> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> RimResult<u32> {
+        self.take().map(u32::from_be_bytes)
+    }
+}
@@ -0,0 +1,85 @@
+//! Once the screen has presented, stepping allocates nothing: not for the
+//! instructions, the screen's changes, or the frames it presents.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use pact::console::{Buffer, Console};
+use pact::screen::Present;
+use pact::Rim;
+
+/// Counts allocations made on threads that ask for it, so the test
+/// harness's own don't count.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn count() {
+    if COUNTING.try_with(Cell::get).unwrap_or(false) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Enough steps to fill the screen and scroll it.
+const STEPS: usize = 50_000;
+
+#[test]
+fn stepping_allocates_nothing_after_the_first_present() {
+    let program = pact::asm::assemble("
+    loop:
+        li ra, 65
+        ioi scr, 2
+        ioi scr, 6
+        jne loop
+    ").unwrap();
+
+    for present in [Present::Manual, Present::Immediate] {
+        let mut rim = Rim::new(program.clone());
+        // Capturing nothing keeps the output from growing, but frames are
+        // still rendered for it.
+        let mut buffer = Buffer::new(Vec::new());
+        buffer.set_limit(0);
+        rim.set_console(Console::Buffer(buffer));
+        rim.set_present_mode(present);
+        rim.present();
+
+        COUNTING.with(|counting| counting.set(true));
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..STEPS {
+            rim.step().unwrap();
+        }
+        let after = ALLOCATIONS.load(Ordering::Relaxed);
+        COUNTING.with(|counting| counting.set(false));
+
+        assert_eq!(after, before, "{} allocations under {present:?}", after - before);
+    }
+}
@@ -0,0 +1,114 @@
+//! Random programs, from random states, with every built-in device
+//! attached, only ever halt, fault, or keep running: none of them can panic
+//! the host.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use pact::config::RimConfig;
+use pact::console::{Buffer, Console};
+use pact::disk::{Disk, SECTOR_SIZE};
+use pact::graphics::Graphics;
+use pact::isa::IsaLevel;
+use pact::mailbox::Mailbox;
+use pact::sound::Sound;
+use pact::{Architecture, Arithmetic, Flags, Instruction, Rim};
+
+const PROGRAMS: usize = 2000;
+const STEPS: usize = 500;
+
+/// A small xorshift generator, so failures reproduce from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.byte()).collect()
+    }
+}
+
+fn machine(rng: &mut Rng) -> Rim {
+    let len = 1 + rng.next() as usize % 64;
+    let program = rng.bytes(len).into_iter().map(Instruction::decode).collect();
+
+    let architecture = if rng.byte() & 1 == 0 { Architecture::Harvard } else { Architecture::VonNeumann };
+    let arithmetic = [Arithmetic::Wrapping, Arithmetic::Flagged, Arithmetic::Faulting][rng.next() as usize % 3];
+    let isa = if rng.byte() & 1 == 0 { IsaLevel::V1 } else { IsaLevel::V2 };
+    let input = rng.bytes(16);
+    let mut rim = RimConfig::new()
+        .architecture(architecture)
+        .arithmetic(arithmetic)
+        .isa(isa)
+        .memory_len(rng.next() as usize % (4096 + 1))
+        .console(Console::Buffer(Buffer::new(input)))
+        .disk(Disk::new(rng.bytes(4 * SECTOR_SIZE)))
+        .graphics(Graphics::new())
+        .sound(Sound::new())
+        .mailbox(Mailbox::new())
+        .build(program);
+
+    rim.set_registers([rng.byte(), rng.byte(), rng.byte(), rng.byte()]);
+    rim.set_flags(Flags::from_bits(rng.byte()));
+    let data = rng.bytes(4096);
+    rim.data_mut().copy_from_slice(&data);
+    rim
+}
+
+#[test]
+fn random_programs_never_panic_in_bursts() {
+    let mut rng = Rng(0x5eed_0928);
+    for i in 0..PROGRAMS {
+        let mut rim = machine(&mut rng);
+        let burst = catch_unwind(AssertUnwindSafe(|| rim.burst(STEPS).result.map_err(|e| e.to_string())));
+        assert!(burst.is_ok(), "program {i} panicked in a burst");
+    }
+}
+
+#[test]
+fn random_programs_never_panic_by_steps() {
+    let mut rng = Rng(0x5eed_0929);
+    for i in 0..PROGRAMS {
+        let mut rim = machine(&mut rng);
+        let steps = catch_unwind(AssertUnwindSafe(|| {
+            for _ in 0..STEPS {
+                // A fault is an answer too, and ends the run like a halt.
+                match rim.step() {
+                    Ok(pact::Status::Running) => {}
+                    Ok(_) | Err(_) => break,
+                }
+            }
+        }));
+        assert!(steps.is_ok(), "program {i} panicked while stepping");
+    }
+}
+
+#[test]
+fn out_of_range_selectors_fault() {
+    let program = pact::asm::assemble("
+        li ra, 200
+        ior scr, 2
+    ").unwrap();
+
+    let mut rim = Rim::new(program.clone());
+    rim.set_console(Console::Buffer(Buffer::new(Vec::new())));
+    let result = catch_unwind(AssertUnwindSafe(|| rim.burst(STEPS).result)).expect("a burst panicked");
+    assert_eq!(result.unwrap_err().kind(), "invalid_register");
+
+    let mut rim = Rim::new(program);
+    rim.set_console(Console::Buffer(Buffer::new(Vec::new())));
+    let result = catch_unwind(AssertUnwindSafe(|| loop {
+        if let Err(e) = rim.step() {
+            return e;
+        }
+    }));
+    assert_eq!(result.expect("a step panicked").kind(), "invalid_register");
+}
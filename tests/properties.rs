@@ -0,0 +1,19 @@
+//! Every instruction property, against the same states each run, so a
+//! change in semantics fails `cargo test` rather than waiting for someone
+//! to run `pact properties`.
+
+use pact::properties::{DEFAULT_CASES, PROPERTIES};
+
+/// Fixed, so a failure here reproduces with `Property::check_seed`.
+const SEED: u64 = 0x939;
+
+#[test]
+fn every_property_holds() {
+    let failures: Vec<String> = PROPERTIES
+        .iter()
+        .filter_map(|property| property.check(DEFAULT_CASES, SEED).err())
+        .map(|failure| failure.to_string())
+        .collect();
+
+    assert!(failures.is_empty(), "properties failed:\n{}", failures.join("\n"));
+}
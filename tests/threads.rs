@@ -0,0 +1,177 @@
+//! Machines and mailboxes used across threads the way embedders do: moved
+//! to a worker mid-run, shared behind a lock, and connected by a mailbox.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use pact::asm::assemble;
+use pact::config::RimConfig;
+use pact::console::{Console, Inbox};
+use pact::mailbox::Mailbox;
+use pact::{Rim, Status};
+
+const COUNTER: &str = "loop:\n    adi 1\n    jmp loop\n";
+
+/// The registers and `pc` a machine reaches in `steps` steps of a counter
+/// loop, run on this thread alone.
+fn counted(steps: usize) -> ([u8; 4], usize) {
+    let mut rim = Rim::new(assemble(COUNTER).unwrap());
+    rim.burst(steps).result.unwrap();
+    (rim.registers(), rim.pc())
+}
+
+/// Sends `value` to slot 0's queue `count` times. It's sent from Rc, as
+/// loading Ra after choosing the bank would take the bank's I/O.
+fn sender(value: u8, count: usize) -> String {
+    let send = "    li rb, 1\n    li ra, 2\n    ioi cpu, 7\n    ior mth, 5\n";
+    format!("    li rc, {value}\n{}", send.repeat(count))
+}
+
+/// Receives `count` messages from slot 0's queue, adding them up in Rc.
+fn receiver(count: usize) -> String {
+    let receive = "    li rb, 1\n    li ra, 2\n    ioi cpu, 7\n    ioi mth, 6\n    add ra, rc\n";
+    format!("    sub rc, rc\n{}", receive.repeat(count))
+}
+
+/// Runs a machine until it halts, waiting on its mailbox while it's
+/// blocked.
+fn run(mut rim: Rim) -> Rim {
+    for _ in 0..10_000 {
+        match rim.burst(1000).result.unwrap() {
+            Status::Halted => return rim,
+            Status::Running if rim.is_blocked() => {
+                rim.mailbox().unwrap().wait(Duration::from_millis(10));
+            }
+            Status::Running => {}
+        }
+    }
+
+    panic!("the machine didn't halt");
+}
+
+#[test]
+fn a_running_machine_moves_to_another_thread() {
+    let mut rim = Rim::new(assemble(COUNTER).unwrap());
+    rim.burst(1000).result.unwrap();
+
+    let rim = std::thread::spawn(move || {
+        rim.burst(1000).result.unwrap();
+        rim
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!((rim.registers(), rim.pc()), counted(2000));
+}
+
+#[test]
+fn a_shared_machine_steps_from_two_threads() {
+    let rim = Arc::new(Mutex::new(Rim::new(assemble(COUNTER).unwrap())));
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let rim = rim.clone();
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    rim.lock().unwrap().step().unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let rim = rim.lock().unwrap();
+    assert_eq!((rim.registers(), rim.pc()), counted(2000));
+}
+
+#[test]
+fn machines_on_three_threads_share_a_mailbox() {
+    let mailbox = Mailbox::new();
+    let machine = |source: String| RimConfig::new().mailbox(mailbox.clone()).build(assemble(&source).unwrap());
+
+    let receiver = machine(receiver(20));
+    let senders = [machine(sender(1, 10)), machine(sender(2, 10))];
+
+    let receiver = std::thread::spawn(move || run(receiver));
+    let senders: Vec<_> = senders.into_iter().map(|sender| std::thread::spawn(move || run(sender))).collect();
+    for sender in senders {
+        sender.join().unwrap();
+    }
+
+    let receiver = receiver.join().unwrap();
+    assert_eq!(receiver.registers()[2], 10 + 2 * 10);
+    assert_eq!(mailbox.pending(0), 0);
+}
+
+#[test]
+fn threads_share_a_mailbox_lock() {
+    let mailbox = Mailbox::new();
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let mailbox = mailbox.clone();
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    // Slot 0 is the lock, and slot 1 what it guards.
+                    while !mailbox.compare_and_swap(0, 0, 1) {
+                        std::thread::yield_now();
+                    }
+
+                    mailbox.set_slot(1, mailbox.slot(1).wrapping_add(1));
+                    mailbox.set_slot(0, 0);
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    assert_eq!(mailbox.slot(1), 2000u16 as u8);
+}
+
+#[test]
+fn a_waiting_machine_parks_until_keys_are_sent() {
+    let inbox = Inbox::new();
+    let mut rim = Rim::new(assemble("    li ra, 11\n    ioi cpu, 7\n    ioi kbd, 0\n").unwrap());
+    rim.set_console(Console::Inbox(inbox.clone()));
+
+    let machine = std::thread::spawn(move || {
+        rim.run().unwrap();
+        rim
+    });
+    std::thread::sleep(Duration::from_millis(100));
+    inbox.send_keys(b"k");
+
+    let rim = machine.join().unwrap();
+    assert_eq!(rim.registers()[0], b'k');
+    // Parked, it only retried the wait once it was woken, rather than
+    // every tick it was asleep.
+    let counters = rim.counters();
+    assert!(counters.cycles - counters.instructions <= 2, "{counters:?}");
+}
+
+#[test]
+fn an_interrupt_wakes_a_waiting_machine() {
+    let mut program = assemble("    li rb, 1\n    li ra, 4\n    ioi cpu, 7\n    li ra, 11\n    ioi cpu, 7\n    ioi cpu, 0\n").unwrap();
+    assert!(program.len() <= 16);
+    program.resize(16, assemble("adi 0").unwrap()[0]);
+    // The handler, at the start of page 1, stores 42 at 0x02a and returns.
+    program.extend(assemble("    li rd, 2\n    li ra, 42\n    ioi cpu, 4\n    li ra, 5\n    ioi cpu, 7\n").unwrap());
+
+    let inbox = Inbox::new();
+    let mut rim = Rim::new(program);
+    rim.set_console(Console::Inbox(inbox.clone()));
+
+    let machine = std::thread::spawn(move || {
+        rim.run().unwrap();
+        rim
+    });
+    std::thread::sleep(Duration::from_millis(100));
+    inbox.interrupt();
+
+    let rim = machine.join().unwrap();
+    assert_eq!(rim.data()[0x02a], 42);
+}